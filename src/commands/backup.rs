@@ -1,29 +1,1210 @@
+use chrono::Local;
 use clap::ArgMatches;
-use flate2::write::GzEncoder;
-use flate2::Compression;
-use log::{debug, error};
-use std::fs::File;
-use std::process::exit;
+use log::{debug, error, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-pub fn invoke(args: &ArgMatches) {
+use crate::errors::{CommandError, CommandResult};
+use crate::files::{
+  archive::{
+    checksum_path, create_archive, verify_archive, write_checksum, ArchiveFormat, Verification,
+    BACKUP_COMPRESSION_LEVEL_VAR, BACKUP_COMPRESSION_VAR, CHECKSUM_EXTENSION,
+  },
+  compression::{
+    lower_priority, parse_threads, BACKUP_NICE_VAR, BACKUP_THREADS_VAR, DEFAULT_THREADS,
+  },
+  destination::{
+    destinations_from_env, BackupDestination, DestinationPolicy, DestinationResult,
+    BACKUP_DESTINATION_POLICY_VAR,
+  },
+  dir_size,
+  encryption::{encrypt_file, encrypted_path, plaintext_name, Encryption, ENCRYPTED_EXTENSION},
+  failure::{begin_operation, enter_phase, record_failure, FailureFile},
+  filter::{PathFilter, BACKUP_EXCLUDE_VAR, BACKUP_INCLUDE_VAR},
+  hooks::{BackupHooks, BACKUP_POST_HOOK_VAR, BACKUP_PRE_HOOK_VAR},
+  hot_backup::{
+    stage_saves, wait_for_quiescence, StagedSaves, BACKUP_MAX_WAIT_VAR, DEFAULT_MAX_WAIT,
+  },
+  listing::{list_bucket, list_local, sort_newest_first, BackupListing},
+  lock::{lock_operation, OperationLock, BACKUP_LOCK_FILE_NAME},
+  metadata::{configured_world, BackupMetadata},
+  naming::{
+    render_name, unique_path, validate_template, NameTokens, BACKUP_NAME_FORMAT_VAR,
+    DEFAULT_NAME_FORMAT,
+  },
+  progress::{format_bytes, measure_input, BackupSummary, InputSize, Progress},
+  retention::{
+    log_pruned, parse_age, prune_backups, prune_directory, PruneAction, PruneEntry, RetentionPolicy,
+  },
+  s3::{S3Client, S3Config, BACKUP_S3_BUCKET_VAR},
+  scope::{archive_sources, parse_scopes, BackupScope, BACKUP_SCOPE_VAR},
+  snapshot::{
+    create_snapshot, full_snapshot_due, latest_snapshot, BackupStrategy, BACKUP_FULL_INTERVAL_VAR,
+    BACKUP_STRATEGY_VAR,
+  },
+  state::record_backup,
+  worlds::{parse_world_names, saves_dir, worlds_with_files, BACKUP_WORLDS_VAR},
+};
+use crate::notifications::{
+  enums::{event_status::EventStatus, notification_event::NotificationEvent},
+  ping::{is_ping_enabled, send_backup_ping, BackupPing, BACKUP_PING_URL_VAR},
+};
+use crate::server;
+use crate::utils::{
+  environment::fetch_var,
+  get_working_dir, parse_duration,
+  schedule::{parse_schedule, run_on_schedule},
+};
+
+pub const BACKUP_OUTPUT_DIR_VAR: &str = "BACKUP_OUTPUT_DIR";
+const DEFAULT_BACKUP_OUTPUT_DIR: &str = "/home/steam/backups";
+
+fn name_format() -> Result<String, String> {
+  let name_format = fetch_var(BACKUP_NAME_FORMAT_VAR, "");
+  if name_format.is_empty() {
+    return Ok(DEFAULT_NAME_FORMAT.to_string());
+  }
+  validate_template(&name_format)?;
+  Ok(name_format)
+}
+
+/// The archive settings picked with `--format`/`--level` or their environment defaults, and the
+/// bucket backups are uploaded to if one is configured.
+struct BackupOptions {
+  strategy: BackupStrategy,
+  /// How old the full snapshot an incremental one builds on may get before everything is copied
+  /// again, never when unset.
+  full_interval: Option<Duration>,
+  format: ArchiveFormat,
+  level: Option<i32>,
+  filter: PathFilter,
+  /// The template backups written into a directory are named with.
+  name_format: String,
+  /// Leaves out the progress lines, the summary is still logged.
+  quiet: bool,
+  /// Archives are encrypted into `<archive>.age` when a key is configured.
+  encryption: Option<Encryption>,
+  /// Where the backup is sent once it is written and verified.
+  destinations: Vec<BackupDestination>,
+  /// How many of the destinations have to get it.
+  policy: DestinationPolicy,
+  hooks: BackupHooks,
+  /// How long a backup of a running server waits for the world to settle.
+  max_wait: Duration,
+  /// What goes into the backup, sorted.
+  scopes: Vec<BackupScope>,
+  /// How many threads compress `tar.gz` and `zstd` archives.
+  threads: u32,
+}
+
+fn parse_full_interval(value: &str) -> Result<Duration, String> {
+  parse_duration(value)
+    .and_then(|interval| interval.to_std().ok())
+    .filter(|interval| !interval.is_zero())
+    .ok_or_else(|| {
+      format!(
+        "Invalid {} '{}', expected something like 24h or 7d",
+        BACKUP_FULL_INTERVAL_VAR, value
+      )
+    })
+}
+
+fn parse_max_wait(value: &str) -> Result<Duration, String> {
+  value
+    .trim()
+    .parse::<u64>()
+    .map(Duration::from_secs)
+    .map_err(|_| format!("Invalid max wait '{}', expected a number of seconds", value))
+}
+
+impl BackupOptions {
+  fn from_args(args: &ArgMatches) -> Result<Self, String> {
+    let mut options = Self::new(
+      args.is_present("incremental"),
+      args.value_of("format"),
+      args.value_of("level"),
+    )?;
+    if args.is_present("include") || args.is_present("exclude") {
+      options.filter = PathFilter::new(
+        &args
+          .value_of("include")
+          .map_or_else(|| fetch_var(BACKUP_INCLUDE_VAR, ""), str::to_string),
+        &args
+          .value_of("exclude")
+          .map_or_else(|| fetch_var(BACKUP_EXCLUDE_VAR, ""), str::to_string),
+      )
+      .for_worlds(options.filter.worlds());
+    }
+    if let Some(worlds) = args.value_of("worlds") {
+      options.filter = options
+        .filter
+        .for_worlds(&parse_world_names(worlds, &configured_world()));
+    }
+    options.quiet = args.is_present("quiet");
+    if let Some(pre_hook) = args.value_of("pre_hook") {
+      options.hooks.pre = Some(pre_hook.to_string());
+    }
+    if let Some(post_hook) = args.value_of("post_hook") {
+      options.hooks.post = Some(post_hook.to_string());
+    }
+    if let Some(max_wait) = args.value_of("max_wait") {
+      options.max_wait = parse_max_wait(max_wait)?;
+    }
+    if let Some(name_format) = args.value_of("name") {
+      validate_template(name_format)?;
+      options.name_format = name_format.to_string();
+    }
+    if let Some(scope) = args.value_of("scope") {
+      options.scopes = parse_scopes(scope)?;
+    }
+    if args.is_present("require_all") {
+      options.policy = DestinationPolicy::RequireAll;
+    } else if args.is_present("require_any") {
+      options.policy = DestinationPolicy::RequireAny;
+    }
+    if options.strategy == BackupStrategy::Incremental && options.scopes != [BackupScope::Worlds] {
+      return Err(
+        "Incremental backups only hold worlds, use archives to back up config or mods".to_string(),
+      );
+    }
+    if options.strategy == BackupStrategy::Incremental && options.encryption.is_some() {
+      return Err(
+        "Incremental backups can't be encrypted, use archives or unset the encryption key"
+          .to_string(),
+      );
+    }
+    Ok(options)
+  }
+
+  fn new(incremental: bool, format: Option<&str>, level: Option<&str>) -> Result<Self, String> {
+    let strategy = if incremental {
+      BackupStrategy::Incremental
+    } else {
+      match fetch_var(BACKUP_STRATEGY_VAR, "").as_str() {
+        "" => BackupStrategy::default(),
+        value => BackupStrategy::from_str(value).map_err(|e| e.to_string())?,
+      }
+    };
+    let format_value = match format {
+      Some(format) => format.to_string(),
+      None => fetch_var(BACKUP_COMPRESSION_VAR, ""),
+    };
+    let format = if format_value.is_empty() {
+      ArchiveFormat::default()
+    } else {
+      ArchiveFormat::from_str(&format_value).map_err(|e| e.to_string())?
+    };
+    let level_value = match level {
+      Some(level) => level.to_string(),
+      None => fetch_var(BACKUP_COMPRESSION_LEVEL_VAR, ""),
+    };
+    let level = if level_value.is_empty() {
+      None
+    } else {
+      let level = level_value
+        .parse::<i32>()
+        .map_err(|_| format!("Invalid compression level '{}'", level_value))?;
+      format.validate_level(level)?;
+      Some(level)
+    };
+    Ok(BackupOptions {
+      strategy,
+      full_interval: match fetch_var(BACKUP_FULL_INTERVAL_VAR, "").as_str() {
+        "" => None,
+        value => Some(parse_full_interval(value)?),
+      },
+      format,
+      level,
+      filter: PathFilter::new(
+        &fetch_var(BACKUP_INCLUDE_VAR, ""),
+        &fetch_var(BACKUP_EXCLUDE_VAR, ""),
+      )
+      .for_worlds(&parse_world_names(
+        &fetch_var(BACKUP_WORLDS_VAR, ""),
+        &configured_world(),
+      )),
+      name_format: name_format()?,
+      quiet: false,
+      encryption: Encryption::from_env()?,
+      destinations: destinations_from_env()?,
+      policy: match fetch_var(BACKUP_DESTINATION_POLICY_VAR, "").as_str() {
+        "" => DestinationPolicy::default(),
+        value => DestinationPolicy::from_str(value).map_err(|e| e.to_string())?,
+      },
+      hooks: BackupHooks::from_env(),
+      max_wait: match fetch_var(BACKUP_MAX_WAIT_VAR, "").as_str() {
+        "" => DEFAULT_MAX_WAIT,
+        value => parse_max_wait(value)?,
+      },
+      scopes: match fetch_var(BACKUP_SCOPE_VAR, "").as_str() {
+        "" => vec![BackupScope::Worlds],
+        value => parse_scopes(value)?,
+      },
+      threads: match fetch_var(BACKUP_THREADS_VAR, "").as_str() {
+        "" => DEFAULT_THREADS,
+        value => parse_threads(value)?,
+      },
+    })
+  }
+
+  /// Where the next backup in `directory` goes, named with the name format and never over an
+  /// existing backup.
+  fn backup_path(&self, directory: &str) -> Result<String, String> {
+    let extension = self.extension();
+    let name = render_name(
+      &self.name_format,
+      &NameTokens::from_env(Local::now(), &extension),
+    )?;
+    Ok(
+      unique_path(Path::new(directory), &name, &extension)
+        .to_string_lossy()
+        .to_string(),
+    )
+  }
+
+  /// What goes after the backup's name, snapshots are directories and have none.
+  fn extension(&self) -> String {
+    match (self.strategy, &self.encryption) {
+      (BackupStrategy::Archive, Some(_)) => {
+        format!("{}{}", self.format.extension(), ENCRYPTED_EXTENSION)
+      }
+      (BackupStrategy::Archive, None) => self.format.extension().to_string(),
+      (BackupStrategy::Incremental, _) => String::new(),
+    }
+  }
+
+  /// Swaps a backup extension that doesn't match the format, `backup.tar.gz` becomes
+  /// `backup.tar.zst` for zstd, and drops it for snapshots. Anything else is left as given, apart
+  /// from `.age` being added when encrypting.
+  fn output_path(&self, output: &str) -> String {
+    let path = self.archive_output_path(plaintext_name(output));
+    if self.encryption.is_some() && self.strategy == BackupStrategy::Archive {
+      let encrypted = encrypted_path(Path::new(&path))
+        .to_string_lossy()
+        .to_string();
+      if encrypted != output {
+        info!("Writing the encrypted backup to {}", encrypted);
+      }
+      return encrypted;
+    }
+    path
+  }
+
+  fn archive_output_path(&self, output: &str) -> String {
+    let named = ArchiveFormat::from_extension(Path::new(output));
+    if let (BackupStrategy::Incremental, Some(named)) = (self.strategy, named) {
+      let path = output[..output.len() - named.extension().len()].to_string();
+      info!(
+        "Writing the incremental backup to {} instead of {}",
+        path, output
+      );
+      return path;
+    }
+    match named {
+      Some(named) if named != self.format => {
+        let path = format!(
+          "{}{}",
+          &output[..output.len() - named.extension().len()],
+          self.format.extension()
+        );
+        info!(
+          "Writing {} backup to {} instead of {}",
+          self.format, path, output
+        );
+        path
+      }
+      _ => output.to_string(),
+    }
+  }
+}
+
+/// Writes the archive and its manifest, then reads it back before calling it a backup.
+fn write_verified_archive(
+  input: &Path,
+  output: &Path,
+  options: &BackupOptions,
+) -> Result<BackupSummary, String> {
+  enter_phase("archiving");
+  let sources = archive_sources(&options.scopes, input, &options.filter, &get_working_dir());
+  let size = sources
+    .iter()
+    .map(|source| measure_input(&source.directory, &source.filter))
+    .fold(InputSize::default(), |total, size| InputSize {
+      files: total.files + size.files,
+      bytes: total.bytes + size.bytes,
+    });
+  info!(
+    "Found {} file(s), {} to back up{}",
+    size.files,
+    format_bytes(size.bytes),
+    if options.filter.is_empty() {
+      ""
+    } else {
+      " after filtering"
+    }
+  );
+  let metadata = BackupMetadata::collect(input, &options.filter, &options.scopes).map_err(|e| {
+    format!(
+      "Failed to describe the worlds in {}: {}",
+      input.display(),
+      e
+    )
+  })?;
+  info!(
+    "Writing a {} archive{}",
+    options.format,
+    match options.level {
+      Some(level) => format!(" at level {}", level),
+      None => String::new(),
+    }
+  );
+  let mut progress = Progress::new(size.bytes, options.quiet);
+  create_archive(
+    &sources,
+    output,
+    options.format,
+    options.level,
+    options.threads,
+    Some(&metadata),
+    &mut progress,
+  )
+  .map_err(|e| {
+    format!(
+      "Failed to back up {} to {}: {}",
+      input.display(),
+      output.display(),
+      e
+    )
+  })?;
+  enter_phase("verifying");
+  let hash = write_checksum(output).map_err(|e| {
+    format!(
+      "Failed to write the manifest of {}: {}",
+      output.display(),
+      e
+    )
+  })?;
+  verify_archive(output)?;
+  debug!("Verified {} with SHA-256 {}", output.display(), hash);
+  Ok(BackupSummary {
+    files: size.files,
+    input_bytes: size.bytes,
+    output_bytes: fs::metadata(output).map(|meta| meta.len()).unwrap_or(0),
+    elapsed: progress.elapsed(),
+  })
+}
+
+/// The snapshot the next one in `output`'s directory links to, `None` when there is none or a full
+/// snapshot is due.
+fn snapshot_base(output: &str, options: &BackupOptions) -> Option<PathBuf> {
+  let previous = latest_snapshot(output_dir(output))?;
+  match options.full_interval {
+    Some(interval) if full_snapshot_due(&previous, interval, Local::now()) => {
+      info!(
+        "The last full snapshot before {} is older than {} or unknown, copying everything",
+        previous.display(),
+        fetch_var(BACKUP_FULL_INTERVAL_VAR, "")
+      );
+      None
+    }
+    _ => Some(previous),
+  }
+}
+
+/// Writes a snapshot directory next to the previous one, linking whatever didn't change.
+fn write_snapshot(
+  input: &Path,
+  output: &Path,
+  options: &BackupOptions,
+) -> Result<(u64, String), String> {
+  enter_phase("archiving");
+  let previous = snapshot_base(&output.to_string_lossy(), options);
+  match &previous {
+    Some(previous) => info!("Linking unchanged files to {}", previous.display()),
+    None => info!("Taking a full snapshot, copying everything"),
+  }
+  let filter = &options.filter;
+  let stats = create_snapshot(input, output, previous.as_deref(), filter).map_err(|e| {
+    format!(
+      "Failed to back up {} to {}: {}",
+      input.display(),
+      output.display(),
+      e
+    )
+  })?;
+  let summary = format!(
+    "Copied {} changed file(s), {}, and linked {} unchanged file(s)",
+    stats.copied,
+    format_bytes(stats.copied_bytes),
+    stats.linked
+  );
+  Ok((stats.copied_bytes, summary))
+}
+
+/// Encrypts the verified `archive` into `output` with its own manifest, then removes the
+/// plaintext archive and its manifest.
+fn encrypt_archive(archive: &Path, output: &Path, encryption: &Encryption) -> Result<(), String> {
+  enter_phase("encrypting");
+  info!(
+    "Encrypting {} with {}",
+    archive.display(),
+    encryption.describe()
+  );
+  encrypt_file(archive, output, encryption)
+    .map_err(|e| format!("Failed to encrypt {}: {}", archive.display(), e))?;
+  let hash = write_checksum(output).map_err(|e| {
+    format!(
+      "Failed to write the manifest of {}: {}",
+      output.display(),
+      e
+    )
+  })?;
+  verify_archive(output)?;
+  debug!("Verified {} with SHA-256 {}", output.display(), hash);
+  remove_files(&[archive.to_path_buf(), checksum_path(archive)]);
+  Ok(())
+}
+
+fn remove_files(paths: &[PathBuf]) {
+  for path in paths {
+    if path.exists() {
+      if let Err(e) = fs::remove_file(path) {
+        warn!("Failed to remove {}: {}", path.display(), e);
+      }
+    }
+  }
+}
+
+/// Writes the backup and returns its summary.
+/// A running server rewrites the world every autosave, so its saves are copied aside once the
+/// world has settled and the backup is taken from the copy. `None` backs up `input` as it is.
+fn stage_running_server(input: &str, options: &BackupOptions) -> Option<StagedSaves> {
+  if !server::is_running() {
+    return None;
+  }
+  enter_phase("waiting for the world to settle");
+  info!(
+    "The server is running, waiting up to {}s for it to finish saving",
+    options.max_wait.as_secs()
+  );
+  if !wait_for_quiescence(Path::new(input), options.max_wait) {
+    warn!(
+      "!!! The world was still being written after {}s, backing up the live files. This backup may hold a half saved world, check it before relying on it !!!",
+      options.max_wait.as_secs()
+    );
+    return None;
+  }
+  match stage_saves(Path::new(input), &options.filter) {
+    Ok(staged) => Some(staged),
+    Err(e) => {
+      warn!(
+        "!!! Unable to copy the saves aside ({}), backing up the live files. This backup may hold a half saved world, check it before relying on it !!!",
+        e
+      );
+      None
+    }
+  }
+}
+
+fn create_backup(input: &str, output: &str, options: &BackupOptions) -> Result<String, String> {
+  let staged = stage_running_server(input, options);
+  let source = staged.as_ref().map_or(Path::new(input), StagedSaves::path);
+  if options.strategy == BackupStrategy::Incremental {
+    let (size, summary) = write_snapshot(source, Path::new(output), options)?;
+    record_backup(output, size, dir_size(input));
+    return Ok(summary);
+  }
+  // Encrypted backups are archived next to where they end up, then encrypted into place.
+  let archive = Path::new(plaintext_name(output));
+  let result = write_verified_archive(source, archive, options).and_then(|summary| match &options
+    .encryption
+  {
+    Some(encryption) => {
+      encrypt_archive(archive, Path::new(output), encryption).map(|_| BackupSummary {
+        output_bytes: fs::metadata(output).map(|meta| meta.len()).unwrap_or(0),
+        ..summary
+      })
+    }
+    None => Ok(summary),
+  });
+  match result {
+    Ok(summary) => {
+      debug!("Successfully created backup at {}", output);
+      record_backup(output, summary.output_bytes, dir_size(input));
+      Ok(summary.to_string())
+    }
+    Err(message) => {
+      // A partial archive, say from a full disk, must never be mistaken for a backup.
+      let mut paths = vec![PathBuf::from(output), checksum_path(Path::new(output))];
+      if archive != Path::new(output) {
+        paths.extend([archive.to_path_buf(), checksum_path(archive)]);
+      }
+      remove_files(&paths);
+      Err(message)
+    }
+  }
+}
+
+fn output_dir(output: &str) -> &Path {
+  match Path::new(output).parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => parent,
+    _ => Path::new("."),
+  }
+}
+
+/// Why `run_backup` didn't finish cleanly.
+enum BackupError {
+  /// There is no backup, or it couldn't be uploaded.
+  Failed(String),
+  /// The backup was made but `BACKUP_POST_HOOK` failed, the backup is kept.
+  PostHook(String),
+}
+
+impl From<String> for BackupError {
+  fn from(message: String) -> Self {
+    BackupError::Failed(message)
+  }
+}
+
+fn run_backup(
+  input: &str,
+  output: &str,
+  options: &BackupOptions,
+  retention: RetentionPolicy,
+) -> Result<(), BackupError> {
+  let _lock = match lock_operation(BACKUP_LOCK_FILE_NAME) {
+    OperationLock::Acquired(lock) => lock,
+    OperationLock::InProgress => {
+      warn!("Another backup is still in progress, skipping this one");
+      return Ok(());
+    }
+  };
+  enter_phase("running the pre-backup hook");
+  options
+    .hooks
+    .run_pre(input, output)
+    .map_err(|e| format!("{}, the backup was aborted", e))?;
+  let started = Instant::now();
+  let result = back_up_and_upload(input, output, options, retention);
+  enter_phase("running the post-backup hook");
+  let hook = options.hooks.run_post(
+    input,
+    output,
+    &result.as_ref().map(|_| ()).map_err(String::clone),
+  );
+  let result = match (result, hook) {
+    (Err(message), hook) => {
+      if let Err(e) = hook {
+        error!("{}", e);
+      }
+      Err(BackupError::Failed(message))
+    }
+    (Ok(summary), Ok(_)) => Ok(summary),
+    (Ok(_), Err(e)) => Err(BackupError::PostHook(format!(
+      "{}, the backup at {} was kept",
+      e, output
+    ))),
+  };
+  send_backup_ping(&BackupPing {
+    success: result.is_ok(),
+    archive: output.to_string(),
+    size_bytes: if result.is_ok() { dir_size(output) } else { 0 },
+    duration_seconds: started.elapsed().as_secs_f64(),
+    worlds: backed_up_worlds(input, options),
+    message: match &result {
+      Ok(summary) => summary.clone(),
+      Err(BackupError::Failed(message)) | Err(BackupError::PostHook(message)) => message.clone(),
+    },
+  });
+  result.map(|_| ())
+}
+
+/// The worlds that went into a backup of `input`, the picked ones or every one there.
+fn backed_up_worlds(input: &str, options: &BackupOptions) -> Vec<String> {
+  if !options.scopes.contains(&BackupScope::Worlds) {
+    return Vec::new();
+  }
+  let found = worlds_with_files(Path::new(input));
+  match options.filter.worlds() {
+    [] => found,
+    picked => picked
+      .iter()
+      .filter(|world| found.contains(world))
+      .cloned()
+      .collect(),
+  }
+}
+
+/// Sends the backup at `output` to every destination, logging how each went.
+fn send_to_destinations(output: &str, options: &BackupOptions) -> Vec<DestinationResult> {
+  options
+    .destinations
+    .iter()
+    .map(|destination| {
+      enter_phase("uploading");
+      info!("Sending {} to {}", output, destination);
+      let result = DestinationResult {
+        destination: destination.to_string(),
+        outcome: destination.store(Path::new(output)),
+      };
+      match result.outcome {
+        Ok(_) => info!("{}", result),
+        Err(_) => error!("{}", result),
+      }
+      result
+    })
+    .collect()
+}
+
+/// Returns the backup's summary.
+fn back_up_and_upload(
+  input: &str,
+  output: &str,
+  options: &BackupOptions,
+  retention: RetentionPolicy,
+) -> Result<String, String> {
+  let summary = create_backup(input, output, options)?;
+  info!("{}", summary);
+  if !options.destinations.is_empty() && options.strategy == BackupStrategy::Incremental {
+    warn!(
+      "Only archives can be sent to other destinations, the snapshot {} stays local",
+      output
+    );
+    prune_and_notify(output, options, retention, &summary, &[])?;
+    return Ok(summary);
+  }
+  let results = send_to_destinations(output, options);
+  let stored = results
+    .iter()
+    .filter(|result| result.outcome.is_ok())
+    .count();
+  if stored < results.len() {
+    let message = format!(
+      "The backup reached {} of {} destinations, it was kept at {}. {}",
+      stored,
+      results.len(),
+      output,
+      results
+        .iter()
+        .filter(|result| result.outcome.is_err())
+        .map(DestinationResult::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
+    );
+    if !options.policy.is_met(stored, results.len()) {
+      return Err(message);
+    }
+    warn!("{}", message);
+  }
+  let details = results.iter().fold(summary.clone(), |details, result| {
+    format!("{}\n{}", details, result)
+  });
+  prune_and_notify(output, options, retention, &details, &results)?;
+  Ok(summary)
+}
+
+/// Only reached once the backup succeeded, failures return before pruning anything. Remote
+/// destinations are only pruned when they got the backup.
+fn prune_and_notify(
+  output: &str,
+  options: &BackupOptions,
+  retention: RetentionPolicy,
+  details: &str,
+  results: &[DestinationResult],
+) -> Result<(), String> {
+  enter_phase("pruning");
+  prune_backups(output_dir(output), retention, false);
+  for (destination, result) in options.destinations.iter().zip(results) {
+    if destination.prune && result.outcome.is_ok() && !destination.holds(Path::new(output)) {
+      // The backup itself made it, a destination that can't be pruned is only worth a warning.
+      if let Err(e) = destination.prune(retention, false) {
+        warn!("{}", e);
+      }
+    }
+  }
+  NotificationEvent::Backup(EventStatus::Successful).send_notification_with_details(details);
+  Ok(())
+}
+
+/// Logs the files `run_backup` would write and upload, in order.
+/// Warns about every picked world without a file in `input`, they are likely misspelled.
+fn check_worlds(input: &str, options: &BackupOptions) {
+  let picked = options.filter.worlds();
+  if picked.is_empty() || !options.scopes.contains(&BackupScope::Worlds) {
+    return;
+  }
+  let found = worlds_with_files(Path::new(input));
+  for world in picked.iter().filter(|world| !found.contains(world)) {
+    warn!(
+      "There are no files of the world {} in {}, {}",
+      world,
+      input,
+      if found.is_empty() {
+        "it holds no worlds at all".to_string()
+      } else {
+        format!("it holds {}", found.join(", "))
+      }
+    );
+  }
+}
+
+fn log_backup_steps(input: &str, output: &str, options: &BackupOptions) {
+  if options.hooks.pre.is_some() {
+    info!("Dry run: would run {}", BACKUP_PRE_HOOK_VAR);
+  }
+  if server::is_running() {
+    info!(
+      "Dry run: would wait up to {}s for the running server to finish saving and back up a copy of {}",
+      options.max_wait.as_secs(),
+      input
+    );
+  }
+  log_archive_steps(input, output, options);
+  if options.hooks.post.is_some() {
+    info!("Dry run: would run {}", BACKUP_POST_HOOK_VAR);
+  }
+  if is_ping_enabled() {
+    info!(
+      "Dry run: would ping {} with the outcome",
+      BACKUP_PING_URL_VAR
+    );
+  }
+}
+
+fn log_archive_steps(input: &str, output: &str, options: &BackupOptions) {
+  if !options.filter.worlds().is_empty() {
+    info!(
+      "Dry run: would only back up the worlds {}",
+      options.filter.worlds().join(", ")
+    );
+  }
+  if options.strategy == BackupStrategy::Incremental {
+    info!(
+      "Dry run: would snapshot {} to {}{}",
+      input,
+      output,
+      match snapshot_base(output, options) {
+        Some(previous) => format!(", linking unchanged files to {}", previous.display()),
+        None => " as a full snapshot".to_string(),
+      }
+    );
+    return;
+  }
+  info!(
+    "Dry run: would archive {} of {} to {} as {}{}",
+    options
+      .scopes
+      .iter()
+      .map(BackupScope::to_string)
+      .collect::<Vec<String>>()
+      .join(", "),
+    input,
+    plaintext_name(output),
+    options.format,
+    match options.level {
+      Some(level) => format!(" at level {}", level),
+      None => String::new(),
+    }
+  );
+  if options.threads > 1
+    && (options.format == ArchiveFormat::TarGz || options.format == ArchiveFormat::Zstd)
+  {
+    info!("Dry run: would compress on {} threads", options.threads);
+  }
+  if let Some(encryption) = &options.encryption {
+    info!(
+      "Dry run: would encrypt it to {} with {} and remove the unencrypted archive",
+      output,
+      encryption.describe()
+    );
+  }
+  let manifest = checksum_path(Path::new(output));
+  info!("Dry run: would write {}", manifest.display());
+  for destination in &options.destinations {
+    if destination.holds(Path::new(output)) {
+      continue;
+    }
+    for path in [Path::new(output), manifest.as_path()].iter() {
+      let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+      info!(
+        "Dry run: would send {} to {}",
+        path.display(),
+        destination.location_of(&file_name)
+      );
+    }
+  }
+  if options.destinations.len() > 1 {
+    info!(
+      "Dry run: would count the backup as failed unless {} of the destinations got it",
+      match options.policy {
+        DestinationPolicy::RequireAll => "all",
+        DestinationPolicy::RequireAny => "any",
+      }
+    );
+  }
+}
+
+fn schedule_backups(
+  input: &str,
+  output_dir: &str,
+  expression: &str,
+  options: BackupOptions,
+  dry_run: bool,
+) -> CommandResult {
+  // Parsed up front so a typo is reported now rather than at the first run.
+  let schedule = parse_schedule(expression)?;
+  let retention = RetentionPolicy::from_env()?;
+  if dry_run {
+    info!(
+      "Dry run: would back up {} into {} on the schedule '{}'",
+      input, output_dir, expression
+    );
+    return Ok(());
+  }
+  info!(
+    "Backing up {} into {} on the schedule '{}'",
+    input, output_dir, expression
+  );
+  run_on_schedule("backup", &schedule, || {
+    run_scheduled_backup(input, output_dir, &options, retention)
+  })
+}
+
+/// One backup of a schedule, into a new file in `output_dir`. A failed backup is reported and the
+/// next one still runs.
+fn run_scheduled_backup(
+  input: &str,
+  output_dir: &str,
+  options: &BackupOptions,
+  retention: RetentionPolicy,
+) {
+  let result = options
+    .backup_path(output_dir)
+    .map_err(BackupError::Failed)
+    .and_then(|output| {
+      info!("Starting scheduled backup to {}", output);
+      run_backup(input, &output, options, retention)
+    });
+  match result {
+    Ok(_) => FailureFile::new().clear_for("backup"),
+    Err(BackupError::Failed(message)) => {
+      record_failure(1, &message);
+      NotificationEvent::Backup(EventStatus::Failed).send_notification_with_details(&message);
+    }
+    Err(BackupError::PostHook(message)) => record_failure(1, &message),
+  }
+}
+
+/// Checks the backup settings from the environment, so `odin start --backup-every` can refuse to
+/// start with settings every backup would fail on.
+pub fn check_backup_settings() -> Result<(), String> {
+  RetentionPolicy::from_env()?;
+  BackupOptions::new(false, None, None).map(|_| ())
+}
+
+/// Backs up the saves into `BACKUP_OUTPUT_DIR` with the settings from the environment, for
+/// `odin start --backup-every`.
+pub fn backup_while_running() {
+  begin_operation("backup");
+  let input = saves_dir().to_string_lossy().to_string();
+  let output_dir = fetch_var(BACKUP_OUTPUT_DIR_VAR, DEFAULT_BACKUP_OUTPUT_DIR);
+  let settings = BackupOptions::new(false, None, None)
+    .and_then(|options| RetentionPolicy::from_env().map(|retention| (options, retention)));
+  let (options, retention) = match settings {
+    Ok(settings) => settings,
+    Err(message) => {
+      record_failure(1, &message);
+      NotificationEvent::Backup(EventStatus::Failed).send_notification_with_details(&message);
+      return;
+    }
+  };
+  if let Err(e) = fs::create_dir_all(&output_dir) {
+    let message = format!("Unable to create {}: {}", output_dir, e);
+    record_failure(1, &message);
+    NotificationEvent::Backup(EventStatus::Failed).send_notification_with_details(&message);
+    return;
+  }
+  run_scheduled_backup(&input, &output_dir, &options, retention);
+}
+
+/// Backs up the saves before an update replaces the server, into
+/// `pre-update-<buildid>-<timestamp>` with the configured format. Returns where it went, `None`
+/// when there is nothing to back up.
+pub fn backup_before_update(build_id: &str, dry_run: bool) -> Result<Option<String>, String> {
+  let input = saves_dir().to_string_lossy().to_string();
+  if !Path::new(&input).is_dir() {
+    warn!(
+      "No saves found at {}, skipping the pre-update backup",
+      input
+    );
+    return Ok(None);
+  }
+  let mut options = BackupOptions::new(false, None, None)?;
+  // Always a single archive, it has to be restorable on its own if the update goes wrong.
+  options.strategy = BackupStrategy::Archive;
+  let output = PathBuf::from(fetch_var(BACKUP_OUTPUT_DIR_VAR, DEFAULT_BACKUP_OUTPUT_DIR))
+    .join(format!(
+      "pre-update-{}-{}{}",
+      build_id,
+      Local::now().format("%Y%m%d-%H%M%S"),
+      options.extension()
+    ))
+    .to_string_lossy()
+    .to_string();
+  if dry_run {
+    log_backup_steps(&input, &output, &options);
+    return Ok(Some(output));
+  }
+  if let Some(parent) = Path::new(&output).parent() {
+    fs::create_dir_all(parent)
+      .map_err(|e| format!("Unable to create {}: {}", parent.display(), e))?;
+  }
+  info!("Backing up {} to {} before updating", input, output);
+  match run_backup(&input, &output, &options, RetentionPolicy::from_env()?) {
+    Err(BackupError::Failed(message)) => return Err(message),
+    // The saves are safe, a failing hook is no reason to hold back the update.
+    Err(BackupError::PostHook(message)) => error!("{}", message),
+    Ok(_) => {}
+  }
+  Ok(Some(output))
+}
+
+fn list_remote() -> CommandResult {
+  let config = S3Config::from_env()?.ok_or_else(|| {
+    CommandError::failed(format!(
+      "Set {} to list remote backups",
+      BACKUP_S3_BUCKET_VAR
+    ))
+  })?;
+  let mut backups = S3Client::new(config.clone()).and_then(|client| client.list())?;
+  // Manifests are uploaded next to their archive.
+  backups.retain(|backup| !backup.key.ends_with(CHECKSUM_EXTENSION));
+  if backups.is_empty() {
+    println!("No backups found in {}", config.uri_for(&config.prefix));
+  }
+  for backup in backups {
+    println!(
+      "{}\t{:.1} MB\t{}",
+      backup.last_modified,
+      backup.size as f64 / (1024.0 * 1024.0),
+      config.uri_for(&backup.key)
+    );
+  }
+  Ok(())
+}
+
+fn print_listing(backups: &[BackupListing]) {
+  if backups.is_empty() {
+    println!("No backups found");
+    return;
+  }
+  for backup in backups {
+    let (world, build) = match &backup.metadata {
+      Some(metadata) => (
+        metadata.world.as_str(),
+        metadata.valheim_build_id.as_deref().unwrap_or("-"),
+      ),
+      None => ("-", "-"),
+    };
+    println!(
+      "{}\t{:.1} MB\t{}\t{}\t{}\t{}\t{}",
+      backup.timestamp.as_deref().unwrap_or("-"),
+      backup.size_bytes as f64 / (1024.0 * 1024.0),
+      if backup.encrypted {
+        format!("{} (encrypted)", backup.format)
+      } else {
+        backup.format.clone()
+      },
+      backup
+        .checksum
+        .map_or_else(|| "-".to_string(), |status| status.to_string()),
+      world,
+      build,
+      backup.path
+    );
+  }
+}
+
+/// `odin backup list`, the local backups and those in the bucket when one is configured.
+fn list(args: &ArgMatches) -> CommandResult {
+  let limit = args
+    .value_of("limit")
+    .map(|limit| {
+      limit
+        .parse::<usize>()
+        .map_err(|_| format!("--limit must be a positive number, got '{}'", limit))
+    })
+    .transpose()?;
+  let directory = args.value_of("dir").map_or_else(
+    || fetch_var(BACKUP_OUTPUT_DIR_VAR, DEFAULT_BACKUP_OUTPUT_DIR),
+    str::to_string,
+  );
+  let mut backups = list_local(Path::new(&directory));
+  match S3Config::from_env() {
+    Ok(Some(config)) => match S3Client::new(config.clone()).and_then(|client| client.list()) {
+      Ok(objects) => backups.extend(list_bucket(&config, &objects)),
+      // The local backups are still worth showing.
+      Err(e) => warn!("Unable to list the backups in {}: {}", config.bucket, e),
+    },
+    Ok(None) => debug!("No bucket configured, listing local backups only"),
+    Err(e) => warn!("{}", e),
+  }
+  let backups = sort_newest_first(backups, limit);
+  if args.value_of("output") == Some("json") {
+    println!("{}", serde_json::to_string_pretty(&backups).unwrap());
+  } else {
+    print_listing(&backups);
+  }
+  Ok(())
+}
+
+fn print_pruned(entries: &[PruneEntry]) {
+  if entries.is_empty() {
+    println!("No backups found");
+    return;
+  }
+  for entry in entries {
+    println!(
+      "{}\t{}\t{:.1} MB\t{}{}",
+      entry.action,
+      entry.modified,
+      entry.size_bytes as f64 / (1024.0 * 1024.0),
+      entry.path,
+      entry
+        .error
+        .as_ref()
+        .map_or_else(String::new, |error| format!("\t{}", error))
+    );
+  }
+}
+
+/// The retention `odin backup prune` applies, its flags over `BACKUPS_TO_KEEP` and
+/// `BACKUPS_MAX_AGE_DAYS`.
+fn prune_policy(args: &ArgMatches) -> Result<RetentionPolicy, String> {
+  let mut policy = RetentionPolicy::from_env()?;
+  if let Some(keep) = args.value_of("keep") {
+    let keep = keep
+      .parse::<usize>()
+      .map_err(|_| format!("--keep must be a positive number, got '{}'", keep))?;
+    // Like after a backup, the newest one is always kept.
+    policy.keep = Some(keep.max(1));
+  }
+  if let Some(age) = args.value_of("older_than") {
+    policy.max_age = Some(parse_age(age)?);
+  }
+  if !policy.is_configured() {
+    return Err(
+      "Nothing to prune by, pass --keep or --older-than or set BACKUPS_TO_KEEP or BACKUPS_MAX_AGE_DAYS"
+        .to_string(),
+    );
+  }
+  Ok(policy)
+}
+
+/// `odin backup prune`, retention on its own for any directory of backups.
+fn prune(args: &ArgMatches) -> CommandResult {
+  let policy = prune_policy(args)?;
+  let dry_run = args.is_present("dry_run");
+  let directory = args.value_of("output_dir").map_or_else(
+    || fetch_var(BACKUP_OUTPUT_DIR_VAR, DEFAULT_BACKUP_OUTPUT_DIR),
+    str::to_string,
+  );
+  if !Path::new(&directory).is_dir() {
+    return Err(CommandError::failed(format!(
+      "{} isn't a directory",
+      directory
+    )));
+  }
+  let entries = prune_directory(Path::new(&directory), policy, dry_run, Local::now());
+  if args.value_of("output") == Some("json") {
+    println!("{}", serde_json::to_string_pretty(&entries).unwrap());
+  } else {
+    print_pruned(&entries);
+  }
+  log_pruned(&entries, dry_run);
+  let failed = entries
+    .iter()
+    .filter(|entry| entry.action == PruneAction::Failed)
+    .count();
+  if failed > 0 {
+    return Err(CommandError::failed(format!(
+      "Failed to prune {} backup(s) in {}",
+      failed, directory
+    )));
+  }
+  Ok(())
+}
+
+fn verify(archive: &str) -> CommandResult {
+  match verify_archive(Path::new(archive)) {
+    Ok(Verification::Verified) => {
+      info!("{} matches its manifest", archive);
+      Ok(())
+    }
+    Ok(Verification::Unverified) => Err(CommandError::failed(format!(
+      "{} can be read but has no manifest to verify it against",
+      archive
+    ))),
+    Err(e) => Err(CommandError::failed(e.to_string())),
+  }
+}
+
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  if let Some(list_matches) = args.subcommand_matches("list") {
+    return list(list_matches);
+  }
+  if let Some(prune_matches) = args.subcommand_matches("prune") {
+    return prune(prune_matches);
+  }
+  if args.is_present("list_remote") {
+    return list_remote();
+  }
+  if let Some(archive) = args.value_of("verify") {
+    return verify(archive);
+  }
   let input = args.value_of("INPUT_DIR").unwrap();
   let output = args.value_of("OUTPUT_FILE").unwrap();
+  let dry_run = args.is_present("dry_run");
+  let options = BackupOptions::from_args(args)?;
+  // Before any compression thread is started, they take the priority they start with.
+  if !dry_run && fetch_var(BACKUP_NICE_VAR, "0") == "1" {
+    lower_priority();
+  }
+  check_worlds(input, &options);
+  if let Some(expression) = args.value_of("schedule") {
+    return schedule_backups(input, output, expression, options, dry_run);
+  }
+  // A directory gets a backup named with the name format, a file name is used as given.
+  let output = &if args.is_present("name") || Path::new(output).is_dir() {
+    options.backup_path(output)?
+  } else {
+    options.output_path(output)
+  };
+  let retention = RetentionPolicy::from_env()?;
   debug!("Creating archive of {}", input);
   debug!("Output set to {}", output);
-  let tar_gz = match File::create(output) {
-    Ok(file) => file,
-    Err(_) => {
-      error!("Failed to create backup file at {}", output);
-      exit(1)
+  if dry_run {
+    log_backup_steps(input, output, &options);
+    prune_backups(output_dir(output), retention, true);
+    for destination in options
+      .destinations
+      .iter()
+      .filter(|destination| destination.prune && !destination.holds(Path::new(output)))
+    {
+      if let Err(e) = destination.prune(retention, true) {
+        warn!("{}", e);
+      }
     }
-  };
-  let enc = GzEncoder::new(tar_gz, Compression::default());
-  let mut tar = tar::Builder::new(enc);
-  match tar.append_dir_all("saves", input) {
-    Ok(_) => debug!("Successfully created backup zip at {}", output),
-    Err(_) => {
-      error!("Failed to add {} to backup file", input);
-      exit(1)
+    return Ok(());
+  }
+  match run_backup(input, output, &options, retention) {
+    Err(BackupError::Failed(message)) => {
+      NotificationEvent::Backup(EventStatus::Failed).send_notification_with_details(&message);
+      Err(CommandError::failed(message))
     }
-  };
+    Err(BackupError::PostHook(message)) => Err(CommandError::failed(message)),
+    Ok(_) => Ok(()),
+  }
 }
@@ -0,0 +1,23 @@
+use clap::ArgMatches;
+use log::info;
+
+use crate::errors::{CommandError, CommandResult};
+use crate::server;
+
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  let command = args.value_of("COMMAND").unwrap();
+  if args.is_present("dry_run") {
+    info!(
+      "Dry run: would send '{}' to {}",
+      command,
+      server::console_path().display()
+    );
+    return Ok(());
+  }
+  if !server::is_running() {
+    return Err(CommandError::failed("The server isn't running!"));
+  }
+  server::send_command(command)?;
+  info!("Sent '{}' to the server", command.trim());
+  Ok(())
+}
@@ -0,0 +1,20 @@
+use clap::{App, ArgMatches};
+use clap_generate::{
+  generate,
+  generators::{Bash, Elvish, Fish, PowerShell, Zsh},
+};
+
+use std::io;
+
+/// Prints the completion script for the shell picked, built from the same definition as the cli.
+pub fn invoke(args: &ArgMatches, app: &mut App) {
+  let mut stdout = io::stdout();
+  match args.value_of("SHELL").unwrap() {
+    "bash" => generate::<Bash, _>(app, "odin", &mut stdout),
+    "zsh" => generate::<Zsh, _>(app, "odin", &mut stdout),
+    "fish" => generate::<Fish, _>(app, "odin", &mut stdout),
+    "powershell" => generate::<PowerShell, _>(app, "odin", &mut stdout),
+    "elvish" => generate::<Elvish, _>(app, "odin", &mut stdout),
+    shell => unreachable!("clap only accepts known shells, got {}", shell),
+  }
+}
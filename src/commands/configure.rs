@@ -1,10 +1,39 @@
+use crate::errors::{CommandError, CommandResult};
 use crate::files::config::{config_file, write_config};
+use crate::files::export::{effective_settings, render_settings, write_export, ExportFormat};
+use crate::server;
 use clap::ArgMatches;
-use log::debug;
+use log::{debug, info, warn};
+use std::path::Path;
+use std::str::FromStr;
 
-pub fn invoke(args: &ArgMatches) {
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  if let Some(export_path) = args.value_of("export") {
+    return export(args, export_path);
+  }
+  if args.is_present("ignore_password_validation") {
+    server::ignore_password_validation();
+  }
   debug!("Pulling config file...");
   let config = config_file();
   debug!("Writing config file...");
-  write_config(config, args);
+  write_config(config, args)?;
+  Ok(())
+}
+
+fn export(args: &ArgMatches, export_path: &str) -> CommandResult {
+  let format = ExportFormat::from_str(args.value_of("export_format").unwrap_or("toml"))?;
+  let include_secrets = args.is_present("include_secrets");
+  if include_secrets {
+    warn!("Secrets will be written in plain text to {}", export_path);
+  }
+  let content = render_settings(&effective_settings(args), format, include_secrets);
+  write_export(Path::new(export_path), &content, include_secrets).map_err(|e| {
+    CommandError::failed(format!(
+      "Failed to export configuration to {}: {}",
+      export_path, e
+    ))
+  })?;
+  info!("Exported the current configuration to {}", export_path);
+  Ok(())
 }
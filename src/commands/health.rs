@@ -0,0 +1,264 @@
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime};
+use clap::ArgMatches;
+use log::{error, info};
+
+use std::{net::SocketAddr, path::Path, str::FromStr, time};
+
+use crate::errors::{CommandError, CommandResult, VariantNotFound};
+use crate::files::config::configured;
+use crate::files::state::{parse_timestamp, StateFile};
+use crate::server;
+use crate::utils::{environment::fetch_var, parse_duration};
+
+pub const HEALTH_CHECKS_VAR: &str = "HEALTH_CHECKS";
+pub const HEALTH_BACKUP_MAX_AGE_VAR: &str = "HEALTH_BACKUP_MAX_AGE";
+pub const HEALTH_UPDATE_MAX_AGE_VAR: &str = "HEALTH_UPDATE_MAX_AGE";
+pub const HEALTH_MAX_SAVE_AGE_VAR: &str = "HEALTH_MAX_SAVE_AGE_MINUTES";
+/// Keeps `odin health` well within Docker's healthcheck timeout.
+const QUERY_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// In the order they run, the cheap ones first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthCheck {
+  Process,
+  /// Crossplay servers don't answer Steam queries, leave it out for them.
+  Query,
+  Save,
+  Backup,
+  Update,
+}
+
+impl FromStr for HealthCheck {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> Result<HealthCheck, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "process" => Ok(HealthCheck::Process),
+      "query" => Ok(HealthCheck::Query),
+      "save" => Ok(HealthCheck::Save),
+      "backup" => Ok(HealthCheck::Backup),
+      "update" => Ok(HealthCheck::Update),
+      _ => Err(VariantNotFound {
+        v: format!(
+          "Unknown health check '{}', expected process, query, save, backup or update",
+          s
+        ),
+      }),
+    }
+  }
+}
+
+pub fn parse_checks(value: &str) -> Result<Vec<HealthCheck>, VariantNotFound> {
+  let mut checks = value
+    .split(',')
+    .filter(|check| !check.trim().is_empty())
+    .map(HealthCheck::from_str)
+    .collect::<Result<Vec<_>, _>>()?;
+  checks.sort();
+  checks.dedup();
+  Ok(checks)
+}
+
+/// A server that hasn't saved yet is given `max_age` after it started to do so.
+pub fn check_save_age(
+  last_save: Option<NaiveDateTime>,
+  uptime: Option<time::Duration>,
+  now: NaiveDateTime,
+  max_age: Duration,
+) -> Result<(), String> {
+  match last_save {
+    Some(at) if now.signed_duration_since(at) <= max_age => Ok(()),
+    Some(at) => Err(format!(
+      "The world was last saved at {}, more than {} minutes ago",
+      at,
+      max_age.num_minutes()
+    )),
+    None if uptime.is_some_and(|uptime| uptime.as_secs() as i64 <= max_age.num_seconds()) => Ok(()),
+    None => Err(format!(
+      "The server hasn't logged a world save in the {} minutes since it started",
+      max_age.num_minutes()
+    )),
+  }
+}
+
+fn check_process() -> Result<(), String> {
+  let recorded = server::recorded_server_pid();
+  if recorded.is_some_and(|pid| server::is_pid_alive(Path::new("/proc"), pid)) {
+    return Ok(());
+  }
+  // A server odin didn't launch, or one from before the pid file.
+  if server::is_running() {
+    return Ok(());
+  }
+  Err(match recorded {
+    Some(pid) => format!("The server process {} is not running", pid),
+    None => "The server process is not running".to_string(),
+  })
+}
+
+fn check_query() -> Result<(), String> {
+  let port = server::game_port(
+    &configured()
+      .map(|config| config.port)
+      .unwrap_or_else(|| fetch_var("PORT", "2456")),
+  );
+  let port = server::query_port(&port).ok_or_else(|| format!("'{}' isn't a port", port))?;
+  server::query_info(SocketAddr::from(([127, 0, 0, 1], port)), QUERY_TIMEOUT)
+    .map(|_| ())
+    .map_err(|e| format!("The server didn't answer queries on port {}: {}", port, e))
+}
+
+pub fn check_backup_freshness(
+  last_backup: Option<DateTime<FixedOffset>>,
+  now: DateTime<Local>,
+  max_age: Duration,
+) -> Result<(), String> {
+  match last_backup {
+    None => Err("No backup has been recorded yet".to_string()),
+    Some(at) if now.signed_duration_since(at) > max_age => Err(format!(
+      "The last backup from {} is older than {} minutes",
+      at.to_rfc3339(),
+      max_age.num_minutes()
+    )),
+    Some(_) => Ok(()),
+  }
+}
+
+pub fn check_update_overdue(
+  pending_since: Option<DateTime<FixedOffset>>,
+  now: DateTime<Local>,
+  max_age: Duration,
+) -> Result<(), String> {
+  match pending_since {
+    Some(at) if now.signed_duration_since(at) > max_age => Err(format!(
+      "An update has been available since {} without being applied",
+      at.to_rfc3339()
+    )),
+    _ => Ok(()),
+  }
+}
+
+fn fetch_max_age(name: &str, default: &str) -> Result<Duration, CommandError> {
+  let value = fetch_var(name, default);
+  parse_duration(&value).ok_or_else(|| {
+    CommandError::failed(format!(
+      "Invalid {} '{}', expected something like 30m, 12h or 2d",
+      name, value
+    ))
+  })
+}
+
+/// Fails quietly after logging why the server is unhealthy, an unhealthy server is no failed
+/// command. A setting the check can't run with is one.
+fn run_check(check: HealthCheck) -> CommandResult {
+  let now = Local::now();
+  let outcome = match check {
+    HealthCheck::Process => check_process(),
+    HealthCheck::Query => check_query(),
+    HealthCheck::Save => check_save_age(
+      server::last_world_save(),
+      server::server_process().map(|(_, uptime)| uptime),
+      now.naive_local(),
+      fetch_max_age(HEALTH_MAX_SAVE_AGE_VAR, "60")?,
+    ),
+    HealthCheck::Backup => check_backup_freshness(
+      StateFile::new().load().last_backup_at(),
+      now,
+      fetch_max_age(HEALTH_BACKUP_MAX_AGE_VAR, "24h")?,
+    ),
+    HealthCheck::Update => check_update_overdue(
+      StateFile::new()
+        .load()
+        .update_pending_since
+        .and_then(|at| parse_timestamp(&at)),
+      now,
+      fetch_max_age(HEALTH_UPDATE_MAX_AGE_VAR, "24h")?,
+    ),
+  };
+  outcome.map_err(|reason| {
+    error!("Unhealthy: {}", reason);
+    CommandError::quiet(1)
+  })
+}
+
+pub fn invoke(_args: &ArgMatches) -> CommandResult {
+  let checks = parse_checks(&fetch_var(HEALTH_CHECKS_VAR, "process"))?;
+  for check in checks {
+    run_check(check)?;
+  }
+  info!("Healthy");
+  Ok(())
+}
+
+#[cfg(test)]
+mod health_tests {
+  use super::*;
+
+  fn hours_ago(now: DateTime<Local>, hours: i64) -> Option<DateTime<FixedOffset>> {
+    Some((now - Duration::hours(hours)).into())
+  }
+
+  #[test]
+  fn fresh_backup_is_healthy() {
+    let now = Local::now();
+    assert!(check_backup_freshness(hours_ago(now, 1), now, Duration::hours(24)).is_ok());
+    assert!(check_backup_freshness(hours_ago(now, 0), now, Duration::hours(24)).is_ok());
+  }
+
+  #[test]
+  fn stale_backup_is_unhealthy() {
+    let now = Local::now();
+    assert!(check_backup_freshness(hours_ago(now, 25), now, Duration::hours(24)).is_err());
+    assert!(check_backup_freshness(hours_ago(now, 2), now, Duration::hours(1)).is_err());
+  }
+
+  #[test]
+  fn missing_backup_is_unhealthy() {
+    assert!(check_backup_freshness(None, Local::now(), Duration::hours(24)).is_err());
+  }
+
+  #[test]
+  fn pending_update_becomes_overdue() {
+    let now = Local::now();
+    assert!(check_update_overdue(None, now, Duration::hours(24)).is_ok());
+    assert!(check_update_overdue(hours_ago(now, 2), now, Duration::hours(24)).is_ok());
+    assert!(check_update_overdue(hours_ago(now, 30), now, Duration::hours(24)).is_err());
+  }
+
+  #[test]
+  fn parses_check_list() {
+    assert_eq!(
+      parse_checks("process, backup").unwrap(),
+      vec![HealthCheck::Process, HealthCheck::Backup]
+    );
+    assert_eq!(
+      parse_checks("save,query,process,query").unwrap(),
+      vec![HealthCheck::Process, HealthCheck::Query, HealthCheck::Save]
+    );
+    assert!(parse_checks("process,disk").is_err());
+  }
+
+  #[test]
+  fn saves_must_be_recent() {
+    let now = Local::now().naive_local();
+    let max_age = Duration::minutes(60);
+    let minutes = |minutes: u64| Some(time::Duration::from_secs(minutes * 60));
+    assert!(check_save_age(
+      Some(now - Duration::minutes(30)),
+      minutes(600),
+      now,
+      max_age
+    )
+    .is_ok());
+    assert!(check_save_age(
+      Some(now - Duration::minutes(90)),
+      minutes(600),
+      now,
+      max_age
+    )
+    .is_err());
+    // Just started, it hasn't had to save yet.
+    assert!(check_save_age(None, minutes(10), now, max_age).is_ok());
+    assert!(check_save_age(None, minutes(61), now, max_age).is_err());
+    assert!(check_save_age(None, None, now, max_age).is_err());
+  }
+}
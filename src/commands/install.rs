@@ -1,6 +1,65 @@
-use crate::server;
-use std::process::ExitStatus;
+use clap::ArgMatches;
+use log::info;
 
-pub fn invoke(app_id: i64) -> std::io::Result<ExitStatus> {
-  server::install(app_id)
+use crate::{
+  constants,
+  errors::{CommandError, CommandResult},
+  notifications::enums::{event_status::EventStatus, notification_event::NotificationEvent},
+  server,
+  steamcmd::steamcmd_display,
+  utils::get_working_dir,
+};
+
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  let validate = args.is_present("validate");
+  if args.is_present("dry_run") {
+    info!(
+      "Dry run: would install {} to {} by running:",
+      constants::GAME_ID,
+      get_working_dir().display()
+    );
+    info!(
+      "{} {}",
+      steamcmd_display(),
+      server::install_args(constants::GAME_ID, validate).join(" ")
+    );
+    return Ok(());
+  }
+  match server::install(constants::GAME_ID, validate) {
+    Ok(run) if !run.success() => {
+      let message = match &run.outcome {
+        Some(outcome) => format!("Failed to install Valheim, steamcmd {}", outcome),
+        None => format!(
+          "Failed to install Valheim, steamcmd exited with {}",
+          run.status
+        ),
+      };
+      NotificationEvent::Update(EventStatus::Failed).send_notification_with_details(&message);
+      Err(CommandError::new(
+        run.status.code().filter(|code| *code != 0).unwrap_or(1),
+        message,
+      ))
+    }
+    Ok(run) => {
+      if let Some(outcome) = &run.outcome {
+        info!(
+          "steamcmd reports Valheim is {}{}",
+          outcome,
+          if validate {
+            ", every file was checked"
+          } else {
+            ""
+          }
+        );
+      }
+      NotificationEvent::Update(EventStatus::Successful).send_notification();
+      info!("Successfully installed Valheim!");
+      Ok(())
+    }
+    Err(e) => {
+      let message = format!("Failed to install Valheim: {}", e);
+      NotificationEvent::Update(EventStatus::Failed).send_notification_with_details(&message);
+      Err(CommandError::failed(message))
+    }
+  }
 }
@@ -0,0 +1,183 @@
+use clap::ArgMatches;
+use log::{debug, info};
+use regex::Regex;
+
+use std::{path::PathBuf, str::FromStr, sync::atomic::Ordering, thread::sleep, time::Duration};
+
+use crate::errors::{CommandError, CommandResult, VariantNotFound};
+use crate::logger::log_file_path;
+use crate::mods::bepinex::{bepinex_log_path, is_bepinex_installed};
+use crate::server::{self, LogTail};
+
+/// How often a followed log is checked for new lines.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Lines shown when `--lines` isn't given, like `tail`.
+const DEFAULT_LINES: usize = 10;
+/// How far back `--grep` looks for the last matching lines.
+const GREP_SEARCH_LINES: usize = 20_000;
+
+/// The logs `odin logs` can print.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LogSource {
+  Server,
+  BepInEx,
+  Odin,
+}
+
+impl FromStr for LogSource {
+  type Err = VariantNotFound;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "server" => Ok(LogSource::Server),
+      "bepinex" => Ok(LogSource::BepInEx),
+      "odin" => Ok(LogSource::Odin),
+      _ => Err(VariantNotFound {
+        v: format!(
+          "Unknown log source '{}', expected server, bepinex or odin",
+          s
+        ),
+      }),
+    }
+  }
+}
+
+impl LogSource {
+  fn name(&self) -> &'static str {
+    match self {
+      LogSource::Server => "server log",
+      LogSource::BepInEx => "BepInEx log",
+      LogSource::Odin => "odin log",
+    }
+  }
+
+  fn path(&self) -> Result<PathBuf, String> {
+    match self {
+      LogSource::Server => Ok(server::server_log_path()),
+      LogSource::BepInEx => {
+        let installation = is_bepinex_installed();
+        if installation.is_installed() {
+          Ok(bepinex_log_path())
+        } else {
+          Err(format!(
+            "BepInEx isn't installed, so there is no {}. Missing: {}",
+            bepinex_log_path().display(),
+            installation.missing.join(", ")
+          ))
+        }
+      }
+      LogSource::Odin => log_file_path().ok_or_else(|| {
+        "Odin only writes its log to a file when LOG_FILE is set, see docker logs otherwise"
+          .to_string()
+      }),
+    }
+  }
+}
+
+fn parse_lines(value: &str) -> Result<usize, String> {
+  value
+    .trim()
+    .parse::<usize>()
+    .map_err(|_| format!("Invalid number of lines '{}', expected a number", value))
+}
+
+fn parse_grep(value: &str) -> Result<Regex, String> {
+  Regex::new(value).map_err(|e| format!("Invalid pattern '{}': {}", value, e))
+}
+
+/// The last `count` of `lines` that `grep` matches, all of them without a pattern.
+fn last_matching(lines: Vec<String>, grep: Option<&Regex>, count: usize) -> Vec<String> {
+  let matching: Vec<String> = lines
+    .into_iter()
+    .filter(|line| grep.is_none_or(|grep| grep.is_match(line)))
+    .collect();
+  let skipped = matching.len().saturating_sub(count);
+  matching.into_iter().skip(skipped).collect()
+}
+
+/// Prints the last lines of a log and, with `--follow`, everything written to it after that until
+/// odin is told to stop.
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  let count = args
+    .value_of("lines")
+    .map_or(Ok(DEFAULT_LINES), parse_lines)?;
+  let source = LogSource::from_str(args.value_of("source").unwrap_or("server"))
+    .map_err(|e| CommandError::failed(e.v))?;
+  let grep = args.value_of("grep").map(parse_grep).transpose()?;
+  let path = source.path()?;
+  let follow = args.is_present("follow");
+  let mut tail = LogTail::from_end(&path);
+  let searched = if grep.is_some() {
+    GREP_SEARCH_LINES.max(count)
+  } else {
+    count
+  };
+  match tail.previous_lines(searched) {
+    Ok(lines) => last_matching(lines, grep.as_ref(), count)
+      .iter()
+      .for_each(|line| println!("{}", line)),
+    Err(e) if follow => info!("Waiting for {} to be written: {}", path.display(), e),
+    Err(e) if source == LogSource::Server => {
+      return Err(CommandError::failed(format!(
+        "Unable to read the server log {}: {}. Has the server been started?",
+        path.display(),
+        e
+      )))
+    }
+    Err(e) => {
+      return Err(CommandError::failed(format!(
+        "Unable to read the {} {}: {}",
+        source.name(),
+        path.display(),
+        e
+      )))
+    }
+  }
+  if !follow {
+    return Ok(());
+  }
+  let terminate = server::register_termination();
+  while !terminate.load(Ordering::SeqCst) {
+    last_matching(tail.read_lines(), grep.as_ref(), usize::MAX)
+      .iter()
+      .for_each(|line| println!("{}", line));
+    sleep(POLL_INTERVAL);
+  }
+  debug!("Stopped following {}", path.display());
+  Ok(())
+}
+
+#[cfg(test)]
+mod logs_tests {
+  use super::*;
+
+  #[test]
+  fn parses_line_counts() {
+    assert_eq!(parse_lines("50"), Ok(50));
+    assert_eq!(parse_lines(" 0 "), Ok(0));
+    assert!(parse_lines("-1").is_err());
+    assert!(parse_lines("all").is_err());
+  }
+
+  #[test]
+  fn keeps_the_last_matching_lines() {
+    let lines = vec![
+      "Got connection SteamID 1".to_string(),
+      "World saved ( 1.2ms )".to_string(),
+      "Got connection SteamID 2".to_string(),
+      "Got connection SteamID 3".to_string(),
+    ];
+    let grep = parse_grep("^Got connection").unwrap();
+    assert_eq!(
+      last_matching(lines.clone(), Some(&grep), 2),
+      vec!["Got connection SteamID 2", "Got connection SteamID 3"]
+    );
+    assert_eq!(
+      last_matching(lines, None, 1),
+      vec!["Got connection SteamID 3"]
+    );
+    assert!(parse_grep("(unclosed").is_err());
+    assert_eq!(LogSource::from_str("BepInEx").unwrap(), LogSource::BepInEx);
+    assert!(LogSource::from_str("steam").is_err());
+  }
+}
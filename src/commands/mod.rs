@@ -1,7 +1,16 @@
 pub mod backup;
+pub mod cmd;
+pub mod completions;
 pub mod configure;
+pub mod health;
 pub mod install;
+pub mod logs;
 pub mod notify;
+pub mod report;
+pub mod restore;
 pub mod start;
+pub mod status;
 pub mod stop;
 pub mod update;
+pub mod watch;
+pub mod world;
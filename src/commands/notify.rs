@@ -1,15 +1,74 @@
+use crate::errors::{CommandError, CommandResult};
+use crate::notifications::destination::WebhookDestination;
 use crate::notifications::enums::notification_event::NotificationEvent;
-use crate::utils::get_variable;
+use crate::notifications::enums::notification_level::NotificationLevel;
+use crate::notifications::{
+  broadcast_message_override, configured_destinations, event_webhook_url, send_broadcast,
+  send_test_notification,
+};
 use clap::ArgMatches;
 use log::{error, info};
+use std::str::FromStr;
 
-pub fn invoke(args: &ArgMatches) {
-  let message = get_variable(&args, "MESSAGE", String::from("Test Notification"));
-  let webhook_url = get_variable(&args, "webhook_url", "".to_string());
-  if !webhook_url.is_empty() {
-    info!("Sending Broadcast: {}", message);
-    NotificationEvent::Broadcast.send_custom_notification(webhook_url.as_str(), message.as_str())
-  } else {
-    error!("Failed to send notification! Webhook url not provided!")
+/// Sends a test notification to `--webhook`, or every destination broadcasts go to, and fails
+/// unless all of them accepted it.
+fn send_test(args: &ArgMatches) -> CommandResult {
+  let destinations = match args.value_of("webhook_url") {
+    Some(webhook_url) => vec![WebhookDestination::parse(webhook_url)?],
+    None => configured_destinations(&NotificationEvent::Broadcast),
+  };
+  if destinations.is_empty() {
+    return Err(CommandError::failed(
+      "No webhook to test! Set WEBHOOK_URL, WEBHOOK_DESTINATIONS or pass --webhook",
+    ));
   }
+  let mut failed = 0;
+  for destination in &destinations {
+    info!(
+      "Sending a test notification as {:?} to {}",
+      destination.format, destination.url
+    );
+    match send_test_notification(destination) {
+      Ok(status) => info!("Delivered, the webhook answered {}", status),
+      Err(e) => {
+        error!("Not delivered: {}", e);
+        failed += 1;
+      }
+    }
+  }
+  if failed > 0 {
+    return Err(CommandError::failed(format!(
+      "{} of {} webhook(s) didn't accept the test notification",
+      failed,
+      destinations.len()
+    )));
+  }
+  Ok(())
+}
+
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  if args.is_present("test") {
+    return send_test(args);
+  }
+  let message = match args.value_of("MESSAGE") {
+    Some(message) => message.to_string(),
+    None => broadcast_message_override().unwrap_or_else(|| String::from("Test Notification")),
+  };
+  let level = NotificationLevel::from_str(args.value_of("level").unwrap_or("info"))?;
+  // An explicit flag wins over WEBHOOK_BROADCAST_URL and WEBHOOK_URL so one-off sends can go elsewhere.
+  let webhook_url = match args.value_of("webhook_url") {
+    Some(webhook_url) => webhook_url.to_string(),
+    None => event_webhook_url(&NotificationEvent::Broadcast),
+  };
+  if webhook_url.is_empty() {
+    return Err(CommandError::failed(
+      "Failed to send notification! Webhook url not provided!",
+    ));
+  }
+  let destination = WebhookDestination::parse(&webhook_url)?;
+  info!("Sending Broadcast: {}", message);
+  if !send_broadcast(&destination, &message, args.value_of("title"), level) {
+    return Err(CommandError::failed("Failed to deliver the notification!"));
+  }
+  Ok(())
 }
@@ -0,0 +1,377 @@
+use chrono::{DateTime, Duration, Local};
+use clap::ArgMatches;
+use log::info;
+
+use std::{collections::HashSet, path::Path};
+
+use crate::errors::{CommandError, CommandResult};
+use crate::files::state::{parse_timestamp, BackupRecord, OdinState, StateFile, WarningRecord};
+use crate::notifications::destination::{WebhookDestination, WebhookFormat};
+use crate::notifications::enums::notification_event::NotificationEvent;
+use crate::utils::get_variable;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportStyle {
+  Plain,
+  Markdown,
+}
+
+#[derive(Debug)]
+pub struct MaintenanceReport {
+  period_start: DateTime<Local>,
+  period_end: DateTime<Local>,
+  uptime: Duration,
+  restarts: usize,
+  unique_players: usize,
+  backups_taken: usize,
+  newest_backup: Option<BackupRecord>,
+  oldest_backup: Option<BackupRecord>,
+  world_size: Option<u64>,
+  world_size_delta: Option<i64>,
+  updates_applied: usize,
+  warnings: Vec<WarningRecord>,
+}
+
+fn to_local(timestamp: &str) -> Option<DateTime<Local>> {
+  parse_timestamp(timestamp).map(|at| at.with_timezone(&Local))
+}
+
+fn in_period(timestamp: &str, start: DateTime<Local>, end: DateTime<Local>) -> bool {
+  match to_local(timestamp) {
+    Some(at) => at >= start && at <= end,
+    None => false,
+  }
+}
+
+/// Adds up the time between each start and the stop that follows it, clipped to the period.
+fn compute_uptime(state: &OdinState, start: DateTime<Local>, end: DateTime<Local>) -> Duration {
+  let mut events: Vec<(DateTime<Local>, bool)> = state
+    .server_starts
+    .iter()
+    .filter_map(|at| to_local(at).map(|at| (at, true)))
+    .chain(
+      state
+        .server_stops
+        .iter()
+        .filter_map(|at| to_local(at).map(|at| (at, false))),
+    )
+    .filter(|(at, _)| *at <= end)
+    .collect();
+  events.sort_by_key(|(at, _)| *at);
+
+  let mut uptime = Duration::zero();
+  let mut running_since: Option<DateTime<Local>> = None;
+  let mut add_interval = |from: DateTime<Local>, to: DateTime<Local>| {
+    let from = from.max(start);
+    if to > from {
+      uptime = uptime + (to - from);
+    }
+  };
+  for (at, is_start) in events {
+    match (is_start, running_since) {
+      (true, None) => running_since = Some(at),
+      (false, Some(since)) => {
+        add_interval(since, at);
+        running_since = None;
+      }
+      _ => {}
+    }
+  }
+  if let Some(since) = running_since {
+    add_interval(since, end);
+  }
+  uptime
+}
+
+pub fn build_report(
+  state: &OdinState,
+  now: DateTime<Local>,
+  period: Duration,
+  is_retained: impl Fn(&str) -> bool,
+) -> MaintenanceReport {
+  let period_start = now - period;
+  let first_start = state
+    .server_starts
+    .iter()
+    .filter_map(|at| to_local(at))
+    .min();
+  let restarts = state
+    .server_starts
+    .iter()
+    .filter_map(|at| to_local(at))
+    .filter(|at| *at >= period_start && *at <= now && Some(*at) != first_start)
+    .count();
+  let unique_players: HashSet<&str> = state
+    .players_seen
+    .iter()
+    .filter(|player| in_period(&player.seen_at, period_start, now))
+    .map(|player| player.name.as_str())
+    .collect();
+
+  let mut backups: Vec<&BackupRecord> = state
+    .backups
+    .iter()
+    .filter(|backup| to_local(&backup.created_at).is_some())
+    .collect();
+  backups.sort_by_key(|backup| to_local(&backup.created_at));
+  let backups_taken = backups
+    .iter()
+    .filter(|backup| in_period(&backup.created_at, period_start, now))
+    .count();
+  let retained: Vec<&&BackupRecord> = backups
+    .iter()
+    .filter(|backup| is_retained(&backup.path))
+    .collect();
+
+  // Compare the latest world size against the last one known before the period began.
+  let latest_sample = backups.last();
+  let baseline_sample = backups
+    .iter()
+    .rev()
+    .find(|backup| to_local(&backup.created_at).is_some_and(|at| at < period_start))
+    .or_else(|| backups.first());
+  let world_size_delta = match (latest_sample, baseline_sample) {
+    (Some(latest), Some(baseline)) => {
+      Some(latest.world_size_bytes as i64 - baseline.world_size_bytes as i64)
+    }
+    _ => None,
+  };
+
+  MaintenanceReport {
+    period_start,
+    period_end: now,
+    uptime: compute_uptime(state, period_start, now),
+    restarts,
+    unique_players: unique_players.len(),
+    backups_taken,
+    newest_backup: retained.last().map(|backup| (**backup).clone()),
+    oldest_backup: retained.first().map(|backup| (**backup).clone()),
+    world_size: latest_sample.map(|backup| backup.world_size_bytes),
+    world_size_delta,
+    updates_applied: state
+      .updates
+      .iter()
+      .filter(|at| in_period(at, period_start, now))
+      .count(),
+    warnings: state
+      .warnings
+      .iter()
+      .filter(|warning| in_period(&warning.raised_at, period_start, now))
+      .cloned()
+      .collect(),
+  }
+}
+
+fn format_duration(duration: Duration) -> String {
+  format!(
+    "{}d {}h {}m",
+    duration.num_days(),
+    duration.num_hours() % 24,
+    duration.num_minutes() % 60
+  )
+}
+
+fn format_bytes(bytes: u64) -> String {
+  format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn format_backup(backup: &Option<BackupRecord>) -> String {
+  match backup {
+    Some(backup) => format!("{} ({})", backup.path, backup.created_at),
+    None => "none".to_string(),
+  }
+}
+
+impl MaintenanceReport {
+  pub fn render(&self, style: ReportStyle) -> String {
+    let label = |name: &str| match style {
+      ReportStyle::Plain => format!("{}:", name),
+      ReportStyle::Markdown => format!("**{}:**", name),
+    };
+    let period = self.period_end - self.period_start;
+    let uptime_percent = if period.num_seconds() > 0 {
+      self.uptime.num_seconds() as f64 * 100.0 / period.num_seconds() as f64
+    } else {
+      0.0
+    };
+    let world_size = match (self.world_size, self.world_size_delta) {
+      (Some(size), Some(delta)) => format!(
+        "{} ({}{})",
+        format_bytes(size),
+        if delta < 0 { "-" } else { "+" },
+        format_bytes(delta.unsigned_abs())
+      ),
+      _ => "unknown".to_string(),
+    };
+    let mut lines = vec![
+      format!(
+        "Server report for {} to {}",
+        self.period_start.format("%Y-%m-%d"),
+        self.period_end.format("%Y-%m-%d")
+      ),
+      format!(
+        "{} {} ({:.1}%)",
+        label("Uptime"),
+        format_duration(self.uptime),
+        uptime_percent
+      ),
+      format!("{} {}", label("Restarts"), self.restarts),
+      format!("{} {}", label("Unique players"), self.unique_players),
+      format!("{} {}", label("Backups taken"), self.backups_taken),
+      format!(
+        "{} {}",
+        label("Newest backup"),
+        format_backup(&self.newest_backup)
+      ),
+      format!(
+        "{} {}",
+        label("Oldest backup"),
+        format_backup(&self.oldest_backup)
+      ),
+      format!("{} {}", label("World size"), world_size),
+      format!("{} {}", label("Updates applied"), self.updates_applied),
+      format!("{} {}", label("Warnings"), self.warnings.len()),
+    ];
+    for warning in &self.warnings {
+      lines.push(format!("- {} {}", warning.raised_at, warning.message));
+    }
+    lines.join("\n")
+  }
+}
+
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  let days = get_variable(args, "report_period_days", "7".to_string());
+  let days = match days.parse::<i64>() {
+    Ok(days) if days > 0 => days,
+    _ => {
+      return Err(CommandError::failed(format!(
+        "Report period must be a positive number of days, got '{}'",
+        days
+      )))
+    }
+  };
+  let report = build_report(
+    &StateFile::new().load(),
+    Local::now(),
+    Duration::days(days),
+    |path| Path::new(path).exists(),
+  );
+  let webhook_url = get_variable(args, "webhook_url", "".to_string());
+  if webhook_url.is_empty() {
+    info!("{}", report.render(ReportStyle::Plain));
+    return Ok(());
+  }
+  let destination = WebhookDestination::parse(&webhook_url)?;
+  let style = match destination.format {
+    WebhookFormat::Discord => ReportStyle::Markdown,
+    WebhookFormat::Json | WebhookFormat::Matrix => ReportStyle::Plain,
+  };
+  info!("Sending server report");
+  NotificationEvent::Broadcast.send_custom_notification(&[destination], &report.render(style));
+  Ok(())
+}
+
+#[cfg(test)]
+mod report_tests {
+  use super::*;
+  use crate::files::state::PlayerSighting;
+
+  fn at(now: DateTime<Local>, days_ago: i64, hours_ago: i64) -> String {
+    (now - Duration::days(days_ago) - Duration::hours(hours_ago)).to_rfc3339()
+  }
+
+  fn backup(path: &str, created_at: String, world_size_bytes: u64) -> BackupRecord {
+    BackupRecord {
+      path: path.to_string(),
+      size_bytes: 100,
+      world_size_bytes,
+      created_at,
+    }
+  }
+
+  fn fixture(now: DateTime<Local>) -> OdinState {
+    OdinState {
+      // Up since before the period, crashed two days ago and came back two hours later.
+      server_starts: vec![at(now, 10, 0), at(now, 2, 0)],
+      server_stops: vec![at(now, 2, 2)],
+      backups: vec![
+        backup("/backups/old.tar.gz", at(now, 9, 0), 1000),
+        backup("/backups/pruned.tar.gz", at(now, 6, 0), 1500),
+        backup("/backups/new.tar.gz", at(now, 1, 0), 3000),
+      ],
+      updates: vec![at(now, 8, 0), at(now, 3, 0)],
+      warnings: vec![WarningRecord {
+        message: "Server exited on its own".to_string(),
+        raised_at: at(now, 2, 2),
+      }],
+      players_seen: vec![
+        PlayerSighting {
+          name: "Ragnar".to_string(),
+          seen_at: at(now, 1, 0),
+        },
+        PlayerSighting {
+          name: "Ragnar".to_string(),
+          seen_at: at(now, 3, 0),
+        },
+        PlayerSighting {
+          name: "Lagertha".to_string(),
+          seen_at: at(now, 4, 0),
+        },
+        PlayerSighting {
+          name: "Rollo".to_string(),
+          seen_at: at(now, 20, 0),
+        },
+      ],
+      update_pending_since: None,
+    }
+  }
+
+  #[test]
+  fn aggregates_fixture_state() {
+    let now = Local::now();
+    let report = build_report(&fixture(now), now, Duration::days(7), |path| {
+      !path.contains("pruned")
+    });
+    assert_eq!(report.uptime, Duration::days(7) - Duration::hours(2));
+    assert_eq!(report.restarts, 1);
+    assert_eq!(report.unique_players, 2);
+    assert_eq!(report.backups_taken, 2);
+    assert_eq!(
+      report.newest_backup.unwrap().path,
+      "/backups/new.tar.gz".to_string()
+    );
+    assert_eq!(
+      report.oldest_backup.unwrap().path,
+      "/backups/old.tar.gz".to_string()
+    );
+    assert_eq!(report.world_size, Some(3000));
+    assert_eq!(report.world_size_delta, Some(2000));
+    assert_eq!(report.updates_applied, 1);
+    assert_eq!(report.warnings.len(), 1);
+  }
+
+  #[test]
+  fn empty_state_reports_nothing() {
+    let now = Local::now();
+    let report = build_report(&OdinState::default(), now, Duration::days(7), |_| true);
+    assert_eq!(report.uptime, Duration::zero());
+    assert_eq!(report.restarts, 0);
+    assert!(report.newest_backup.is_none());
+    assert!(report
+      .render(ReportStyle::Plain)
+      .contains("World size: unknown"));
+  }
+
+  #[test]
+  fn renders_per_style() {
+    let now = Local::now();
+    let report = build_report(&fixture(now), now, Duration::days(7), |_| true);
+    let plain = report.render(ReportStyle::Plain);
+    let markdown = report.render(ReportStyle::Markdown);
+    assert!(plain.contains("Restarts: 1"));
+    assert!(plain.contains("Uptime: 6d 22h 0m"));
+    assert!(markdown.contains("**Restarts:** 1"));
+    assert!(markdown.contains("- "));
+    assert!(!plain.contains("**"));
+  }
+}
@@ -0,0 +1,399 @@
+use clap::ArgMatches;
+use log::{debug, info, warn};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::errors::{CommandError, CommandResult};
+use crate::files::{
+  archive::{
+    checksum_path, extract_archive, list_archive, read_metadata, verify_archive, ArchiveEntry,
+    ArchiveFormat, Verification, ARCHIVE_ROOT, CHECKSUM_EXTENSION,
+  },
+  encryption::{decrypt_file, is_encrypted, plaintext_name},
+  failure::enter_phase,
+  metadata::{configured_world, BackupMetadata},
+  progress::format_bytes,
+  s3::{is_s3_uri, parse_s3_uri, S3Client, S3Config},
+  scope::{parse_scopes, BackupScope},
+  snapshot::{list_snapshot, restore_snapshot},
+  worlds::{world_filter, world_of},
+};
+use crate::server;
+use crate::utils::get_working_dir;
+
+/// Downloads an `s3://` backup next to the other temporary files and returns where it went.
+fn download(uri: &str) -> Result<PathBuf, CommandError> {
+  let (bucket, key) = parse_s3_uri(uri).ok_or_else(|| {
+    CommandError::failed(format!("Invalid uri '{}', expected s3://bucket/key", uri))
+  })?;
+  let file_name = key.rsplit('/').next().unwrap_or(&key);
+  let destination = env::temp_dir().join(format!("odin-restore-{}", file_name));
+  enter_phase("downloading");
+  info!("Downloading {}", uri);
+  let client = S3Config::for_bucket(&bucket).and_then(S3Client::new)?;
+  if let Err(e) = client.download(&key, &destination) {
+    let _ = fs::remove_file(&destination);
+    return Err(e.into());
+  }
+  // Older backups were uploaded without a manifest, verification notes that on its own.
+  let manifest_key = format!("{}{}", key, CHECKSUM_EXTENSION);
+  if client
+    .download(&manifest_key, &checksum_path(&destination))
+    .is_err()
+  {
+    let _ = fs::remove_file(checksum_path(&destination));
+  }
+  Ok(destination)
+}
+
+fn remove_download(downloaded: bool, backup: &Path) {
+  if !downloaded {
+    return;
+  }
+  for path in [backup.to_path_buf(), checksum_path(backup)].iter() {
+    if path.exists() {
+      if let Err(e) = fs::remove_file(path) {
+        warn!("Failed to remove {}: {}", path.display(), e);
+      }
+    }
+  }
+}
+
+/// Decrypts an encrypted backup next to the other temporary files and returns where it went.
+fn decrypt(backup: &Path) -> Result<PathBuf, String> {
+  let file_name = backup
+    .file_name()
+    .map(|name| plaintext_name(&name.to_string_lossy()).to_string())
+    .unwrap_or_default();
+  let destination = env::temp_dir().join(format!("odin-decrypted-{}", file_name));
+  enter_phase("decrypting");
+  info!("Decrypting {}", backup.display());
+  decrypt_file(backup, &destination)?;
+  Ok(destination)
+}
+
+/// The scopes as a list, naming `world` in place of the worlds when only it is restored.
+fn scope_names(scopes: &[BackupScope], world: Option<&str>) -> String {
+  scopes
+    .iter()
+    .map(|scope| match (scope, world) {
+      (BackupScope::Worlds, Some(world)) => format!("world {}", world),
+      _ => scope.to_string(),
+    })
+    .collect::<Vec<String>>()
+    .join(", ")
+}
+
+/// Logs what the backup was taken of, and warns when that isn't the world the server loads.
+fn describe(archive: &Path) -> Option<BackupMetadata> {
+  let metadata = match read_metadata(archive) {
+    Ok(Some(metadata)) => metadata,
+    Ok(None) => {
+      debug!("{} has no odin-backup.json", archive.display());
+      return None;
+    }
+    Err(e) => {
+      warn!("Unable to read what {} holds: {}", archive.display(), e);
+      return None;
+    }
+  };
+  info!("Backup of {}", metadata);
+  info!("Holds {}", scope_names(&metadata.scopes(), None));
+  for file in &metadata.files {
+    info!("  {}", file);
+  }
+  let world = configured_world();
+  if metadata.world != world {
+    warn!(
+      "This backup is of the world {} but the server is set to load {}, set WORLD to {} or use odin world use to load it",
+      metadata.world, world, metadata.world
+    );
+  }
+  Some(metadata)
+}
+
+/// The scopes asked for that the backup doesn't hold. Backups without metadata are taken to
+/// hold worlds.
+fn missing_scopes(
+  requested: &[BackupScope],
+  metadata: Option<&BackupMetadata>,
+) -> Vec<BackupScope> {
+  let held = metadata.map_or_else(|| vec![BackupScope::Worlds], BackupMetadata::scopes);
+  requested
+    .iter()
+    .filter(|scope| !held.contains(scope))
+    .copied()
+    .collect()
+}
+
+/// The worlds with files among `entries`, whose paths start with `saves`. Sorted.
+fn worlds_in(entries: &[ArchiveEntry], saves: &Path) -> Vec<String> {
+  let mut worlds: Vec<String> = entries
+    .iter()
+    .filter(|entry| !entry.is_dir)
+    .filter_map(|entry| world_of(entry.path.strip_prefix(saves).ok()?))
+    .collect();
+  worlds.sort();
+  worlds.dedup();
+  worlds
+}
+
+/// Fails when `world` isn't among the worlds `found` in a backup, naming the ones that are.
+fn check_world(world: &str, found: &[String]) -> Result<(), String> {
+  if found.iter().any(|name| name == world) {
+    return Ok(());
+  }
+  Err(if found.is_empty() {
+    format!("it holds no files of the world {}, or of any other", world)
+  } else {
+    format!(
+      "it holds no files of the world {}, only of {}",
+      world,
+      found.join(", ")
+    )
+  })
+}
+
+/// Logs the worlds and files in a backup.
+fn log_contents(source: &str, entries: &[ArchiveEntry], saves: &Path) {
+  let worlds = worlds_in(entries, saves);
+  if worlds.is_empty() {
+    info!("{} holds no worlds", source);
+  } else {
+    info!("{} holds the worlds {}", source, worlds.join(", "));
+  }
+  for entry in entries.iter().filter(|entry| !entry.is_dir) {
+    info!("  {} ({})", entry.path.display(), format_bytes(entry.size));
+  }
+}
+
+/// Decrypts `backup` if it is encrypted, removing the download when that fails.
+fn readable_archive(backup: &Path, downloaded: bool) -> Result<PathBuf, CommandError> {
+  if !is_encrypted(backup) {
+    return Ok(backup.to_path_buf());
+  }
+  decrypt(backup).map_err(|e| {
+    remove_download(downloaded, backup);
+    e.into()
+  })
+}
+
+/// Removes the files a restore downloaded or decrypted.
+fn remove_temporary_files(archive: &Path, backup: &Path, downloaded: bool) {
+  if archive != backup {
+    if let Err(e) = fs::remove_file(archive) {
+      warn!("Failed to remove {}: {}", archive.display(), e);
+    }
+  }
+  remove_download(downloaded, backup);
+}
+
+/// Lists what a backup holds without restoring any of it.
+fn list(source: &str) -> CommandResult {
+  let failed =
+    |e: std::io::Error| CommandError::failed(format!("Failed to list {}: {}", source, e));
+  if Path::new(source).is_dir() {
+    let entries = list_snapshot(Path::new(source)).map_err(failed)?;
+    log_contents(source, &entries, Path::new(""));
+    return Ok(());
+  }
+  let downloaded = is_s3_uri(source);
+  let backup = if downloaded {
+    download(source)?
+  } else {
+    PathBuf::from(source)
+  };
+  let archive = readable_archive(&backup, downloaded)?;
+  let entries = list_archive(&archive);
+  remove_temporary_files(&archive, &backup, downloaded);
+  log_contents(source, &entries.map_err(failed)?, Path::new(ARCHIVE_ROOT));
+  Ok(())
+}
+
+/// Why the archive can't be restored as asked, if it can't.
+fn check_contents(
+  archive: &Path,
+  scopes: &[BackupScope],
+  metadata: Option<&BackupMetadata>,
+  world: Option<&str>,
+) -> Result<(), String> {
+  let missing = missing_scopes(scopes, metadata);
+  if !missing.is_empty() {
+    return Err(format!("it doesn't hold {}", scope_names(&missing, None)));
+  }
+  match world {
+    Some(world) => {
+      let entries = list_archive(archive).map_err(|e| e.to_string())?;
+      check_world(world, &worlds_in(&entries, Path::new(ARCHIVE_ROOT)))
+    }
+    None => Ok(()),
+  }
+}
+
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  let source = args.value_of("BACKUP_FILE").unwrap();
+  if args.is_present("list") {
+    return list(source);
+  }
+  let output = Path::new(args.value_of("OUTPUT_DIR").unwrap());
+  let scopes = parse_scopes(args.value_of("scope").unwrap_or("worlds"))?;
+  let world = args.value_of("world");
+  if world.is_some() && !scopes.contains(&BackupScope::Worlds) {
+    return Err(CommandError::failed(
+      "--world picks one of the worlds, add worlds to --scope",
+    ));
+  }
+  let working_dir = get_working_dir();
+  let targets: Vec<_> = scopes
+    .iter()
+    .map(|scope| {
+      let mut target = scope.restore_target(output, &working_dir);
+      if let (BackupScope::Worlds, Some(world)) = (scope, world) {
+        target.filter = world_filter(world);
+      }
+      target
+    })
+    .collect();
+  if args.is_present("dry_run") {
+    for (scope, target) in scopes.iter().zip(&targets) {
+      info!(
+        "Dry run: would restore the {} in {} into {}",
+        scope_names(&[*scope], world),
+        source,
+        target.directory.display()
+      );
+    }
+    return Ok(());
+  }
+  // The server keeps the world in memory and would overwrite whatever is restored.
+  if server::is_running() {
+    return Err(CommandError::failed(
+      "Stop the server before restoring a backup!",
+    ));
+  }
+  if Path::new(source).is_dir() {
+    if scopes != [BackupScope::Worlds] {
+      return Err(CommandError::failed(
+        "Snapshots only hold worlds, restore them with --scope worlds",
+      ));
+    }
+    if let Some(world) = world {
+      let found =
+        list_snapshot(Path::new(source)).map(|entries| worlds_in(&entries, Path::new("")));
+      if let Err(e) = found
+        .map_err(|e| e.to_string())
+        .and_then(|found| check_world(world, &found))
+      {
+        return Err(CommandError::failed(format!(
+          "Failed to restore {}: {}",
+          source, e
+        )));
+      }
+    }
+    // Snapshots are plain copies of the saves, there is no manifest to check.
+    info!(
+      "Restoring the {} of the snapshot {}",
+      scope_names(&scopes, world),
+      source
+    );
+    enter_phase("restoring");
+    let restored = restore_snapshot(Path::new(source), output, &targets[0].filter)
+      .map_err(|e| CommandError::failed(format!("Failed to restore {}: {}", source, e)))?;
+    info!(
+      "Restored {} files from {} into {}",
+      restored,
+      source,
+      output.display()
+    );
+    return Ok(());
+  }
+  let downloaded = is_s3_uri(source);
+  let backup = if downloaded {
+    download(source)?
+  } else {
+    PathBuf::from(source)
+  };
+  enter_phase("verifying");
+  match verify_archive(&backup) {
+    Ok(Verification::Verified) => info!("{} matches its manifest", source),
+    Ok(Verification::Unverified) => warn!(
+      "{} has no manifest, it can be read but can't be checked against one",
+      source
+    ),
+    Err(e) if args.is_present("force") => warn!("{}. Restoring anyway because of --force", e),
+    Err(e) => {
+      remove_download(downloaded, &backup);
+      return Err(CommandError::failed(format!(
+        "{}. Pass --force to restore it anyway",
+        e
+      )));
+    }
+  }
+  let archive = readable_archive(&backup, downloaded)?;
+  let result =
+    check_contents(&archive, &scopes, describe(&archive).as_ref(), world).and_then(|_| {
+      ArchiveFormat::detect(&archive)
+        .and_then(|format| {
+          info!(
+            "Restoring the {} of the {} backup {}",
+            scope_names(&scopes, world),
+            format,
+            source
+          );
+          enter_phase("restoring");
+          extract_archive(&archive, &targets)
+        })
+        .map_err(|e| e.to_string())
+    });
+  remove_temporary_files(&archive, &backup, downloaded);
+  let restored =
+    result.map_err(|e| CommandError::failed(format!("Failed to restore {}: {}", source, e)))?;
+  info!("Restored {} entries from {}", restored, source);
+  Ok(())
+}
+
+#[cfg(test)]
+mod restore_tests {
+  use super::*;
+
+  #[test]
+  fn backups_without_scopes_only_hold_worlds() {
+    let all = [BackupScope::Worlds, BackupScope::Mods];
+    assert_eq!(missing_scopes(&all, None), vec![BackupScope::Mods]);
+    let metadata = BackupMetadata {
+      scopes: vec![BackupScope::Config, BackupScope::Mods],
+      ..BackupMetadata::default()
+    };
+    assert_eq!(
+      missing_scopes(&all, Some(&metadata)),
+      vec![BackupScope::Worlds]
+    );
+    assert!(missing_scopes(&[BackupScope::Mods], Some(&metadata)).is_empty());
+  }
+
+  #[test]
+  fn names_the_worlds_found_when_one_is_missing() {
+    let entry = |path: &str, is_dir: bool| ArchiveEntry {
+      path: PathBuf::from(path),
+      size: 0,
+      is_dir,
+    };
+    let entries = [
+      entry("saves/worlds_local", true),
+      entry("saves/worlds_local/Fine.fwl", false),
+      entry("saves/worlds_local/Fine.db.old", false),
+      entry("saves/worlds_local/Broken.db", false),
+      entry("saves/adminlist.txt", false),
+      entry("config/adminlist.txt", false),
+    ];
+    let found = worlds_in(&entries, Path::new(ARCHIVE_ROOT));
+    assert_eq!(found, vec!["Broken".to_string(), "Fine".to_string()]);
+    assert_eq!(check_world("Broken", &found), Ok(()));
+    assert_eq!(
+      check_world("Other", &found),
+      Err("it holds no files of the world Other, only of Broken, Fine".to_string())
+    );
+    assert!(check_world("Other", &[]).is_err());
+  }
+}
@@ -1,35 +1,404 @@
-use crate::files::config::load_config;
+use crate::commands::{backup, watch};
+use crate::errors::{CommandError, CommandResult};
+use crate::files::{
+  config::{invalid_settings, try_load_config},
+  failure::{conclude_operation, enter_phase, finish_operation},
+  state::record_server_start,
+  validation::{ensure_valid_arguments, is_public},
+  worlds::resolve_world,
+};
+use crate::notifications::{
+  self,
+  enums::{event_status::EventStatus, notification_event::NotificationEvent},
+};
 use crate::server;
+use crate::utils::{
+  environment::{
+    active_profile, fetch_config_value, fetch_profile_value, fetch_var, profile_names,
+  },
+  parse_bool,
+};
 use clap::ArgMatches;
-use log::{debug, error, info};
-use std::process::exit;
+use log::{debug, info, warn};
 
-pub fn invoke(args: &ArgMatches) {
+use std::{cell::Cell, net::SocketAddr, thread, time::Duration};
+
+/// `1` makes every `odin start` wait like `--wait`.
+pub const START_WAIT_FOR_READY_VAR: &str = "START_WAIT_FOR_READY";
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(300);
+const QUERY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The other profiles whose servers would share a port with one on `game_port`.
+fn conflicting_profiles(profile: &str, game_port: u16) -> Vec<(String, u16)> {
+  profile_names()
+    .into_iter()
+    .filter(|other| other != profile)
+    .filter_map(|other| {
+      // Like the active profile, one that doesn't set PORT uses the shared one.
+      let port = fetch_profile_value(&other, "PORT")
+        .or_else(|| std::env::var("PORT").ok().filter(|port| !port.is_empty()))
+        .or_else(|| fetch_config_value("PORT"))
+        .unwrap_or_else(|| "2456".to_string());
+      let port = port.trim().parse::<u16>().ok()?;
+      server::ports_overlap(game_port, port).then_some((other, port))
+    })
+    .collect()
+}
+
+fn parse_wait_timeout(value: &str) -> Result<Duration, String> {
+  value
+    .trim()
+    .parse::<u64>()
+    .map(Duration::from_secs)
+    .map_err(|_| {
+      format!(
+        "Invalid wait timeout '{}', expected a number of seconds",
+        value
+      )
+    })
+}
+
+/// Blocks until the server logs that players can join, over Steam or crossplay, and only then
+/// sends the `Start` `Successful` notification. A start that never gets that far is a failed one.
+fn wait_until_ready(address: SocketAddr, crossplay: bool, timeout: Duration) -> CommandResult {
+  enter_phase("waiting for the server");
+  info!(
+    "Waiting up to {}s for the server to log that it is ready...",
+    timeout.as_secs()
+  );
+  let ready = Cell::new(false);
+  // This odin isn't the one the server's output passes through, it reads the log files.
+  server::LogWatcher::new(&[server::server_log_path(), server::server_error_log_path()]).watch(
+    QUERY_INTERVAL,
+    Some(timeout),
+    || ready.get(),
+    |event| {
+      if event.is_ready() {
+        ready.set(true)
+      }
+    },
+  );
+  if !ready.get() && !server::server_logged_ready() {
+    NotificationEvent::Start(EventStatus::Failed).send_notification();
+    return Err(CommandError::failed(format!(
+      "The server didn't log that it is ready within {}s",
+      timeout.as_secs()
+    )));
+  }
+  info!("The server is ready for players");
+  // Crossplay servers may never answer on the query port.
+  if !crossplay {
+    match server::query_info(address, QUERY_INTERVAL) {
+      Ok(info) => info!(
+        "{} is up with {} on version {}, {}/{} players online",
+        info.name, info.map, info.version, info.players, info.max_players
+      ),
+      Err(e) => debug!("{} didn't answer the query yet: {}", address, e),
+    }
+  }
+  NotificationEvent::Start(EventStatus::Successful).send_notification();
+  Ok(())
+}
+
+/// `--crossplay` on its own turns crossplay on, with a value it is parsed like `CROSSPLAY`.
+fn parse_crossplay(value: Option<&str>) -> Result<bool, String> {
+  match value {
+    None => Ok(true),
+    Some(value) => parse_bool(value).ok_or_else(|| {
+      format!(
+        "--crossplay must be 1 or 0 (or true/false), got '{}'",
+        value
+      )
+    }),
+  }
+}
+
+pub fn invoke(args: &ArgMatches) -> CommandResult {
   info!("Setting up start scripts...");
+  if args.is_present("ignore_password_validation") {
+    server::ignore_password_validation();
+  }
   debug!("Loading config file...");
-  let config = load_config();
+  let mut config = try_load_config()?;
+  config.world = resolve_world(&config.world);
+  if args.is_present("crossplay") {
+    config.crossplay = parse_crossplay(args.value_of("crossplay"))?;
+  }
+  let joined = |name: &str| {
+    args
+      .values_of(name)
+      .map(|values| values.collect::<Vec<_>>().join(","))
+  };
+  if ["preset", "modifier", "set_key"]
+    .iter()
+    .any(|name| args.is_present(name))
+  {
+    config.world_modifiers = server::WorldModifiers::resolve(
+      args.value_of("preset"),
+      joined("modifier").as_deref(),
+      joined("set_key").as_deref(),
+    )
+    .map_err(|problems| invalid_settings("world modifiers", &problems))?;
+  }
+  if [
+    "save_interval",
+    "game_backups",
+    "game_backup_short",
+    "game_backup_long",
+  ]
+  .iter()
+  .any(|name| args.is_present(name))
+  {
+    config.save_settings = server::SaveSettings::resolve(
+      args.value_of("save_interval"),
+      args.value_of("game_backups"),
+      args.value_of("game_backup_short"),
+      args.value_of("game_backup_long"),
+    )
+    .map_err(|problems| invalid_settings("save settings", &problems))?;
+  }
+  debug!("Valheim's own saves: {}", config.save_settings.summary());
+  if args.is_present("extra_args") || args.is_present("allow_overrides") {
+    let allow_overrides = args.is_present("allow_overrides")
+      || parse_bool(&fetch_var(
+        server::SERVER_EXTRA_ARGS_ALLOW_OVERRIDES_VAR,
+        "0",
+      )) == Some(true);
+    config.extra_args = server::extra_args(args.value_of("extra_args"), allow_overrides)
+      .map_err(|problem| invalid_settings("extra arguments", &[problem]))?;
+  }
+  if !config.extra_args.is_empty() {
+    debug!(
+      "Extra arguments: {}",
+      server::display_args(config.extra_args.iter().map(String::as_str))
+    );
+  }
+  if !config.world_modifiers.is_empty() {
+    debug!("World modifiers: {}", config.world_modifiers);
+  }
+  if let Some(port) = args.value_of("port") {
+    config.port = port.to_string();
+  }
+  if let Some(public) = args.value_of("public") {
+    config.public = public.to_string();
+  }
+  // A public server needs a password the configured private one may not have.
+  if args.is_present("port") || args.is_present("public") {
+    ensure_valid_arguments(&config)?;
+  }
+  debug!(
+    "Launching with -public {}, {}",
+    config.public,
+    if is_public(&config) {
+      "listed in the community server list"
+    } else {
+      "private"
+    }
+  );
 
   let dry_run: bool = args.is_present("dry_run");
   debug!("Dry run condition: {}", dry_run);
+  // Validated above, the port is a number.
+  let game_port = config.port.trim().parse::<u16>().unwrap_or_default();
+  let query_port = match server::query_port(&config.port) {
+    Some(query_port) if query_port != game_port => query_port,
+    _ => {
+      return Err(CommandError::failed(format!(
+        "Invalid {} '{}', expected a port other than the game port {}",
+        server::QUERY_PORT_VAR,
+        fetch_var(server::QUERY_PORT_VAR, ""),
+        game_port
+      )))
+    }
+  };
+  info!(
+    "Game port {}/udp, query port {}/udp{}",
+    game_port,
+    query_port,
+    if server::is_query_port_set() {
+      format!(" (from {})", server::QUERY_PORT_VAR)
+    } else {
+      String::new()
+    }
+  );
+  if let Some(profile) = active_profile() {
+    if let Some((other, port)) = conflicting_profiles(&profile, game_port).first() {
+      return Err(CommandError::failed(format!(
+        "Profile '{}' uses port {} and '{}' uses port {}, they would share a port. Set ODIN_PROFILE_{}_PORT so they are at least 3 apart",
+        profile,
+        game_port,
+        other,
+        port,
+        profile.to_uppercase()
+      )));
+    }
+  }
+  // The server only says it couldn't start when a port is taken, say which one and by what.
+  let ports_in_use = server::describe_ports_in_use(game_port);
+  if !ports_in_use.is_empty() {
+    let message = format!(
+      "{}. Stop it or pick another PORT before starting the server",
+      ports_in_use.join(", ")
+    );
+    if dry_run {
+      warn!("The start would be aborted: {}", message);
+    } else {
+      return Err(CommandError::failed(message));
+    }
+  }
+
+  let exit_behavior = server::server_exit_behavior()?;
+  let restart = server::RestartSchedule::from_arg_or_env(args.value_of("schedule_restart"))?;
+  let backups = args
+    .value_of("backup_every")
+    .map(|every| {
+      server::PeriodicBackup::parse(every, backup::backup_while_running)
+        .and_then(|backups| backup::check_backup_settings().map(|_| backups))
+    })
+    .transpose()?;
+  let wait_for_ready =
+    args.is_present("wait") || parse_bool(&fetch_var(START_WAIT_FOR_READY_VAR, "0")) == Some(true);
+  let wait = if wait_for_ready {
+    let timeout = args
+      .value_of("wait_timeout")
+      .map_or(Ok(DEFAULT_WAIT_TIMEOUT), parse_wait_timeout)?;
+    Some((SocketAddr::from(([127, 0, 0, 1], query_port)), timeout))
+  } else {
+    None
+  };
+  let idle_shutdown = server::IdleShutdown::from_env()?;
+  // Restarting or backing up on a schedule and shutting down when idle need odin around, if the
+  // server exits on its own odin does too.
+  let behavior = match exit_behavior {
+    Some(behavior) => Some(behavior),
+    None if restart.is_some() || backups.is_some() || idle_shutdown.is_some() => {
+      Some(server::ServerExitBehavior::Propagate)
+    }
+    None => None,
+  };
+  // The server's output only passes through odin when it was asked to watch the server.
+  let log_filter = behavior
+    .map(|_| server::LogFilter::from_env())
+    .transpose()
+    .map_err(|problems| invalid_settings("log filter", &problems))?;
+  let crossplay = config.crossplay;
 
   info!("Looking for burial mounds...");
   if !dry_run {
-    match server::start_daemonized(config) {
-      Ok(_) => info!("Success, daemonized"),
+    // Anything left over from a stop that no supervisor saw would swallow the next crash.
+    server::take_stop_request();
+    if log_filter.is_some() {
+      server::own_server_output();
+    }
+    // Only the process that launched the daemon gets to decide how `odin start` exits, it
+    // never returns to `main`.
+    let after_launch = move || {
+      if let Some((address, timeout)) = wait {
+        conclude_operation(wait_until_ready(address, crossplay, timeout))
+      }
+    };
+    match server::start_daemonized_then(config, after_launch) {
+      Ok(Ok(child)) => {
+        info!("Success, daemonized");
+        record_server_start();
+        thread::spawn(server::watch_crossplay_registration);
+        // Waiting, the odin that waits sends it once the server is ready.
+        if wait.is_none() {
+          NotificationEvent::Start(EventStatus::Successful).send_notification();
+        }
+        // Supervising lasts as long as the server, so the start itself is done at this point.
+        finish_operation();
+        // --no-notify only keeps the start quiet, crashes and players are still announced.
+        notifications::resume_notifications();
+        // Docker stops the container with SIGTERM, the world is saved before odin exits. Even
+        // unasked to watch the server odin waits on it for that, exiting along with it.
+        let terminate = server::register_termination();
+        if log_filter.is_some() && watch::is_asked_for() {
+          // Reads the server's output as it passes through instead of the log files.
+          thread::spawn(|| watch::watch_server(false, || false));
+        }
+        let behavior = behavior.unwrap_or(server::ServerExitBehavior::Propagate);
+        return server::supervise(child, behavior, terminate, restart, backups);
+      }
+      Ok(Err(e)) => {
+        NotificationEvent::Start(EventStatus::Failed).send_notification();
+        return Err(CommandError::failed(format!(
+          "Failed to launch the server: {}",
+          e
+        )));
+      }
       Err(e) => {
-        error!("Error: {}", e);
-        exit(1);
+        NotificationEvent::Start(EventStatus::Failed).send_notification();
+        return Err(CommandError::failed(format!("Error: {}", e)));
       }
     }
   } else {
+    // Printed rather than logged so it can be copied, or diffed as JSON, and so --show-secrets
+    // isn't redacted by the logger.
+    let plan = server::LaunchPlan::new(&config);
+    let plan = if args.is_present("show_secrets") {
+      plan
+    } else {
+      plan.masked()
+    };
+    if args.value_of("output") == Some("json") {
+      println!("{}", serde_json::to_string_pretty(&plan).unwrap());
+    } else {
+      info!("This command would have launched");
+      println!("{}", plan.shell_command());
+    }
     info!(
-      "This command would have launched\n{} -nographics -batchmode -port {} -name {} -world {} -password {} -public {}",
-      &config.command,
-      &config.port,
-      &config.name,
-      &config.world,
-      &config.password,
-      &config.public,
-    )
+      "as a daemon, logging to {}",
+      server::server_log_path().display()
+    );
+    if let Some(restart) = restart {
+      info!(
+        "and restarted it {}, warning {} minute(s) ahead",
+        restart.at,
+        restart.warning.num_minutes()
+      );
+    }
+    if let Some(backups) = backups {
+      info!(
+        "and backed up the saves every {} minute(s) while it runs",
+        (backups.every.as_secs() / 60).max(1)
+      );
+    }
+    if let Some(idle) = idle_shutdown {
+      info!(
+        "and stopped it once nobody was online for {} minute(s), exiting with {}",
+        idle.after.as_secs() / 60,
+        server::IDLE_SHUTDOWN_EXIT_CODE
+      );
+    }
+    if let Some(filter) = &log_filter {
+      info!(
+        "and passed its output on to {} rotated every {}MB, leaving {} noise pattern(s){} out",
+        server::server_log_path().display(),
+        server::server_log_max_size(),
+        filter.patterns.len(),
+        if filter.empty { " and empty lines" } else { "" }
+      );
+    }
+    if let Some((_, timeout)) = wait {
+      info!(
+        "then waited up to {}s for its log to say that players can join",
+        timeout.as_secs()
+      );
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod start_tests {
+  use super::*;
+
+  #[test]
+  fn parses_the_crossplay_flag() {
+    assert_eq!(parse_crossplay(None), Ok(true));
+    assert_eq!(parse_crossplay(Some("0")), Ok(false));
+    assert_eq!(parse_crossplay(Some("true")), Ok(true));
+    assert!(parse_crossplay(Some("xbox")).is_err());
   }
 }
@@ -0,0 +1,346 @@
+use clap::ArgMatches;
+use log::{debug, info, warn};
+use serde::Serialize;
+use sysinfo::Pid;
+
+use std::{net::SocketAddr, time::Duration};
+
+use crate::errors::{CommandError, CommandResult};
+use crate::files::{config::configured, failure::FailureFile, ValheimArguments};
+use crate::mods::bepinex;
+use crate::server::{self, CrossplayBackend, PlayerInfo, ServerInfo};
+use crate::utils::environment::fetch_var;
+
+/// How long the server gets to answer each query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The server answered queries.
+const EXIT_UP: i32 = 0;
+/// The server process is running but didn't answer, it is likely still starting.
+const EXIT_NOT_ANSWERING: i32 = 1;
+/// Nothing is running.
+const EXIT_DOWN: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ServerState {
+  /// Answering queries.
+  Running,
+  /// The process runs but doesn't answer yet.
+  Starting,
+  Stopped,
+}
+
+impl ServerState {
+  fn exit_code(self) -> i32 {
+    match self {
+      ServerState::Running => EXIT_UP,
+      ServerState::Starting => EXIT_NOT_ANSWERING,
+      ServerState::Stopped => EXIT_DOWN,
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct PlayerCount {
+  online: Option<u8>,
+  max: Option<u8>,
+}
+
+/// `null` when the configured port isn't one.
+#[derive(Debug, PartialEq, Serialize)]
+struct Ports {
+  game: Option<u16>,
+  query: Option<u16>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct BepInExStatus {
+  installed: bool,
+}
+
+/// What `--output json` prints. Every field is always there, `null` when it can't be told, so
+/// scripts don't have to check for missing keys.
+#[derive(Debug, PartialEq, Serialize)]
+struct StatusReport {
+  state: ServerState,
+  pid: Option<Pid>,
+  uptime_seconds: Option<u64>,
+  players: PlayerCount,
+  world: Option<String>,
+  version: Option<String>,
+  crossplay: bool,
+  /// Whether the server registered with PlayFab, `null` without crossplay.
+  crossplay_backend: Option<CrossplayBackend>,
+  ports: Ports,
+  bepinex: BepInExStatus,
+}
+
+impl StatusReport {
+  /// A crossplay server that doesn't answer queries is running once it logged that it is ready.
+  fn new(
+    process: Option<(Pid, Duration)>,
+    info: Option<&ServerInfo>,
+    crossplay: Option<bool>,
+    crossplay_backend: Option<CrossplayBackend>,
+    game_port: Option<u16>,
+    bepinex: bool,
+  ) -> Self {
+    let state = match (info, process, crossplay) {
+      (Some(_), _, _) => ServerState::Running,
+      (None, Some(_), Some(true)) => ServerState::Running,
+      (None, Some(_), _) => ServerState::Starting,
+      (None, None, _) => ServerState::Stopped,
+    };
+    StatusReport {
+      state,
+      pid: process.map(|(pid, _)| pid),
+      uptime_seconds: process.map(|(_, uptime)| uptime.as_secs()),
+      players: PlayerCount {
+        online: info.map(|info| info.players),
+        max: info.map(|info| info.max_players),
+      },
+      world: info.map(|info| info.map.clone()),
+      version: info.map(|info| info.version.clone()),
+      crossplay: crossplay.is_some(),
+      crossplay_backend,
+      ports: Ports {
+        game: game_port,
+        query: game_port.map(|port| server::server_ports(port)[1]),
+      },
+      bepinex: BepInExStatus { installed: bepinex },
+    }
+  }
+}
+
+/// The port from `odin configure`, or `PORT` before odin has been configured.
+fn game_port(config: Option<&ValheimArguments>) -> String {
+  server::game_port(
+    &config
+      .map(|config| config.port.clone())
+      .unwrap_or_else(|| fetch_var("PORT", "2456")),
+  )
+}
+
+/// `Some` with whether the server logged that it is ready when crossplay is on, its Steam query
+/// port may never answer.
+fn crossplay_ready(config: Option<&ValheimArguments>) -> Option<bool> {
+  config
+    .filter(|config| config.crossplay)
+    .map(|_| server::server_logged_ready())
+}
+
+fn describe_players(players: &[PlayerInfo]) -> Option<String> {
+  let names: Vec<&str> = players
+    .iter()
+    .map(|player| player.name.as_str())
+    .filter(|name| !name.is_empty())
+    .collect();
+  (!names.is_empty()).then(|| names.join(", "))
+}
+
+fn print_info(address: SocketAddr, info: &ServerInfo, ping: Duration) {
+  info!(
+    "Server is up and answering queries on {} (ping {} ms)",
+    address,
+    ping.as_millis()
+  );
+  info!("Name: {}", info.name);
+  info!("World: {}", info.map);
+  info!("Players: {}/{}", info.players, info.max_players);
+  info!("Version: {}", info.version);
+  match server::query_players(address, QUERY_TIMEOUT) {
+    Ok(players) => {
+      if let Some(names) = describe_players(&players) {
+        info!("Online: {}", names);
+      }
+    }
+    Err(e) => debug!("{} didn't answer the player query: {}", address, e),
+  }
+}
+
+fn print_json(
+  info: Option<&ServerInfo>,
+  crossplay: Option<bool>,
+  game_port: Option<u16>,
+) -> CommandResult {
+  let installed = bepinex::is_bepinex_installed().is_installed();
+  let report = StatusReport::new(
+    server::server_process(),
+    info,
+    crossplay,
+    crossplay.map(|_| server::logged_crossplay_backend()),
+    game_port,
+    installed,
+  );
+  println!("{}", serde_json::to_string_pretty(&report).unwrap());
+  exited_with(report.state.exit_code())
+}
+
+fn exited_with(code: i32) -> CommandResult {
+  match code {
+    EXIT_UP => Ok(()),
+    code => Err(CommandError::quiet(code)),
+  }
+}
+
+/// Exits with 0 when the server answers A2S queries (or, with crossplay, logged that it is ready), 1 when its process runs but it doesn't answer
+/// and 2 when it isn't running at all.
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  if args.is_present("last_failure") {
+    match FailureFile::new().load() {
+      Some(report) => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+      None => info!("No failure has been recorded"),
+    }
+    return Ok(());
+  }
+  let json = args.value_of("output") == Some("json");
+  let config = configured();
+  let crossplay = crossplay_ready(config.as_ref());
+  if crossplay.is_some() && !json {
+    info!("Crossplay backend: {}", server::logged_crossplay_backend());
+  }
+  let port = game_port(config.as_ref());
+  let parsed_port = port.trim().parse::<u16>().ok();
+  let address = match server::query_port(&port) {
+    Some(query_port) => {
+      info!(
+        "Game port {}/udp, query port {}/udp",
+        port.trim(),
+        query_port
+      );
+      SocketAddr::from(([127, 0, 0, 1], query_port))
+    }
+    None => {
+      warn!("'{}' isn't a port, unable to query the server", port);
+      if json {
+        return print_json(None, crossplay, parsed_port);
+      }
+      return exited_with(if server::is_running() {
+        EXIT_NOT_ANSWERING
+      } else {
+        EXIT_DOWN
+      });
+    }
+  };
+  match server::query_info_with_ping(address, QUERY_TIMEOUT) {
+    Ok((info, _)) if json => return print_json(Some(&info), crossplay, parsed_port),
+    Ok((info, ping)) => {
+      print_info(address, &info, ping);
+      return Ok(());
+    }
+    Err(e) => debug!("{} didn't answer the query: {}", address, e),
+  }
+  if json {
+    return print_json(None, crossplay, parsed_port);
+  }
+  if crossplay == Some(true) && server::is_running() {
+    info!(
+      "Server is running with crossplay and logged that it is ready, it doesn't answer queries on {}",
+      address
+    );
+    return Ok(());
+  }
+  if server::is_running() {
+    warn!(
+      "Server is running but didn't answer queries on {} within {}s, it may still be starting",
+      address,
+      QUERY_TIMEOUT.as_secs()
+    );
+    return exited_with(EXIT_NOT_ANSWERING);
+  }
+  info!("Server is not running");
+  exited_with(EXIT_DOWN)
+}
+
+#[cfg(test)]
+mod status_tests {
+  use super::*;
+
+  #[test]
+  fn exits_quietly_unless_up() {
+    assert_eq!(exited_with(EXIT_UP), Ok(()));
+    let down = exited_with(EXIT_DOWN).unwrap_err();
+    assert_eq!(down.code, EXIT_DOWN);
+    assert!(down.is_quiet());
+    assert!(!CommandError::failed("Stop the server first").is_quiet());
+    assert!(!CommandError::failed("").is_quiet());
+  }
+
+  #[test]
+  fn lists_only_named_players() {
+    let player = |name: &str| PlayerInfo {
+      name: name.to_string(),
+      ..PlayerInfo::default()
+    };
+    assert_eq!(describe_players(&[player(""), player("")]), None);
+    assert_eq!(
+      describe_players(&[player("Ragnar"), player(""), player("Lagertha")]),
+      Some("Ragnar, Lagertha".to_string())
+    );
+  }
+
+  #[test]
+  fn reports_unknown_fields_as_null() {
+    let stopped =
+      serde_json::to_value(StatusReport::new(None, None, None, None, None, false)).unwrap();
+    assert_eq!(
+      stopped,
+      serde_json::json!({
+        "state": "stopped",
+        "pid": null,
+        "uptime_seconds": null,
+        "players": {"online": null, "max": null},
+        "world": null,
+        "version": null,
+        "crossplay": false,
+        "crossplay_backend": null,
+        "ports": {"game": null, "query": null},
+        "bepinex": {"installed": false},
+      })
+    );
+
+    let info = ServerInfo {
+      map: "Dedicated".to_string(),
+      players: 2,
+      max_players: 10,
+      version: "0.217.22".to_string(),
+      ..ServerInfo::default()
+    };
+    let process = Some((42, Duration::from_secs(90)));
+    let running = StatusReport::new(process, Some(&info), None, None, Some(2456), true);
+    assert_eq!(running.state, ServerState::Running);
+    assert_eq!(running.players.online, Some(2));
+    assert_eq!(running.uptime_seconds, Some(90));
+    assert_eq!(
+      running.ports,
+      Ports {
+        game: Some(2456),
+        query: Some(2457)
+      }
+    );
+    assert_eq!(
+      StatusReport::new(process, None, None, None, None, false).state,
+      ServerState::Starting
+    );
+    // Crossplay servers may never answer, their log tells when they are up.
+    assert_eq!(
+      StatusReport::new(process, None, Some(false), None, None, false).state,
+      ServerState::Starting
+    );
+    let crossplay = StatusReport::new(
+      process,
+      None,
+      Some(true),
+      Some(CrossplayBackend::Failed),
+      None,
+      false,
+    );
+    assert_eq!(crossplay.state, ServerState::Running);
+    assert!(crossplay.crossplay);
+    assert_eq!(
+      serde_json::to_value(&crossplay).unwrap()["crossplay_backend"],
+      "failed"
+    );
+  }
+}
@@ -1,20 +1,48 @@
 use clap::ArgMatches;
-use log::{error, info};
+use log::info;
 
-use std::process::exit;
+use crate::{
+  constants,
+  errors::{CommandError, CommandResult},
+  notifications::enums::{event_status::EventStatus, notification_event::NotificationEvent},
+  server,
+  utils::get_working_dir,
+};
 
-use crate::{constants, server, utils::get_working_dir};
-
-pub fn invoke(args: &ArgMatches) {
-  info!("Stopping server {}", get_working_dir());
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  info!("Stopping server {}", get_working_dir().display());
   if args.is_present("dry_run") {
-    info!("This command would have run: ");
-    info!("kill -2 {}", constants::VALHEIM_EXECUTABLE_NAME)
+    let pids = server::server_pids();
+    if pids.is_empty() {
+      info!(
+        "Dry run: no {} process is running, nothing would be stopped",
+        constants::VALHEIM_EXECUTABLE_NAME
+      );
+    } else {
+      info!("This command would have run: ");
+      for pid in pids {
+        info!("kill -2 {}", pid);
+      }
+      info!(
+        "and then waited up to {} seconds for {} to exit before killing it",
+        server::stop_timeout()?.as_secs(),
+        constants::VALHEIM_EXECUTABLE_NAME
+      );
+    }
   } else {
     if !server::is_installed() {
-      error!("Failed to find server executable!");
-      exit(1);
+      return Err(CommandError::failed("Failed to find server executable!"));
+    }
+    let outcome = server::blocking_shutdown().map_err(|e| {
+      NotificationEvent::Stop(EventStatus::Failed).send_notification();
+      CommandError::failed(e)
+    })?;
+    if outcome.forced {
+      NotificationEvent::Stop(EventStatus::Failed).send_notification();
+      return Err(CommandError::failed(
+        "The server had to be killed, it didn't shutdown gracefully",
+      ));
     }
-    server::blocking_shutdown();
   }
+  Ok(())
 }
@@ -1,13 +1,22 @@
 use clap::ArgMatches;
-use log::{debug, error, info};
-
-use std::process::exit;
-
-use crate::server;
+use log::{debug, info, warn};
+
+use crate::{
+  commands::backup,
+  constants,
+  errors::{CommandError, CommandResult},
+  files::{failure::enter_phase, state::record_update_check},
+  notifications::enums::{event_status::EventStatus, notification_event::NotificationEvent},
+  server,
+  steamcmd::steamcmd_display,
+  utils::environment::fetch_var,
+};
 
 const EXIT_NO_UPDATE_AVAILABLE: i32 = 10;
 const EXIT_UPDATE_AVAILABLE: i32 = 0;
 
+pub const BACKUP_ON_UPDATE_VAR: &str = "BACKUP_ON_UPDATE";
+
 enum UpdateAction {
   Check,
   Force,
@@ -37,12 +46,12 @@ enum UpdateState {
 }
 
 impl UpdateState {
-  fn new() -> Self {
-    if server::update_is_available() {
+  fn new() -> Result<Self, String> {
+    Ok(if server::update_is_available()? {
       Self::Pending
     } else {
       Self::UpToDate
-    }
+    })
   }
 
   fn as_exit_code(&self) -> i32 {
@@ -68,15 +77,14 @@ impl ServerState {
   }
 }
 
-pub fn invoke(args: &ArgMatches) {
+pub fn invoke(args: &ArgMatches) -> CommandResult {
   info!("Checking for updates");
 
   if !server::is_installed() {
-    error!(
+    return Err(CommandError::failed(
       "Failed to find server executable. Can't update! If the server isn't installed yet then you \
-        likely need to run `odin install`."
-    );
-    exit(1);
+        likely need to run `odin install`.",
+    ));
   }
 
   let run_action = if args.is_present("dry_run") {
@@ -86,9 +94,12 @@ pub fn invoke(args: &ArgMatches) {
   };
   let check = args.is_present("check");
   let force = args.is_present("force");
+  let skip_backup_check = args.is_present("skip_backup_check");
 
+  enter_phase("checking for updates");
   let server_state = ServerState::new();
-  let update_state = UpdateState::new();
+  let update_state = UpdateState::new()?;
+  record_update_check(matches!(update_state, UpdateState::Pending));
   match update_state {
     UpdateState::Pending => info!("A server update is available!"),
     UpdateState::UpToDate => info!("No server updates found"),
@@ -96,64 +107,131 @@ pub fn invoke(args: &ArgMatches) {
 
   match UpdateAction::new(check, force) {
     UpdateAction::Check => update_check(run_action, update_state),
-    UpdateAction::Force => update_force(run_action, server_state),
-    UpdateAction::Regular => update_regular(run_action, server_state, update_state),
+    UpdateAction::Force => update_force(run_action, server_state, skip_backup_check),
+    UpdateAction::Regular => {
+      update_regular(run_action, server_state, update_state, skip_backup_check)
+    }
   }
 }
 
-fn update_check(run_action: RunAction, update_state: UpdateState) {
+fn update_check(run_action: RunAction, update_state: UpdateState) -> CommandResult {
   match (run_action, update_state) {
     (RunAction::Dry, UpdateState::Pending) => {
       info!(
         "Dry run: An update is available. This would exit with {} to indicate this.",
         update_state.as_exit_code()
-      )
+      );
+      Ok(())
     }
     (RunAction::Dry, UpdateState::UpToDate) => {
       info!(
         "Dry run: No update is available. This would exit with {} to indicate this.",
         update_state.as_exit_code()
-      )
+      );
+      Ok(())
     }
-    (_, update_state) => exit(update_state.as_exit_code()),
+    (_, UpdateState::Pending) => Ok(()),
+    (_, update_state) => Err(CommandError::quiet(update_state.as_exit_code())),
   }
 }
 
-fn update_force(run_action: RunAction, server_state: ServerState) {
+fn update_force(
+  run_action: RunAction,
+  server_state: ServerState,
+  skip_backup_check: bool,
+) -> CommandResult {
   match (run_action, server_state) {
     (RunAction::Dry, ServerState::Running) => {
-      info!("Dry run: Server would be shutdown, updated, and brought back online")
+      info!("Dry run: Server would be shutdown, updated, and brought back online");
+      log_update_steps(&ServerState::Running)
     }
     (RunAction::Dry, ServerState::Stopped) => {
-      info!("Dry run: The server is offline and would be updated")
+      info!("Dry run: The server is offline and would be updated");
+      log_update_steps(&ServerState::Stopped)
     }
     _ => {
       debug!("Force updating!");
+      backup_before_update(false, skip_backup_check)?;
       server::update_server();
     }
   }
+  Ok(())
 }
 
-fn update_regular(run_action: RunAction, server_state: ServerState, update_state: UpdateState) {
+fn update_regular(
+  run_action: RunAction,
+  server_state: ServerState,
+  update_state: UpdateState,
+  skip_backup_check: bool,
+) -> CommandResult {
   match (run_action, server_state, update_state) {
-    (RunAction::Dry, ServerState::Running, UpdateState::Pending) => {
+    (RunAction::Dry, server_state @ ServerState::Running, UpdateState::Pending) => {
       info!(
         "Dry run: An update is available and the server is ONLINE. The server would be shutdown \
           updated, and brought back online."
-      )
+      );
+      log_update_steps(&server_state)
     }
-    (RunAction::Dry, ServerState::Stopped, UpdateState::Pending) => {
+    (RunAction::Dry, server_state @ ServerState::Stopped, UpdateState::Pending) => {
       info!(
         "Dry run: An update is available and the server is OFFLINE. The server would be updated."
-      )
+      );
+      log_update_steps(&server_state)
     }
     (RunAction::Dry, _, UpdateState::UpToDate) => {
       info!("Dry run: No update is available. Nothing to do.")
     }
     (_, _, UpdateState::Pending) => {
       debug!("Updating the installation!");
+      backup_before_update(false, skip_backup_check)?;
       server::update_server()
     }
     _ => debug!("No update available, nothing to do!"),
   }
+  Ok(())
+}
+
+/// Backs up the saves with `BACKUP_ON_UPDATE=1`, the default, before steamcmd replaces the server.
+/// A failed backup aborts the update unless `--skip-backup-check` is passed.
+fn backup_before_update(dry_run: bool, skip_backup_check: bool) -> CommandResult {
+  if fetch_var(BACKUP_ON_UPDATE_VAR, "1") != "1" {
+    debug!("{} is off, updating without a backup", BACKUP_ON_UPDATE_VAR);
+    return Ok(());
+  }
+  enter_phase("backing up");
+  let build_id = server::installed_build_id().unwrap_or_else(|| "unknown".to_string());
+  match backup::backup_before_update(&build_id, dry_run) {
+    Ok(_) => Ok(()),
+    Err(message) if dry_run || skip_backup_check => {
+      warn!("{}. Updating anyway", message);
+      Ok(())
+    }
+    Err(message) => {
+      NotificationEvent::Backup(EventStatus::Failed).send_notification();
+      Err(CommandError::failed(format!(
+        "{}. The update was aborted, pass --skip-backup-check to update anyway",
+        message
+      )))
+    }
+  }
+}
+
+/// Logs what `server::update_server` would run, in order.
+fn log_update_steps(server_state: &ServerState) {
+  // A dry run only logs a backup that would fail, it never fails itself.
+  let _ = backup_before_update(true, true);
+  info!("This command would have run: ");
+  if let ServerState::Running = server_state {
+    for pid in server::server_pids() {
+      info!("kill -2 {}", pid);
+    }
+  }
+  info!(
+    "{} {}",
+    steamcmd_display(),
+    server::install_args(constants::GAME_ID, false).join(" ")
+  );
+  if let ServerState::Running = server_state {
+    info!("odin start");
+  }
 }
@@ -0,0 +1,102 @@
+use clap::ArgMatches;
+use log::{debug, info, warn};
+
+use std::{sync::atomic::Ordering, time::Duration};
+
+use crate::errors::CommandResult;
+use crate::files::state::record_player_seen;
+use crate::server::{self, LogWatcher, ServerEvent};
+use crate::utils::{environment::fetch_var, parse_bool};
+
+/// How often the server log is checked for new lines.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Players joining and leaving are notified unless this is `0`.
+const PLAYER_NOTIFICATIONS_VAR: &str = "PLAYER_NOTIFICATIONS";
+/// World saves are only notified when this is `1`, they happen every autosave.
+const WORLD_SAVE_NOTIFICATIONS_VAR: &str = "WORLD_SAVE_NOTIFICATIONS";
+
+fn is_enabled(name: &str, default: &str) -> bool {
+  let value = fetch_var(name, default);
+  parse_bool(&value).unwrap_or_else(|| {
+    warn!("{} must be 1 or 0, got '{}'", name, value);
+    false
+  })
+}
+
+/// Follows the server log until odin is told to stop, sending a notification for every player that
+/// joins or leaves and, when asked for, every world save.
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  let terminate = server::register_termination();
+  watch_server(args.is_present("dry_run"), || {
+    terminate.load(Ordering::SeqCst)
+  });
+  Ok(())
+}
+
+/// Whether the notifications are asked for by name, like the container does before it runs
+/// `odin watch`.
+pub fn is_asked_for() -> bool {
+  fetch_var(PLAYER_NOTIFICATIONS_VAR, "0") == "1"
+    || fetch_var(WORLD_SAVE_NOTIFICATIONS_VAR, "0") == "1"
+}
+
+/// Sends the notifications `odin watch` does until `should_stop` says so.
+pub fn watch_server<S: Fn() -> bool>(dry_run: bool, should_stop: S) {
+  let players = is_enabled(PLAYER_NOTIFICATIONS_VAR, "1");
+  let world_saves = is_enabled(WORLD_SAVE_NOTIFICATIONS_VAR, "0");
+  let watching: Vec<&str> = [
+    (players, "players joining and leaving"),
+    (world_saves, "world saves"),
+  ]
+  .iter()
+  .filter(|(enabled, _)| *enabled)
+  .map(|(_, description)| *description)
+  .collect();
+  if watching.is_empty() {
+    warn!(
+      "Both {} and {} are off, there is nothing to watch for",
+      PLAYER_NOTIFICATIONS_VAR, WORLD_SAVE_NOTIFICATIONS_VAR
+    );
+    return;
+  }
+  info!(
+    "Watching {} for {}{}",
+    server::server_log_path().display(),
+    watching.join(" and "),
+    if dry_run {
+      ", dry run: nothing will be sent"
+    } else {
+      ""
+    }
+  );
+  LogWatcher::for_server().watch(POLL_INTERVAL, None, should_stop, |event| {
+    let wanted = match event {
+      ServerEvent::PlayerConnected(_) | ServerEvent::PlayerDisconnected(_) => players,
+      ServerEvent::WorldSaved(_) => world_saves,
+      _ => false,
+    };
+    let notification = match event.notification_event().filter(|_| wanted) {
+      Some(notification) => notification,
+      None => {
+        debug!("{}", event);
+        return;
+      }
+    };
+    info!("{}", event);
+    if dry_run {
+      return;
+    }
+    match event {
+      ServerEvent::PlayerConnected(name) => {
+        record_player_seen(name);
+        notification.send_player_notification(name, &event.to_string());
+      }
+      ServerEvent::PlayerDisconnected(name) => {
+        notification.send_player_notification(name, &event.to_string())
+      }
+      _ => notification.send_notification_with_details(&event.to_string()),
+    }
+  });
+  info!("Stopped watching the server log");
+}
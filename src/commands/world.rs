@@ -0,0 +1,102 @@
+use clap::ArgMatches;
+use log::{error, info, warn};
+
+use crate::errors::{CommandError, CommandResult};
+use crate::files::{
+  config::config_file,
+  progress::format_bytes,
+  validation::validate_world,
+  worlds::{clear_selected_world, list_worlds, saves_dir, select_world, selected_world},
+  FileManager, ValheimArguments,
+};
+use crate::server;
+use crate::utils::environment::fetch_var;
+
+/// The world from `odin configure`, or `WORLD` before odin has been configured.
+fn configured_world() -> String {
+  serde_json::from_str::<ValheimArguments>(&config_file().read())
+    .map(|config| config.world)
+    .unwrap_or_else(|_| fetch_var("WORLD", "Dedicated"))
+}
+
+fn list() {
+  let saves = saves_dir();
+  let worlds = list_worlds(&saves);
+  if worlds.is_empty() {
+    println!("No worlds found in {}", saves.display());
+    return;
+  }
+  let active = selected_world().unwrap_or_else(configured_world);
+  for world in worlds {
+    println!(
+      "{}\t{}\t{}\t{}\t{}",
+      if world.name == active { "*" } else { " " },
+      world.name,
+      format_bytes(world.size),
+      world.modified.map_or_else(
+        || "-".to_string(),
+        |modified| modified.format("%Y-%m-%d %H:%M:%S").to_string(),
+      ),
+      world.directory.display()
+    );
+  }
+}
+
+fn use_world(args: &ArgMatches) -> CommandResult {
+  if server::is_running() {
+    return Err(CommandError::failed(
+      "Stop the server before switching worlds, the current one is still being played",
+    ));
+  }
+  let dry_run = args.is_present("dry_run");
+  if args.is_present("clear") {
+    if dry_run {
+      info!(
+        "Dry run: would go back to the configured world {}",
+        configured_world()
+      );
+      return Ok(());
+    }
+    clear_selected_world()
+      .map_err(|e| CommandError::failed(format!("Failed to clear the picked world: {}", e)))?;
+    info!(
+      "The configured world {} will be loaded on the next start",
+      configured_world()
+    );
+    return Ok(());
+  }
+  let name = args.value_of("NAME").unwrap();
+  let problems = validate_world(name);
+  if !problems.is_empty() {
+    problems.iter().for_each(|problem| error!("{}", problem));
+    return Err(CommandError::failed(format!(
+      "Unable to switch to {}",
+      name
+    )));
+  }
+  let saves = saves_dir();
+  if !list_worlds(&saves).iter().any(|world| world.name == name) {
+    warn!(
+      "There is no world named {} in {}, Valheim will generate it on the next start",
+      name,
+      saves.display()
+    );
+  }
+  if dry_run {
+    info!("Dry run: would load {} on the next start", name);
+    return Ok(());
+  }
+  select_world(name)
+    .map_err(|e| CommandError::failed(format!("Failed to switch to {}: {}", name, e)))?;
+  info!("{} will be loaded on the next start", name);
+  Ok(())
+}
+
+pub fn invoke(args: &ArgMatches) -> CommandResult {
+  if args.subcommand_matches("list").is_some() {
+    list();
+  } else if let Some(use_matches) = args.subcommand_matches("use") {
+    return use_world(use_matches);
+  }
+  Ok(())
+}
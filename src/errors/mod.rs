@@ -13,3 +13,92 @@ impl Display for VariantNotFound {
     write!(f, "VariantNotFound: {}", &self.v)
   }
 }
+
+/// Why a command failed, with the code odin exits with for it.
+#[derive(Debug, PartialEq)]
+pub struct CommandError {
+  pub(crate) code: i32,
+  pub(crate) message: String,
+  /// Exit without logging or recording a failure.
+  pub(crate) quiet: bool,
+}
+
+impl CommandError {
+  pub fn new(code: i32, message: impl Into<String>) -> Self {
+    CommandError {
+      code,
+      message: message.into(),
+      quiet: false,
+    }
+  }
+
+  /// A failure exiting with `1`.
+  pub fn failed(message: impl Into<String>) -> Self {
+    Self::new(1, message)
+  }
+
+  /// A failure listing each of `problems` under `heading`.
+  pub fn with_problems(heading: &str, problems: &[String]) -> Self {
+    let list: String = problems
+      .iter()
+      .map(|problem| format!("\n  - {}", problem))
+      .collect();
+    Self::failed(format!("{}{}", heading, list))
+  }
+
+  /// Exits with `code` without logging or recording a failure, for commands like `odin status`
+  /// whose exit code is the answer.
+  pub fn quiet(code: i32) -> Self {
+    CommandError {
+      quiet: true,
+      ..Self::new(code, "")
+    }
+  }
+
+  pub fn is_quiet(&self) -> bool {
+    self.quiet
+  }
+}
+
+impl From<String> for CommandError {
+  fn from(message: String) -> Self {
+    Self::failed(message)
+  }
+}
+
+impl From<VariantNotFound> for CommandError {
+  fn from(e: VariantNotFound) -> Self {
+    Self::failed(e.to_string())
+  }
+}
+
+impl error::Error for CommandError {}
+
+impl Display for CommandError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} (exit code {})", &self.message, self.code)
+  }
+}
+
+/// What a command's `invoke` returns, `main` exits with the error's code.
+pub type CommandResult = Result<(), CommandError>;
+
+#[cfg(test)]
+mod errors_tests {
+  use super::*;
+
+  #[test]
+  fn lists_problems_under_the_heading() {
+    let error = CommandError::with_problems(
+      "The save settings are invalid:",
+      &["a".to_string(), "b".to_string()],
+    );
+    assert_eq!(
+      error.message,
+      "The save settings are invalid:\n  - a\n  - b"
+    );
+    assert_eq!(error.code, 1);
+    assert!(!error.is_quiet());
+    assert!(CommandError::quiet(3).is_quiet());
+  }
+}
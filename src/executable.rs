@@ -1,12 +1,14 @@
 use log::{error, info};
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
-use std::process::{exit, Command, ExitStatus};
+use std::process::{exit, Command, ExitStatus, Stdio};
+use std::thread;
 
 pub fn find_command(executable: &str) -> Option<Command> {
   let script_file = Path::new(executable);
   if script_file.exists() {
     info!("Executing: {} .....", executable.to_string());
-    Option::from(Command::new(executable.to_string()))
+    Option::from(Command::new(executable))
   } else {
     match which::which(executable) {
       Ok(executable_path) => Option::from(Command::new(executable_path)),
@@ -28,14 +30,30 @@ pub fn create_execution(executable: &str) -> Command {
   }
 }
 
-pub fn execute_mut(command: &mut Command) -> std::io::Result<ExitStatus> {
-  match command.spawn() {
-    Ok(mut subprocess) => subprocess.wait(),
-    _ => {
-      error!("Failed to run process!");
-      exit(1)
-    }
+fn log_lines(output: impl Read, prefix: &str) {
+  for line in BufReader::new(output).lines().map_while(Result::ok) {
+    info!("[{}] {}", prefix, line);
+  }
+}
+
+/// Runs `command` to completion, logging what it prints to stdout and stderr at info level as it
+/// goes, each line prefixed with `prefix`.
+pub fn execute_logged(command: &mut Command, prefix: &str) -> std::io::Result<ExitStatus> {
+  let mut subprocess = command
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()?;
+  let stderr = subprocess.stderr.take().map(|stderr| {
+    let prefix = prefix.to_string();
+    thread::spawn(move || log_lines(stderr, &prefix))
+  });
+  if let Some(stdout) = subprocess.stdout.take() {
+    log_lines(stdout, prefix);
+  }
+  if let Some(stderr) = stderr {
+    let _ = stderr.join();
   }
+  subprocess.wait()
 }
 
 pub fn handle_exit_status(result: std::io::Result<ExitStatus>, success_message: String) {
@@ -0,0 +1,876 @@
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use std::{
+  fmt,
+  fs::{self, File},
+  io::{self, Read, Write},
+  path::{Component, Path, PathBuf},
+  str::FromStr,
+};
+
+use crate::errors::VariantNotFound;
+use crate::files::compression::ParallelGzEncoder;
+use crate::files::encryption::is_encrypted;
+use crate::files::filter::PathFilter;
+use crate::files::metadata::{BackupMetadata, METADATA_FILE_NAME};
+use crate::files::progress::{Progress, ProgressReader};
+
+pub const BACKUP_COMPRESSION_VAR: &str = "BACKUP_COMPRESSION";
+pub const BACKUP_COMPRESSION_LEVEL_VAR: &str = "BACKUP_COMPRESSION_LEVEL";
+
+/// The saves are archived under this directory so restores know what to unpack.
+pub const ARCHIVE_ROOT: &str = "saves";
+
+/// Appended to an archive's name for the file holding its SHA-256.
+pub const CHECKSUM_EXTENSION: &str = ".sha256";
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const ZIP_MAGIC: &[u8] = &[0x50, 0x4b, 0x03, 0x04];
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum ArchiveFormat {
+  Tar,
+  /// What backups have always been written as.
+  #[default]
+  TarGz,
+  Zip,
+  Zstd,
+}
+
+impl FromStr for ArchiveFormat {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> Result<ArchiveFormat, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "tar" => Ok(ArchiveFormat::Tar),
+      "tar.gz" | "tgz" | "gzip" => Ok(ArchiveFormat::TarGz),
+      "zip" => Ok(ArchiveFormat::Zip),
+      "zstd" | "zst" | "tar.zst" => Ok(ArchiveFormat::Zstd),
+      _ => Err(VariantNotFound {
+        v: format!(
+          "Unknown backup format '{}', expected tar, tar.gz, zip or zstd",
+          s
+        ),
+      }),
+    }
+  }
+}
+
+impl fmt::Display for ArchiveFormat {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      ArchiveFormat::Tar => "tar",
+      ArchiveFormat::TarGz => "tar.gz",
+      ArchiveFormat::Zip => "zip",
+      ArchiveFormat::Zstd => "zstd",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+impl ArchiveFormat {
+  pub const ALL: [ArchiveFormat; 4] = [
+    ArchiveFormat::Tar,
+    ArchiveFormat::TarGz,
+    ArchiveFormat::Zip,
+    ArchiveFormat::Zstd,
+  ];
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ArchiveFormat::Tar => ".tar",
+      ArchiveFormat::TarGz => ".tar.gz",
+      ArchiveFormat::Zip => ".zip",
+      ArchiveFormat::Zstd => ".tar.zst",
+    }
+  }
+
+  /// The range `--level` accepts, `None` when the format isn't compressed.
+  pub fn levels(&self) -> Option<(i32, i32)> {
+    match self {
+      ArchiveFormat::Tar => None,
+      ArchiveFormat::TarGz | ArchiveFormat::Zip => Some((0, 9)),
+      ArchiveFormat::Zstd => Some((1, 22)),
+    }
+  }
+
+  pub fn validate_level(&self, level: i32) -> Result<(), String> {
+    match self.levels() {
+      Some((min, max)) if level >= min && level <= max => Ok(()),
+      Some((min, max)) => Err(format!(
+        "Compression level {} is out of range for {}, expected {} to {}",
+        level, self, min, max
+      )),
+      None => Err(format!("{} backups are not compressed", self)),
+    }
+  }
+
+  pub fn from_extension(path: &Path) -> Option<Self> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    // Longest first so `.tar.gz` isn't taken for `.tar`.
+    let mut formats = Self::ALL.to_vec();
+    formats.sort_by_key(|format| std::cmp::Reverse(format.extension().len()));
+    formats
+      .into_iter()
+      .find(|format| name.ends_with(format.extension()))
+      .or_else(|| name.ends_with(".tgz").then_some(ArchiveFormat::TarGz))
+  }
+
+  pub fn from_magic(header: &[u8]) -> Option<Self> {
+    if header.starts_with(GZIP_MAGIC) {
+      Some(ArchiveFormat::TarGz)
+    } else if header.starts_with(ZSTD_MAGIC) {
+      Some(ArchiveFormat::Zstd)
+    } else if header.starts_with(ZIP_MAGIC) {
+      Some(ArchiveFormat::Zip)
+    } else if header.len() >= TAR_MAGIC_OFFSET + TAR_MAGIC.len()
+      && &header[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()] == TAR_MAGIC
+    {
+      Some(ArchiveFormat::Tar)
+    } else {
+      None
+    }
+  }
+
+  /// Reads the format from the file's magic bytes, falling back on its extension.
+  pub fn detect(path: &Path) -> io::Result<Self> {
+    let mut header = Vec::new();
+    File::open(path)?
+      .take((TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64)
+      .read_to_end(&mut header)?;
+    Self::from_magic(&header)
+      .or_else(|| Self::from_extension(path))
+      .ok_or_else(|| {
+        io::Error::new(
+          io::ErrorKind::InvalidData,
+          format!("{} is not a backup odin can read", path.display()),
+        )
+      })
+  }
+}
+
+/// A directory archived under `root`, whatever `filter` lets through of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveSource {
+  pub(crate) root: PathBuf,
+  pub(crate) directory: PathBuf,
+  pub(crate) filter: PathFilter,
+}
+
+impl ArchiveSource {
+  /// Just the saves, what every backup holds unless told otherwise.
+  pub fn saves(saves: &Path, filter: &PathFilter) -> Self {
+    ArchiveSource {
+      root: PathBuf::from(ARCHIVE_ROOT),
+      directory: saves.to_path_buf(),
+      filter: filter.clone(),
+    }
+  }
+}
+
+/// Where the entries archived under `root` are unpacked to, whatever `filter` lets through of
+/// them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestoreTarget {
+  pub(crate) root: PathBuf,
+  pub(crate) directory: PathBuf,
+  pub(crate) filter: PathFilter,
+}
+
+impl RestoreTarget {
+  pub fn saves(output: &Path) -> Self {
+    RestoreTarget {
+      root: PathBuf::from(ARCHIVE_ROOT),
+      directory: output.to_path_buf(),
+      filter: PathFilter::default(),
+    }
+  }
+}
+
+/// A file or directory in an archive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveEntry {
+  pub(crate) path: PathBuf,
+  pub(crate) size: u64,
+  pub(crate) is_dir: bool,
+}
+
+fn append_tar<W: Write>(
+  writer: W,
+  sources: &[ArchiveSource],
+  metadata: Option<&BackupMetadata>,
+  progress: &mut Progress,
+) -> io::Result<W> {
+  let mut tar = tar::Builder::new(writer);
+  // First, so it can be read without going through the whole archive.
+  if let Some(metadata) = metadata {
+    let content = metadata.to_json()?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+      std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs()),
+    );
+    tar.append_data(&mut header, METADATA_FILE_NAME, content.as_slice())?;
+  }
+  for source in sources {
+    tar.append_dir(&source.root, &source.directory)?;
+    tar_dir(&mut tar, source, Path::new(""), progress)?;
+  }
+  tar.into_inner()
+}
+
+fn tar_dir<W: Write>(
+  tar: &mut tar::Builder<W>,
+  source: &ArchiveSource,
+  relative: &Path,
+  progress: &mut Progress,
+) -> io::Result<()> {
+  for entry in fs::read_dir(source.directory.join(relative))? {
+    let entry = entry?;
+    let relative = relative.join(entry.file_name());
+    let name = source.root.join(&relative);
+    if entry.path().is_dir() {
+      if source.filter.descends_into(&relative) {
+        tar.append_dir(&name, entry.path())?;
+        tar_dir(tar, source, &relative, progress)?;
+      }
+    } else if source.filter.includes(&relative) {
+      let file = File::open(entry.path())?;
+      let mut header = tar::Header::new_gnu();
+      header.set_metadata(&file.metadata()?);
+      tar.append_data(&mut header, &name, ProgressReader::new(file, progress))?;
+    }
+  }
+  Ok(())
+}
+
+fn zip_dir<W: Write + io::Seek>(
+  zip: &mut ZipWriter<W>,
+  source: &ArchiveSource,
+  relative: &Path,
+  options: FileOptions,
+  progress: &mut Progress,
+) -> io::Result<()> {
+  let mut entries: Vec<_> =
+    fs::read_dir(source.directory.join(relative))?.collect::<io::Result<_>>()?;
+  entries.sort_by_key(|entry| entry.file_name());
+  for entry in entries {
+    let relative = relative.join(entry.file_name());
+    let name = source.root.join(&relative).to_string_lossy().to_string();
+    if entry.file_type()?.is_dir() {
+      if source.filter.descends_into(&relative) {
+        zip.add_directory(name.as_str(), options)?;
+        zip_dir(zip, source, &relative, options, progress)?;
+      }
+    } else if source.filter.includes(&relative) {
+      zip.start_file(name.as_str(), options)?;
+      io::copy(
+        &mut ProgressReader::new(File::open(entry.path())?, progress),
+        zip,
+      )?;
+    }
+  }
+  Ok(())
+}
+
+/// Archives every source into `output`, with `metadata` as `odin-backup.json` at the root.
+/// `level` is the format's own compression level, `tar.gz` and `tar.zst` are compressed on
+/// `threads` threads, and every byte read from the sources counts towards `progress`.
+pub fn create_archive(
+  sources: &[ArchiveSource],
+  output: &Path,
+  format: ArchiveFormat,
+  level: Option<i32>,
+  threads: u32,
+  metadata: Option<&BackupMetadata>,
+  progress: &mut Progress,
+) -> io::Result<()> {
+  for source in sources {
+    debug!(
+      "Archiving {} under {} as {}",
+      source.directory.display(),
+      source.root.display(),
+      format
+    );
+  }
+  let file = File::create(output)?;
+  match format {
+    ArchiveFormat::Tar => append_tar(file, sources, metadata, progress)?.flush(),
+    ArchiveFormat::TarGz => {
+      let compression = level.map_or(Compression::default(), |level| {
+        Compression::new(level as u32)
+      });
+      if threads > 1 {
+        append_tar(
+          ParallelGzEncoder::new(file, compression, threads),
+          sources,
+          metadata,
+          progress,
+        )?
+        .finish()
+        .map(|_| ())
+      } else {
+        append_tar(
+          GzEncoder::new(file, compression),
+          sources,
+          metadata,
+          progress,
+        )?
+        .finish()
+        .map(|_| ())
+      }
+    }
+    ArchiveFormat::Zstd => {
+      let mut encoder = zstd::Encoder::new(file, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))?;
+      if threads > 1 {
+        encoder.multithread(threads)?;
+      }
+      append_tar(encoder, sources, metadata, progress)?
+        .finish()
+        .map(|_| ())
+    }
+    ArchiveFormat::Zip => {
+      let mut zip = ZipWriter::new(file);
+      let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .compression_level(level);
+      if let Some(metadata) = metadata {
+        zip.start_file(METADATA_FILE_NAME, options)?;
+        zip.write_all(&metadata.to_json()?)?;
+      }
+      for source in sources {
+        zip.add_directory(source.root.to_string_lossy(), options)?;
+        zip_dir(&mut zip, source, Path::new(""), options, progress)?;
+      }
+      zip.finish()?;
+      Ok(())
+    }
+  }
+}
+
+/// Where an archived entry lands, `None` for the roots themselves, entries under none of the
+/// targets' roots or anything that would escape its target.
+/// Where `entry` is unpacked to. Filtered targets leave out directories, the ones holding a
+/// restored file are created for it.
+fn restore_path(targets: &[RestoreTarget], entry: &Path, is_dir: bool) -> Option<PathBuf> {
+  let (target, relative) = targets
+    .iter()
+    .find_map(|target| Some((target, entry.strip_prefix(&target.root).ok()?)))?;
+  if relative.as_os_str().is_empty()
+    || relative
+      .components()
+      .any(|component| !matches!(component, Component::Normal(_)))
+  {
+    return None;
+  }
+  let restored = if is_dir {
+    target.filter.is_empty()
+  } else {
+    target.filter.includes(relative)
+  };
+  restored.then(|| target.directory.join(relative))
+}
+
+fn extract_tar<R: Read>(reader: R, targets: &[RestoreTarget]) -> io::Result<usize> {
+  let mut archive = tar::Archive::new(reader);
+  let mut restored = 0;
+  for entry in archive.entries()? {
+    let mut entry = entry?;
+    let is_dir = entry.header().entry_type().is_dir();
+    let destination = match restore_path(targets, &entry.path()?, is_dir) {
+      Some(destination) => destination,
+      None => continue,
+    };
+    if let Some(parent) = destination.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    entry.unpack(&destination)?;
+    restored += 1;
+  }
+  Ok(restored)
+}
+
+fn extract_zip(file: File, targets: &[RestoreTarget]) -> io::Result<usize> {
+  let mut archive = ZipArchive::new(file)?;
+  let mut restored = 0;
+  for index in 0..archive.len() {
+    let mut entry = archive.by_index(index)?;
+    let destination = match entry
+      .enclosed_name()
+      .and_then(|name| restore_path(targets, name, entry.is_dir()))
+    {
+      Some(destination) => destination,
+      None => continue,
+    };
+    if entry.is_dir() {
+      fs::create_dir_all(&destination)?;
+    } else {
+      if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+      }
+      io::copy(&mut entry, &mut File::create(&destination)?)?;
+    }
+    restored += 1;
+  }
+  Ok(restored)
+}
+
+/// Unpacks what a backup made by `create_archive` holds under the targets' roots, returning how
+/// many entries it wrote.
+pub fn extract_archive(archive: &Path, targets: &[RestoreTarget]) -> io::Result<usize> {
+  let format = ArchiveFormat::detect(archive)?;
+  debug!("Restoring {} as {}", archive.display(), format);
+  for target in targets {
+    fs::create_dir_all(&target.directory)?;
+  }
+  let file = File::open(archive)?;
+  match format {
+    ArchiveFormat::Tar => extract_tar(file, targets),
+    ArchiveFormat::TarGz => extract_tar(MultiGzDecoder::new(file), targets),
+    ArchiveFormat::Zstd => extract_tar(zstd::Decoder::new(file)?, targets),
+    ArchiveFormat::Zip => extract_zip(file, targets),
+  }
+}
+
+fn read_tar_metadata<R: Read>(reader: R) -> io::Result<Option<BackupMetadata>> {
+  for entry in tar::Archive::new(reader).entries()? {
+    let mut entry = entry?;
+    if entry.path()? == Path::new(METADATA_FILE_NAME) {
+      let mut content = Vec::new();
+      entry.read_to_end(&mut content)?;
+      return BackupMetadata::from_json(&content).map(Some);
+    }
+  }
+  Ok(None)
+}
+
+/// The `odin-backup.json` of an unencrypted archive, `None` for backups made before odin wrote
+/// one.
+pub fn read_metadata(archive: &Path) -> io::Result<Option<BackupMetadata>> {
+  let file = File::open(archive)?;
+  match ArchiveFormat::detect(archive)? {
+    ArchiveFormat::Tar => read_tar_metadata(file),
+    ArchiveFormat::TarGz => read_tar_metadata(MultiGzDecoder::new(file)),
+    ArchiveFormat::Zstd => read_tar_metadata(zstd::Decoder::new(file)?),
+    ArchiveFormat::Zip => {
+      let mut zip = ZipArchive::new(file)?;
+      let mut entry = match zip.by_name(METADATA_FILE_NAME) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+      };
+      let mut content = Vec::new();
+      entry.read_to_end(&mut content)?;
+      BackupMetadata::from_json(&content).map(Some)
+    }
+  }
+}
+
+fn list_tar<R: Read>(reader: R) -> io::Result<Vec<ArchiveEntry>> {
+  tar::Archive::new(reader)
+    .entries()?
+    .map(|entry| {
+      let entry = entry?;
+      Ok(ArchiveEntry {
+        path: entry.path()?.to_path_buf(),
+        size: entry.size(),
+        is_dir: entry.header().entry_type().is_dir(),
+      })
+    })
+    .collect()
+}
+
+/// Every entry of an unencrypted archive, in the order they were archived.
+pub fn list_archive(archive: &Path) -> io::Result<Vec<ArchiveEntry>> {
+  let file = File::open(archive)?;
+  match ArchiveFormat::detect(archive)? {
+    ArchiveFormat::Tar => list_tar(file),
+    ArchiveFormat::TarGz => list_tar(MultiGzDecoder::new(file)),
+    ArchiveFormat::Zstd => list_tar(zstd::Decoder::new(file)?),
+    ArchiveFormat::Zip => {
+      let mut zip = ZipArchive::new(file)?;
+      (0..zip.len())
+        .map(|index| {
+          let entry = zip.by_index(index)?;
+          Ok(ArchiveEntry {
+            path: PathBuf::from(entry.name()),
+            size: entry.size(),
+            is_dir: entry.is_dir(),
+          })
+        })
+        .collect()
+    }
+  }
+}
+
+pub fn checksum_path(archive: &Path) -> PathBuf {
+  let mut name = archive.as_os_str().to_owned();
+  name.push(CHECKSUM_EXTENSION);
+  PathBuf::from(name)
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+  let mut hasher = Sha256::new();
+  io::copy(&mut File::open(path)?, &mut hasher)?;
+  Ok(hex::encode(hasher.finalize()))
+}
+
+/// Writes `<archive>.sha256` in the format `sha256sum -c` understands and returns the hash.
+pub fn write_checksum(archive: &Path) -> io::Result<String> {
+  let hash = hash_file(archive)?;
+  let file_name = archive
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default();
+  fs::write(checksum_path(archive), format!("{}  {}\n", hash, file_name))?;
+  Ok(hash)
+}
+
+/// The hash recorded for the archive, `None` when it has no manifest.
+pub fn read_checksum(archive: &Path) -> io::Result<Option<String>> {
+  match fs::read_to_string(checksum_path(archive)) {
+    Ok(content) => Ok(content.split_whitespace().next().map(str::to_lowercase)),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e),
+  }
+}
+
+/// Reads every entry to the end, which catches truncated archives and bad compressed data.
+fn read_all_entries(archive: &Path) -> io::Result<usize> {
+  let file = File::open(archive)?;
+  let read_tar = |reader: Box<dyn Read>| -> io::Result<usize> {
+    let mut count = 0;
+    for entry in tar::Archive::new(reader).entries()? {
+      io::copy(&mut entry?, &mut io::sink())?;
+      count += 1;
+    }
+    Ok(count)
+  };
+  match ArchiveFormat::detect(archive)? {
+    ArchiveFormat::Tar => read_tar(Box::new(file)),
+    ArchiveFormat::TarGz => read_tar(Box::new(MultiGzDecoder::new(file))),
+    ArchiveFormat::Zstd => read_tar(Box::new(zstd::Decoder::new(file)?)),
+    ArchiveFormat::Zip => {
+      let mut zip = ZipArchive::new(file)?;
+      for index in 0..zip.len() {
+        io::copy(&mut zip.by_index(index)?, &mut io::sink())?;
+      }
+      Ok(zip.len())
+    }
+  }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+  /// The hash matches the manifest and every entry could be read.
+  Verified,
+  /// There is no manifest, but every entry could be read.
+  Unverified,
+}
+
+/// Checks the archive against its manifest and reads it through.
+pub fn verify_archive(archive: &Path) -> Result<Verification, String> {
+  let expected = read_checksum(archive).map_err(|e| {
+    format!(
+      "Unable to read the manifest of {}: {}",
+      archive.display(),
+      e
+    )
+  })?;
+  if let Some(expected) = &expected {
+    let actual =
+      hash_file(archive).map_err(|e| format!("Unable to hash {}: {}", archive.display(), e))?;
+    if &actual != expected {
+      return Err(format!(
+        "{} does not match its manifest, expected {} but got {}",
+        archive.display(),
+        expected,
+        actual
+      ));
+    }
+  }
+  // Reading the entries would need the key, the manifest is all there is to check.
+  if is_encrypted(archive) {
+    return Ok(match expected {
+      Some(_) => Verification::Verified,
+      None => Verification::Unverified,
+    });
+  }
+  let entries =
+    read_all_entries(archive).map_err(|e| format!("{} is damaged: {}", archive.display(), e))?;
+  if entries == 0 {
+    return Err(format!("{} is empty", archive.display()));
+  }
+  Ok(match expected {
+    Some(_) => Verification::Verified,
+    None => Verification::Unverified,
+  })
+}
+
+#[cfg(test)]
+mod archive_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+
+  #[test]
+  fn parses_formats() {
+    assert_eq!(
+      ArchiveFormat::from_str("tar.gz").unwrap(),
+      ArchiveFormat::TarGz
+    );
+    assert_eq!(
+      ArchiveFormat::from_str("ZSTD").unwrap(),
+      ArchiveFormat::Zstd
+    );
+    assert_eq!(
+      ArchiveFormat::from_str("gzip").unwrap(),
+      ArchiveFormat::TarGz
+    );
+    assert!(ArchiveFormat::from_str("rar").is_err());
+    assert_eq!(ArchiveFormat::default(), ArchiveFormat::TarGz);
+  }
+
+  #[test]
+  fn validates_levels() {
+    assert!(ArchiveFormat::Zstd.validate_level(3).is_ok());
+    assert!(ArchiveFormat::Zstd.validate_level(23).is_err());
+    assert!(ArchiveFormat::TarGz.validate_level(10).is_err());
+    assert!(ArchiveFormat::Tar.validate_level(1).is_err());
+  }
+
+  #[test]
+  fn detects_extensions() {
+    let format = |name: &str| ArchiveFormat::from_extension(Path::new(name));
+    assert_eq!(format("a-backup.tar.gz"), Some(ArchiveFormat::TarGz));
+    assert_eq!(format("a-backup.tar"), Some(ArchiveFormat::Tar));
+    assert_eq!(format("a-backup.tar.zst"), Some(ArchiveFormat::Zstd));
+    assert_eq!(format("a-backup.zip"), Some(ArchiveFormat::Zip));
+    assert_eq!(format("a-backup.txt"), None);
+  }
+
+  #[test]
+  fn round_trips_every_format() {
+    let temp = test_dir("archive");
+    let root = temp.path();
+    let input = root.join("input");
+    fs::create_dir_all(input.join("worlds")).unwrap();
+    fs::write(input.join("worlds/Dedicated.db"), b"world data").unwrap();
+    fs::write(input.join("adminlist.txt"), b"admins").unwrap();
+    let metadata = BackupMetadata {
+      world: "Dedicated".to_string(),
+      valheim_build_id: Some("6315977".to_string()),
+      ..BackupMetadata::default()
+    };
+
+    for format in ArchiveFormat::ALL.iter() {
+      // Named without an extension so the magic bytes have to be read.
+      let archive = root.join(format!("backup-{}", format));
+      let level = format.levels().map(|(min, _)| min);
+      create_archive(
+        &[ArchiveSource::saves(&input, &PathFilter::default())],
+        &archive,
+        *format,
+        level,
+        1,
+        Some(&metadata),
+        &mut Progress::new(0, true),
+      )
+      .unwrap();
+      assert_eq!(ArchiveFormat::detect(&archive).unwrap(), *format);
+      assert_eq!(read_metadata(&archive).unwrap(), Some(metadata.clone()));
+
+      let output = root.join(format!("restored-{}", format));
+      assert!(extract_archive(&archive, &[RestoreTarget::saves(&output)]).unwrap() >= 2);
+      assert_eq!(
+        fs::read(output.join("worlds/Dedicated.db")).unwrap(),
+        b"world data"
+      );
+      assert_eq!(fs::read(output.join("adminlist.txt")).unwrap(), b"admins");
+      assert!(!output.join(METADATA_FILE_NAME).exists());
+    }
+  }
+
+  #[test]
+  fn verifies_against_the_manifest() {
+    let temp = test_dir("archive");
+    let root = temp.path();
+    let input = root.join("input");
+    fs::create_dir_all(&input).unwrap();
+    fs::write(input.join("Dedicated.db"), vec![7; 64 * 1024]).unwrap();
+    let archive = root.join("20210301-120000-backup.tar.gz");
+    create_archive(
+      &[ArchiveSource::saves(&input, &PathFilter::default())],
+      &archive,
+      ArchiveFormat::TarGz,
+      None,
+      1,
+      None,
+      &mut Progress::new(0, true),
+    )
+    .unwrap();
+
+    assert_eq!(read_metadata(&archive).unwrap(), None);
+    assert_eq!(verify_archive(&archive), Ok(Verification::Unverified));
+    let hash = write_checksum(&archive).unwrap();
+    assert!(fs::read_to_string(checksum_path(&archive))
+      .unwrap()
+      .starts_with(&format!("{}  20210301-120000-backup.tar.gz", hash)));
+    assert_eq!(verify_archive(&archive), Ok(Verification::Verified));
+
+    // A backup cut short by a full disk.
+    let content = fs::read(&archive).unwrap();
+    fs::write(&archive, &content[..content.len() / 2]).unwrap();
+    assert!(verify_archive(&archive)
+      .unwrap_err()
+      .contains("does not match"));
+    fs::remove_file(checksum_path(&archive)).unwrap();
+    assert!(verify_archive(&archive).unwrap_err().contains("damaged"));
+  }
+
+  #[test]
+  fn restores_archives_compressed_on_several_threads() {
+    let temp = test_dir("archive");
+    let root = temp.path();
+    let input = root.join("input");
+    fs::create_dir_all(input.join("worlds_local")).unwrap();
+    // Big enough for several gzip members.
+    let world: Vec<u8> = (0..10 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    fs::write(input.join("worlds_local/Dedicated.db"), &world).unwrap();
+
+    for format in [ArchiveFormat::TarGz, ArchiveFormat::Zstd].iter() {
+      let archive = root.join(format!("backup{}", format.extension()));
+      create_archive(
+        &[ArchiveSource::saves(&input, &PathFilter::default())],
+        &archive,
+        *format,
+        format.levels().map(|(min, _)| min),
+        4,
+        Some(&BackupMetadata::default()),
+        &mut Progress::new(0, true),
+      )
+      .unwrap();
+      write_checksum(&archive).unwrap();
+      assert_eq!(verify_archive(&archive), Ok(Verification::Verified));
+      assert_eq!(
+        read_metadata(&archive).unwrap(),
+        Some(BackupMetadata::default())
+      );
+
+      let output = root.join(format!("restored-{}", format));
+      extract_archive(&archive, &[RestoreTarget::saves(&output)]).unwrap();
+      assert!(fs::read(output.join("worlds_local/Dedicated.db")).unwrap() == world);
+    }
+  }
+
+  #[test]
+  fn ignores_entries_outside_the_archive_root() {
+    let output = &[RestoreTarget::saves(Path::new("/restore"))];
+    assert_eq!(
+      restore_path(output, Path::new("saves/worlds/a.db"), false),
+      Some(PathBuf::from("/restore/worlds/a.db"))
+    );
+    assert_eq!(restore_path(output, Path::new("saves"), false), None);
+    assert_eq!(
+      restore_path(output, Path::new("saves/../etc/passwd"), false),
+      None
+    );
+    assert_eq!(restore_path(output, Path::new("other/a.db"), false), None);
+  }
+
+  #[test]
+  fn restores_each_root_to_its_target() {
+    let temp = test_dir("archive");
+    let root = temp.path();
+    let (saves, plugins) = (root.join("saves"), root.join("plugins"));
+    fs::create_dir_all(saves.join("worlds_local")).unwrap();
+    fs::create_dir_all(&plugins).unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.db"), b"world").unwrap();
+    fs::write(plugins.join("Mod.dll"), b"mod").unwrap();
+    let archive = root.join("backup.zip");
+    create_archive(
+      &[
+        ArchiveSource::saves(&saves, &PathFilter::default()),
+        ArchiveSource {
+          root: PathBuf::from("mods/BepInEx/plugins"),
+          directory: plugins,
+          filter: PathFilter::default(),
+        },
+      ],
+      &archive,
+      ArchiveFormat::Zip,
+      None,
+      1,
+      None,
+      &mut Progress::new(0, true),
+    )
+    .unwrap();
+
+    let (restored_saves, working_dir) = (root.join("restored"), root.join("server"));
+    extract_archive(&archive, &[RestoreTarget::saves(&restored_saves)]).unwrap();
+    assert!(restored_saves.join("worlds_local/Dedicated.db").exists());
+    assert!(!working_dir.exists());
+    extract_archive(
+      &archive,
+      &[RestoreTarget {
+        root: PathBuf::from("mods"),
+        directory: working_dir.clone(),
+        filter: PathFilter::default(),
+      }],
+    )
+    .unwrap();
+    assert_eq!(
+      fs::read(working_dir.join("BepInEx/plugins/Mod.dll")).unwrap(),
+      b"mod"
+    );
+  }
+
+  #[test]
+  fn restores_only_what_the_filter_lets_through() {
+    let temp = test_dir("archive");
+    let root = temp.path();
+    let saves = root.join("saves");
+    fs::create_dir_all(saves.join("worlds_local")).unwrap();
+    fs::create_dir_all(saves.join("characters")).unwrap();
+    for name in ["Broken.db", "Broken.fwl", "Fine.db", "Fine.fwl"].iter() {
+      fs::write(saves.join("worlds_local").join(name), name.as_bytes()).unwrap();
+    }
+    let archive = root.join("backup.tar.gz");
+    create_archive(
+      &[ArchiveSource::saves(&saves, &PathFilter::default())],
+      &archive,
+      ArchiveFormat::TarGz,
+      None,
+      1,
+      None,
+      &mut Progress::new(0, true),
+    )
+    .unwrap();
+
+    let entries = list_archive(&archive).unwrap();
+    assert!(entries.contains(&ArchiveEntry {
+      path: PathBuf::from("saves/worlds_local/Fine.db"),
+      size: 7,
+      is_dir: false,
+    }));
+    assert!(entries
+      .iter()
+      .any(|entry| entry.is_dir && entry.path == Path::new("saves/characters")));
+
+    let output = root.join("restored");
+    let mut target = RestoreTarget::saves(&output);
+    target.filter = PathFilter::new("Broken.*", "");
+    assert_eq!(extract_archive(&archive, &[target]).unwrap(), 2);
+    assert!(output.join("worlds_local/Broken.fwl").exists());
+    assert!(!output.join("worlds_local/Fine.db").exists());
+    assert!(!output.join("characters").exists());
+  }
+}
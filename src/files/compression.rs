@@ -0,0 +1,164 @@
+use flate2::{write::GzEncoder, Compression};
+use log::{debug, warn};
+
+use std::{
+  io::{self, Write},
+  mem, thread,
+};
+
+pub const BACKUP_THREADS_VAR: &str = "BACKUP_THREADS";
+pub const BACKUP_NICE_VAR: &str = "BACKUP_NICE";
+pub const DEFAULT_THREADS: u32 = 2;
+
+/// How much a `tar.gz` worker compresses at a time, each block becoming its own gzip member.
+const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+/// The niceness `BACKUP_NICE` runs backups at.
+const BACKUP_NICENESS: libc::c_int = 10;
+
+pub fn parse_threads(value: &str) -> Result<u32, String> {
+  match value.trim().parse::<u32>() {
+    Ok(threads) if threads > 0 => Ok(threads),
+    _ => Err(format!(
+      "Invalid backup threads '{}', expected a number above 0",
+      value
+    )),
+  }
+}
+
+/// Lowers the priority of the calling thread and every thread it starts afterwards, so a backup
+/// doesn't take CPU time from the server.
+pub fn lower_priority() {
+  // Linux keeps the nice value per thread, new threads inherit it from the one starting them.
+  if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, BACKUP_NICENESS) } == 0 {
+    debug!("Running the backup at niceness {}", BACKUP_NICENESS);
+  } else {
+    warn!(
+      "Unable to lower the backup's priority: {}",
+      io::Error::last_os_error()
+    );
+  }
+}
+
+fn compress_block(block: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+  let mut encoder = GzEncoder::new(Vec::with_capacity(block.len() / 2), compression);
+  encoder.write_all(block)?;
+  encoder.finish()
+}
+
+/// A gzip writer that compresses blocks on several threads, the way pigz does. The output is a
+/// series of gzip members, which every gzip reader that handles multi-member files reads as one
+/// stream.
+pub struct ParallelGzEncoder<W: Write> {
+  inner: W,
+  compression: Compression,
+  threads: usize,
+  block: Vec<u8>,
+  /// Full blocks waiting for a batch of one per thread.
+  pending: Vec<Vec<u8>>,
+  wrote_member: bool,
+}
+
+impl<W: Write> ParallelGzEncoder<W> {
+  pub fn new(inner: W, compression: Compression, threads: u32) -> Self {
+    ParallelGzEncoder {
+      inner,
+      compression,
+      threads: threads.max(1) as usize,
+      block: Vec::with_capacity(BLOCK_SIZE),
+      pending: Vec::new(),
+      wrote_member: false,
+    }
+  }
+
+  /// Compresses the pending blocks side by side and writes them in order.
+  fn compress_pending(&mut self) -> io::Result<()> {
+    let compression = self.compression;
+    let compressed = thread::scope(|scope| {
+      let workers: Vec<_> = self
+        .pending
+        .iter()
+        .map(|block| scope.spawn(move || compress_block(block, compression)))
+        .collect();
+      workers
+        .into_iter()
+        .map(|worker| {
+          worker
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("a compression thread panicked")))
+        })
+        .collect::<io::Result<Vec<Vec<u8>>>>()
+    })?;
+    for member in compressed {
+      self.inner.write_all(&member)?;
+      self.wrote_member = true;
+    }
+    self.pending.clear();
+    Ok(())
+  }
+
+  /// Compresses what is left and hands back the writer.
+  pub fn finish(mut self) -> io::Result<W> {
+    if !self.block.is_empty() {
+      self.pending.push(mem::take(&mut self.block));
+    }
+    self.compress_pending()?;
+    // An empty stream is still a gzip file.
+    if !self.wrote_member {
+      self
+        .inner
+        .write_all(&compress_block(&[], self.compression)?)?;
+    }
+    self.inner.flush()?;
+    Ok(self.inner)
+  }
+}
+
+impl<W: Write> Write for ParallelGzEncoder<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let taken = buf.len().min(BLOCK_SIZE - self.block.len());
+    self.block.extend_from_slice(&buf[..taken]);
+    if self.block.len() == BLOCK_SIZE {
+      let full = mem::replace(&mut self.block, Vec::with_capacity(BLOCK_SIZE));
+      self.pending.push(full);
+      if self.pending.len() == self.threads {
+        self.compress_pending()?;
+      }
+    }
+    Ok(taken)
+  }
+
+  /// Only flushes the inner writer, blocks are compressed once full or on `finish`.
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
+
+#[cfg(test)]
+mod compression_tests {
+  use super::*;
+  use flate2::read::MultiGzDecoder;
+  use std::io::Read;
+
+  #[test]
+  fn parses_thread_counts() {
+    assert_eq!(parse_threads("4"), Ok(4));
+    assert_eq!(parse_threads(" 1 "), Ok(1));
+    assert!(parse_threads("0").is_err());
+    assert!(parse_threads("many").is_err());
+  }
+
+  #[test]
+  fn parallel_members_read_back_as_one_stream() {
+    let data: Vec<u8> = (0..BLOCK_SIZE * 3 + 123).map(|i| (i % 251) as u8).collect();
+    for input in [&data[..], &[][..]].iter() {
+      let mut encoder = ParallelGzEncoder::new(Vec::new(), Compression::fast(), 2);
+      encoder.write_all(input).unwrap();
+      let compressed = encoder.finish().unwrap();
+      let mut decompressed = Vec::new();
+      MultiGzDecoder::new(&compressed[..])
+        .read_to_end(&mut decompressed)
+        .unwrap();
+      assert_eq!(&decompressed[..], *input);
+    }
+  }
+}
@@ -1,35 +1,78 @@
 use crate::constants;
+use crate::errors::CommandError;
+use crate::files::failure::fail_operation;
+use crate::files::validation::ensure_valid_arguments;
 use crate::files::ValheimArguments;
 use crate::files::{FileManager, ManagedFile};
-use crate::utils::environment::fetch_var;
-use crate::utils::{get_variable, get_working_dir};
+use crate::logger::register_secret;
+use crate::server::{
+  extra_args, game_port, SaveSettings, WorldModifiers, SERVER_EXTRA_ARGS_ALLOW_OVERRIDES_VAR,
+};
+use crate::utils::environment::{active_profile, fetch_var};
+use crate::utils::{get_variable, get_working_dir, parse_bool};
 use clap::ArgMatches;
-use log::{debug, error};
+use log::debug;
 use std::fs;
 use std::path::PathBuf;
-use std::process::exit;
 
 const ODIN_CONFIG_FILE_VAR: &str = "ODIN_CONFIG_FILE";
+/// Another name for `CROSSPLAY`, `CROSSPLAY` wins when both are set.
+const ENABLE_CROSSPLAY_VAR: &str = "ENABLE_CROSSPLAY";
 
-pub fn load_config() -> ValheimArguments {
+/// The `crossplay` setting, falling back to `ENABLE_CROSSPLAY`.
+pub fn crossplay_setting(args: &ArgMatches) -> String {
+  get_variable(args, "crossplay", fetch_var(ENABLE_CROSSPLAY_VAR, "0"))
+}
+
+/// The configured launch settings with the ones from the environment, validated.
+pub fn try_load_config() -> Result<ValheimArguments, CommandError> {
   let file = config_file();
-  let config = read_config(file);
+  let mut config = read_config(file);
+  config.world_modifiers = WorldModifiers::from_env()
+    .map_err(|problems| invalid_settings("world modifiers", &problems))?;
+  config.save_settings =
+    SaveSettings::from_env().map_err(|problems| invalid_settings("save settings", &problems))?;
+  let allow_overrides =
+    parse_bool(&fetch_var(SERVER_EXTRA_ARGS_ALLOW_OVERRIDES_VAR, "0")) == Some(true);
+  config.extra_args = extra_args(None, allow_overrides)
+    .map_err(|problem| invalid_settings("extra arguments", &[problem]))?;
+  config.port = game_port(&config.port);
+  // The password ends up in the launch arguments, which are logged.
+  register_secret(&config.password);
 
-  debug!("Checking password compliance...");
-  if config.password.len() < 5 {
-    error!("The supplied password is too short! It must be 5 characters or greater!");
-    exit(1);
-  }
+  debug!("Validating the server configuration...");
+  ensure_valid_arguments(&config)?;
+
+  Ok(config)
+}
 
-  config
+/// Like `try_load_config`, but exits when the settings are invalid, for a supervising odin that
+/// reloads them to restart the server.
+pub fn load_config() -> ValheimArguments {
+  try_load_config().unwrap_or_else(|e| fail_operation(e.code, &e.message))
+}
+
+/// Lists each problem with the launch settings, like world modifiers Valheim would silently
+/// ignore.
+pub fn invalid_settings(settings: &str, problems: &[String]) -> CommandError {
+  CommandError::with_problems(&format!("The {} are invalid:", settings), problems)
 }
 
 pub fn config_file() -> ManagedFile {
-  let name = fetch_var(ODIN_CONFIG_FILE_VAR, "config.json");
+  let mut name = fetch_var(ODIN_CONFIG_FILE_VAR, "config.json");
+  // A profile's config is in its own server directory, never one that happens to be next to odin.
+  if active_profile().is_some() {
+    name = get_working_dir().join(&name).to_string_lossy().to_string();
+  }
   debug!("Config file set to: {}", name);
   ManagedFile { name }
 }
 
+/// The config from `odin configure`, `None` before odin has been configured.
+pub fn configured() -> Option<ValheimArguments> {
+  serde_json::from_str(&config_file().read()).ok()
+}
+
 pub fn read_config(config: ManagedFile) -> ValheimArguments {
   let content = config.read();
   if content.is_empty() {
@@ -38,12 +81,9 @@ pub fn read_config(config: ManagedFile) -> ValheimArguments {
   serde_json::from_str(content.as_str()).unwrap()
 }
 
-pub fn write_config(config: ManagedFile, args: &ArgMatches) -> bool {
-  let server_executable: &str = &[
-    get_working_dir(),
-    constants::VALHEIM_EXECUTABLE_NAME.to_string(),
-  ]
-  .join("/");
+pub fn write_config(config: ManagedFile, args: &ArgMatches) -> Result<bool, CommandError> {
+  let server_executable = get_working_dir().join(constants::VALHEIM_EXECUTABLE_NAME);
+  let server_executable: &str = &server_executable.to_string_lossy();
   let command = match fs::canonicalize(PathBuf::from(get_variable(
     args,
     "server_executable",
@@ -51,25 +91,38 @@ pub fn write_config(config: ManagedFile, args: &ArgMatches) -> bool {
   ))) {
     std::result::Result::Ok(command_path) => command_path.to_str().unwrap().to_string(),
     std::result::Result::Err(_) => {
-      error!("Failed to find server executable! Please run `odin install`");
-      exit(1)
+      return Err(CommandError::failed(
+        "Failed to find server executable! Please run `odin install`",
+      ))
     }
   };
 
+  let crossplay_value = crossplay_setting(args);
+  let crossplay = parse_bool(&crossplay_value).ok_or_else(|| {
+    CommandError::failed(format!(
+      "CROSSPLAY must be 1 or 0 (or true/false), got '{}'",
+      crossplay_value
+    ))
+  })?;
   let content = &ValheimArguments {
-    port: get_variable(args, "port", "2456".to_string()),
+    port: game_port(&get_variable(args, "port", "2456".to_string())),
     name: get_variable(args, "name", "Valheim powered by Odin".to_string()),
     world: get_variable(args, "world", "Dedicated".to_string()),
     public: get_variable(args, "public", "1".to_string()),
     password: get_variable(args, "password", "12345".to_string()),
     command,
+    crossplay,
+    world_modifiers: WorldModifiers::default(),
+    save_settings: SaveSettings::default(),
+    extra_args: Vec::new(),
   };
+  ensure_valid_arguments(content)?;
   let content_to_write = serde_json::to_string(content).unwrap();
   debug!(
     "Writing config content: \n{}",
     serde_json::to_string_pretty(content).unwrap()
   );
-  config.write(content_to_write)
+  Ok(config.write(content_to_write))
 }
 
 #[cfg(test)]
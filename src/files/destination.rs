@@ -0,0 +1,301 @@
+use log::debug;
+
+use std::{
+  fmt, fs,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use crate::errors::VariantNotFound;
+use crate::files::{
+  archive::checksum_path,
+  retention::{prune_backups, RetentionPolicy},
+  s3::{is_s3_uri, S3Client, S3Config},
+  sftp::{SftpClient, SftpConfig},
+};
+use crate::utils::environment::fetch_var;
+
+pub const BACKUP_DESTINATIONS_VAR: &str = "BACKUP_DESTINATIONS";
+pub const BACKUP_DESTINATION_POLICY_VAR: &str = "BACKUP_DESTINATION_POLICY";
+
+const LOCAL_PREFIX: &str = "local:";
+/// Copies go here first so an interrupted copy never looks like a backup.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// How many destinations have to get the backup for it to count.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DestinationPolicy {
+  #[default]
+  RequireAll,
+  RequireAny,
+}
+
+impl FromStr for DestinationPolicy {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> Result<DestinationPolicy, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "all" => Ok(DestinationPolicy::RequireAll),
+      "any" => Ok(DestinationPolicy::RequireAny),
+      _ => Err(VariantNotFound {
+        v: format!("Unknown destination policy '{}', expected all or any", s),
+      }),
+    }
+  }
+}
+
+impl DestinationPolicy {
+  pub fn is_met(&self, succeeded: usize, total: usize) -> bool {
+    match self {
+      DestinationPolicy::RequireAll => succeeded == total,
+      DestinationPolicy::RequireAny => total == 0 || succeeded > 0,
+    }
+  }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum DestinationTarget {
+  /// A directory the backup is copied into.
+  Local(PathBuf),
+  S3(S3Config),
+  Sftp(SftpConfig),
+}
+
+/// Somewhere a finished backup is sent. There is no `Debug`, SFTP settings hold a password.
+#[derive(Clone, PartialEq)]
+pub struct BackupDestination {
+  pub(crate) target: DestinationTarget,
+  /// Whether the retention policy is applied there once it has the backup.
+  pub(crate) prune: bool,
+}
+
+impl fmt::Display for BackupDestination {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.target {
+      DestinationTarget::Local(directory) => write!(f, "{}", directory.display()),
+      DestinationTarget::S3(config) => write!(f, "{}", config.uri_for(&config.prefix)),
+      DestinationTarget::Sftp(config) => write!(f, "{}", config.uri_for(&config.remote_dir)),
+    }
+  }
+}
+
+/// Copies `file` into `directory` through a partial file, replacing what is there.
+fn copy_into(file: &Path, directory: &Path) -> Result<PathBuf, String> {
+  let file_name = file
+    .file_name()
+    .ok_or_else(|| format!("{} is not a file", file.display()))?;
+  let destination = directory.join(file_name);
+  let mut partial = destination.clone().into_os_string();
+  partial.push(PARTIAL_SUFFIX);
+  fs::create_dir_all(directory)
+    .and_then(|_| fs::copy(file, &partial))
+    .and_then(|_| fs::rename(&partial, &destination))
+    .map_err(|e| {
+      let _ = fs::remove_file(&partial);
+      format!(
+        "Unable to copy {} to {}: {}",
+        file.display(),
+        directory.display(),
+        e
+      )
+    })?;
+  Ok(destination)
+}
+
+fn is_same_directory(a: &Path, b: &Path) -> bool {
+  match (fs::canonicalize(a), fs::canonicalize(b)) {
+    (Ok(a), Ok(b)) => a == b,
+    _ => a == b,
+  }
+}
+
+impl BackupDestination {
+  /// `local:/path`, `s3://bucket/prefix` or `sftp://[user@]host[:port]/path`. Remote credentials
+  /// come from the same variables as `BACKUP_S3_BUCKET` and `BACKUP_SFTP_HOST` use.
+  pub fn parse(entry: &str) -> Result<Self, String> {
+    let entry = entry.trim().trim_matches('"');
+    let target = if let Some(directory) = entry.strip_prefix(LOCAL_PREFIX) {
+      if directory.trim().is_empty() {
+        return Err(format!(
+          "Invalid local destination '{}', expected local:/path",
+          entry
+        ));
+      }
+      DestinationTarget::Local(PathBuf::from(directory.trim()))
+    } else if is_s3_uri(entry) {
+      DestinationTarget::S3(S3Config::for_uri(entry)?)
+    } else if entry.starts_with("sftp://") {
+      DestinationTarget::Sftp(SftpConfig::for_uri(entry)?)
+    } else {
+      return Err(format!(
+        "Unknown backup destination '{}', expected local:/path, s3://bucket/prefix or sftp://host/path",
+        entry
+      ));
+    };
+    Ok(BackupDestination {
+      target,
+      prune: true,
+    })
+  }
+
+  /// Whether this is the directory `archive` was written to, which already has it.
+  pub fn holds(&self, archive: &Path) -> bool {
+    match (&self.target, archive.parent()) {
+      (DestinationTarget::Local(directory), Some(parent)) => {
+        let parent = if parent.as_os_str().is_empty() {
+          Path::new(".")
+        } else {
+          parent
+        };
+        is_same_directory(directory, parent)
+      }
+      _ => false,
+    }
+  }
+
+  /// Where a backup named `file_name` ends up.
+  pub fn location_of(&self, file_name: &str) -> String {
+    match &self.target {
+      DestinationTarget::Local(directory) => directory.join(file_name).display().to_string(),
+      DestinationTarget::S3(config) => config.uri_for(&config.key_for(file_name)),
+      DestinationTarget::Sftp(config) => config.uri_for(&config.path_for(file_name)),
+    }
+  }
+
+  /// Sends `archive` and its manifest, returning where the archive went.
+  pub fn store(&self, archive: &Path) -> Result<String, String> {
+    if self.holds(archive) {
+      debug!("{} is already in {}", archive.display(), self);
+      return Ok(archive.display().to_string());
+    }
+    let files = [archive.to_path_buf(), checksum_path(archive)];
+    let mut stored = match &self.target {
+      DestinationTarget::Local(directory) => files
+        .iter()
+        .map(|file| copy_into(file, directory).map(|path| path.display().to_string()))
+        .collect::<Result<Vec<String>, String>>()?,
+      DestinationTarget::S3(config) => {
+        let client = S3Client::new(config.clone())?;
+        files
+          .iter()
+          .map(|file| client.upload(file))
+          .collect::<Result<Vec<String>, String>>()?
+      }
+      DestinationTarget::Sftp(config) => {
+        let client = SftpClient::new(config.clone());
+        files
+          .iter()
+          .map(|file| client.upload(file))
+          .collect::<Result<Vec<String>, String>>()?
+      }
+    };
+    Ok(stored.remove(0))
+  }
+
+  /// Applies `policy` to the backups at this destination.
+  pub fn prune(&self, policy: RetentionPolicy, dry_run: bool) -> Result<(), String> {
+    match &self.target {
+      DestinationTarget::Local(directory) => {
+        prune_backups(directory, policy, dry_run);
+        Ok(())
+      }
+      DestinationTarget::S3(config) => S3Client::new(config.clone())?.prune(policy, dry_run),
+      DestinationTarget::Sftp(config) => SftpClient::new(config.clone()).prune(policy, dry_run),
+    }
+  }
+}
+
+/// Parses a comma separated list of destinations, the first invalid one is the error.
+pub fn parse_destinations(value: &str) -> Result<Vec<BackupDestination>, String> {
+  value
+    .split(',')
+    .filter(|entry| !entry.trim().is_empty())
+    .map(BackupDestination::parse)
+    .collect()
+}
+
+/// `BACKUP_DESTINATIONS`, or the bucket and SFTP box set with `BACKUP_S3_BUCKET` and
+/// `BACKUP_SFTP_HOST` when it isn't set. Only `BACKUP_SFTP_PRUNE` prunes those.
+pub fn destinations_from_env() -> Result<Vec<BackupDestination>, String> {
+  let value = fetch_var(BACKUP_DESTINATIONS_VAR, "");
+  if !value.trim().is_empty() {
+    return parse_destinations(&value);
+  }
+  let mut destinations = Vec::new();
+  if let Some(config) = S3Config::from_env()? {
+    destinations.push(BackupDestination {
+      target: DestinationTarget::S3(config),
+      prune: false,
+    });
+  }
+  if let Some(config) = SftpConfig::from_env()? {
+    destinations.push(BackupDestination {
+      prune: config.prune,
+      target: DestinationTarget::Sftp(config),
+    });
+  }
+  Ok(destinations)
+}
+
+/// What happened to the backup at one destination.
+pub struct DestinationResult {
+  pub(crate) destination: String,
+  /// Where the backup went, or why it didn't.
+  pub(crate) outcome: Result<String, String>,
+}
+
+impl fmt::Display for DestinationResult {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.outcome {
+      Ok(location) => write!(f, "{}: stored as {}", self.destination, location),
+      Err(e) => write!(f, "{}: failed, {}", self.destination, e),
+    }
+  }
+}
+
+#[cfg(test)]
+mod destination_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+
+  #[test]
+  fn parses_destinations_and_policies() {
+    let destinations = parse_destinations("local:/home/steam/backups, local:mirror").unwrap();
+    assert_eq!(destinations.len(), 2);
+    assert_eq!(destinations[0].to_string(), "/home/steam/backups");
+    assert_eq!(destinations[1].location_of("a.tar.gz"), "mirror/a.tar.gz");
+    assert!(destinations.iter().all(|destination| destination.prune));
+    assert!(parse_destinations("local:").is_err());
+    assert!(parse_destinations("ftp://host/backups").is_err());
+
+    assert_eq!(
+      DestinationPolicy::from_str("ANY").unwrap(),
+      DestinationPolicy::RequireAny
+    );
+    assert!(DestinationPolicy::from_str("most").is_err());
+    assert!(DestinationPolicy::RequireAll.is_met(2, 2));
+    assert!(!DestinationPolicy::RequireAll.is_met(1, 2));
+    assert!(DestinationPolicy::RequireAny.is_met(1, 2));
+    assert!(!DestinationPolicy::RequireAny.is_met(0, 2));
+  }
+
+  #[test]
+  fn copies_into_local_directories() {
+    let temp = test_dir("destination");
+    let root = temp.path();
+    let archive = root.join("20210301-120000-backup.tar.gz");
+    fs::write(&archive, b"archive").unwrap();
+    fs::write(checksum_path(&archive), b"hash").unwrap();
+
+    let here = BackupDestination::parse(&format!("local:{}", root.display())).unwrap();
+    assert!(here.holds(&archive));
+    assert_eq!(here.store(&archive).unwrap(), archive.display().to_string());
+
+    let mirror = root.join("mirror");
+    let copy = BackupDestination::parse(&format!("local:{}", mirror.display())).unwrap();
+    assert!(!copy.holds(&archive));
+    let stored = copy.store(&archive).unwrap();
+    assert_eq!(fs::read(&stored).unwrap(), b"archive");
+    assert!(checksum_path(Path::new(&stored)).exists());
+    assert!(!Path::new(&format!("{}{}", stored, PARTIAL_SUFFIX)).exists());
+  }
+}
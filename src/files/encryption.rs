@@ -0,0 +1,275 @@
+use age::{
+  secrecy::SecretString,
+  x25519::{Identity, Recipient},
+  DecryptError, Decryptor, Encryptor,
+};
+
+use std::{
+  fs::{self, File},
+  io::{self, BufReader, BufWriter, Write},
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use crate::utils::environment::{fetch_secret_var, fetch_var};
+
+pub const BACKUP_ENCRYPTION_KEY_VAR: &str = "BACKUP_ENCRYPTION_KEY";
+pub const BACKUP_AGE_RECIPIENT_VAR: &str = "BACKUP_AGE_RECIPIENT";
+pub const BACKUP_AGE_IDENTITY_VAR: &str = "BACKUP_AGE_IDENTITY";
+
+/// Appended to the archive's name once it is encrypted.
+pub const ENCRYPTED_EXTENSION: &str = ".age";
+
+/// How backups are encrypted. There is deliberately no `Debug`, the passphrase must never end up
+/// in a log line.
+pub enum Encryption {
+  Passphrase(SecretString),
+  Recipient(Box<Recipient>),
+}
+
+impl Encryption {
+  /// `None` when neither `BACKUP_ENCRYPTION_KEY` nor `BACKUP_AGE_RECIPIENT` is set.
+  pub fn from_env() -> Result<Option<Self>, String> {
+    let passphrase = fetch_secret_var(BACKUP_ENCRYPTION_KEY_VAR);
+    let recipient = fetch_var(BACKUP_AGE_RECIPIENT_VAR, "");
+    match (passphrase.is_empty(), recipient.trim().is_empty()) {
+      (true, true) => Ok(None),
+      (false, false) => Err(format!(
+        "Set either {} or {}, not both",
+        BACKUP_ENCRYPTION_KEY_VAR, BACKUP_AGE_RECIPIENT_VAR
+      )),
+      (false, true) => Ok(Some(Encryption::Passphrase(SecretString::new(passphrase)))),
+      (true, false) => Recipient::from_str(recipient.trim())
+        .map(|recipient| Some(Encryption::Recipient(Box::new(recipient))))
+        .map_err(|_| {
+          format!(
+            "{} '{}' isn't a valid age public key",
+            BACKUP_AGE_RECIPIENT_VAR, recipient
+          )
+        }),
+    }
+  }
+
+  pub fn describe(&self) -> String {
+    match self {
+      Encryption::Passphrase(_) => format!("the passphrase in {}", BACKUP_ENCRYPTION_KEY_VAR),
+      Encryption::Recipient(recipient) => format!("the age recipient {}", recipient),
+    }
+  }
+}
+
+pub fn is_encrypted(path: &Path) -> bool {
+  path
+    .file_name()
+    .is_some_and(|name| name.to_string_lossy().ends_with(ENCRYPTED_EXTENSION))
+}
+
+/// The name of the archive inside an encrypted backup, `name` itself for anything else.
+pub fn plaintext_name(name: &str) -> &str {
+  name.strip_suffix(ENCRYPTED_EXTENSION).unwrap_or(name)
+}
+
+/// `<archive>.age`, where the encrypted copy of `archive` goes.
+pub fn encrypted_path(archive: &Path) -> PathBuf {
+  let mut name = archive.as_os_str().to_owned();
+  name.push(ENCRYPTED_EXTENSION);
+  PathBuf::from(name)
+}
+
+/// Encrypts `input` into `output`. The plaintext is left for the caller to remove once the
+/// encrypted copy is known to be good.
+pub fn encrypt_file(input: &Path, output: &Path, encryption: &Encryption) -> io::Result<()> {
+  let encryptor = match encryption {
+    Encryption::Passphrase(passphrase) => Encryptor::with_user_passphrase(passphrase.clone()),
+    Encryption::Recipient(recipient) => {
+      Encryptor::with_recipients(vec![Box::new(recipient.as_ref().clone())])
+        .expect("there is always one recipient")
+    }
+  };
+  let mut writer = encryptor
+    .wrap_output(BufWriter::new(File::create(output)?))
+    .map_err(|e| io::Error::other(e.to_string()))?;
+  io::copy(&mut File::open(input)?, &mut writer)?;
+  writer.finish()?.flush()
+}
+
+/// The identities in `BACKUP_AGE_IDENTITY`, either the secret keys themselves or the path of an
+/// identity file holding them.
+fn identities() -> Result<Vec<Identity>, String> {
+  let value = fetch_secret_var(BACKUP_AGE_IDENTITY_VAR);
+  let content = if Path::new(value.trim()).is_file() {
+    fs::read_to_string(value.trim())
+      .map_err(|e| format!("Unable to read {}: {}", BACKUP_AGE_IDENTITY_VAR, e))?
+  } else {
+    value
+  };
+  content
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .flat_map(|line| line.split(',').map(str::trim))
+    .map(|key| {
+      Identity::from_str(key).map_err(|_| {
+        format!(
+          "{} holds something that isn't an age secret key",
+          BACKUP_AGE_IDENTITY_VAR
+        )
+      })
+    })
+    .collect()
+}
+
+fn decrypt_error(input: &Path, error: DecryptError) -> String {
+  match error {
+    DecryptError::DecryptionFailed | DecryptError::KeyDecryptionFailed => format!(
+      "Unable to decrypt {}, {} is wrong",
+      input.display(),
+      BACKUP_ENCRYPTION_KEY_VAR
+    ),
+    DecryptError::NoMatchingKeys => format!(
+      "Unable to decrypt {}, it wasn't encrypted for any key in {}",
+      input.display(),
+      BACKUP_AGE_IDENTITY_VAR
+    ),
+    e => format!("Unable to decrypt {}: {}", input.display(), e),
+  }
+}
+
+/// Decrypts a backup made with `encrypt_file` into `output`, with `BACKUP_ENCRYPTION_KEY` or
+/// `BACKUP_AGE_IDENTITY` depending on how it was encrypted.
+pub fn decrypt_file(input: &Path, output: &Path) -> Result<(), String> {
+  let file = File::open(input).map_err(|e| format!("Unable to read {}: {}", input.display(), e))?;
+  let decryptor = Decryptor::new(BufReader::new(file)).map_err(|e| decrypt_error(input, e))?;
+  let mut reader = match decryptor {
+    Decryptor::Passphrase(decryptor) => {
+      let passphrase = fetch_secret_var(BACKUP_ENCRYPTION_KEY_VAR);
+      if passphrase.is_empty() {
+        return Err(format!(
+          "{} is encrypted with a passphrase, set {} to restore it",
+          input.display(),
+          BACKUP_ENCRYPTION_KEY_VAR
+        ));
+      }
+      decryptor
+        .decrypt(&SecretString::new(passphrase), None)
+        .map_err(|e| decrypt_error(input, e))?
+    }
+    Decryptor::Recipients(decryptor) => {
+      let identities = identities()?;
+      if identities.is_empty() {
+        return Err(format!(
+          "{} is encrypted to an age recipient, set {} to restore it",
+          input.display(),
+          BACKUP_AGE_IDENTITY_VAR
+        ));
+      }
+      decryptor
+        .decrypt(
+          identities
+            .iter()
+            .map(|identity| identity as &dyn age::Identity),
+        )
+        .map_err(|e| decrypt_error(input, e))?
+    }
+  };
+  let mut writer = BufWriter::new(
+    File::create(output).map_err(|e| format!("Unable to write {}: {}", output.display(), e))?,
+  );
+  io::copy(&mut reader, &mut writer)
+    .and_then(|_| writer.flush())
+    .map_err(|e| {
+      let _ = fs::remove_file(output);
+      format!(
+        "Unable to decrypt {}, it is damaged: {}",
+        input.display(),
+        e
+      )
+    })?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod encryption_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use age::secrecy::ExposeSecret;
+  use serial_test::serial;
+  use std::env;
+
+  #[test]
+  #[serial]
+  fn round_trips_with_a_passphrase() {
+    let temp = test_dir("encryption");
+    let root = temp.path();
+    fs::write(root.join("backup.tar.gz"), "the world").unwrap();
+    env::set_var(BACKUP_ENCRYPTION_KEY_VAR, "correct horse battery staple");
+    let encryption = Encryption::from_env().unwrap().unwrap();
+    let encrypted = encrypted_path(&root.join("backup.tar.gz"));
+    encrypt_file(&root.join("backup.tar.gz"), &encrypted, &encryption).unwrap();
+    assert!(is_encrypted(&encrypted));
+    assert_ne!(fs::read(&encrypted).unwrap(), b"the world");
+
+    decrypt_file(&encrypted, &root.join("restored.tar.gz")).unwrap();
+    assert_eq!(
+      fs::read_to_string(root.join("restored.tar.gz")).unwrap(),
+      "the world"
+    );
+
+    env::set_var(BACKUP_ENCRYPTION_KEY_VAR, "wrong horse");
+    let error = decrypt_file(&encrypted, &root.join("wrong.tar.gz")).unwrap_err();
+    assert!(error.contains("is wrong"));
+    assert!(!error.contains("horse"));
+    env::remove_var(BACKUP_ENCRYPTION_KEY_VAR);
+  }
+
+  #[test]
+  #[serial]
+  fn round_trips_with_an_age_key() {
+    let temp = test_dir("encryption");
+    let root = temp.path();
+    fs::write(root.join("backup.zip"), "the world").unwrap();
+    let identity = Identity::generate();
+    env::set_var(BACKUP_AGE_RECIPIENT_VAR, identity.to_public().to_string());
+    let encryption = Encryption::from_env().unwrap().unwrap();
+    let encrypted = encrypted_path(&root.join("backup.zip"));
+    encrypt_file(&root.join("backup.zip"), &encrypted, &encryption).unwrap();
+
+    let identity_file = root.join("key.txt");
+    fs::write(
+      &identity_file,
+      format!(
+        "# created: today\n{}\n",
+        identity.to_string().expose_secret()
+      ),
+    )
+    .unwrap();
+    env::set_var(BACKUP_AGE_IDENTITY_VAR, &identity_file);
+    decrypt_file(&encrypted, &root.join("restored.zip")).unwrap();
+    assert_eq!(
+      fs::read_to_string(root.join("restored.zip")).unwrap(),
+      "the world"
+    );
+
+    env::set_var(
+      BACKUP_AGE_IDENTITY_VAR,
+      Identity::generate().to_string().expose_secret(),
+    );
+    assert!(decrypt_file(&encrypted, &root.join("wrong.zip"))
+      .unwrap_err()
+      .contains("wasn't encrypted for any key"));
+    env::remove_var(BACKUP_AGE_RECIPIENT_VAR);
+    env::remove_var(BACKUP_AGE_IDENTITY_VAR);
+  }
+
+  #[test]
+  #[serial]
+  fn refuses_both_kinds_of_key() {
+    env::set_var(BACKUP_ENCRYPTION_KEY_VAR, "secret");
+    env::set_var(BACKUP_AGE_RECIPIENT_VAR, "age1notakey");
+    assert!(Encryption::from_env().is_err());
+    env::remove_var(BACKUP_ENCRYPTION_KEY_VAR);
+    assert!(Encryption::from_env().is_err());
+    env::remove_var(BACKUP_AGE_RECIPIENT_VAR);
+    assert!(Encryption::from_env().unwrap().is_none());
+  }
+}
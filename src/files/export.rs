@@ -0,0 +1,209 @@
+use clap::ArgMatches;
+
+use std::{
+  fs::{self, OpenOptions, Permissions},
+  io::{self, Write},
+  os::unix::fs::{OpenOptionsExt, PermissionsExt},
+  path::Path,
+  str::FromStr,
+};
+
+use crate::errors::VariantNotFound;
+use crate::files::config::crossplay_setting;
+use crate::logger::REDACTED;
+use crate::mods::bepinex;
+use crate::notifications::WEBHOOK_URL;
+use crate::server::ON_SERVER_EXIT_VAR;
+use crate::utils::{environment::fetch_var, get_variable, parse_bool};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+  Toml,
+  Env,
+}
+
+impl FromStr for ExportFormat {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> Result<ExportFormat, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "toml" => Ok(ExportFormat::Toml),
+      "env" => Ok(ExportFormat::Env),
+      _ => Err(VariantNotFound {
+        v: format!("Unknown export format '{}', expected toml or env", s),
+      }),
+    }
+  }
+}
+
+pub struct ExportedSetting {
+  key: String,
+  value: String,
+  secret: bool,
+}
+
+impl ExportedSetting {
+  fn new(key: &str, value: String, secret: bool) -> Self {
+    ExportedSetting {
+      key: key.to_string(),
+      value,
+      secret,
+    }
+  }
+
+  fn shown_value(&self, include_secrets: bool) -> &str {
+    if self.secret && !include_secrets && !self.value.is_empty() {
+      REDACTED
+    } else {
+      &self.value
+    }
+  }
+}
+
+/// The settings odin would run with right now, keyed by their environment variable names so a
+/// TOML export can be fed straight back in with `--config`.
+pub fn effective_settings(args: &ArgMatches) -> Vec<ExportedSetting> {
+  let bool_flag = |enabled: bool| if enabled { "1" } else { "0" }.to_string();
+  vec![
+    ExportedSetting::new(
+      "NAME",
+      get_variable(args, "name", "Valheim powered by Odin".to_string()),
+      false,
+    ),
+    ExportedSetting::new(
+      "WORLD",
+      get_variable(args, "world", "Dedicated".to_string()),
+      false,
+    ),
+    ExportedSetting::new(
+      "PORT",
+      get_variable(args, "port", "2456".to_string()),
+      false,
+    ),
+    ExportedSetting::new(
+      "PUBLIC",
+      get_variable(args, "public", "1".to_string()),
+      false,
+    ),
+    ExportedSetting::new(
+      "CROSSPLAY",
+      bool_flag(parse_bool(&crossplay_setting(args)).unwrap_or(false)),
+      false,
+    ),
+    ExportedSetting::new(
+      "PASSWORD",
+      get_variable(args, "password", "12345".to_string()),
+      true,
+    ),
+    ExportedSetting::new(
+      "MODS_ENABLED",
+      bool_flag(bepinex::is_bepinex_installed().is_installed()),
+      false,
+    ),
+    ExportedSetting::new(ON_SERVER_EXIT_VAR, fetch_var(ON_SERVER_EXIT_VAR, ""), false),
+    ExportedSetting::new(WEBHOOK_URL, fetch_var(WEBHOOK_URL, ""), true),
+  ]
+}
+
+fn quote_env(value: &str) -> String {
+  format!(
+    "\"{}\"",
+    value
+      .replace('\\', "\\\\")
+      .replace('"', "\\\"")
+      .replace('$', "\\$")
+      .replace('`', "\\`")
+  )
+}
+
+pub fn render_settings(
+  settings: &[ExportedSetting],
+  format: ExportFormat,
+  include_secrets: bool,
+) -> String {
+  let lines: Vec<String> = settings
+    .iter()
+    .map(|setting| {
+      let value = setting.shown_value(include_secrets);
+      match format {
+        ExportFormat::Toml => format!(
+          "{} = {}",
+          setting.key.to_lowercase(),
+          toml::Value::String(value.to_string())
+        ),
+        ExportFormat::Env => format!("{}={}", setting.key, quote_env(value)),
+      }
+    })
+    .collect();
+  format!("{}\n", lines.join("\n"))
+}
+
+/// Writes the rendered settings to `path`. With secrets in it only the owner may read the file,
+/// an existing one included.
+pub fn write_export(path: &Path, content: &str, include_secrets: bool) -> io::Result<()> {
+  if !include_secrets {
+    return fs::write(path, content);
+  }
+  let mut file = OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .mode(0o600)
+    .open(path)?;
+  file.set_permissions(Permissions::from_mode(0o600))?;
+  file.write_all(content.as_bytes())
+}
+
+#[cfg(test)]
+mod export_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+
+  fn settings() -> Vec<ExportedSetting> {
+    vec![
+      ExportedSetting::new("NAME", "My \"Cool\" Server".to_string(), false),
+      ExportedSetting::new("PASSWORD", "hunter22".to_string(), true),
+      ExportedSetting::new("WEBHOOK_URL", "".to_string(), true),
+    ]
+  }
+
+  #[test]
+  fn masks_secrets_by_default() {
+    let output = render_settings(&settings(), ExportFormat::Toml, false);
+    assert!(output.contains("password = \"<redacted>\""));
+    assert!(!output.contains("hunter22"));
+    // An unset secret is shown as unset rather than hiding that it is missing.
+    assert!(output.contains("webhook_url = \"\""));
+  }
+
+  #[test]
+  fn includes_secrets_when_asked() {
+    let output = render_settings(&settings(), ExportFormat::Env, true);
+    assert!(output.contains("PASSWORD=\"hunter22\""));
+  }
+
+  #[test]
+  fn only_the_owner_reads_exported_secrets() {
+    let dir = test_dir("export");
+    let path = dir.path().join("odin.env");
+    fs::write(&path, "").unwrap();
+    let output = render_settings(&settings(), ExportFormat::Env, true);
+    write_export(&path, &output, true).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), output);
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+  }
+
+  #[test]
+  fn toml_export_round_trips() {
+    let output = render_settings(&settings(), ExportFormat::Toml, true);
+    let parsed = crate::utils::environment::parse_config(&output).unwrap();
+    assert_eq!(parsed.get("NAME").unwrap(), "My \"Cool\" Server");
+    assert_eq!(parsed.get("PASSWORD").unwrap(), "hunter22");
+  }
+
+  #[test]
+  fn env_export_escapes_values() {
+    let output = render_settings(&settings(), ExportFormat::Env, false);
+    assert!(output.contains("NAME=\"My \\\"Cool\\\" Server\""));
+  }
+}
@@ -0,0 +1,183 @@
+use chrono::Local;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+
+use std::{fs, io, path::PathBuf, process::exit, sync::Mutex};
+
+use crate::errors::CommandResult;
+use crate::server;
+use crate::utils::get_odin_dir;
+
+const LAST_FAILURE_FILE_NAME: &str = "last_failure.json";
+
+// The command odin is currently running and how far it got, reported if it fails.
+static OPERATION: Mutex<Option<Operation>> = Mutex::new(None);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operation {
+  command: String,
+  phase: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FailureReport {
+  pub(crate) command: String,
+  pub(crate) exit_code: i32,
+  pub(crate) message: String,
+  pub(crate) phase: String,
+  pub(crate) server_healthy: bool,
+  pub(crate) failed_at: String,
+}
+
+impl FailureReport {
+  pub fn new(operation: &Operation, exit_code: i32, message: &str, server_healthy: bool) -> Self {
+    FailureReport {
+      command: operation.command.clone(),
+      exit_code,
+      message: message.to_string(),
+      phase: operation.phase.clone(),
+      server_healthy,
+      failed_at: Local::now().to_rfc3339(),
+    }
+  }
+}
+
+pub struct FailureFile {
+  path: PathBuf,
+}
+
+impl FailureFile {
+  pub fn new() -> Self {
+    FailureFile {
+      path: get_odin_dir().join(LAST_FAILURE_FILE_NAME),
+    }
+  }
+
+  pub fn load(&self) -> Option<FailureReport> {
+    let content = fs::read_to_string(&self.path).ok()?;
+    serde_json::from_str(&content).ok()
+  }
+
+  pub fn write(&self, report: &FailureReport) -> io::Result<()> {
+    if let Some(parent) = self.path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::write(&self.path, serde_json::to_string_pretty(report)?)
+  }
+
+  /// Removes the report if it was left behind by `command`, failures of other commands stay.
+  pub fn clear_for(&self, command: &str) {
+    if let Some(report) = self.load() {
+      if report.command == command {
+        debug!("Clearing last failure of {}", command);
+        if let Err(e) = fs::remove_file(&self.path) {
+          warn!("Failed to clear {}: {}", self.path.display(), e);
+        }
+      }
+    }
+  }
+}
+
+pub fn begin_operation(command: &str) {
+  *OPERATION.lock().unwrap() = Some(Operation {
+    command: command.to_string(),
+    phase: "starting".to_string(),
+  });
+}
+
+pub fn enter_phase(phase: &str) {
+  if let Some(operation) = OPERATION.lock().unwrap().as_mut() {
+    debug!("Entering phase '{}' of {}", phase, operation.command);
+    operation.phase = phase.to_string();
+  }
+}
+
+pub fn current_operation() -> Option<Operation> {
+  OPERATION.lock().unwrap().clone()
+}
+
+/// Called once a command has completed, clears any failure it left behind on a previous run.
+pub fn finish_operation() {
+  if let Some(operation) = OPERATION.lock().unwrap().take() {
+    FailureFile::new().clear_for(&operation.command);
+  }
+}
+
+/// Logs the error and writes `last_failure.json` for whatever operation is running.
+pub fn record_failure(exit_code: i32, message: &str) {
+  error!("{}", message);
+  if let Some(operation) = current_operation() {
+    let report = FailureReport::new(&operation, exit_code, message, server::is_running());
+    if let Err(e) = FailureFile::new().write(&report) {
+      warn!("Failed to write the failure report: {}", e);
+    }
+  }
+}
+
+/// Records the failure and exits.
+pub fn fail_operation(exit_code: i32, message: &str) -> ! {
+  record_failure(exit_code, message);
+  exit(exit_code);
+}
+
+/// Ends the operation the way `result` says, for the processes a command ends in. Returns after
+/// finishing a successful one, exits with the error's code otherwise.
+pub fn conclude_operation(result: CommandResult) {
+  match result {
+    Ok(()) => finish_operation(),
+    Err(e) if e.is_quiet() => exit(e.code),
+    Err(e) => fail_operation(e.code, &e.message),
+  }
+}
+
+#[cfg(test)]
+mod failure_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use serial_test::serial;
+  use tempfile::TempDir;
+
+  fn test_failure_file() -> (TempDir, FailureFile) {
+    let dir = test_dir("failure");
+    let failure_file = FailureFile {
+      path: dir.path().join(LAST_FAILURE_FILE_NAME),
+    };
+    (dir, failure_file)
+  }
+
+  #[test]
+  #[serial]
+  fn reports_phase_reached_mid_update() {
+    begin_operation("update");
+    enter_phase("stopping server");
+    enter_phase("installing");
+    let operation = current_operation().unwrap();
+    let report = FailureReport::new(&operation, 12, "steamcmd exploded", false);
+    assert_eq!(report.command, "update");
+    assert_eq!(report.phase, "installing");
+    assert_eq!(report.exit_code, 12);
+    assert!(!report.server_healthy);
+
+    let (_dir, failure_file) = test_failure_file();
+    failure_file.write(&report).unwrap();
+    assert_eq!(failure_file.load().unwrap().phase, "installing");
+    *OPERATION.lock().unwrap() = None;
+  }
+
+  #[test]
+  #[serial]
+  fn cleared_only_by_the_same_command() {
+    let (_dir, failure_file) = test_failure_file();
+    let operation = Operation {
+      command: "update".to_string(),
+      phase: "installing".to_string(),
+    };
+    failure_file
+      .write(&FailureReport::new(&operation, 11, "failed", true))
+      .unwrap();
+    failure_file.clear_for("backup");
+    assert!(failure_file.load().is_some());
+    failure_file.clear_for("update");
+    assert!(failure_file.load().is_none());
+  }
+}
@@ -0,0 +1,241 @@
+use std::path::{Component, Path};
+
+use crate::files::worlds::world_of;
+
+pub const BACKUP_INCLUDE_VAR: &str = "BACKUP_INCLUDE";
+pub const BACKUP_EXCLUDE_VAR: &str = "BACKUP_EXCLUDE";
+
+/// Which files under the saves root end up in a backup. Patterns are globs matched against the
+/// path relative to the root: `*` and `?` stay within one directory, `**` crosses directories, and
+/// a pattern without a `/` is matched against every file and directory name on the way. Matching a
+/// directory matches everything in it. Matching is case sensitive.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathFilter {
+  include: Vec<String>,
+  exclude: Vec<String>,
+  /// The only worlds whose files are let through, every world when empty.
+  worlds: Vec<String>,
+}
+
+fn parse_patterns(value: &str) -> Vec<String> {
+  value
+    .split(',')
+    .map(|pattern| pattern.trim().trim_start_matches("./").trim_matches('/'))
+    .filter(|pattern| !pattern.is_empty())
+    .map(str::to_string)
+    .collect()
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+  match pattern.first() {
+    None => text.is_empty(),
+    Some('*') if pattern.get(1) == Some(&'*') => {
+      let rest = &pattern[2..];
+      // `**/` also matches no directories at all.
+      if rest.first() == Some(&'/') && glob_match(&rest[1..], text) {
+        return true;
+      }
+      (0..=text.len()).any(|skip| glob_match(rest, &text[skip..]))
+    }
+    Some('*') => {
+      let rest = &pattern[1..];
+      for skip in 0..=text.len() {
+        if glob_match(rest, &text[skip..]) {
+          return true;
+        }
+        if text.get(skip) == Some(&'/') {
+          break;
+        }
+      }
+      false
+    }
+    Some('?') => text.first().is_some_and(|c| *c != '/') && glob_match(&pattern[1..], &text[1..]),
+    Some(c) => text.first() == Some(c) && glob_match(&pattern[1..], &text[1..]),
+  }
+}
+
+/// Whether `pattern` matches `components` or any of the directories leading up to it.
+fn matches_path(pattern: &str, components: &[String]) -> bool {
+  let pattern: Vec<char> = pattern.chars().collect();
+  if !pattern.contains(&'/') {
+    return components.iter().any(|component| {
+      let component: Vec<char> = component.chars().collect();
+      glob_match(&pattern, &component)
+    });
+  }
+  (1..=components.len()).any(|depth| {
+    let path: Vec<char> = components[..depth].join("/").chars().collect();
+    glob_match(&pattern, &path)
+  })
+}
+
+fn components(relative: &Path) -> Vec<String> {
+  relative
+    .components()
+    .filter_map(|component| match component {
+      Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+      _ => None,
+    })
+    .collect()
+}
+
+impl PathFilter {
+  /// Both lists are comma separated.
+  pub fn new(include: &str, exclude: &str) -> Self {
+    PathFilter {
+      include: parse_patterns(include),
+      exclude: parse_patterns(exclude),
+      worlds: Vec::new(),
+    }
+  }
+
+  /// The same filter, only letting through the world files of `worlds`. Files that don't belong to
+  /// a world are left to the patterns.
+  pub fn for_worlds(&self, worlds: &[String]) -> Self {
+    let mut filter = self.clone();
+    filter.worlds = worlds.to_vec();
+    filter
+  }
+
+  /// The worlds picked with `for_worlds`, empty when every world is let through.
+  pub fn worlds(&self) -> &[String] {
+    &self.worlds
+  }
+
+  /// The same filter, also leaving out the comma separated `exclude`.
+  pub fn excluding(&self, exclude: &str) -> Self {
+    let mut filter = self.clone();
+    filter.exclude.extend(parse_patterns(exclude));
+    filter
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.include.is_empty() && self.exclude.is_empty() && self.worlds.is_empty()
+  }
+
+  /// Whether the file at `relative` is backed up. Excludes win over includes, and with no includes
+  /// everything that isn't excluded is.
+  pub fn includes(&self, relative: &Path) -> bool {
+    if !self.worlds.is_empty() {
+      if let Some(world) = world_of(relative) {
+        if !self.worlds.contains(&world) {
+          return false;
+        }
+      }
+    }
+    let components = components(relative);
+    if self
+      .exclude
+      .iter()
+      .any(|pattern| matches_path(pattern, &components))
+    {
+      return false;
+    }
+    self.include.is_empty()
+      || self
+        .include
+        .iter()
+        .any(|pattern| matches_path(pattern, &components))
+  }
+
+  /// Whether the directory at `relative` is worth walking into. Only excludes can rule one out,
+  /// something further down could still be included.
+  pub fn descends_into(&self, relative: &Path) -> bool {
+    let components = components(relative);
+    !self
+      .exclude
+      .iter()
+      .any(|pattern| matches_path(pattern, &components))
+  }
+}
+
+#[cfg(test)]
+mod filter_tests {
+  use super::*;
+
+  fn included(filter: &PathFilter, path: &str) -> bool {
+    filter.includes(Path::new(path))
+  }
+
+  #[test]
+  fn everything_is_included_by_default() {
+    let filter = PathFilter::new("", " , ");
+    assert!(filter.is_empty());
+    assert!(included(&filter, "worlds_local/Dedicated.db"));
+    assert!(included(&filter, "characters/Viking.fch"));
+    assert!(filter.descends_into(Path::new("characters")));
+  }
+
+  #[test]
+  fn extension_patterns_match_at_any_depth() {
+    let filter = PathFilter::new("", "*.old");
+    assert!(!included(&filter, "Dedicated.db.old"));
+    assert!(!included(&filter, "worlds_local/Dedicated.db.old"));
+    assert!(!included(&filter, "worlds/nested/deeper/Dedicated.fwl.old"));
+    assert!(included(&filter, "worlds_local/Dedicated.db"));
+    assert!(included(&filter, "worlds_local/old/Dedicated.db"));
+  }
+
+  #[test]
+  fn directories_match_everything_inside() {
+    let filter = PathFilter::new("", "characters");
+    assert!(!filter.descends_into(Path::new("characters")));
+    assert!(!included(&filter, "characters/Viking.fch"));
+    assert!(included(&filter, "characters_local/Viking.fch"));
+  }
+
+  #[test]
+  fn paths_with_slashes_are_anchored_to_the_root() {
+    let filter = PathFilter::new("worlds_local/*.db,worlds_local/*.fwl", "");
+    assert!(included(&filter, "worlds_local/Dedicated.db"));
+    assert!(included(&filter, "worlds_local/Dedicated.fwl"));
+    assert!(!included(&filter, "worlds_local/Dedicated.db.old"));
+    assert!(!included(&filter, "backup/worlds_local/Dedicated.db"));
+    // `*` doesn't cross directories, `**` does.
+    assert!(!included(&filter, "worlds_local/nested/Dedicated.db"));
+    let filter = PathFilter::new("worlds_local/**/*.db", "");
+    assert!(included(&filter, "worlds_local/Dedicated.db"));
+    assert!(included(&filter, "worlds_local/nested/deeper/Dedicated.db"));
+  }
+
+  #[test]
+  fn excludes_win_over_includes() {
+    let filter = PathFilter::new("worlds_local", "*.old");
+    assert!(included(&filter, "worlds_local/Dedicated.db"));
+    assert!(!included(&filter, "worlds_local/Dedicated.db.old"));
+    assert!(!included(&filter, "characters/Viking.fch"));
+    // Includes never stop the walk, something further down may match.
+    assert!(filter.descends_into(Path::new("characters")));
+  }
+
+  #[test]
+  fn leaves_out_the_files_of_other_worlds() {
+    let filter = PathFilter::new("", "*.old").for_worlds(&["Dedicated".to_string()]);
+    assert!(!filter.is_empty());
+    assert_eq!(filter.worlds(), ["Dedicated".to_string()]);
+    assert!(included(&filter, "worlds_local/Dedicated.db"));
+    assert!(included(&filter, "worlds/Dedicated.fwl"));
+    assert!(!included(&filter, "worlds_local/Dedicated.db.old"));
+    assert!(!included(&filter, "worlds_local/Ashlands.db"));
+    assert!(!included(&filter, "worlds_local/Dedicated2.fwl"));
+    assert!(included(&filter, "worlds_local/notes.txt"));
+    assert!(included(&filter, "adminlist.txt"));
+  }
+
+  #[test]
+  fn matching_is_case_sensitive() {
+    let filter = PathFilter::new("", "*.OLD,Characters");
+    assert!(included(&filter, "worlds_local/Dedicated.db.old"));
+    assert!(!included(&filter, "worlds_local/Dedicated.db.OLD"));
+    assert!(included(&filter, "characters/Viking.fch"));
+    assert!(!included(&filter, "Characters/Viking.fch"));
+  }
+
+  #[test]
+  fn question_marks_match_one_character() {
+    let filter = PathFilter::new("", "Dedicated.d?");
+    assert!(!included(&filter, "worlds_local/Dedicated.db"));
+    assert!(included(&filter, "worlds_local/Dedicated.d"));
+    assert!(included(&filter, "worlds_local/Dedicated.dbb"));
+  }
+}
@@ -0,0 +1,147 @@
+use log::{debug, info};
+
+use crate::executable::{execute_logged, find_command};
+use crate::files::dir_size;
+use crate::utils::environment::fetch_var;
+
+pub const BACKUP_PRE_HOOK_VAR: &str = "BACKUP_PRE_HOOK";
+pub const BACKUP_POST_HOOK_VAR: &str = "BACKUP_POST_HOOK";
+
+/// Where the backup goes, set for both hooks.
+pub const ODIN_BACKUP_PATH: &str = "ODIN_BACKUP_PATH";
+/// The directory being backed up, set for both hooks.
+pub const ODIN_BACKUP_INPUT: &str = "ODIN_BACKUP_INPUT";
+/// `success` or `failure`, post hook only.
+pub const ODIN_BACKUP_STATUS: &str = "ODIN_BACKUP_STATUS";
+/// Size of the backup in bytes, `0` when it failed. Post hook only.
+pub const ODIN_BACKUP_SIZE: &str = "ODIN_BACKUP_SIZE";
+/// Why the backup failed, empty when it didn't. Post hook only.
+pub const ODIN_BACKUP_ERROR: &str = "ODIN_BACKUP_ERROR";
+
+/// Shell commands run around every backup, from `--pre-hook`/`--post-hook` or
+/// `BACKUP_PRE_HOOK`/`BACKUP_POST_HOOK`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BackupHooks {
+  pub(crate) pre: Option<String>,
+  pub(crate) post: Option<String>,
+}
+
+fn hook_from_env(name: &str) -> Option<String> {
+  let command = fetch_var(name, "");
+  (!command.trim().is_empty()).then_some(command)
+}
+
+impl BackupHooks {
+  pub fn from_env() -> Self {
+    BackupHooks {
+      pre: hook_from_env(BACKUP_PRE_HOOK_VAR),
+      post: hook_from_env(BACKUP_POST_HOOK_VAR),
+    }
+  }
+
+  /// Runs before anything is archived, an error means the backup must not go ahead.
+  pub fn run_pre(&self, input: &str, output: &str) -> Result<(), String> {
+    match &self.pre {
+      Some(command) => run_hook(
+        BACKUP_PRE_HOOK_VAR,
+        command,
+        &[(ODIN_BACKUP_PATH, output), (ODIN_BACKUP_INPUT, input)],
+      ),
+      None => Ok(()),
+    }
+  }
+
+  /// Runs once the backup is done or has failed, told which through `ODIN_BACKUP_STATUS`.
+  pub fn run_post(
+    &self,
+    input: &str,
+    output: &str,
+    result: &Result<(), String>,
+  ) -> Result<(), String> {
+    let command = match &self.post {
+      Some(command) => command,
+      None => return Ok(()),
+    };
+    let (status, size, error) = match result {
+      Ok(_) => ("success", dir_size(output), ""),
+      Err(message) => ("failure", 0, message.as_str()),
+    };
+    run_hook(
+      BACKUP_POST_HOOK_VAR,
+      command,
+      &[
+        (ODIN_BACKUP_PATH, output),
+        (ODIN_BACKUP_INPUT, input),
+        (ODIN_BACKUP_STATUS, status),
+        (ODIN_BACKUP_SIZE, &size.to_string()),
+        (ODIN_BACKUP_ERROR, error),
+      ],
+    )
+  }
+}
+
+fn run_hook(name: &str, command: &str, env: &[(&str, &str)]) -> Result<(), String> {
+  info!("Running {}", name);
+  // The command can hold tokens, like a ping url, so it is only shown when debugging.
+  debug!("{}: {}", name, command);
+  let mut shell =
+    find_command("sh").ok_or_else(|| format!("Unable to run {}, sh wasn't found", name))?;
+  shell.arg("-c").arg(command).envs(env.iter().copied());
+  match execute_logged(&mut shell, name) {
+    Ok(status) if status.success() => Ok(()),
+    Ok(status) => Err(match status.code() {
+      Some(code) => format!("{} exited with status code {}", name, code),
+      None => format!("{} was terminated by a signal", name),
+    }),
+    Err(e) => Err(format!("Unable to run {}: {}", name, e)),
+  }
+}
+
+#[cfg(test)]
+mod hooks_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::fs;
+
+  #[test]
+  fn failing_pre_hook_is_an_error() {
+    let hooks = BackupHooks {
+      pre: Some("echo quiescing; exit 3".to_string()),
+      post: None,
+    };
+    assert_eq!(
+      hooks.run_pre("/saves", "/backups/backup.tar.gz"),
+      Err("BACKUP_PRE_HOOK exited with status code 3".to_string())
+    );
+    assert_eq!(BackupHooks::default().run_pre("/saves", "/backups"), Ok(()));
+  }
+
+  #[test]
+  fn post_hook_is_told_how_the_backup_went() {
+    let temp = test_dir("hooks");
+    let root = temp.path();
+    let archive = root.join("backup.tar.gz");
+    fs::write(&archive, "12345").unwrap();
+    let report = root.join("report");
+    let hooks = BackupHooks {
+      pre: None,
+      post: Some(format!(
+        "echo \"$ODIN_BACKUP_STATUS $ODIN_BACKUP_SIZE $ODIN_BACKUP_PATH $ODIN_BACKUP_ERROR\" > {}",
+        report.display()
+      )),
+    };
+    let output = archive.to_string_lossy();
+    hooks.run_post("/saves", &output, &Ok(())).unwrap();
+    assert_eq!(
+      fs::read_to_string(&report).unwrap().trim(),
+      format!("success 5 {}", output)
+    );
+    hooks
+      .run_post("/saves", &output, &Err("disk full".to_string()))
+      .unwrap();
+    assert_eq!(
+      fs::read_to_string(&report).unwrap().trim(),
+      format!("failure 0 {} disk full", output)
+    );
+  }
+}
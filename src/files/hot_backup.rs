@@ -0,0 +1,179 @@
+use log::{debug, info};
+
+use std::{
+  env, fs, io,
+  path::{Path, PathBuf},
+  process, thread,
+  time::{Duration, Instant, SystemTime},
+};
+
+use crate::files::{filter::PathFilter, snapshot::create_snapshot, worlds::list_worlds};
+use crate::server::{LogWatcher, ServerEvent};
+
+pub const BACKUP_MAX_WAIT_VAR: &str = "BACKUP_MAX_WAIT";
+pub const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(120);
+
+/// A world file left alone this long isn't in the middle of being saved.
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether every file was last written at least `settle` before `now`. A time after `now` means
+/// the clock moved and isn't trusted.
+pub fn is_quiescent(modified: &[SystemTime], now: SystemTime, settle: Duration) -> bool {
+  modified
+    .iter()
+    .all(|modified| now.duration_since(*modified).is_ok_and(|age| age >= settle))
+}
+
+/// When each `.fwl` and `.db` in `saves` was last written.
+fn world_modified_times(saves: &Path) -> Vec<SystemTime> {
+  list_worlds(saves)
+    .iter()
+    .flat_map(|world| {
+      ["fwl", "db"]
+        .iter()
+        .map(move |extension| {
+          world
+            .directory
+            .join(format!("{}.{}", world.name, extension))
+        })
+        .collect::<Vec<PathBuf>>()
+    })
+    .filter_map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+    .collect()
+}
+
+/// Waits until the worlds in `saves` haven't been written for a few seconds, or the server logs
+/// that it finished saving. Returns `false` when that didn't happen within `max_wait`.
+pub fn wait_for_quiescence(saves: &Path, max_wait: Duration) -> bool {
+  let mut watcher = LogWatcher::for_server();
+  let started = Instant::now();
+  loop {
+    if is_quiescent(&world_modified_times(saves), SystemTime::now(), SETTLE_TIME) {
+      debug!("The world files haven't changed for {:?}", SETTLE_TIME);
+      return true;
+    }
+    if let Some(event) = watcher
+      .poll()
+      .into_iter()
+      .find(|event| matches!(event, ServerEvent::WorldSaved(_)))
+    {
+      info!("{}, backing up now", event);
+      return true;
+    }
+    if started.elapsed() >= max_wait {
+      return false;
+    }
+    thread::sleep(POLL_INTERVAL);
+  }
+}
+
+/// A copy of the saves taken while they were quiet, removed once dropped.
+pub struct StagedSaves {
+  path: PathBuf,
+}
+
+impl StagedSaves {
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+}
+
+impl Drop for StagedSaves {
+  fn drop(&mut self) {
+    if let Err(e) = fs::remove_dir_all(&self.path) {
+      debug!("Unable to remove {}: {}", self.path.display(), e);
+    }
+  }
+}
+
+/// Copies what `filter` lets through of `saves` to a staging directory in one pass, keeping
+/// modification times. Fails when a world was written to while it was being copied.
+pub fn stage_saves(saves: &Path, filter: &PathFilter) -> io::Result<StagedSaves> {
+  let before = world_modified_times(saves);
+  let nanos = SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .map_or(0, |elapsed| elapsed.subsec_nanos());
+  let staged = StagedSaves {
+    path: env::temp_dir().join(format!("odin-staging-{}-{}", process::id(), nanos)),
+  };
+  create_snapshot(saves, &staged.path, None, filter)?;
+  if world_modified_times(saves) != before {
+    return Err(io::Error::other(
+      "the server saved the world while it was being copied",
+    ));
+  }
+  debug!("Staged {} at {}", saves.display(), staged.path.display());
+  Ok(staged)
+}
+
+#[cfg(test)]
+mod hot_backup_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::fs::File;
+  use tempfile::TempDir;
+
+  fn saves_dir() -> TempDir {
+    let temp = test_dir("hot-backup");
+    let saves = temp.path();
+    fs::create_dir_all(saves.join("worlds_local")).unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.fwl"), "12").unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.db"), "345").unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.db.old"), "345").unwrap();
+    temp
+  }
+
+  fn age(saves: &Path, name: &str, age: Duration) {
+    File::options()
+      .write(true)
+      .open(saves.join("worlds_local").join(name))
+      .unwrap()
+      .set_modified(SystemTime::now() - age)
+      .unwrap();
+  }
+
+  #[test]
+  fn quiescent_once_nothing_was_written_recently() {
+    let now = SystemTime::now();
+    let settle = Duration::from_secs(5);
+    assert!(is_quiescent(&[], now, settle));
+    assert!(is_quiescent(
+      &[now - Duration::from_secs(60), now - settle],
+      now,
+      settle
+    ));
+    assert!(!is_quiescent(
+      &[now - Duration::from_secs(60), now - Duration::from_secs(2)],
+      now,
+      settle
+    ));
+    assert!(!is_quiescent(&[now + Duration::from_secs(60)], now, settle));
+  }
+
+  #[test]
+  fn waits_for_a_world_being_written() {
+    let temp = saves_dir();
+    let saves = temp.path();
+    age(saves, "Dedicated.fwl", Duration::from_secs(60));
+    age(saves, "Dedicated.db", Duration::from_secs(60));
+    assert!(wait_for_quiescence(saves, Duration::from_secs(0)));
+    age(saves, "Dedicated.db", Duration::from_secs(0));
+    assert!(!wait_for_quiescence(saves, Duration::from_secs(0)));
+  }
+
+  #[test]
+  fn stages_a_filtered_copy_that_is_removed_when_dropped() {
+    let temp = saves_dir();
+    let saves = temp.path();
+    let staged = stage_saves(saves, &PathFilter::new("", "*.old")).unwrap();
+    let path = staged.path().to_path_buf();
+    assert_eq!(
+      fs::read(path.join("worlds_local/Dedicated.db")).unwrap(),
+      b"345"
+    );
+    assert!(!path.join("worlds_local/Dedicated.db.old").exists());
+    drop(staged);
+    assert!(!path.exists());
+  }
+}
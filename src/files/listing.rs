@@ -0,0 +1,249 @@
+use chrono::{DateTime, Local, TimeZone};
+use serde::Serialize;
+
+use std::{fmt, fs, path::Path};
+
+use crate::files::{
+  archive::{hash_file, read_checksum, read_metadata, ArchiveFormat, CHECKSUM_EXTENSION},
+  encryption::{is_encrypted, plaintext_name},
+  metadata::BackupMetadata,
+  retention::parse_name_timestamp,
+  s3::{RemoteBackup, S3Config},
+  snapshot::{is_snapshot_name, snapshot_size},
+};
+
+const PARTIAL_SUFFIX: &str = ".partial";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Location {
+  Local,
+  Remote,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChecksumStatus {
+  /// The archive hashes to what its manifest says.
+  Valid,
+  Mismatch,
+  /// No manifest was written for the archive.
+  Missing,
+  /// There is a manifest but the archive wasn't hashed, remote backups aren't downloaded to list
+  /// them.
+  Unverified,
+}
+
+impl fmt::Display for ChecksumStatus {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      ChecksumStatus::Valid => "valid",
+      ChecksumStatus::Mismatch => "mismatch",
+      ChecksumStatus::Missing => "missing",
+      ChecksumStatus::Unverified => "unverified",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// One row of `odin backup list`. The field names are what the JSON output is keyed by, keep them
+/// stable for the scripts restoring from it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BackupListing {
+  pub(crate) name: String,
+  pub(crate) location: Location,
+  /// The local path or the `s3://` uri, what `odin restore` takes.
+  pub(crate) path: String,
+  /// Taken from the `YYYYMMDD-HHMMSS` in the name, the file's age otherwise.
+  pub(crate) timestamp: Option<String>,
+  pub(crate) size_bytes: u64,
+  /// The archive format, or `incremental` for snapshot directories.
+  pub(crate) format: String,
+  /// `None` for snapshots, they never have a manifest.
+  pub(crate) checksum: Option<ChecksumStatus>,
+  /// Ends in `.age`, restoring it takes the key.
+  pub(crate) encrypted: bool,
+  /// The archive's `odin-backup.json`, only read from local archives that aren't encrypted.
+  pub(crate) metadata: Option<BackupMetadata>,
+}
+
+fn timestamp_of(name: &str, fallback: Option<DateTime<Local>>) -> Option<String> {
+  parse_name_timestamp(name)
+    .and_then(|timestamp| Local.from_local_datetime(&timestamp).earliest())
+    .or(fallback)
+    .map(|timestamp| timestamp.to_rfc3339())
+}
+
+fn checksum_status(archive: &Path) -> ChecksumStatus {
+  match read_checksum(archive) {
+    Ok(Some(expected)) => match hash_file(archive) {
+      Ok(actual) if actual == expected => ChecksumStatus::Valid,
+      _ => ChecksumStatus::Mismatch,
+    },
+    Ok(None) => ChecksumStatus::Missing,
+    Err(_) => ChecksumStatus::Unverified,
+  }
+}
+
+/// Every archive and snapshot in `directory`, whatever it is named. Archives are hashed against
+/// their manifest, so this reads each of them once.
+pub fn list_local(directory: &Path) -> Vec<BackupListing> {
+  let entries = match fs::read_dir(directory) {
+    Ok(entries) => entries,
+    Err(_) => return Vec::new(),
+  };
+  entries
+    .filter_map(|entry| entry.ok())
+    .filter_map(|entry| {
+      let name = entry.file_name().to_string_lossy().to_string();
+      let metadata = entry.metadata().ok()?;
+      let modified = metadata.modified().ok().map(DateTime::<Local>::from);
+      let path = entry.path();
+      let (size_bytes, format, checksum) = if metadata.is_dir() && is_snapshot_name(&name) {
+        (snapshot_size(&path), "incremental".to_string(), None)
+      } else if metadata.is_file() && !name.ends_with(PARTIAL_SUFFIX) {
+        let format = ArchiveFormat::from_extension(Path::new(plaintext_name(&name)))?;
+        (
+          metadata.len(),
+          format.to_string(),
+          Some(checksum_status(&path)),
+        )
+      } else {
+        return None;
+      };
+      let encrypted = is_encrypted(&path);
+      let metadata = if checksum.is_some() && !encrypted {
+        read_metadata(&path).ok().flatten()
+      } else {
+        None
+      };
+      Some(BackupListing {
+        timestamp: timestamp_of(&name, modified),
+        name,
+        location: Location::Local,
+        path: path.to_string_lossy().to_string(),
+        size_bytes,
+        encrypted,
+        format,
+        checksum,
+        metadata,
+      })
+    })
+    .collect()
+}
+
+/// The archives in the bucket. Manifests are only checked for, not compared.
+pub fn list_bucket(config: &S3Config, objects: &[RemoteBackup]) -> Vec<BackupListing> {
+  objects
+    .iter()
+    .filter(|object| !object.key.ends_with(CHECKSUM_EXTENSION))
+    .filter_map(|object| {
+      let name = object
+        .key
+        .rsplit('/')
+        .next()
+        .unwrap_or(&object.key)
+        .to_string();
+      let format = ArchiveFormat::from_extension(Path::new(plaintext_name(&name)))?;
+      let manifest = format!("{}{}", object.key, CHECKSUM_EXTENSION);
+      let has_manifest = objects.iter().any(|other| other.key == manifest);
+      let modified = DateTime::parse_from_rfc3339(&object.last_modified)
+        .ok()
+        .map(|modified| modified.with_timezone(&Local));
+      Some(BackupListing {
+        timestamp: timestamp_of(&name, modified),
+        encrypted: is_encrypted(Path::new(&name)),
+        name,
+        location: Location::Remote,
+        path: config.uri_for(&object.key),
+        size_bytes: object.size,
+        format: format.to_string(),
+        checksum: Some(if has_manifest {
+          ChecksumStatus::Unverified
+        } else {
+          ChecksumStatus::Missing
+        }),
+        metadata: None,
+      })
+    })
+    .collect()
+}
+
+/// Newest first, undated backups last, and at most `limit` of them.
+pub fn sort_newest_first(
+  mut backups: Vec<BackupListing>,
+  limit: Option<usize>,
+) -> Vec<BackupListing> {
+  let parsed = |backup: &BackupListing| {
+    backup
+      .timestamp
+      .as_deref()
+      .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+  };
+  backups.sort_by(|a, b| parsed(b).cmp(&parsed(a)).then_with(|| a.name.cmp(&b.name)));
+  if let Some(limit) = limit {
+    backups.truncate(limit);
+  }
+  backups
+}
+
+#[cfg(test)]
+mod listing_tests {
+  use super::*;
+  use crate::files::archive::write_checksum;
+  use crate::utils::testing::test_dir;
+
+  #[test]
+  fn lists_archives_with_their_manifest_status() {
+    let temp = test_dir("listing");
+    let root = temp.path();
+    fs::create_dir_all(root.join("20210302-120000-backup")).unwrap();
+    fs::write(root.join("20210301-120000-backup.tar.gz"), "archive").unwrap();
+    write_checksum(&root.join("20210301-120000-backup.tar.gz")).unwrap();
+    fs::write(root.join("20210303-120000-backup.zip"), "archive").unwrap();
+    write_checksum(&root.join("20210303-120000-backup.zip")).unwrap();
+    fs::write(root.join("20210303-120000-backup.zip"), "tampered").unwrap();
+    fs::write(root.join("world.tar"), "archive").unwrap();
+    fs::write(root.join("20210304-120000-backup.tar.zst.age"), "archive").unwrap();
+    write_checksum(&root.join("20210304-120000-backup.tar.zst.age")).unwrap();
+    fs::write(root.join("notes.txt"), "not a backup").unwrap();
+
+    let backups = sort_newest_first(list_local(root), None);
+    let summary: Vec<(&str, &str, Option<ChecksumStatus>)> = backups
+      .iter()
+      .map(|backup| {
+        (
+          backup.name.as_str(),
+          backup.format.as_str(),
+          backup.checksum,
+        )
+      })
+      .collect();
+    // The undated archive takes the file's age, which is now.
+    assert_eq!(
+      summary,
+      vec![
+        ("world.tar", "tar", Some(ChecksumStatus::Missing)),
+        (
+          "20210304-120000-backup.tar.zst.age",
+          "zstd",
+          Some(ChecksumStatus::Valid)
+        ),
+        (
+          "20210303-120000-backup.zip",
+          "zip",
+          Some(ChecksumStatus::Mismatch)
+        ),
+        ("20210302-120000-backup", "incremental", None),
+        (
+          "20210301-120000-backup.tar.gz",
+          "tar.gz",
+          Some(ChecksumStatus::Valid)
+        ),
+      ]
+    );
+    assert!(backups[1].encrypted && !backups[0].encrypted);
+    assert!(backups.iter().all(|backup| backup.metadata.is_none()));
+    assert_eq!(sort_newest_first(backups, Some(2)).len(), 2);
+  }
+}
@@ -0,0 +1,73 @@
+use log::warn;
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+
+use crate::utils::get_odin_dir;
+
+/// Held while a backup is being written.
+pub const BACKUP_LOCK_FILE_NAME: &str = "backup.lock";
+/// Held while the server is being updated.
+pub const UPDATE_LOCK_FILE_NAME: &str = "update.lock";
+
+pub enum OperationLock {
+  /// Held for as long as the file is kept. `None` when locking isn't possible, which shouldn't
+  /// stop the operation itself.
+  Acquired(Option<File>),
+  InProgress,
+}
+
+/// Takes the lock `name` in the odin directory, unless another odin process or thread holds it.
+pub fn lock_operation(name: &str) -> OperationLock {
+  let odin_dir = get_odin_dir();
+  let lock_file = fs::create_dir_all(&odin_dir).and_then(|_| {
+    OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(odin_dir.join(name))
+  });
+  let lock_file = match lock_file {
+    Ok(file) => file,
+    Err(e) => {
+      warn!("Unable to open the lock {}: {}", name, e);
+      return OperationLock::Acquired(None);
+    }
+  };
+  match lock_file.try_lock() {
+    Ok(_) => OperationLock::Acquired(Some(lock_file)),
+    Err(TryLockError::WouldBlock) => OperationLock::InProgress,
+    Err(TryLockError::Error(e)) => {
+      warn!("Unable to take the lock {}: {}", name, e);
+      OperationLock::Acquired(None)
+    }
+  }
+}
+
+/// Whether something holds the lock `name` right now.
+pub fn is_in_progress(name: &str) -> bool {
+  matches!(lock_operation(name), OperationLock::InProgress)
+}
+
+#[cfg(test)]
+mod lock_tests {
+  use super::*;
+  use crate::constants;
+  use crate::utils::testing::test_dir;
+  use serial_test::serial;
+  use std::env;
+
+  #[test]
+  #[serial]
+  fn tells_when_an_operation_holds_the_lock() {
+    let temp = test_dir("lock");
+    let root = temp.path();
+    env::set_var(constants::ODIN_WORKING_DIR, root);
+    assert!(!is_in_progress(UPDATE_LOCK_FILE_NAME));
+    let held = lock_operation(UPDATE_LOCK_FILE_NAME);
+    assert!(is_in_progress(UPDATE_LOCK_FILE_NAME));
+    assert!(!is_in_progress(BACKUP_LOCK_FILE_NAME));
+    drop(held);
+    assert!(!is_in_progress(UPDATE_LOCK_FILE_NAME));
+    env::remove_var(constants::ODIN_WORKING_DIR);
+  }
+}
@@ -0,0 +1,203 @@
+use chrono::{Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+
+use std::{fmt, io, path::Path};
+
+use crate::constants;
+use crate::files::{
+  archive::hash_file,
+  filter::PathFilter,
+  progress::format_bytes,
+  scope::BackupScope,
+  worlds::{list_worlds, selected_world},
+};
+use crate::mods::bepinex::{bepinex_version, is_bepinex_present};
+use crate::server::installed_build_id;
+use crate::utils::environment::fetch_var;
+
+/// Written at the root of every archive, next to the saves.
+pub const METADATA_FILE_NAME: &str = "odin-backup.json";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldFile {
+  /// Relative to the saves directory, like `worlds_local/Dedicated.db`.
+  pub(crate) path: String,
+  pub(crate) size_bytes: u64,
+  pub(crate) sha256: String,
+}
+
+/// What a backup was taken of, so it can still be told apart long after. Every field defaults so
+/// metadata written by a later odin can still be read.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupMetadata {
+  pub(crate) server_name: String,
+  pub(crate) world: String,
+  pub(crate) odin_version: String,
+  /// `None` when the Steam app manifest couldn't be read.
+  pub(crate) valheim_build_id: Option<String>,
+  pub(crate) bepinex_installed: bool,
+  /// `None` when BepInEx isn't installed or hasn't logged its version yet.
+  pub(crate) bepinex_version: Option<String>,
+  pub(crate) timestamp: String,
+  pub(crate) files: Vec<WorldFile>,
+  /// Empty for backups made before scopes, those only hold worlds.
+  pub(crate) scopes: Vec<BackupScope>,
+  /// The worlds picked with `BACKUP_WORLDS`, empty when every world was backed up.
+  pub(crate) worlds: Vec<String>,
+}
+
+/// The `.fwl` and `.db` of every world in `saves` that `filter` lets into the backup.
+pub fn world_files(saves: &Path, filter: &PathFilter) -> io::Result<Vec<WorldFile>> {
+  let mut files = Vec::new();
+  for world in list_worlds(saves) {
+    for extension in ["fwl", "db"].iter() {
+      let path = world
+        .directory
+        .join(format!("{}.{}", world.name, extension));
+      let relative = match path.strip_prefix(saves) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => continue,
+      };
+      if !path.is_file() || !filter.includes(&relative) {
+        continue;
+      }
+      files.push(WorldFile {
+        path: relative.to_string_lossy().to_string(),
+        size_bytes: path.metadata()?.len(),
+        sha256: hash_file(&path)?,
+      });
+    }
+  }
+  Ok(files)
+}
+
+impl BackupMetadata {
+  /// Describes a backup of `scopes` taken now, on this server.
+  pub fn collect(saves: &Path, filter: &PathFilter, scopes: &[BackupScope]) -> io::Result<Self> {
+    let bepinex_installed = is_bepinex_present();
+    Ok(BackupMetadata {
+      server_name: fetch_var("NAME", "Valheim powered by Odin"),
+      world: configured_world(),
+      odin_version: constants::VERSION.to_string(),
+      valheim_build_id: installed_build_id(),
+      bepinex_installed,
+      bepinex_version: if bepinex_installed {
+        bepinex_version()
+      } else {
+        None
+      },
+      timestamp: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+      files: if scopes.contains(&BackupScope::Worlds) {
+        world_files(saves, filter)?
+      } else {
+        Vec::new()
+      },
+      scopes: scopes.to_vec(),
+      worlds: filter.worlds().to_vec(),
+    })
+  }
+
+  /// What the backup holds, worlds for backups made before scopes.
+  pub fn scopes(&self) -> Vec<BackupScope> {
+    if self.scopes.is_empty() {
+      vec![BackupScope::Worlds]
+    } else {
+      self.scopes.clone()
+    }
+  }
+
+  pub fn to_json(&self) -> io::Result<Vec<u8>> {
+    serde_json::to_vec_pretty(self).map_err(io::Error::other)
+  }
+
+  pub fn from_json(content: &[u8]) -> io::Result<Self> {
+    serde_json::from_slice(content).map_err(|e| {
+      io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} is invalid: {}", METADATA_FILE_NAME, e),
+      )
+    })
+  }
+}
+
+impl fmt::Display for BackupMetadata {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} on {}, taken {} with odin {}, Valheim build {}, {}",
+      self.world,
+      self.server_name,
+      self.timestamp,
+      self.odin_version,
+      self.valheim_build_id.as_deref().unwrap_or("unknown"),
+      match (self.bepinex_installed, &self.bepinex_version) {
+        (true, Some(version)) => format!("BepInEx {}", version),
+        (true, None) => "BepInEx".to_string(),
+        (false, _) => "no BepInEx".to_string(),
+      }
+    )
+  }
+}
+
+impl fmt::Display for WorldFile {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{} ({}, sha256 {})",
+      self.path,
+      format_bytes(self.size_bytes),
+      self.sha256
+    )
+  }
+}
+
+/// The world the server loads, the one picked with `odin world use` over `WORLD`.
+pub fn configured_world() -> String {
+  selected_world().unwrap_or_else(|| fetch_var("WORLD", "Dedicated"))
+}
+
+#[cfg(test)]
+mod metadata_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::fs;
+
+  #[test]
+  fn lists_the_world_files_backed_up() {
+    let temp = test_dir("metadata");
+    let saves = temp.path();
+    fs::create_dir_all(saves.join("worlds_local")).unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.fwl"), "12").unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.db"), "345").unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.db.old"), "345").unwrap();
+    fs::write(saves.join("worlds_local/Ashlands.fwl"), "1").unwrap();
+
+    let files = world_files(saves, &PathFilter::new("", "Ashlands.*")).unwrap();
+    let paths: Vec<(&str, u64)> = files
+      .iter()
+      .map(|file| (file.path.as_str(), file.size_bytes))
+      .collect();
+    assert_eq!(
+      paths,
+      vec![
+        ("worlds_local/Dedicated.fwl", 2),
+        ("worlds_local/Dedicated.db", 3)
+      ]
+    );
+    assert_eq!(
+      files[1].sha256,
+      hash_file(&saves.join("worlds_local/Dedicated.db")).unwrap()
+    );
+  }
+
+  #[test]
+  fn reads_metadata_with_missing_fields() {
+    let metadata = BackupMetadata::from_json(br#"{"world": "Dedicated", "extra": 1}"#).unwrap();
+    assert_eq!(metadata.world, "Dedicated");
+    assert_eq!(metadata.valheim_build_id, None);
+    assert!(metadata.files.is_empty());
+    assert_eq!(metadata.scopes(), vec![BackupScope::Worlds]);
+    assert!(BackupMetadata::from_json(b"not json").is_err());
+  }
+}
@@ -1,11 +1,33 @@
+pub mod archive;
+pub mod compression;
 pub mod config;
+pub mod destination;
+pub mod encryption;
+pub mod export;
+pub mod failure;
+pub mod filter;
+pub mod hooks;
+pub mod hot_backup;
+pub mod listing;
+pub mod lock;
+pub mod metadata;
+pub mod naming;
+pub mod progress;
+pub mod retention;
+pub mod s3;
+pub mod scope;
+pub mod sftp;
+pub mod snapshot;
+pub mod state;
+pub mod validation;
+pub mod worlds;
 
-use crate::executable::create_execution;
+use crate::server::{SaveSettings, WorldModifiers};
 use crate::utils::get_working_dir;
 use log::{error, info};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::fs::{remove_file, File};
+use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use std::process::exit;
@@ -18,6 +40,33 @@ pub struct ValheimArguments {
   pub(crate) public: String,
   pub(crate) password: String,
   pub(crate) command: String,
+  /// Configs written before crossplay was an option have it off.
+  #[serde(default)]
+  pub(crate) crossplay: bool,
+  /// Read from `WORLD_PRESET`, `WORLD_MODIFIERS` and `WORLD_SET_KEYS` on every launch, never stored.
+  #[serde(skip)]
+  pub(crate) world_modifiers: WorldModifiers,
+  /// Read from `SAVE_INTERVAL_SECONDS` and the `GAME_BACKUP*` variables on every launch, never stored.
+  #[serde(skip)]
+  pub(crate) save_settings: SaveSettings,
+  /// Read from `SERVER_EXTRA_ARGS` on every launch, never stored.
+  #[serde(skip)]
+  pub(crate) extra_args: Vec<String>,
+}
+
+/// Total size in bytes of every file under `path`, unreadable entries are skipped.
+pub fn dir_size(path: &str) -> u64 {
+  let entries = match fs::read_dir(path) {
+    Ok(entries) => entries,
+    Err(_) => return fs::metadata(path).map(|meta| meta.len()).unwrap_or(0),
+  };
+  entries
+    .filter_map(|entry| entry.ok())
+    .map(|entry| match entry.file_type() {
+      Ok(file_type) if file_type.is_dir() => dir_size(entry.path().to_str().unwrap_or_default()),
+      _ => entry.metadata().map(|meta| meta.len()).unwrap_or(0),
+    })
+    .sum()
 }
 
 pub fn create_file(path: &str) -> File {
@@ -36,18 +85,6 @@ pub trait FileManager {
   fn exists(&self) -> bool {
     Path::new(self.path().as_str()).exists()
   }
-  fn remove(&self) -> bool {
-    match remove_file(self.path()) {
-      Ok(_) => {
-        info!("Successfully deleted {}", self.path());
-        true
-      }
-      Err(_) => {
-        error!("Did not find or could not delete {}", self.path());
-        false
-      }
-    }
-  }
   fn read(&self) -> String {
     if self.exists() {
       fs::read_to_string(self.path()).unwrap()
@@ -68,18 +105,6 @@ pub trait FileManager {
       }
     }
   }
-  fn set_executable(&self) -> bool {
-    if let Ok(_output) = create_execution("chmod")
-      .args(&["+x", self.path().as_str()])
-      .output()
-    {
-      info!("Successfully set {} to executable", self.path());
-      true
-    } else {
-      error!("Unable to set {} to executable", self.path());
-      false
-    }
-  }
 }
 
 pub struct ManagedFile {
@@ -92,7 +117,10 @@ impl FileManager for ManagedFile {
     if supplied_path.exists() {
       supplied_path.to_str().unwrap().to_string()
     } else {
-      format!("{}/{}", get_working_dir(), self.name)
+      get_working_dir()
+        .join(&self.name)
+        .to_string_lossy()
+        .to_string()
     }
   }
 }
@@ -0,0 +1,199 @@
+use chrono::{DateTime, Local};
+
+use std::path::{Path, PathBuf};
+
+use crate::files::archive::checksum_path;
+use crate::utils::environment::fetch_var;
+
+pub const BACKUP_NAME_FORMAT_VAR: &str = "BACKUP_NAME_FORMAT";
+/// How backups have always been named.
+pub const DEFAULT_NAME_FORMAT: &str = "{timestamp}-backup{ext}";
+
+const TOKENS: [&str; 5] = ["server_name", "world", "timestamp", "date", "ext"];
+/// Can't be used in a file name on at least one of the file systems backups end up on.
+const ILLEGAL_CHARACTERS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// What the tokens of a name template are replaced with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameTokens {
+  pub(crate) server_name: String,
+  pub(crate) world: String,
+  pub(crate) taken_at: DateTime<Local>,
+  pub(crate) ext: String,
+}
+
+impl NameTokens {
+  pub fn from_env(taken_at: DateTime<Local>, ext: &str) -> Self {
+    NameTokens {
+      server_name: fetch_var("NAME", "Valheim powered by Odin"),
+      world: fetch_var("WORLD", "Dedicated"),
+      taken_at,
+      ext: ext.to_string(),
+    }
+  }
+
+  fn value_of(&self, token: &str) -> String {
+    match token {
+      "server_name" => self.server_name.clone(),
+      "world" => self.world.clone(),
+      "timestamp" => self.taken_at.format("%Y%m%d-%H%M%S").to_string(),
+      "date" => self.taken_at.format("%Y-%m-%d").to_string(),
+      _ => self.ext.clone(),
+    }
+  }
+}
+
+enum Part<'a> {
+  Text(&'a str),
+  Token(&'a str),
+}
+
+fn parse_template(template: &str) -> Result<Vec<Part<'_>>, String> {
+  let mut parts = Vec::new();
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    parts.push(Part::Text(&rest[..start]));
+    let end = rest[start..]
+      .find('}')
+      .ok_or_else(|| format!("Unclosed {{ in the backup name format '{}'", template))?;
+    let token = &rest[start + 1..start + end];
+    if !TOKENS.contains(&token) {
+      return Err(format!(
+        "Unknown token {{{}}} in the backup name format '{}', expected one of {}",
+        token,
+        template,
+        TOKENS
+          .iter()
+          .map(|token| format!("{{{}}}", token))
+          .collect::<Vec<String>>()
+          .join(", ")
+      ));
+    }
+    parts.push(Part::Token(token));
+    rest = &rest[start + end + 1..];
+  }
+  parts.push(Part::Text(rest));
+  Ok(parts)
+}
+
+/// Checks a template before any backup is named with it, so a typo fails up front.
+pub fn validate_template(template: &str) -> Result<(), String> {
+  let parts = parse_template(template)?;
+  // Retention and `odin backup list` date backups by it.
+  if !parts
+    .iter()
+    .any(|part| matches!(part, Part::Token("timestamp")))
+  {
+    return Err(format!(
+      "The backup name format '{}' needs a {{timestamp}} so old backups can be told apart and pruned",
+      template
+    ));
+  }
+  Ok(())
+}
+
+fn sanitize(name: &str) -> String {
+  name
+    .chars()
+    .map(|c| {
+      if ILLEGAL_CHARACTERS.contains(&c) || c.is_control() {
+        '_'
+      } else {
+        c
+      }
+    })
+    .collect()
+}
+
+/// The file name `template` gives a backup. Characters that aren't allowed in file names are
+/// replaced with `_`, and the extension is added if the template leaves out `{ext}`.
+pub fn render_name(template: &str, tokens: &NameTokens) -> Result<String, String> {
+  validate_template(template)?;
+  let parts = parse_template(template)?;
+  let mut name: String = parts
+    .iter()
+    .map(|part| match part {
+      Part::Text(text) => text.to_string(),
+      Part::Token(token) => tokens.value_of(token),
+    })
+    .collect();
+  if !parts.iter().any(|part| matches!(part, Part::Token("ext"))) {
+    name.push_str(&tokens.ext);
+  }
+  Ok(sanitize(name.trim()))
+}
+
+/// `directory/name`, or `directory/<name>-<n><ext>` with the first counter that isn't taken when
+/// another backup already has the name, say a second server writing to the same share.
+pub fn unique_path(directory: &Path, name: &str, ext: &str) -> PathBuf {
+  let is_taken = |path: &Path| path.exists() || checksum_path(path).exists();
+  let path = directory.join(name);
+  if !is_taken(&path) {
+    return path;
+  }
+  let stem = name.strip_suffix(ext).unwrap_or(name);
+  (1..)
+    .map(|counter| directory.join(format!("{}-{}{}", stem, counter, ext)))
+    .find(|path| !is_taken(path))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod naming_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use chrono::TimeZone;
+  use std::fs;
+
+  fn tokens(ext: &str) -> NameTokens {
+    NameTokens {
+      server_name: "My \"Cool\" Server: EU/West".to_string(),
+      world: "Dedicated".to_string(),
+      taken_at: Local.ymd(2021, 3, 1).and_hms(12, 0, 0),
+      ext: ext.to_string(),
+    }
+  }
+
+  #[test]
+  fn default_format_matches_the_old_names() {
+    assert_eq!(
+      render_name(DEFAULT_NAME_FORMAT, &tokens(".tar.gz")).unwrap(),
+      "20210301-120000-backup.tar.gz"
+    );
+    // Snapshots have no extension.
+    assert_eq!(
+      render_name(DEFAULT_NAME_FORMAT, &tokens("")).unwrap(),
+      "20210301-120000-backup"
+    );
+  }
+
+  #[test]
+  fn sanitizes_token_values() {
+    assert_eq!(
+      render_name("{server_name}-{world}-{date}-{timestamp}", &tokens(".zip")).unwrap(),
+      "My _Cool_ Server_ EU_West-Dedicated-2021-03-01-20210301-120000.zip"
+    );
+  }
+
+  #[test]
+  fn rejects_bad_templates() {
+    assert!(validate_template("{world}-{date}{ext}").is_err());
+    assert!(validate_template("{world}-{timestamp").is_err());
+    assert!(validate_template("{wrld}-{timestamp}{ext}").is_err());
+    assert!(validate_template("{world}-{timestamp}{ext}").is_ok());
+  }
+
+  #[test]
+  fn counts_up_past_taken_names() {
+    let temp = test_dir("naming");
+    let root = temp.path();
+    let name = "20210301-120000-backup.tar.gz";
+    assert_eq!(unique_path(root, name, ".tar.gz"), root.join(name));
+    fs::write(root.join(name), "").unwrap();
+    fs::write(root.join("20210301-120000-backup-1.tar.gz.sha256"), "").unwrap();
+    assert_eq!(
+      unique_path(root, name, ".tar.gz"),
+      root.join("20210301-120000-backup-2.tar.gz")
+    );
+  }
+}
@@ -0,0 +1,225 @@
+use log::info;
+
+use std::{
+  fmt, fs,
+  io::{self, Read},
+  path::Path,
+  time::{Duration, Instant},
+};
+
+use crate::files::filter::PathFilter;
+
+/// How long a backup can go without logging how far it got.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+/// Percent of the input between progress lines, whichever comes first with the interval.
+const REPORT_STEP: u64 = 10;
+
+pub fn format_bytes(bytes: u64) -> String {
+  let units = ["B", "KB", "MB", "GB", "TB"];
+  let mut value = bytes as f64;
+  let mut unit = 0;
+  while value >= 1024.0 && unit < units.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{} {}", bytes, units[0])
+  } else {
+    format!("{:.1} {}", value, units[unit])
+  }
+}
+
+pub fn format_elapsed(elapsed: Duration) -> String {
+  let seconds = elapsed.as_secs();
+  if seconds >= 60 {
+    format!("{}m {}s", seconds / 60, seconds % 60)
+  } else {
+    format!("{:.1}s", elapsed.as_secs_f64())
+  }
+}
+
+/// The files and bytes `filter` lets through of a directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InputSize {
+  pub(crate) files: usize,
+  pub(crate) bytes: u64,
+}
+
+fn measure_dir(size: &mut InputSize, directory: &Path, relative: &Path, filter: &PathFilter) {
+  let entries = match fs::read_dir(directory) {
+    Ok(entries) => entries,
+    Err(_) => return,
+  };
+  for entry in entries.filter_map(|entry| entry.ok()) {
+    let relative = relative.join(entry.file_name());
+    let metadata = match fs::metadata(entry.path()) {
+      Ok(metadata) => metadata,
+      Err(_) => continue,
+    };
+    if metadata.is_dir() {
+      if filter.descends_into(&relative) {
+        measure_dir(size, &entry.path(), &relative, filter);
+      }
+    } else if filter.includes(&relative) {
+      size.files += 1;
+      size.bytes += metadata.len();
+    }
+  }
+}
+
+/// Walks `input` the same way archiving it does, so progress can be told in percent.
+pub fn measure_input(input: &Path, filter: &PathFilter) -> InputSize {
+  let mut size = InputSize::default();
+  measure_dir(&mut size, input, Path::new(""), filter);
+  size
+}
+
+/// Logs how much of the input has been read, every `REPORT_STEP` percent or `REPORT_INTERVAL`.
+pub struct Progress {
+  total_bytes: u64,
+  processed: u64,
+  started: Instant,
+  last_report: Instant,
+  next_step: u64,
+  quiet: bool,
+}
+
+impl Progress {
+  pub fn new(total_bytes: u64, quiet: bool) -> Self {
+    let now = Instant::now();
+    Progress {
+      total_bytes,
+      processed: 0,
+      started: now,
+      last_report: now,
+      next_step: REPORT_STEP,
+      quiet,
+    }
+  }
+
+  fn percent(&self) -> u64 {
+    if self.total_bytes == 0 {
+      return 100;
+    }
+    (self.processed * 100 / self.total_bytes).min(100)
+  }
+
+  pub fn advance(&mut self, bytes: u64) {
+    self.processed += bytes;
+    if self.quiet {
+      return;
+    }
+    let percent = self.percent();
+    if percent < self.next_step && self.last_report.elapsed() < REPORT_INTERVAL {
+      return;
+    }
+    if percent < 100 {
+      info!(
+        "Backed up {}% ({} of {}) in {}",
+        percent,
+        format_bytes(self.processed),
+        format_bytes(self.total_bytes),
+        format_elapsed(self.elapsed())
+      );
+    }
+    self.last_report = Instant::now();
+    self.next_step = (percent / REPORT_STEP + 1) * REPORT_STEP;
+  }
+
+  pub fn elapsed(&self) -> Duration {
+    self.started.elapsed()
+  }
+}
+
+/// Counts what is read through it towards the progress.
+pub struct ProgressReader<'a, R> {
+  inner: R,
+  progress: &'a mut Progress,
+}
+
+impl<'a, R: Read> ProgressReader<'a, R> {
+  pub fn new(inner: R, progress: &'a mut Progress) -> Self {
+    ProgressReader { inner, progress }
+  }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let read = self.inner.read(buf)?;
+    self.progress.advance(read as u64);
+    Ok(read)
+  }
+}
+
+/// What a finished backup is summed up with, in the log and the `Backup Successful` notification.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackupSummary {
+  pub(crate) files: usize,
+  pub(crate) input_bytes: u64,
+  pub(crate) output_bytes: u64,
+  pub(crate) elapsed: Duration,
+}
+
+impl fmt::Display for BackupSummary {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "Backed up {} file(s), {} into {}",
+      self.files,
+      format_bytes(self.input_bytes),
+      format_bytes(self.output_bytes)
+    )?;
+    if self.output_bytes > 0 {
+      write!(
+        f,
+        " (compression ratio {:.2}:1)",
+        self.input_bytes as f64 / self.output_bytes as f64
+      )?;
+    }
+    write!(f, " in {}", format_elapsed(self.elapsed))
+  }
+}
+
+#[cfg(test)]
+mod progress_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+
+  #[test]
+  fn formats_sizes_and_durations() {
+    assert_eq!(format_bytes(512), "512 B");
+    assert_eq!(format_bytes(4 * 1024 * 1024 * 1024), "4.0 GB");
+    assert_eq!(format_elapsed(Duration::from_millis(2500)), "2.5s");
+    assert_eq!(format_elapsed(Duration::from_secs(125)), "2m 5s");
+  }
+
+  #[test]
+  fn summarizes_the_backup() {
+    let summary = BackupSummary {
+      files: 3,
+      input_bytes: 4 * 1024 * 1024,
+      output_bytes: 1024 * 1024,
+      elapsed: Duration::from_secs(90),
+    };
+    assert_eq!(
+      summary.to_string(),
+      "Backed up 3 file(s), 4.0 MB into 1.0 MB (compression ratio 4.00:1) in 1m 30s"
+    );
+  }
+
+  #[test]
+  fn measures_only_what_the_filter_lets_through() {
+    let temp = test_dir("progress");
+    let root = temp.path();
+    fs::create_dir_all(root.join("worlds_local")).unwrap();
+    fs::write(root.join("worlds_local/Dedicated.db"), "12345").unwrap();
+    fs::write(root.join("worlds_local/Dedicated.db.old"), "123").unwrap();
+    let size = measure_input(root, &PathFilter::new("", "*.old"));
+    assert_eq!(size, InputSize { files: 1, bytes: 5 });
+
+    let mut progress = Progress::new(size.bytes, true);
+    let mut reader = ProgressReader::new(&b"12345"[..], &mut progress);
+    io::copy(&mut reader, &mut io::sink()).unwrap();
+    assert_eq!(progress.percent(), 100);
+  }
+}
@@ -0,0 +1,478 @@
+use chrono::{DateTime, Duration, Local, NaiveDateTime};
+use log::{debug, info, warn};
+use serde::Serialize;
+
+use std::{fmt, fs, io, path::Path, path::PathBuf};
+
+use crate::files::snapshot::{is_snapshot_name, snapshot_size};
+use crate::files::{
+  archive::{checksum_path, ArchiveFormat},
+  encryption::plaintext_name,
+};
+use crate::utils::environment::fetch_var;
+
+pub const BACKUPS_TO_KEEP_VAR: &str = "BACKUPS_TO_KEEP";
+pub const BACKUPS_MAX_AGE_DAYS_VAR: &str = "BACKUPS_MAX_AGE_DAYS";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupFile {
+  pub(crate) path: PathBuf,
+  pub(crate) modified: DateTime<Local>,
+  pub(crate) size: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RetentionPolicy {
+  pub(crate) keep: Option<usize>,
+  pub(crate) max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+  pub fn from_env() -> Result<Self, String> {
+    let parse = |name: &str| -> Result<Option<i64>, String> {
+      let value = fetch_var(name, "");
+      if value.is_empty() {
+        return Ok(None);
+      }
+      match value.parse::<i64>() {
+        Ok(parsed) if parsed >= 0 => Ok(Some(parsed)),
+        _ => Err(format!(
+          "{} must be a positive number, got '{}'",
+          name, value
+        )),
+      }
+    };
+    Ok(RetentionPolicy {
+      // The backup that was just taken is always kept.
+      keep: parse(BACKUPS_TO_KEEP_VAR)?.map(|keep| keep.max(1) as usize),
+      max_age: parse(BACKUPS_MAX_AGE_DAYS_VAR)?.map(Duration::days),
+    })
+  }
+
+  pub fn is_configured(&self) -> bool {
+    self.keep.is_some() || self.max_age.is_some()
+  }
+}
+
+/// Parses a backup age like `7d` or `12h`, a plain number is a count of days.
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+  let value = value.trim();
+  let invalid = || {
+    format!(
+      "Invalid age '{}', expected days like 7d or 7, or hours like 12h",
+      value
+    )
+  };
+  let (amount, to_duration): (&str, fn(i64) -> Duration) = match value.strip_suffix('h') {
+    Some(hours) => (hours, Duration::hours),
+    None => (value.strip_suffix('d').unwrap_or(value), Duration::days),
+  };
+  match amount.parse::<i64>() {
+    Ok(amount) if amount >= 0 => Ok(to_duration(amount)),
+    _ => Err(invalid()),
+  }
+}
+
+/// The `YYYYMMDD-HHMMSS` odin puts in backup names, wherever the name template put it.
+pub fn parse_name_timestamp(name: &str) -> Option<NaiveDateTime> {
+  let bytes = name.as_bytes();
+  (0..bytes.len().saturating_sub(14))
+    .filter(|start| *start == 0 || !bytes[start - 1].is_ascii_digit())
+    .filter(|start| bytes.get(start + 15).is_none_or(|c| !c.is_ascii_digit()))
+    .filter_map(|start| std::str::from_utf8(&bytes[start..start + 15]).ok())
+    .find_map(|stamp| NaiveDateTime::parse_from_str(stamp, "%Y%m%d-%H%M%S").ok())
+}
+
+/// Whether `name` has a timestamp and something besides it, like every backup odin names.
+pub fn has_backup_timestamp(name: &str) -> bool {
+  parse_name_timestamp(name).is_some_and(|timestamp| {
+    let rest = name.replacen(&timestamp.format("%Y%m%d-%H%M%S").to_string(), "", 1);
+    !rest
+      .trim_matches(|c: char| c == '-' || c == '_' || c == '.')
+      .is_empty()
+  })
+}
+
+/// Matches the names odin's backups are given, `YYYYMMDD-HHMMSS-<name>.tar.gz` by default or
+/// whatever `BACKUP_NAME_FORMAT` renders, with the extension of any backup format and `.age` when
+/// encrypted.
+pub fn is_backup_file_name(name: &str) -> bool {
+  let name = plaintext_name(name);
+  ArchiveFormat::ALL.iter().any(|format| {
+    name
+      .strip_suffix(format.extension())
+      .is_some_and(has_backup_timestamp)
+  })
+}
+
+/// Everything in `backups` that falls outside the policy. A backup is pruned when it is beyond the
+/// newest `keep` or older than `max_age`, whichever is configured.
+pub fn select_for_pruning(
+  mut backups: Vec<BackupFile>,
+  policy: RetentionPolicy,
+  now: DateTime<Local>,
+) -> Vec<BackupFile> {
+  backups.sort_by_key(|backup| std::cmp::Reverse(backup.modified));
+  backups
+    .into_iter()
+    .enumerate()
+    .filter(|(index, backup)| {
+      let over_count = policy.keep.is_some_and(|keep| *index >= keep);
+      let too_old = policy
+        .max_age
+        .is_some_and(|max_age| now.signed_duration_since(backup.modified) > max_age);
+      // Never prune the newest backup, it is the one that just ran.
+      *index > 0 && (over_count || too_old)
+    })
+    .map(|(_, backup)| backup)
+    .collect()
+}
+
+pub fn list_backups(directory: &Path) -> Vec<BackupFile> {
+  let entries = match fs::read_dir(directory) {
+    Ok(entries) => entries,
+    Err(e) => {
+      warn!("Unable to read backups in {}: {}", directory.display(), e);
+      return Vec::new();
+    }
+  };
+  entries
+    .filter_map(|entry| entry.ok())
+    .filter_map(|entry| {
+      let name = entry.file_name().to_string_lossy().to_string();
+      let metadata = entry.metadata().ok()?;
+      let size = if metadata.is_file() && is_backup_file_name(&name) {
+        metadata.len()
+      } else if metadata.is_dir() && is_snapshot_name(&name) {
+        snapshot_size(&entry.path())
+      } else {
+        return None;
+      };
+      Some(BackupFile {
+        path: entry.path(),
+        modified: DateTime::<Local>::from(metadata.modified().ok()?),
+        size,
+      })
+    })
+    .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PruneAction {
+  Kept,
+  Deleted,
+  /// Would have been deleted, in a dry run.
+  WouldDelete,
+  /// Should have been deleted but couldn't be.
+  Failed,
+}
+
+impl fmt::Display for PruneAction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      PruneAction::Kept => "kept",
+      PruneAction::Deleted => "deleted",
+      PruneAction::WouldDelete => "would delete",
+      PruneAction::Failed => "failed",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// What pruning did with one backup. The field names key the JSON of `odin backup prune`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PruneEntry {
+  pub(crate) path: String,
+  pub(crate) modified: String,
+  pub(crate) size_bytes: u64,
+  pub(crate) action: PruneAction,
+  /// Why it couldn't be deleted.
+  pub(crate) error: Option<String>,
+}
+
+impl PruneEntry {
+  fn new(backup: &BackupFile, action: PruneAction, error: Option<String>) -> Self {
+    PruneEntry {
+      path: backup.path.display().to_string(),
+      modified: backup.modified.format("%Y-%m-%d %H:%M:%S").to_string(),
+      size_bytes: backup.size,
+      action,
+      error,
+    }
+  }
+}
+
+/// Removes a backup along with its manifest.
+fn remove_backup(path: &Path) -> io::Result<()> {
+  // Newer snapshots hold their own links to anything they share with this one.
+  if path.is_dir() {
+    fs::remove_dir_all(path)?;
+  } else {
+    fs::remove_file(path)?;
+  }
+  let manifest = checksum_path(path);
+  if manifest.exists() {
+    if let Err(e) = fs::remove_file(&manifest) {
+      warn!("Failed to prune {}: {}", manifest.display(), e);
+    }
+  }
+  Ok(())
+}
+
+/// Applies `policy` to the backups in `directory`, newest first in what it returns. Anything that
+/// isn't named like an odin backup or snapshot is never looked at.
+pub fn prune_directory(
+  directory: &Path,
+  policy: RetentionPolicy,
+  dry_run: bool,
+  now: DateTime<Local>,
+) -> Vec<PruneEntry> {
+  let mut backups = list_backups(directory);
+  backups.sort_by_key(|backup| std::cmp::Reverse(backup.modified));
+  let to_prune = select_for_pruning(backups.clone(), policy, now);
+  backups
+    .iter()
+    .map(|backup| {
+      if !to_prune.contains(backup) {
+        return PruneEntry::new(backup, PruneAction::Kept, None);
+      }
+      if dry_run {
+        info!("Dry run: would prune {}", backup.path.display());
+        return PruneEntry::new(backup, PruneAction::WouldDelete, None);
+      }
+      match remove_backup(&backup.path) {
+        Ok(_) => {
+          info!("Pruned old backup {}", backup.path.display());
+          PruneEntry::new(backup, PruneAction::Deleted, None)
+        }
+        Err(e) => {
+          warn!("Failed to prune {}: {}", backup.path.display(), e);
+          PruneEntry::new(backup, PruneAction::Failed, Some(e.to_string()))
+        }
+      }
+    })
+    .collect()
+}
+
+pub fn prune_backups(directory: &Path, policy: RetentionPolicy, dry_run: bool) {
+  if !policy.is_configured() {
+    debug!("No backup retention configured, keeping every backup");
+    return;
+  }
+  log_pruned(
+    &prune_directory(directory, policy, dry_run, Local::now()),
+    dry_run,
+  );
+}
+
+/// Sums up what `prune_directory` reclaimed.
+pub fn log_pruned(entries: &[PruneEntry], dry_run: bool) {
+  let pruned: Vec<&PruneEntry> = entries
+    .iter()
+    .filter(|entry| {
+      matches!(
+        entry.action,
+        PruneAction::Deleted | PruneAction::WouldDelete
+      )
+    })
+    .collect();
+  info!(
+    "{} {} backup(s), {:.1} MB {}",
+    if dry_run { "Would prune" } else { "Pruned" },
+    pruned.len(),
+    pruned.iter().map(|entry| entry.size_bytes).sum::<u64>() as f64 / (1024.0 * 1024.0),
+    if dry_run {
+      "would be reclaimed"
+    } else {
+      "reclaimed"
+    }
+  );
+}
+
+#[cfg(test)]
+mod retention_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+
+  fn backup(name: &str, days_old: i64, now: DateTime<Local>) -> BackupFile {
+    BackupFile {
+      path: PathBuf::from(name),
+      modified: now - Duration::days(days_old),
+      size: 10,
+    }
+  }
+
+  fn names(backups: Vec<BackupFile>) -> Vec<String> {
+    backups
+      .into_iter()
+      .map(|backup| backup.path.display().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn matches_only_odin_backups() {
+    assert!(is_backup_file_name("20210301-120000-backup.tar.gz"));
+    assert!(is_backup_file_name(
+      "20210301-120000-pre-update-backup.tar.gz"
+    ));
+    assert!(!is_backup_file_name("20210301-120000-.tar.gz"));
+    assert!(!is_backup_file_name("my-world.tar.gz"));
+    assert!(is_backup_file_name("20210301-120000-backup.tar.zst"));
+    assert!(is_backup_file_name("20210301-120000-backup.zip"));
+    assert!(!is_backup_file_name("20210301-120000-backup.7z"));
+    assert!(!is_backup_file_name("notes.txt"));
+    assert!(is_backup_file_name("20210301-120000-backup.tar.gz.age"));
+    assert!(!is_backup_file_name("20210301-120000-backup.age"));
+  }
+
+  #[test]
+  fn finds_timestamps_anywhere_in_the_name() {
+    let expected = NaiveDateTime::parse_from_str("20210301-120000", "%Y%m%d-%H%M%S").unwrap();
+    assert_eq!(
+      parse_name_timestamp("20210301-120000-backup.tar.gz"),
+      Some(expected)
+    );
+    assert_eq!(
+      parse_name_timestamp("pre-update-6254189-20210301-120000.tar.gz"),
+      Some(expected)
+    );
+    assert_eq!(parse_name_timestamp("my-world.tar.gz"), None);
+    assert_eq!(
+      parse_name_timestamp("wörld-20210301-120000.zip"),
+      Some(expected)
+    );
+    assert_eq!(parse_name_timestamp("20211301-120000-backup.zip"), None);
+  }
+
+  #[test]
+  fn matches_custom_name_formats() {
+    assert!(is_backup_file_name(
+      "My_Server-Dedicated-20210301-120000.tar.gz"
+    ));
+    assert!(is_backup_file_name(
+      "pre-update-6254189-20210301-120000.zip"
+    ));
+    assert!(is_backup_file_name("20210301-120000-backup-2.tar.zst"));
+    assert!(!is_backup_file_name("20210301-120000.tar.gz"));
+    assert!(!is_backup_file_name("My_Server-2021-03-01.tar.gz"));
+    assert!(has_backup_timestamp("Dedicated-20210301-120000"));
+  }
+
+  #[test]
+  fn keeps_newest_by_count() {
+    let now = Local::now();
+    let backups = vec![
+      backup("c", 2, now),
+      backup("a", 0, now),
+      backup("b", 1, now),
+    ];
+    let policy = RetentionPolicy {
+      keep: Some(2),
+      max_age: None,
+    };
+    assert_eq!(names(select_for_pruning(backups, policy, now)), vec!["c"]);
+  }
+
+  #[test]
+  fn prunes_by_age() {
+    let now = Local::now();
+    let backups = vec![
+      backup("a", 0, now),
+      backup("b", 3, now),
+      backup("c", 9, now),
+    ];
+    let policy = RetentionPolicy {
+      keep: None,
+      max_age: Some(Duration::days(5)),
+    };
+    assert_eq!(names(select_for_pruning(backups, policy, now)), vec!["c"]);
+  }
+
+  #[test]
+  fn either_rule_prunes() {
+    let now = Local::now();
+    let backups = vec![
+      backup("a", 0, now),
+      backup("b", 1, now),
+      backup("c", 2, now),
+      backup("d", 9, now),
+    ];
+    let policy = RetentionPolicy {
+      keep: Some(3),
+      max_age: Some(Duration::days(1)),
+    };
+    assert_eq!(
+      names(select_for_pruning(backups, policy, now)),
+      vec!["c", "d"]
+    );
+  }
+
+  #[test]
+  fn parses_ages() {
+    assert_eq!(parse_age("7d"), Ok(Duration::days(7)));
+    assert_eq!(parse_age("30"), Ok(Duration::days(30)));
+    assert_eq!(parse_age("12h"), Ok(Duration::hours(12)));
+    assert!(parse_age("12m").is_err());
+    assert!(parse_age("-1d").is_err());
+    assert!(parse_age("d").is_err());
+  }
+
+  #[test]
+  fn prunes_only_odin_backups_in_a_directory() {
+    use std::{fs::File, time::SystemTime};
+
+    let temp = test_dir("prune");
+    let root = temp.path();
+    for (name, days_old) in [
+      ("20210303-120000-backup.tar.gz", 0),
+      ("20210302-120000-backup.tar.gz", 1),
+      ("20210302-120000-backup.tar.gz.sha256", 1),
+      ("20210301-120000-backup.zip", 2),
+      ("notes.txt", 9),
+    ] {
+      let file = File::create(root.join(name)).unwrap();
+      file
+        .set_modified(SystemTime::now() - std::time::Duration::from_secs(days_old * 86400))
+        .unwrap();
+    }
+    let policy = RetentionPolicy {
+      keep: Some(1),
+      max_age: None,
+    };
+    let actions = |entries: Vec<PruneEntry>| -> Vec<PruneAction> {
+      entries.iter().map(|entry| entry.action).collect()
+    };
+
+    let planned = prune_directory(root, policy, true, Local::now());
+    assert_eq!(
+      actions(planned.clone()),
+      vec![
+        PruneAction::Kept,
+        PruneAction::WouldDelete,
+        PruneAction::WouldDelete
+      ]
+    );
+    assert!(planned[0].path.ends_with("20210303-120000-backup.tar.gz"));
+    assert!(root.join("20210301-120000-backup.zip").exists());
+
+    prune_directory(root, policy, false, Local::now());
+    let mut left: Vec<String> = fs::read_dir(root)
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+      .collect();
+    left.sort();
+    assert_eq!(left, vec!["20210303-120000-backup.tar.gz", "notes.txt"]);
+  }
+
+  #[test]
+  fn never_prunes_the_newest() {
+    let now = Local::now();
+    let backups = vec![backup("a", 10, now), backup("b", 11, now)];
+    let policy = RetentionPolicy {
+      keep: None,
+      max_age: Some(Duration::days(5)),
+    };
+    assert_eq!(names(select_for_pruning(backups, policy, now)), vec!["b"]);
+  }
+}
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use crate::errors::VariantNotFound;
+use crate::files::{
+  archive::{ArchiveSource, RestoreTarget, ARCHIVE_ROOT},
+  filter::PathFilter,
+};
+
+pub const BACKUP_SCOPE_VAR: &str = "BACKUP_SCOPE";
+
+/// The server's permission lists, kept next to the worlds in the saves directory.
+const LIST_FILES: &str = "adminlist.txt,bannedlist.txt,permittedlist.txt";
+/// BepInEx directories backed up with mods, relative to the working directory.
+const MOD_DIRECTORIES: [&str; 2] = ["BepInEx/config", "BepInEx/plugins"];
+
+/// What a backup covers, each archived under its own root directory.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupScope {
+  /// The saves directory, what backups have always held.
+  Worlds,
+  /// `adminlist.txt`, `bannedlist.txt` and `permittedlist.txt`.
+  Config,
+  /// BepInEx configs and plugins.
+  Mods,
+}
+
+impl FromStr for BackupScope {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> Result<BackupScope, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "worlds" | "world" => Ok(BackupScope::Worlds),
+      "config" => Ok(BackupScope::Config),
+      "mods" => Ok(BackupScope::Mods),
+      _ => Err(VariantNotFound {
+        v: format!(
+          "Unknown backup scope '{}', expected worlds, config, mods or all",
+          s
+        ),
+      }),
+    }
+  }
+}
+
+impl fmt::Display for BackupScope {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      BackupScope::Worlds => "worlds",
+      BackupScope::Config => "config",
+      BackupScope::Mods => "mods",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+impl BackupScope {
+  pub const ALL: [BackupScope; 3] = [BackupScope::Worlds, BackupScope::Config, BackupScope::Mods];
+
+  /// The directory the scope is archived under.
+  pub fn archive_root(&self) -> &'static str {
+    match self {
+      BackupScope::Worlds => ARCHIVE_ROOT,
+      BackupScope::Config => "config",
+      BackupScope::Mods => "mods",
+    }
+  }
+
+  /// Where the scope is restored to, the worlds and lists go back into `saves` and the mods into
+  /// the server's `working_dir`.
+  pub fn restore_target(&self, saves: &Path, working_dir: &Path) -> RestoreTarget {
+    let directory = match self {
+      BackupScope::Worlds => return RestoreTarget::saves(saves),
+      BackupScope::Config => saves,
+      BackupScope::Mods => working_dir,
+    };
+    RestoreTarget {
+      root: PathBuf::from(self.archive_root()),
+      directory: directory.to_path_buf(),
+      filter: PathFilter::default(),
+    }
+  }
+}
+
+/// Parses a comma separated list of scopes, `all` being every one of them. Sorted, without
+/// duplicates.
+pub fn parse_scopes(value: &str) -> Result<Vec<BackupScope>, String> {
+  let mut scopes = Vec::new();
+  for scope in value.split(',').filter(|scope| !scope.trim().is_empty()) {
+    if scope.trim().eq_ignore_ascii_case("all") {
+      scopes.extend(BackupScope::ALL.iter());
+    } else {
+      scopes.push(BackupScope::from_str(scope).map_err(|e| e.to_string())?);
+    }
+  }
+  if scopes.is_empty() {
+    return Err(format!(
+      "No backup scope in '{}', expected worlds, config, mods or all",
+      value
+    ));
+  }
+  scopes.sort();
+  scopes.dedup();
+  Ok(scopes)
+}
+
+/// What to archive for `scopes`. `filter` narrows down the saves, and the lists are only archived
+/// once, under `config`, when both worlds and config are backed up. Mod directories that don't
+/// exist are left out.
+pub fn archive_sources(
+  scopes: &[BackupScope],
+  saves: &Path,
+  filter: &PathFilter,
+  working_dir: &Path,
+) -> Vec<ArchiveSource> {
+  let mut sources = Vec::new();
+  for scope in scopes {
+    match scope {
+      BackupScope::Worlds => {
+        let filter = if scopes.contains(&BackupScope::Config) {
+          filter.excluding(LIST_FILES)
+        } else {
+          filter.clone()
+        };
+        sources.push(ArchiveSource::saves(saves, &filter));
+      }
+      BackupScope::Config => sources.push(ArchiveSource {
+        root: PathBuf::from(scope.archive_root()),
+        directory: saves.to_path_buf(),
+        filter: PathFilter::new(LIST_FILES, ""),
+      }),
+      BackupScope::Mods => sources.extend(
+        MOD_DIRECTORIES
+          .iter()
+          .map(|directory| ArchiveSource {
+            root: Path::new(scope.archive_root()).join(directory),
+            directory: working_dir.join(directory),
+            filter: PathFilter::default(),
+          })
+          .filter(|source| source.directory.is_dir()),
+      ),
+    }
+  }
+  sources
+}
+
+#[cfg(test)]
+mod scope_tests {
+  use super::*;
+
+  #[test]
+  fn parses_scope_lists() {
+    assert_eq!(parse_scopes("worlds"), Ok(vec![BackupScope::Worlds]));
+    assert_eq!(
+      parse_scopes("mods, worlds,mods"),
+      Ok(vec![BackupScope::Worlds, BackupScope::Mods])
+    );
+    assert_eq!(parse_scopes("ALL"), Ok(BackupScope::ALL.to_vec()));
+    assert!(parse_scopes("plugins").is_err());
+    assert!(parse_scopes(" , ").is_err());
+  }
+
+  #[test]
+  fn archives_the_lists_once() {
+    let saves = Path::new("/saves");
+    let working_dir = std::env::temp_dir();
+    let worlds_only = archive_sources(
+      &[BackupScope::Worlds],
+      saves,
+      &PathFilter::default(),
+      &working_dir,
+    );
+    assert!(worlds_only[0].filter.includes(Path::new("adminlist.txt")));
+
+    let sources = archive_sources(
+      &[BackupScope::Worlds, BackupScope::Config],
+      saves,
+      &PathFilter::new("", "*.old"),
+      &working_dir,
+    );
+    let roots: Vec<&Path> = sources.iter().map(|source| source.root.as_path()).collect();
+    assert_eq!(roots, vec![Path::new("saves"), Path::new("config")]);
+    assert!(!sources[0].filter.includes(Path::new("adminlist.txt")));
+    assert!(!sources[0]
+      .filter
+      .includes(Path::new("worlds_local/a.db.old")));
+    assert!(sources[0].filter.includes(Path::new("worlds_local/a.db")));
+    assert!(sources[1].filter.includes(Path::new("bannedlist.txt")));
+    assert!(!sources[1].filter.includes(Path::new("worlds_local/a.db")));
+  }
+}
@@ -0,0 +1,449 @@
+use chrono::{Local, TimeZone};
+use log::{debug, info, warn};
+
+use std::{
+  io::Write,
+  path::{Path, PathBuf},
+  process::Stdio,
+};
+
+use crate::executable::find_command;
+use crate::files::{
+  archive::CHECKSUM_EXTENSION,
+  retention::{
+    is_backup_file_name, parse_name_timestamp, select_for_pruning, BackupFile, RetentionPolicy,
+  },
+};
+use crate::utils::environment::{fetch_secret_var, fetch_var};
+
+pub const BACKUP_SFTP_HOST_VAR: &str = "BACKUP_SFTP_HOST";
+pub const BACKUP_SFTP_PORT_VAR: &str = "BACKUP_SFTP_PORT";
+pub const BACKUP_SFTP_USER_VAR: &str = "BACKUP_SFTP_USER";
+pub const BACKUP_SFTP_KEY_PATH_VAR: &str = "BACKUP_SFTP_KEY_PATH";
+pub const BACKUP_SFTP_PASSWORD_VAR: &str = "BACKUP_SFTP_PASSWORD";
+pub const BACKUP_SFTP_REMOTE_DIR_VAR: &str = "BACKUP_SFTP_REMOTE_DIR";
+pub const BACKUP_SFTP_KNOWN_HOSTS_VAR: &str = "BACKUP_SFTP_KNOWN_HOSTS";
+pub const BACKUP_SFTP_INSECURE_VAR: &str = "BACKUP_SFTP_INSECURE";
+pub const BACKUP_SFTP_PRUNE_VAR: &str = "BACKUP_SFTP_PRUNE";
+
+const SFTP_URI_SCHEME: &str = "sftp://";
+/// Uploads go here first so a broken connection never leaves something that looks like a backup.
+const PARTIAL_SUFFIX: &str = ".partial";
+/// Prefix `sftp -b` echoes each batch command with.
+const BATCH_ECHO: &str = "sftp>";
+
+/// The SSH box backups are uploaded to. There is deliberately no `Debug`, the password must never
+/// end up in a log line.
+#[derive(Clone, PartialEq)]
+pub struct SftpConfig {
+  pub(crate) host: String,
+  pub(crate) port: u16,
+  pub(crate) user: String,
+  pub(crate) key_path: String,
+  password: String,
+  pub(crate) remote_dir: String,
+  pub(crate) known_hosts: String,
+  pub(crate) insecure: bool,
+  /// Applies the retention policy to the remote directory as well.
+  pub(crate) prune: bool,
+}
+
+impl SftpConfig {
+  /// The SSH box backups are uploaded to, `None` when `BACKUP_SFTP_HOST` isn't set.
+  pub fn from_env() -> Result<Option<Self>, String> {
+    let host = fetch_var(BACKUP_SFTP_HOST_VAR, "");
+    if host.is_empty() {
+      return Ok(None);
+    }
+    let port_value = fetch_var(BACKUP_SFTP_PORT_VAR, "22");
+    let port = parse_port(&port_value)
+      .ok_or_else(|| format!("Invalid {} '{}'", BACKUP_SFTP_PORT_VAR, port_value))?;
+    let remote_dir = fetch_var(BACKUP_SFTP_REMOTE_DIR_VAR, ".");
+    Self::with_credentials(host, port, fetch_var(BACKUP_SFTP_USER_VAR, ""), &remote_dir).map(Some)
+  }
+
+  /// `sftp://[user@]host[:port][/path]`, the path being absolute and `/~/` starting one relative
+  /// to the home directory. Everything else comes from the environment, the user included when
+  /// the uri doesn't name one.
+  pub fn for_uri(uri: &str) -> Result<Self, String> {
+    let invalid = || {
+      format!(
+        "Invalid sftp destination '{}', expected sftp://[user@]host[:port][/path]",
+        uri
+      )
+    };
+    let rest = uri.strip_prefix(SFTP_URI_SCHEME).ok_or_else(invalid)?;
+    let (authority, path) = match rest.find('/') {
+      Some(slash) => rest.split_at(slash),
+      None => (rest, ""),
+    };
+    let (user, address) = match authority.rsplit_once('@') {
+      Some((user, address)) => (user.to_string(), address),
+      None => (fetch_var(BACKUP_SFTP_USER_VAR, ""), authority),
+    };
+    let (host, port) = match address.rsplit_once(':') {
+      Some((host, port)) => (host, parse_port(port).ok_or_else(invalid)?),
+      None => (address, 22),
+    };
+    if host.is_empty() {
+      return Err(invalid());
+    }
+    let remote_dir = match path {
+      "" | "/" | "/~" => ".",
+      path => path.strip_prefix("/~/").unwrap_or(path),
+    };
+    Self::with_credentials(host.to_string(), port, user, remote_dir)
+  }
+
+  /// Reads the key, password and host checking settings from the environment.
+  fn with_credentials(
+    host: String,
+    port: u16,
+    user: String,
+    remote_dir: &str,
+  ) -> Result<Self, String> {
+    if user.is_empty() {
+      return Err(format!(
+        "{} must be set to use SFTP backups",
+        BACKUP_SFTP_USER_VAR
+      ));
+    }
+    let key_path = fetch_var(BACKUP_SFTP_KEY_PATH_VAR, "");
+    let password = fetch_secret_var(BACKUP_SFTP_PASSWORD_VAR);
+    if key_path.is_empty() && password.is_empty() {
+      return Err(format!(
+        "{} or {} must be set to use SFTP backups",
+        BACKUP_SFTP_KEY_PATH_VAR, BACKUP_SFTP_PASSWORD_VAR
+      ));
+    }
+    if !key_path.is_empty() && !Path::new(&key_path).is_file() {
+      return Err(format!(
+        "{} '{}' doesn't exist",
+        BACKUP_SFTP_KEY_PATH_VAR, key_path
+      ));
+    }
+    Ok(SftpConfig {
+      host,
+      port,
+      user,
+      key_path,
+      password,
+      remote_dir: match remote_dir.trim_end_matches('/') {
+        "" => "/".to_string(),
+        trimmed => trimmed.to_string(),
+      },
+      known_hosts: fetch_var(BACKUP_SFTP_KNOWN_HOSTS_VAR, ""),
+      insecure: fetch_var(BACKUP_SFTP_INSECURE_VAR, "0") == "1",
+      prune: fetch_var(BACKUP_SFTP_PRUNE_VAR, "0") == "1",
+    })
+  }
+
+  pub fn path_for(&self, file_name: &str) -> String {
+    if self.remote_dir == "/" {
+      format!("/{}", file_name)
+    } else {
+      format!("{}/{}", self.remote_dir, file_name)
+    }
+  }
+
+  pub fn uri_for(&self, path: &str) -> String {
+    let separator = if path.starts_with('/') { "" } else { "/" };
+    format!(
+      "{}{}@{}:{}{}{}",
+      SFTP_URI_SCHEME, self.user, self.host, self.port, separator, path
+    )
+  }
+
+  /// Everything `sftp` is run with but the batch file and destination.
+  fn ssh_args(&self) -> Vec<String> {
+    let mut args = vec!["-P".to_string(), self.port.to_string()];
+    let mut option = |value: String| args.extend(["-o".to_string(), value]);
+    if self.password.is_empty() {
+      option("BatchMode=yes".to_string());
+    } else {
+      // `-b` turns batch mode on, which refuses passwords, unless it was already turned off.
+      option("BatchMode=no".to_string());
+      option("NumberOfPasswordPrompts=1".to_string());
+    }
+    if self.insecure {
+      option("StrictHostKeyChecking=no".to_string());
+      option("UserKnownHostsFile=/dev/null".to_string());
+    } else {
+      option("StrictHostKeyChecking=yes".to_string());
+      if !self.known_hosts.is_empty() {
+        option(format!("UserKnownHostsFile={}", self.known_hosts));
+      }
+    }
+    if !self.key_path.is_empty() {
+      args.extend(["-i".to_string(), self.key_path.clone()]);
+    }
+    args
+  }
+
+  fn destination(&self) -> String {
+    format!("{}@{}", self.user, self.host)
+  }
+}
+
+/// Quotes a path for an sftp batch file.
+fn quote(path: &str) -> String {
+  format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The names `ls -1` listed, without the echoed commands or the directory they are in.
+fn parse_listing(output: &str) -> Vec<String> {
+  output
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with(BATCH_ECHO))
+    .map(|line| line.rsplit('/').next().unwrap_or(line).to_string())
+    .collect()
+}
+
+/// Runs the sftp batch `commands`, a line starting with `-` may fail without failing the batch.
+fn run_batch(config: &SftpConfig, commands: &[String]) -> Result<String, String> {
+  let (mut command, using_sshpass) = if config.password.is_empty() {
+    (find_command("sftp"), false)
+  } else {
+    (find_command("sshpass"), true)
+  };
+  let command = command.as_mut().ok_or_else(|| {
+    format!(
+      "{} isn't installed, it is needed for SFTP backups",
+      if using_sshpass { "sshpass" } else { "sftp" }
+    )
+  })?;
+  if using_sshpass {
+    // Read from the environment so the password never shows up in the process list.
+    command
+      .env("SSHPASS", &config.password)
+      .args(["-e", "sftp"]);
+  }
+  command
+    .args(config.ssh_args())
+    .args(["-b", "-"])
+    .arg(config.destination())
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  debug!("Running sftp batch: {}", commands.join("; "));
+  let mut child = command
+    .spawn()
+    .map_err(|e| format!("Unable to run sftp: {}", e))?;
+  if let Some(mut stdin) = child.stdin.take() {
+    stdin
+      .write_all(format!("{}\n", commands.join("\n")).as_bytes())
+      .map_err(|e| format!("Unable to run sftp: {}", e))?;
+  }
+  let output = child
+    .wait_with_output()
+    .map_err(|e| format!("Unable to run sftp: {}", e))?;
+  if output.status.success() {
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+  } else {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(format!(
+      "sftp to {}:{} failed: {}",
+      config.host,
+      config.port,
+      stderr.trim().lines().collect::<Vec<&str>>().join(", ")
+    ))
+  }
+}
+
+fn parse_port(value: &str) -> Option<u16> {
+  value.trim().parse::<u16>().ok().filter(|port| *port > 0)
+}
+
+pub struct SftpClient {
+  config: SftpConfig,
+}
+
+impl SftpClient {
+  pub fn new(config: SftpConfig) -> Self {
+    SftpClient { config }
+  }
+
+  /// Uploads `path` into the remote directory and returns its `sftp://` uri.
+  pub fn upload(&self, path: &Path) -> Result<String, String> {
+    let file_name = path
+      .file_name()
+      .ok_or_else(|| format!("{} is not a file", path.display()))?
+      .to_string_lossy()
+      .to_string();
+    let remote = self.config.path_for(&file_name);
+    let partial = format!("{}{}", remote, PARTIAL_SUFFIX);
+    run_batch(
+      &self.config,
+      &[
+        format!("-mkdir {}", quote(&self.config.remote_dir)),
+        format!("put {} {}", quote(&path.to_string_lossy()), quote(&partial)),
+        format!("-rm {}", quote(&remote)),
+        format!("rename {} {}", quote(&partial), quote(&remote)),
+      ],
+    )?;
+    Ok(self.config.uri_for(&remote))
+  }
+
+  /// The names of the files in the remote directory.
+  pub fn list(&self) -> Result<Vec<String>, String> {
+    run_batch(
+      &self.config,
+      &[format!("ls -1 {}", quote(&self.config.remote_dir))],
+    )
+    .map(|output| parse_listing(&output))
+  }
+
+  /// Applies `policy` to odin's backups in the remote directory, dating them by their name.
+  pub fn prune(&self, policy: RetentionPolicy, dry_run: bool) -> Result<(), String> {
+    if !policy.is_configured() {
+      return Ok(());
+    }
+    let backups: Vec<BackupFile> = self
+      .list()?
+      .into_iter()
+      .filter(|name| is_backup_file_name(name))
+      .filter_map(|name| {
+        let modified = Local
+          .from_local_datetime(&parse_name_timestamp(&name)?)
+          .earliest()?;
+        Some(BackupFile {
+          path: PathBuf::from(self.config.path_for(&name)),
+          modified,
+          size: 0,
+        })
+      })
+      .collect();
+    let to_prune = select_for_pruning(backups, policy, Local::now());
+    if to_prune.is_empty() {
+      debug!("Nothing to prune on {}", self.config.host);
+      return Ok(());
+    }
+    let mut commands = Vec::new();
+    for backup in &to_prune {
+      let path = backup.path.to_string_lossy();
+      if dry_run {
+        info!("Dry run: would prune {}", self.config.uri_for(&path));
+        continue;
+      }
+      commands.push(format!("rm {}", quote(&path)));
+      commands.push(format!(
+        "-rm {}",
+        quote(&format!("{}{}", path, CHECKSUM_EXTENSION))
+      ));
+    }
+    if dry_run {
+      return Ok(());
+    }
+    match run_batch(&self.config, &commands) {
+      Ok(_) => {
+        info!(
+          "Pruned {} backup(s) on {}",
+          to_prune.len(),
+          self.config.host
+        );
+        Ok(())
+      }
+      Err(e) => {
+        warn!("Failed to prune the backups on {}", self.config.host);
+        Err(e)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod sftp_tests {
+  use super::*;
+  use serial_test::serial;
+  use std::env::{remove_var, set_var};
+
+  fn example_config() -> SftpConfig {
+    SftpConfig {
+      host: "backups.example.com".to_string(),
+      port: 2222,
+      user: "steam".to_string(),
+      key_path: "/home/steam/.ssh/id_ed25519".to_string(),
+      password: String::new(),
+      remote_dir: "valheim".to_string(),
+      known_hosts: "/home/steam/.ssh/known_hosts".to_string(),
+      insecure: false,
+      prune: false,
+    }
+  }
+
+  #[test]
+  fn verifies_host_keys_unless_insecure() {
+    let args = example_config().ssh_args().join(" ");
+    assert!(args.contains("-P 2222"));
+    assert!(args.contains("-o BatchMode=yes"));
+    assert!(args.contains("-o StrictHostKeyChecking=yes"));
+    assert!(args.contains("-o UserKnownHostsFile=/home/steam/.ssh/known_hosts"));
+    assert!(args.contains("-i /home/steam/.ssh/id_ed25519"));
+
+    let insecure = SftpConfig {
+      insecure: true,
+      password: "hunter2".to_string(),
+      ..example_config()
+    };
+    let args = insecure.ssh_args().join(" ");
+    assert!(args.contains("-o StrictHostKeyChecking=no"));
+    assert!(args.contains("-o BatchMode=no"));
+    assert!(!args.contains("hunter2"));
+  }
+
+  #[test]
+  fn names_remote_paths() {
+    let config = example_config();
+    assert_eq!(config.path_for("a.tar.gz"), "valheim/a.tar.gz");
+    assert_eq!(
+      config.uri_for(&config.path_for("a.tar.gz")),
+      "sftp://steam@backups.example.com:2222/valheim/a.tar.gz"
+    );
+    let root = SftpConfig {
+      remote_dir: "/".to_string(),
+      ..example_config()
+    };
+    assert_eq!(root.path_for("a.zip"), "/a.zip");
+    assert_eq!(quote("my \"world\".zip"), "\"my \\\"world\\\".zip\"");
+  }
+
+  #[test]
+  #[serial]
+  fn reads_uris() {
+    set_var(BACKUP_SFTP_PASSWORD_VAR, "hunter2");
+    set_var(BACKUP_SFTP_USER_VAR, "steam");
+    remove_var(BACKUP_SFTP_KEY_PATH_VAR);
+    let config = SftpConfig::for_uri("sftp://odin@backups.example.com:2222/srv/valheim/").unwrap();
+    assert_eq!(
+      (config.user.as_str(), config.host.as_str(), config.port),
+      ("odin", "backups.example.com", 2222)
+    );
+    assert_eq!(config.remote_dir, "/srv/valheim");
+    let home = SftpConfig::for_uri("sftp://backups.example.com/~/valheim").unwrap();
+    assert_eq!((home.user.as_str(), home.port), ("steam", 22));
+    assert_eq!(home.remote_dir, "valheim");
+    assert_eq!(
+      SftpConfig::for_uri("sftp://backups.example.com")
+        .unwrap()
+        .remote_dir,
+      "."
+    );
+    assert!(SftpConfig::for_uri("sftp://backups.example.com:ssh/valheim").is_err());
+    assert!(SftpConfig::for_uri("sftp:///valheim").is_err());
+    remove_var(BACKUP_SFTP_PASSWORD_VAR);
+    remove_var(BACKUP_SFTP_USER_VAR);
+  }
+
+  #[test]
+  fn parses_ls_output() {
+    let output = "sftp> ls -1 \"valheim\"\nvalheim/20210301-120000-backup.tar.gz\nvalheim/20210301-120000-backup.tar.gz.sha256\n";
+    assert_eq!(
+      parse_listing(output),
+      vec![
+        "20210301-120000-backup.tar.gz",
+        "20210301-120000-backup.tar.gz.sha256"
+      ]
+    );
+  }
+}
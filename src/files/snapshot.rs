@@ -0,0 +1,460 @@
+use chrono::{DateTime, Local, SecondsFormat};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use std::{
+  fs::{self, File, Metadata},
+  io, mem,
+  os::unix::fs::MetadataExt,
+  path::{Path, PathBuf},
+  str::FromStr,
+  time::Duration,
+};
+
+use crate::errors::VariantNotFound;
+use crate::files::{
+  archive::{ArchiveEntry, ArchiveFormat},
+  filter::PathFilter,
+  retention::{has_backup_timestamp, parse_name_timestamp},
+};
+
+pub const BACKUP_STRATEGY_VAR: &str = "BACKUP_STRATEGY";
+pub const BACKUP_FULL_INTERVAL_VAR: &str = "BACKUP_FULL_INTERVAL";
+/// Written at the root of every snapshot, left out when restoring it.
+pub const SNAPSHOT_MANIFEST_NAME: &str = "odin-snapshot.json";
+
+/// A snapshot is written here first and only renamed into place once it is complete.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BackupStrategy {
+  /// A compressed archive per backup.
+  #[default]
+  Archive,
+  /// A directory per backup, hard linking whatever didn't change since the previous one.
+  Incremental,
+}
+
+impl FromStr for BackupStrategy {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> Result<BackupStrategy, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "archive" | "full" => Ok(BackupStrategy::Archive),
+      "incremental" => Ok(BackupStrategy::Incremental),
+      _ => Err(VariantNotFound {
+        v: format!(
+          "Unknown backup strategy '{}', expected archive or incremental",
+          s
+        ),
+      }),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SnapshotStats {
+  pub(crate) linked: usize,
+  pub(crate) copied: usize,
+  pub(crate) copied_bytes: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+  /// Relative to the snapshot, like `worlds_local/Dedicated.db`.
+  pub(crate) path: String,
+  pub(crate) size_bytes: u64,
+  /// Whether it was copied rather than linked to the previous snapshot.
+  pub(crate) changed: bool,
+}
+
+/// How a snapshot was made. Every field defaults so a manifest written by a later odin can still
+/// be read.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotManifest {
+  /// The snapshot unchanged files were linked to, `None` for a full one that copied everything.
+  pub(crate) previous: Option<String>,
+  /// When the full snapshot this one builds on was taken, empty when that isn't known.
+  pub(crate) full_at: String,
+  pub(crate) files: Vec<SnapshotFile>,
+}
+
+/// The manifest of `snapshot`, `None` for snapshots taken before there were manifests.
+pub fn read_snapshot_manifest(snapshot: &Path) -> Option<SnapshotManifest> {
+  let content = fs::read(snapshot.join(SNAPSHOT_MANIFEST_NAME)).ok()?;
+  serde_json::from_slice(&content)
+    .map_err(|e| debug!("{} is invalid: {}", SNAPSHOT_MANIFEST_NAME, e))
+    .ok()
+}
+
+/// Whether the snapshot after `previous` should copy everything again, because the full snapshot
+/// it builds on is older than `interval` or isn't known.
+pub fn full_snapshot_due(previous: &Path, interval: Duration, now: DateTime<Local>) -> bool {
+  let full_at = read_snapshot_manifest(previous)
+    .and_then(|manifest| DateTime::parse_from_rfc3339(&manifest.full_at).ok());
+  match full_at {
+    Some(full_at) => now
+      .signed_duration_since(full_at)
+      .to_std()
+      .is_ok_and(|age| age >= interval),
+    None => true,
+  }
+}
+
+/// Matches the directories incremental backups are written to, named like archives but without an
+/// extension.
+pub fn is_snapshot_name(name: &str) -> bool {
+  has_backup_timestamp(name)
+    && !name.ends_with(PARTIAL_SUFFIX)
+    && ArchiveFormat::from_extension(Path::new(name)).is_none()
+}
+
+/// The newest snapshot in `directory`, which the next one links against.
+pub fn latest_snapshot(directory: &Path) -> Option<PathBuf> {
+  fs::read_dir(directory)
+    .ok()?
+    .filter_map(|entry| entry.ok())
+    .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+    .filter(|entry| is_snapshot_name(&entry.file_name().to_string_lossy()))
+    // Sorted by the timestamp in the name, wherever the name template put it.
+    .max_by_key(|entry| {
+      let name = entry.file_name().to_string_lossy().to_string();
+      (parse_name_timestamp(&name), name)
+    })
+    .map(|entry| entry.path())
+}
+
+/// Same quick check as rsync, the copy keeps the modification time of the original.
+fn is_unchanged(source: &Metadata, previous: &Path) -> bool {
+  match fs::metadata(previous) {
+    Ok(previous) => {
+      previous.is_file()
+        && previous.len() == source.len()
+        && previous.modified().ok() == source.modified().ok()
+    }
+    Err(_) => false,
+  }
+}
+
+struct Mirror<'a> {
+  filter: &'a PathFilter,
+  stats: SnapshotStats,
+  files: Vec<SnapshotFile>,
+}
+
+fn mirror_dir(
+  mirror: &mut Mirror,
+  source: &Path,
+  target: &Path,
+  relative: &Path,
+  previous: Option<&Path>,
+) -> io::Result<()> {
+  fs::create_dir_all(target)?;
+  for entry in fs::read_dir(source)? {
+    let entry = entry?;
+    let file_type = entry.file_type()?;
+    let relative = relative.join(entry.file_name());
+    let destination = target.join(entry.file_name());
+    let previous = previous.map(|previous| previous.join(entry.file_name()));
+    if file_type.is_dir() {
+      if mirror.filter.descends_into(&relative) {
+        mirror_dir(
+          mirror,
+          &entry.path(),
+          &destination,
+          &relative,
+          previous.as_deref(),
+        )?;
+      }
+    } else if file_type.is_file() && mirror.filter.includes(&relative) {
+      let metadata = entry.metadata()?;
+      let changed = match previous.filter(|previous| is_unchanged(&metadata, previous)) {
+        Some(previous) => {
+          fs::hard_link(&previous, &destination)?;
+          mirror.stats.linked += 1;
+          false
+        }
+        None => {
+          fs::copy(entry.path(), &destination)?;
+          File::options()
+            .write(true)
+            .open(&destination)?
+            .set_modified(metadata.modified()?)?;
+          mirror.stats.copied += 1;
+          mirror.stats.copied_bytes += metadata.len();
+          true
+        }
+      };
+      mirror.files.push(SnapshotFile {
+        path: relative.to_string_lossy().to_string(),
+        size_bytes: metadata.len(),
+        changed,
+      });
+    }
+  }
+  Ok(())
+}
+
+fn write_manifest(
+  partial: &Path,
+  previous: Option<&Path>,
+  files: Vec<SnapshotFile>,
+) -> io::Result<()> {
+  let full_at = match previous {
+    Some(previous) => read_snapshot_manifest(previous)
+      .map(|manifest| manifest.full_at)
+      .unwrap_or_default(),
+    None => Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+  };
+  let manifest = SnapshotManifest {
+    previous: previous
+      .and_then(Path::file_name)
+      .map(|name| name.to_string_lossy().to_string()),
+    full_at,
+    files,
+  };
+  let content = serde_json::to_vec_pretty(&manifest).map_err(io::Error::other)?;
+  fs::write(partial.join(SNAPSHOT_MANIFEST_NAME), content)
+}
+
+/// Mirrors whatever `filter` lets through of `input` into the snapshot directory `output`. Files
+/// unchanged since `previous` are hard linked to it, so only what changed takes up new space, and
+/// `odin-snapshot.json` records which were.
+pub fn create_snapshot(
+  input: &Path,
+  output: &Path,
+  previous: Option<&Path>,
+  filter: &PathFilter,
+) -> io::Result<SnapshotStats> {
+  if output.exists() {
+    return Err(io::Error::new(
+      io::ErrorKind::AlreadyExists,
+      format!("{} already exists", output.display()),
+    ));
+  }
+  let mut partial = output.as_os_str().to_owned();
+  partial.push(PARTIAL_SUFFIX);
+  let partial = PathBuf::from(partial);
+  if partial.exists() {
+    debug!("Removing the unfinished snapshot {}", partial.display());
+    fs::remove_dir_all(&partial)?;
+  }
+  let mut mirror = Mirror {
+    filter,
+    stats: SnapshotStats::default(),
+    files: Vec::new(),
+  };
+  let result = mirror_dir(&mut mirror, input, &partial, Path::new(""), previous)
+    .and_then(|_| write_manifest(&partial, previous, mem::take(&mut mirror.files)))
+    .and_then(|_| fs::rename(&partial, output));
+  if result.is_err() {
+    let _ = fs::remove_dir_all(&partial);
+  }
+  result.map(|_| mirror.stats)
+}
+
+/// Space only this snapshot's files hold, what removing it would give back. Hard linked files are
+/// shared with other snapshots and stay on disk, the manifest isn't counted.
+pub fn snapshot_size(snapshot: &Path) -> u64 {
+  let entries = match fs::read_dir(snapshot) {
+    Ok(entries) => entries,
+    Err(_) => return 0,
+  };
+  entries
+    .filter_map(|entry| entry.ok())
+    .filter_map(|entry| {
+      let metadata = entry.metadata().ok()?;
+      if metadata.is_dir() {
+        Some(snapshot_size(&entry.path()))
+      } else if entry.file_name() == SNAPSHOT_MANIFEST_NAME {
+        Some(0)
+      } else if metadata.nlink() == 1 {
+        Some(metadata.len())
+      } else {
+        Some(0)
+      }
+    })
+    .sum()
+}
+
+/// The manifest describes the snapshot, it isn't part of the saves.
+fn is_manifest(relative: &Path) -> bool {
+  relative == Path::new(SNAPSHOT_MANIFEST_NAME)
+}
+
+fn restore_dir(
+  snapshot: &Path,
+  output: &Path,
+  relative: &Path,
+  filter: &PathFilter,
+) -> io::Result<usize> {
+  let mut restored = 0;
+  for entry in fs::read_dir(snapshot.join(relative))? {
+    let entry = entry?;
+    let file_type = entry.file_type()?;
+    let relative = relative.join(entry.file_name());
+    if is_manifest(&relative) {
+      continue;
+    }
+    let destination = output.join(&relative);
+    if file_type.is_dir() {
+      // A filtered restore only creates the directories holding a restored file.
+      if filter.is_empty() {
+        fs::create_dir_all(&destination)?;
+      }
+      if filter.descends_into(&relative) {
+        restored += restore_dir(snapshot, output, &relative, filter)?;
+      }
+    } else if file_type.is_file() && filter.includes(&relative) {
+      if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+      }
+      fs::copy(entry.path(), &destination)?;
+      restored += 1;
+    }
+  }
+  Ok(restored)
+}
+
+/// Copies what `filter` lets through of a snapshot into `output`. Nothing is linked so the
+/// restored world can't change the snapshot.
+pub fn restore_snapshot(snapshot: &Path, output: &Path, filter: &PathFilter) -> io::Result<usize> {
+  fs::create_dir_all(output)?;
+  restore_dir(snapshot, output, Path::new(""), filter)
+}
+
+fn list_dir(snapshot: &Path, relative: &Path, entries: &mut Vec<ArchiveEntry>) -> io::Result<()> {
+  for entry in fs::read_dir(snapshot.join(relative))? {
+    let entry = entry?;
+    let file_type = entry.file_type()?;
+    let relative = relative.join(entry.file_name());
+    if is_manifest(&relative) {
+      continue;
+    }
+    entries.push(ArchiveEntry {
+      path: relative.clone(),
+      size: if file_type.is_file() {
+        entry.metadata()?.len()
+      } else {
+        0
+      },
+      is_dir: file_type.is_dir(),
+    });
+    if file_type.is_dir() {
+      list_dir(snapshot, &relative, entries)?;
+    }
+  }
+  Ok(())
+}
+
+/// Everything in a snapshot, relative to it.
+pub fn list_snapshot(snapshot: &Path) -> io::Result<Vec<ArchiveEntry>> {
+  let mut entries = Vec::new();
+  list_dir(snapshot, Path::new(""), &mut entries)?;
+  entries.sort_by(|a, b| a.path.cmp(&b.path));
+  Ok(entries)
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+
+  #[test]
+  fn matches_only_snapshot_directories() {
+    assert!(is_snapshot_name("20210301-120000-backup"));
+    assert!(!is_snapshot_name("20210301-120000-backup.tar.gz"));
+    assert!(!is_snapshot_name("20210301-120000-backup.partial"));
+    assert!(!is_snapshot_name("backups"));
+  }
+
+  #[test]
+  fn links_unchanged_files() {
+    let temp = test_dir("snapshot");
+    let root = temp.path();
+    let world = root.join("worlds");
+    fs::create_dir_all(world.join("worlds_local")).unwrap();
+    fs::write(world.join("worlds_local/Dedicated.db"), "db").unwrap();
+    fs::write(world.join("worlds_local/Dedicated.fwl"), "fwl").unwrap();
+
+    let first = root.join("20210301-120000-backup");
+    let stats = create_snapshot(&world, &first, None, &PathFilter::default()).unwrap();
+    assert_eq!(stats.copied, 2);
+    assert_eq!(latest_snapshot(root), Some(first.clone()));
+
+    fs::write(world.join("worlds_local/Dedicated.db"), "db, but bigger").unwrap();
+    let second = root.join("20210301-123000-backup");
+    let stats = create_snapshot(&world, &second, Some(&first), &PathFilter::default()).unwrap();
+    assert_eq!(stats.linked, 1);
+    assert_eq!(stats.copied, 1);
+    assert_eq!(stats.copied_bytes, 14);
+    assert_eq!(snapshot_size(&second), 14);
+
+    let manifest = read_snapshot_manifest(&second).unwrap();
+    assert_eq!(manifest.previous.as_deref(), Some("20210301-120000-backup"));
+    assert_eq!(
+      manifest.full_at,
+      read_snapshot_manifest(&first).unwrap().full_at
+    );
+    let changed: Vec<(&str, bool)> = manifest
+      .files
+      .iter()
+      .map(|file| (file.path.as_str(), file.changed))
+      .collect();
+    assert!(changed.contains(&("worlds_local/Dedicated.db", true)));
+    assert!(changed.contains(&("worlds_local/Dedicated.fwl", false)));
+
+    // The newer snapshot keeps the shared file once the older one is gone.
+    fs::remove_dir_all(&first).unwrap();
+    let restored = root.join("restored");
+    assert_eq!(
+      restore_snapshot(&second, &restored, &PathFilter::default()).unwrap(),
+      2
+    );
+    assert_eq!(
+      fs::read_to_string(restored.join("worlds_local/Dedicated.fwl")).unwrap(),
+      "fwl"
+    );
+  }
+
+  #[test]
+  fn copies_everything_again_once_the_full_snapshot_is_old() {
+    let temp = test_dir("snapshot");
+    let root = temp.path();
+    let world = root.join("worlds");
+    fs::create_dir_all(&world).unwrap();
+    fs::write(world.join("Dedicated.db"), "db").unwrap();
+    let first = root.join("20210301-120000-backup");
+    create_snapshot(&world, &first, None, &PathFilter::default()).unwrap();
+
+    let day = Duration::from_secs(24 * 60 * 60);
+    let now = Local::now();
+    assert!(!full_snapshot_due(&first, day, now));
+    assert!(full_snapshot_due(
+      &first,
+      day,
+      now + chrono::Duration::days(2)
+    ));
+    // Snapshots from before manifests don't say when their chain started.
+    fs::remove_file(first.join(SNAPSHOT_MANIFEST_NAME)).unwrap();
+    assert!(full_snapshot_due(&first, day, now));
+    assert_eq!(
+      list_snapshot(&first).unwrap().len(),
+      1,
+      "the manifest is never listed"
+    );
+  }
+
+  #[test]
+  fn parses_strategies() {
+    assert_eq!(
+      BackupStrategy::from_str("Incremental").unwrap(),
+      BackupStrategy::Incremental
+    );
+    assert_eq!(
+      BackupStrategy::from_str("archive").unwrap(),
+      BackupStrategy::Archive
+    );
+    assert!(BackupStrategy::from_str("rsync").is_err());
+  }
+}
@@ -0,0 +1,240 @@
+use chrono::{DateTime, Duration, FixedOffset, Local};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use std::{
+  fs::{self, OpenOptions},
+  io,
+  path::PathBuf,
+};
+
+use crate::utils::get_odin_dir;
+
+const STATE_FILE_NAME: &str = "state.json";
+/// Anything older than this is dropped on write so the file doesn't grow forever.
+const STATE_RETENTION_DAYS: i64 = 60;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BackupRecord {
+  pub(crate) path: String,
+  pub(crate) size_bytes: u64,
+  pub(crate) world_size_bytes: u64,
+  pub(crate) created_at: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WarningRecord {
+  pub(crate) message: String,
+  pub(crate) raised_at: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlayerSighting {
+  pub(crate) name: String,
+  pub(crate) seen_at: String,
+}
+
+/// Running history of what odin has done to the server, kept in `.odin/state.json`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct OdinState {
+  #[serde(default)]
+  pub(crate) server_starts: Vec<String>,
+  #[serde(default)]
+  pub(crate) server_stops: Vec<String>,
+  #[serde(default)]
+  pub(crate) backups: Vec<BackupRecord>,
+  #[serde(default)]
+  pub(crate) updates: Vec<String>,
+  #[serde(default)]
+  pub(crate) warnings: Vec<WarningRecord>,
+  #[serde(default)]
+  pub(crate) players_seen: Vec<PlayerSighting>,
+  /// When an update was first seen as available without having been applied since.
+  #[serde(default)]
+  pub(crate) update_pending_since: Option<String>,
+}
+
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<FixedOffset>> {
+  DateTime::parse_from_rfc3339(timestamp).ok()
+}
+
+fn is_recent(timestamp: &str, cutoff: DateTime<Local>) -> bool {
+  match parse_timestamp(timestamp) {
+    Some(at) => at >= cutoff,
+    None => false,
+  }
+}
+
+impl OdinState {
+  pub fn last_backup_at(&self) -> Option<DateTime<FixedOffset>> {
+    self
+      .backups
+      .iter()
+      .filter_map(|backup| parse_timestamp(&backup.created_at))
+      .max()
+  }
+
+  fn prune(&mut self, cutoff: DateTime<Local>) {
+    self.server_starts.retain(|at| is_recent(at, cutoff));
+    self.server_stops.retain(|at| is_recent(at, cutoff));
+    self.updates.retain(|at| is_recent(at, cutoff));
+    self
+      .warnings
+      .retain(|warning| is_recent(&warning.raised_at, cutoff));
+    self
+      .players_seen
+      .retain(|player| is_recent(&player.seen_at, cutoff));
+    // Backups are kept for as long as the archive is, the report needs the oldest one.
+    let oldest_backup_cutoff = cutoff - Duration::days(STATE_RETENTION_DAYS);
+    self
+      .backups
+      .retain(|backup| is_recent(&backup.created_at, oldest_backup_cutoff));
+  }
+}
+
+pub struct StateFile {
+  path: PathBuf,
+}
+
+impl StateFile {
+  pub fn new() -> Self {
+    StateFile {
+      path: get_odin_dir().join(STATE_FILE_NAME),
+    }
+  }
+
+  pub fn load(&self) -> OdinState {
+    match fs::read_to_string(&self.path) {
+      Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!(
+          "Ignoring unreadable state file at {}: {}",
+          self.path.display(),
+          e
+        );
+        OdinState::default()
+      }),
+      Err(_) => OdinState::default(),
+    }
+  }
+
+  fn modify(&self, action: impl FnOnce(&mut OdinState)) -> io::Result<()> {
+    if let Some(parent) = self.path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    let lock_file = OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(self.path.with_extension("lock"))?;
+    lock_file.lock()?;
+    let mut state = self.load();
+    action(&mut state);
+    state.prune(Local::now() - Duration::days(STATE_RETENTION_DAYS));
+    fs::write(&self.path, serde_json::to_string_pretty(&state)?)?;
+    lock_file.unlock()
+  }
+
+  /// Recording history is best effort, failing to do so never fails the command.
+  pub fn record(&self, action: impl FnOnce(&mut OdinState)) {
+    match self.modify(action) {
+      Ok(_) => debug!("Updated state file at {}", self.path.display()),
+      Err(e) => warn!(
+        "Failed to update state file at {}: {}",
+        self.path.display(),
+        e
+      ),
+    }
+  }
+}
+
+pub fn now() -> String {
+  Local::now().to_rfc3339()
+}
+
+pub fn record_server_start() {
+  StateFile::new().record(|state| state.server_starts.push(now()));
+}
+
+pub fn record_server_stop() {
+  StateFile::new().record(|state| state.server_stops.push(now()));
+}
+
+pub fn record_update() {
+  StateFile::new().record(|state| {
+    state.updates.push(now());
+    state.update_pending_since = None;
+  });
+}
+
+/// Tracks how long an available update has gone unapplied.
+pub fn record_update_check(update_available: bool) {
+  StateFile::new().record(|state| {
+    if !update_available {
+      state.update_pending_since = None;
+    } else if state.update_pending_since.is_none() {
+      state.update_pending_since = Some(now());
+    }
+  });
+}
+
+pub fn record_warning(message: &str) {
+  let warning = WarningRecord {
+    message: message.to_string(),
+    raised_at: now(),
+  };
+  StateFile::new().record(|state| state.warnings.push(warning));
+}
+
+pub fn record_player_seen(name: &str) {
+  let sighting = PlayerSighting {
+    name: name.to_string(),
+    seen_at: now(),
+  };
+  StateFile::new().record(|state| state.players_seen.push(sighting));
+}
+
+pub fn record_backup(path: &str, size_bytes: u64, world_size_bytes: u64) {
+  let backup = BackupRecord {
+    path: path.to_string(),
+    size_bytes,
+    world_size_bytes,
+    created_at: now(),
+  };
+  StateFile::new().record(|state| state.backups.push(backup));
+}
+
+#[cfg(test)]
+mod state_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use tempfile::TempDir;
+
+  fn test_state_file() -> (TempDir, StateFile) {
+    let dir = test_dir("state");
+    let state_file = StateFile {
+      path: dir.path().join(STATE_FILE_NAME),
+    };
+    (dir, state_file)
+  }
+
+  #[test]
+  fn records_and_loads() {
+    let (_dir, state_file) = test_state_file();
+    state_file.record(|state| state.updates.push(now()));
+    state_file.record(|state| state.server_starts.push(now()));
+    let state = state_file.load();
+    assert_eq!(state.updates.len(), 1);
+    assert_eq!(state.server_starts.len(), 1);
+  }
+
+  #[test]
+  fn drops_old_entries() {
+    let (_dir, state_file) = test_state_file();
+    let old = (Local::now() - Duration::days(STATE_RETENTION_DAYS + 1)).to_rfc3339();
+    state_file.record(|state| {
+      state.updates.push(old.clone());
+      state.updates.push(now());
+    });
+    assert_eq!(state_file.load().updates.len(), 1);
+  }
+}
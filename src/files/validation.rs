@@ -0,0 +1,176 @@
+use log::warn;
+
+use crate::errors::CommandError;
+use crate::files::ValheimArguments;
+use crate::server::{check_password, is_password_validation_ignored, MAX_GAME_PORT, MIN_GAME_PORT};
+
+const UNSAFE_WORLD_CHARACTERS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Valheim only lists the server in the community server list when launched with `-public 1`.
+pub fn is_public(arguments: &ValheimArguments) -> bool {
+  arguments.public.trim() == "1"
+}
+
+fn validate_password(arguments: &ValheimArguments) -> Vec<String> {
+  check_password(&arguments.password, &arguments.name, is_public(arguments))
+    .iter()
+    .map(ToString::to_string)
+    .collect()
+}
+
+pub fn validate_world(world: &str) -> Vec<String> {
+  let mut problems = Vec::new();
+  if world.trim().is_empty() {
+    problems.push("The world name can't be empty".to_string());
+    return problems;
+  }
+  if world.contains(UNSAFE_WORLD_CHARACTERS) || world.chars().any(char::is_control) {
+    problems.push(format!(
+      "The world name '{}' contains characters that aren't allowed in file names ({})",
+      world,
+      UNSAFE_WORLD_CHARACTERS.iter().collect::<String>()
+    ));
+  }
+  if world.starts_with('.') || world.trim() != world {
+    problems.push(format!(
+      "The world name '{}' can't start with a dot or start or end with spaces",
+      world
+    ));
+  }
+  problems
+}
+
+fn validate_port(port: &str) -> Vec<String> {
+  match port.trim().parse::<u16>() {
+    Ok(port) if (MIN_GAME_PORT..=MAX_GAME_PORT).contains(&port) => Vec::new(),
+    _ => vec![format!(
+      "The port '{}' must be a number between {} and {}, the server also uses the ports after it",
+      port, MIN_GAME_PORT, MAX_GAME_PORT
+    )],
+  }
+}
+
+/// Every problem with the arguments that would keep the server from booting, except for the
+/// password's when its validation is ignored.
+pub fn validate_arguments(arguments: &ValheimArguments) -> Vec<String> {
+  let mut problems = if is_password_validation_ignored() {
+    Vec::new()
+  } else {
+    validate_password(arguments)
+  };
+  problems.extend(validate_world(&arguments.world));
+  problems.extend(validate_port(&arguments.port));
+  problems
+}
+
+/// Fails listing each problem with the arguments if there are any.
+pub fn ensure_valid_arguments(arguments: &ValheimArguments) -> Result<(), CommandError> {
+  if is_password_validation_ignored() {
+    for problem in validate_password(arguments) {
+      warn!("Ignoring a password problem: {}", problem);
+    }
+  }
+  let problems = validate_arguments(arguments);
+  if problems.is_empty() {
+    return Ok(());
+  }
+  Err(CommandError::with_problems(
+    "The server configuration is invalid:",
+    &problems,
+  ))
+}
+
+#[cfg(test)]
+mod validation_tests {
+  use super::*;
+
+  fn private(password: &str) -> ValheimArguments {
+    ValheimArguments {
+      public: "0".to_string(),
+      ..arguments("Odin", "w", "2456", password)
+    }
+  }
+
+  fn arguments(name: &str, world: &str, port: &str, password: &str) -> ValheimArguments {
+    ValheimArguments {
+      port: port.to_string(),
+      name: name.to_string(),
+      world: world.to_string(),
+      public: "1".to_string(),
+      password: password.to_string(),
+      command: "valheim_server.x86_64".to_string(),
+      crossplay: false,
+      world_modifiers: Default::default(),
+      save_settings: Default::default(),
+      extra_args: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn accepts_defaults() {
+    assert!(validate_arguments(&arguments(
+      "Valheim powered by Odin",
+      "Dedicated",
+      "2456",
+      "12345"
+    ))
+    .is_empty());
+  }
+
+  #[test]
+  fn rejects_weak_passwords() {
+    assert_eq!(
+      validate_password(&arguments("Odin", "w", "2456", "abc")).len(),
+      1
+    );
+    assert_eq!(
+      validate_password(&arguments("Odin", "w", "2456", "aaaaa")).len(),
+      1
+    );
+    assert_eq!(
+      validate_password(&arguments("My Viking Server", "w", "2456", "viking")).len(),
+      1
+    );
+    assert_eq!(
+      validate_password(&arguments("Odin", "w", "2456", "")).len(),
+      1
+    );
+  }
+
+  #[test]
+  fn lets_private_servers_go_without_a_password() {
+    assert!(validate_password(&private("")).is_empty());
+    assert_eq!(validate_password(&private("abc")).len(), 1);
+    assert!(validate_password(&arguments("Odin", "w", "2456", ""))[0].contains("PUBLIC"));
+  }
+
+  #[test]
+  fn rejects_unsafe_world_names() {
+    assert!(!validate_world("").is_empty());
+    assert!(!validate_world("   ").is_empty());
+    assert!(!validate_world("../etc").is_empty());
+    assert!(!validate_world("my:world").is_empty());
+    assert!(!validate_world(" Dedicated").is_empty());
+    assert!(validate_world("Dedicated World 2").is_empty());
+  }
+
+  #[test]
+  fn rejects_ports_out_of_range() {
+    assert!(validate_port("2456").is_empty());
+    assert!(!validate_port("0").is_empty());
+    assert!(!validate_port("1023").is_empty());
+    assert!(validate_port("1024").is_empty());
+    assert!(validate_port("65533").is_empty());
+    assert!(!validate_port("65534").is_empty());
+    assert!(!validate_port("70000").is_empty());
+    assert!(!validate_port("abc").is_empty());
+  }
+
+  #[test]
+  fn reports_every_problem() {
+    assert_eq!(
+      validate_arguments(&arguments("Odin", "", "-1", "abc")).len(),
+      3
+    );
+  }
+}
@@ -0,0 +1,305 @@
+use chrono::{DateTime, Local};
+use log::{debug, info};
+
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use crate::files::filter::PathFilter;
+use crate::utils::{
+  environment::{active_profile, active_profile_value, fetch_var},
+  get_odin_dir, get_working_dir,
+};
+
+pub const BACKUP_SOURCE_DIR_VAR: &str = "BACKUP_SOURCE_DIR";
+pub const BACKUP_WORLDS_VAR: &str = "BACKUP_WORLDS";
+pub const DEFAULT_SAVES_DIR: &str = "/home/steam/.config/unity3d/IronGate/Valheim";
+
+/// Holds the world picked with `odin world use`, under the odin directory.
+const SELECTED_WORLD_FILE_NAME: &str = "world";
+/// Where Valheim keeps worlds, `worlds` before the move to cloud saves.
+const WORLD_DIRS: [&str; 2] = ["worlds_local", "worlds"];
+const WORLD_EXTENSION: &str = "fwl";
+const WORLD_DATA_EXTENSION: &str = "db";
+/// Valheim keeps the previous save of each world file under this extension.
+const PREVIOUS_SAVE_EXTENSION: &str = "old";
+/// Stands for the world the server loads in `BACKUP_WORLDS`.
+const CURRENT_WORLD: &str = "current";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct World {
+  pub(crate) name: String,
+  pub(crate) directory: PathBuf,
+  /// The `.fwl` and `.db` together.
+  pub(crate) size: u64,
+  pub(crate) modified: Option<DateTime<Local>>,
+}
+
+/// The saves directory, the same one backups are taken of. A profile that doesn't set its own
+/// keeps its saves in its server directory, apart from every other profile's.
+pub fn saves_dir() -> PathBuf {
+  if active_profile().is_some() && active_profile_value(BACKUP_SOURCE_DIR_VAR).is_none() {
+    return get_working_dir().join("saves");
+  }
+  PathBuf::from(fetch_var(BACKUP_SOURCE_DIR_VAR, DEFAULT_SAVES_DIR))
+}
+
+fn world_in(directory: &Path, fwl: &Path) -> Option<World> {
+  let name = fwl.file_stem()?.to_string_lossy().to_string();
+  let files = [
+    fwl.to_path_buf(),
+    directory.join(format!("{}.{}", name, WORLD_DATA_EXTENSION)),
+  ];
+  let metadata: Vec<fs::Metadata> = files
+    .iter()
+    .filter_map(|file| fs::metadata(file).ok())
+    .collect();
+  Some(World {
+    name,
+    directory: directory.to_path_buf(),
+    size: metadata.iter().map(|metadata| metadata.len()).sum(),
+    modified: metadata
+      .iter()
+      .filter_map(|metadata| metadata.modified().ok())
+      .max()
+      .map(DateTime::<Local>::from),
+  })
+}
+
+/// Every world in the saves directory, a world being a `.fwl` with or without its `.db`.
+pub fn list_worlds(saves: &Path) -> Vec<World> {
+  let mut worlds: Vec<World> = WORLD_DIRS
+    .iter()
+    .map(|directory| saves.join(directory))
+    .filter_map(|directory| Some((fs::read_dir(&directory).ok()?, directory)))
+    .flat_map(|(entries, directory)| {
+      entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+          path.is_file()
+            && path
+              .extension()
+              .is_some_and(|extension| extension == WORLD_EXTENSION)
+        })
+        .filter_map(|path| world_in(&directory, &path))
+        .collect::<Vec<World>>()
+    })
+    .collect();
+  worlds.sort_by(|a, b| a.name.cmp(&b.name).then(a.directory.cmp(&b.directory)));
+  worlds
+}
+
+/// The world a file under the saves directory belongs to, `None` when it isn't a `.fwl` or `.db`,
+/// or the `.old` copy of one, in a worlds directory.
+pub fn world_of(relative: &Path) -> Option<String> {
+  let mut components = relative.components();
+  let directory = components.next()?.as_os_str().to_str()?;
+  let file_name = components.next()?.as_os_str().to_str()?;
+  if components.next().is_some() || !WORLD_DIRS.contains(&directory) {
+    return None;
+  }
+  let file_name = file_name
+    .strip_suffix(&format!(".{}", PREVIOUS_SAVE_EXTENSION))
+    .unwrap_or(file_name);
+  [WORLD_EXTENSION, WORLD_DATA_EXTENSION]
+    .iter()
+    .find_map(|extension| file_name.strip_suffix(&format!(".{}", extension)))
+    .filter(|name| !name.is_empty())
+    .map(str::to_string)
+}
+
+/// The names of every world with a file in the saves directory, sorted. Unlike `list_worlds` a
+/// `.db` or `.old` without its `.fwl` counts.
+pub fn worlds_with_files(saves: &Path) -> Vec<String> {
+  let mut worlds: Vec<String> = WORLD_DIRS
+    .iter()
+    .filter_map(|directory| Some((fs::read_dir(saves.join(directory)).ok()?, directory)))
+    .flat_map(|(entries, directory)| {
+      entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| world_of(&Path::new(directory).join(entry.file_name())))
+        .collect::<Vec<String>>()
+    })
+    .collect();
+  worlds.sort();
+  worlds.dedup();
+  worlds
+}
+
+/// Parses a comma separated list of world names, `current` being `current_world`. Empty when
+/// every world is wanted.
+pub fn parse_world_names(value: &str, current_world: &str) -> Vec<String> {
+  let mut worlds: Vec<String> = Vec::new();
+  for world in value
+    .split(',')
+    .map(str::trim)
+    .filter(|world| !world.is_empty())
+  {
+    let world = if world.eq_ignore_ascii_case(CURRENT_WORLD) {
+      current_world
+    } else {
+      world
+    };
+    if !worlds.iter().any(|picked| picked == world) {
+      worlds.push(world.to_string());
+    }
+  }
+  worlds
+}
+
+/// Lets through the `.fwl`, `.db` and their `.old` copies of `world`, and nothing else.
+pub fn world_filter(world: &str) -> PathFilter {
+  let patterns: Vec<String> = WORLD_DIRS
+    .iter()
+    .flat_map(|directory| {
+      [WORLD_EXTENSION, WORLD_DATA_EXTENSION]
+        .iter()
+        .flat_map(move |extension| {
+          vec![
+            format!("{}/{}.{}", directory, world, extension),
+            format!(
+              "{}/{}.{}.{}",
+              directory, world, extension, PREVIOUS_SAVE_EXTENSION
+            ),
+          ]
+        })
+    })
+    .collect();
+  PathFilter::new(&patterns.join(","), "")
+}
+
+fn selected_world_path() -> PathBuf {
+  get_odin_dir().join(SELECTED_WORLD_FILE_NAME)
+}
+
+/// The world picked with `odin world use`, if any.
+pub fn selected_world() -> Option<String> {
+  let world = fs::read_to_string(selected_world_path()).ok()?;
+  let world = world.trim();
+  (!world.is_empty()).then(|| world.to_string())
+}
+
+pub fn select_world(world: &str) -> io::Result<()> {
+  fs::create_dir_all(get_odin_dir())?;
+  fs::write(selected_world_path(), format!("{}\n", world))
+}
+
+/// Goes back to the configured world, returns whether one had been picked.
+pub fn clear_selected_world() -> io::Result<bool> {
+  match fs::remove_file(selected_world_path()) {
+    Ok(_) => Ok(true),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+    Err(e) => Err(e),
+  }
+}
+
+/// The world the server starts with, the one picked with `odin world use` over `configured`.
+pub fn resolve_world(configured: &str) -> String {
+  match selected_world() {
+    Some(world) if world != configured => {
+      info!(
+        "Loading {} picked with odin world use instead of {}",
+        world, configured
+      );
+      world
+    }
+    _ => {
+      debug!("Loading the configured world {}", configured);
+      configured.to_string()
+    }
+  }
+}
+
+#[cfg(test)]
+mod worlds_tests {
+  use super::*;
+  use crate::constants;
+  use crate::utils::testing::test_dir;
+  use serial_test::serial;
+  use std::env;
+
+  #[test]
+  fn lists_worlds_in_both_directories() {
+    let temp = test_dir("worlds");
+    let saves = temp.path();
+    fs::create_dir_all(saves.join("worlds_local")).unwrap();
+    fs::create_dir_all(saves.join("worlds")).unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.fwl"), "12").unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.db"), "345").unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.db.old"), "345").unwrap();
+    fs::write(saves.join("worlds/Ashlands.fwl"), "1").unwrap();
+    fs::write(saves.join("worlds/notes.txt"), "not a world").unwrap();
+
+    let worlds = list_worlds(saves);
+    let names: Vec<(&str, u64)> = worlds
+      .iter()
+      .map(|world| (world.name.as_str(), world.size))
+      .collect();
+    assert_eq!(names, vec![("Ashlands", 1), ("Dedicated", 5)]);
+    assert_eq!(worlds[1].directory, saves.join("worlds_local"));
+  }
+
+  #[test]
+  fn tells_which_world_a_file_belongs_to() {
+    assert_eq!(
+      world_of(Path::new("worlds_local/Dedicated.db")),
+      Some("Dedicated".to_string())
+    );
+    assert_eq!(
+      world_of(Path::new("worlds/Ash.lands.fwl.old")),
+      Some("Ash.lands".to_string())
+    );
+    assert_eq!(world_of(Path::new("worlds_local/notes.txt")), None);
+    assert_eq!(world_of(Path::new("characters/Viking.db")), None);
+    assert_eq!(
+      world_of(Path::new("worlds_local/nested/Dedicated.db")),
+      None
+    );
+
+    let filter = world_filter("Dedicated");
+    assert!(filter.includes(Path::new("worlds_local/Dedicated.fwl")));
+    assert!(filter.includes(Path::new("worlds/Dedicated.db.old")));
+    assert!(!filter.includes(Path::new("worlds_local/Dedicated2.db")));
+    assert!(!filter.includes(Path::new("worlds_local/Other.db")));
+    assert!(!filter.includes(Path::new("adminlist.txt")));
+  }
+
+  #[test]
+  fn picks_worlds_by_name() {
+    assert_eq!(
+      parse_world_names("current, Ashlands,Dedicated", "Dedicated"),
+      vec!["Dedicated".to_string(), "Ashlands".to_string()]
+    );
+    assert!(parse_world_names(" , ", "Dedicated").is_empty());
+
+    let temp = test_dir("worlds");
+    let saves = temp.path();
+    fs::create_dir_all(saves.join("worlds_local")).unwrap();
+    fs::create_dir_all(saves.join("worlds")).unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.fwl"), "12").unwrap();
+    fs::write(saves.join("worlds_local/Dedicated.db"), "345").unwrap();
+    fs::write(saves.join("worlds/Winter.db.old"), "6").unwrap();
+    fs::write(saves.join("worlds/notes.txt"), "not a world").unwrap();
+    assert_eq!(
+      worlds_with_files(saves),
+      vec!["Dedicated".to_string(), "Winter".to_string()]
+    );
+  }
+
+  #[test]
+  #[serial]
+  fn picked_world_wins_until_cleared() {
+    let temp = test_dir("worlds");
+    let working_dir = temp.path();
+    env::set_var(constants::ODIN_WORKING_DIR, working_dir);
+    assert_eq!(resolve_world("Dedicated"), "Dedicated");
+    select_world("Ashlands").unwrap();
+    assert_eq!(resolve_world("Dedicated"), "Ashlands");
+    assert!(clear_selected_world().unwrap());
+    assert!(!clear_selected_world().unwrap());
+    assert_eq!(resolve_world("Dedicated"), "Dedicated");
+    env::remove_var(constants::ODIN_WORKING_DIR);
+  }
+}
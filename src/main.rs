@@ -1,8 +1,11 @@
-use clap::{load_yaml, App};
-use log::{debug, info, LevelFilter, SetLoggerError};
+use clap::{load_yaml, App, ArgMatches};
+use log::{debug, error, info, warn, LevelFilter, SetLoggerError};
 
-use crate::executable::handle_exit_status;
-use crate::logger::OdinLogger;
+use std::{env, process::exit, str::FromStr};
+
+use crate::errors::CommandResult;
+use crate::files::failure;
+use crate::logger::{LogFormat, OdinLogger};
 use crate::utils::environment;
 mod commands;
 mod constants;
@@ -21,13 +24,19 @@ use crate::notifications::enums::event_status::EventStatus;
 use crate::notifications::enums::notification_event::NotificationEvent;
 
 static LOGGER: OdinLogger = OdinLogger;
+/// The commands that send notifications, and so retry the ones queued by earlier runs.
+const NOTIFYING_COMMANDS: [&str; 7] = [
+  "start", "stop", "update", "backup", "install", "watch", "notify",
+];
 
-fn setup_logger(debug: bool) -> Result<(), SetLoggerError> {
+fn setup_logger(debug: bool, format: LogFormat) -> Result<(), SetLoggerError> {
+  logger::set_log_format(format);
   let level = if debug {
     LevelFilter::Debug
   } else {
     LevelFilter::Info
   };
+  logger::setup_log_file();
   let result = log::set_logger(&LOGGER).map(|_| log::set_max_level(level));
   debug!("Debugging set to {}", debug.to_string());
   result
@@ -36,46 +45,158 @@ fn setup_logger(debug: bool) -> Result<(), SetLoggerError> {
 fn main() {
   // The YAML file is found relative to the current file, similar to how modules are found
   let yaml = load_yaml!("cli.yaml");
-  let app = App::from(yaml).version(constants::VERSION);
-  let matches = app.get_matches();
+  let mut app = App::from(yaml).version(constants::VERSION);
+  let matches = app.clone().get_matches();
+  // Handled before the logger is set up so nothing but the script reaches stdout.
+  if let Some(completions_matches) = matches.subcommand_matches("completions") {
+    commands::completions::invoke(completions_matches, &mut app);
+    return;
+  }
+  // Every path is built from the server directory, set it before anything looks one up.
+  if let Some(server_dir) = matches.value_of("server_dir") {
+    utils::set_working_dir(server_dir);
+  }
+  // Load the config file before anything reads the environment, including the logger.
+  let config_result = match matches.value_of("config") {
+    Some(config_path) => environment::load_config_file(config_path),
+    None => Ok(()),
+  };
+  // A profile overlays its own settings, including the server directory, on everything after this.
+  let profile_result = match matches
+    .value_of("profile")
+    .map(str::to_string)
+    .or_else(|| env::var(environment::ODIN_PROFILE_VAR).ok())
+    .filter(|profile| !profile.is_empty())
+  {
+    Some(profile) => environment::use_profile(&profile),
+    None => Ok(()),
+  };
+  if profile_result.is_ok() && !matches.is_present("server_dir") {
+    if let Some(server_dir) = environment::active_profile_value("WORKING_DIR") {
+      utils::set_working_dir(&server_dir);
+    }
+  }
   let debug_mode = matches.is_present("debug") || environment::fetch_var("DEBUG_MODE", "0").eq("1");
-  setup_logger(debug_mode).unwrap();
+  let log_format_value = match matches.value_of("log_format") {
+    Some(value) => value.to_string(),
+    None => environment::fetch_var(logger::LOG_FORMAT_VAR, "text"),
+  };
+  let log_format = LogFormat::from_str(&log_format_value);
+  setup_logger(debug_mode, *log_format.as_ref().unwrap_or(&LogFormat::Text)).unwrap();
+  let status_output = matches
+    .subcommand_matches("status")
+    .and_then(|status_matches| status_matches.value_of("output"));
+  let start_output = matches
+    .subcommand_matches("start")
+    .filter(|start_matches| start_matches.is_present("dry_run"))
+    .and_then(|start_matches| start_matches.value_of("output"));
+  if status_output == Some("json") || start_output == Some("json") {
+    logger::log_to_stderr();
+  }
+  if let Err(e) = log_format {
+    warn!("{}. Falling back to text logs.", e);
+  }
+  if let Err(e) = config_result.and(profile_result) {
+    error!("{}", e);
+    exit(1);
+  }
+  if let Some(profile) = environment::active_profile() {
+    info!(
+      "Using profile '{}' in {}",
+      profile,
+      utils::get_working_dir().display()
+    );
+  }
   if !debug_mode {
     info!("Run with DEBUG_MODE as 1 if you think there is an issue with Odin");
   }
   debug!("Debug mode enabled!");
-  if let Some(ref configure_matches) = matches.subcommand_matches("configure") {
+  if matches.is_present("no_notify") {
+    notifications::silence_notifications();
+  }
+  if let Some(command) = matches.subcommand_name() {
+    // Only commands that notify retry what is queued, a status check shouldn't go posting.
+    if NOTIFYING_COMMANDS.contains(&command) {
+      notifications::flush_queued_notifications();
+    }
+    failure::begin_operation(command);
+  }
+  failure::conclude_operation(run(&matches));
+}
+
+/// Runs the subcommand picked.
+fn run(matches: &ArgMatches) -> CommandResult {
+  if let Some(configure_matches) = matches.subcommand_matches("configure") {
     debug!("Launching configure command...");
-    commands::configure::invoke(configure_matches);
-  };
-  if let Some(ref _match) = matches.subcommand_matches("install") {
+    return commands::configure::invoke(configure_matches);
+  }
+  if let Some(install_matches) = matches.subcommand_matches("install") {
     debug!("Launching install command...");
-    let result = commands::install::invoke(constants::GAME_ID);
-    handle_exit_status(result, "Successfully installed Valheim!".to_string())
-  };
-  if let Some(ref start_matches) = matches.subcommand_matches("start") {
+    return commands::install::invoke(install_matches);
+  }
+  if let Some(start_matches) = matches.subcommand_matches("start") {
     debug!("Launching start command...");
-    NotificationEvent::Start(EventStatus::Running).send_notification();
-    commands::start::invoke(start_matches);
-    NotificationEvent::Start(EventStatus::Successful).send_notification();
-  };
-  if let Some(ref stop_matches) = matches.subcommand_matches("stop") {
+    if !start_matches.is_present("dry_run") {
+      NotificationEvent::Start(EventStatus::Running).send_notification();
+    }
+    return commands::start::invoke(start_matches);
+  }
+  if let Some(stop_matches) = matches.subcommand_matches("stop") {
     debug!("Launching stop command...");
-    NotificationEvent::Stop(EventStatus::Running).send_notification();
-    commands::stop::invoke(stop_matches);
-    NotificationEvent::Stop(EventStatus::Successful).send_notification();
-  };
-  if let Some(ref backup_matches) = matches.subcommand_matches("backup") {
+    let dry_run = stop_matches.is_present("dry_run");
+    if !dry_run {
+      NotificationEvent::Stop(EventStatus::Running).send_notification();
+    }
+    commands::stop::invoke(stop_matches)?;
+    if !dry_run {
+      NotificationEvent::Stop(EventStatus::Successful).send_notification();
+    }
+    return Ok(());
+  }
+  if let Some(backup_matches) = matches.subcommand_matches("backup") {
     debug!("Launching backup command...");
-    commands::backup::invoke(backup_matches);
-  };
-  if let Some(ref notify_matches) = matches.subcommand_matches("notify") {
+    return commands::backup::invoke(backup_matches);
+  }
+  if let Some(restore_matches) = matches.subcommand_matches("restore") {
+    debug!("Launching restore command...");
+    return commands::restore::invoke(restore_matches);
+  }
+  if let Some(cmd_matches) = matches.subcommand_matches("cmd") {
+    debug!("Launching cmd command...");
+    return commands::cmd::invoke(cmd_matches);
+  }
+  if let Some(world_matches) = matches.subcommand_matches("world") {
+    debug!("Launching world command...");
+    return commands::world::invoke(world_matches);
+  }
+  if let Some(watch_matches) = matches.subcommand_matches("watch") {
+    debug!("Launching watch command...");
+    return commands::watch::invoke(watch_matches);
+  }
+  if let Some(logs_matches) = matches.subcommand_matches("logs") {
+    debug!("Launching logs command...");
+    return commands::logs::invoke(logs_matches);
+  }
+  if let Some(notify_matches) = matches.subcommand_matches("notify") {
     debug!("Launching notify command...");
-    commands::notify::invoke(notify_matches);
-  };
+    return commands::notify::invoke(notify_matches);
+  }
+  if let Some(health_matches) = matches.subcommand_matches("health") {
+    debug!("Launching health command...");
+    return commands::health::invoke(health_matches);
+  }
+  if let Some(status_matches) = matches.subcommand_matches("status") {
+    debug!("Launching status command...");
+    return commands::status::invoke(status_matches);
+  }
+  if let Some(report_matches) = matches.subcommand_matches("report") {
+    debug!("Launching report command...");
+    return commands::report::invoke(report_matches);
+  }
 
-  if let Some(ref update_matches) = matches.subcommand_matches("update") {
+  if let Some(update_matches) = matches.subcommand_matches("update") {
     debug!("Launching update command...");
-    commands::update::invoke(update_matches);
+    return commands::update::invoke(update_matches);
   }
+  Ok(())
 }
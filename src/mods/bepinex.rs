@@ -1,8 +1,15 @@
 use crate::constants;
-use crate::utils::{environment, get_working_dir, path_exists};
-use log::{debug, info};
+use crate::files::archive::hash_file;
+use crate::utils::{environment, get_working_dir};
+use log::{debug, info, warn};
+use std::env::{
+  self,
+  consts::{DLL_PREFIX, DLL_SUFFIX},
+};
+use std::fs::{self, File};
+use std::io::Read;
 use std::ops::Add;
-use std::process::{Child, Command};
+use std::path::{Path, PathBuf};
 
 const DYLD_LIBRARY_PATH_VAR: &str = "DYLD_LIBRARY_PATH";
 const DYLD_INSERT_LIBRARIES_VAR: &str = "DYLD_INSERT_LIBRARIES";
@@ -12,26 +19,106 @@ const DOORSTOP_LIBS_VAR: &str = "DOORSTOP_LIBS";
 const DOORSTOP_INVOKE_DLL_PATH_VAR: &str = "DOORSTOP_INVOKE_DLL_PATH";
 const DOORSTOP_CORLIB_OVERRIDE_PATH_VAR: &str = "DOORSTOP_CORLIB_OVERRIDE_PATH";
 
+/// A `sha256sum` style file of known good hashes the BepInEx files are checked against.
+pub const BEPINEX_CHECKSUMS_VAR: &str = "BEPINEX_CHECKSUMS";
+/// Replaced in `BEPINEX_CHECKSUMS` with the version of BepInEx that last loaded.
+const VERSION_PLACEHOLDER: &str = "{version}";
+
+/// BepInEx starts this log with its version every time the server loads it.
+const BEPINEX_LOG: [&str; 2] = ["BepInEx", "LogOutput.log"];
+const BEPINEX_VERSION_MARKER: &str = "] BepInEx ";
+
+/// `e_machine` values of the architectures doorstop is built for.
+const ELF_MACHINE_X86: u16 = 0x03;
+const ELF_MACHINE_ARM: u16 = 0x28;
+const ELF_MACHINE_X64: u16 = 0x3e;
+const ELF_MACHINE_ARM64: u16 = 0xb7;
+
+/// The doorstop name of an ELF binary's architecture, read from its header.
+fn elf_arch(header: &[u8]) -> Option<&'static str> {
+  if header.len() < 20 || &header[..4] != b"\x7fELF" {
+    return None;
+  }
+  let machine = match header[5] {
+    1 => u16::from_le_bytes([header[18], header[19]]),
+    2 => u16::from_be_bytes([header[18], header[19]]),
+    _ => return None,
+  };
+  match machine {
+    ELF_MACHINE_X86 => Some("x86"),
+    ELF_MACHINE_ARM => Some("arm"),
+    ELF_MACHINE_X64 => Some("x64"),
+    ELF_MACHINE_ARM64 => Some("arm64"),
+    _ => None,
+  }
+}
+
+/// The doorstop name of the architecture odin was built for.
+fn build_arch() -> &'static str {
+  match std::env::consts::ARCH {
+    "x86" => "x86",
+    "arm" => "arm",
+    "aarch64" => "arm64",
+    _ => "x64",
+  }
+}
+
+/// Doorstop is loaded into the server, so it has to match the server's architecture. That is
+/// read from the executable, which also covers an x64 server emulated with box64 on ARM, and only
+/// falls back on odin's own architecture when the executable can't be read.
+fn doorstop_arch() -> &'static str {
+  let executable = get_working_dir().join(constants::VALHEIM_EXECUTABLE_NAME);
+  let mut header = [0; 20];
+  let detected = File::open(&executable)
+    .and_then(|mut file| file.read_exact(&mut header))
+    .ok()
+    .and_then(|_| elf_arch(&header));
+  match detected {
+    Some(arch) => {
+      debug!("Detected {} from {}", arch, executable.display());
+      arch
+    }
+    None => {
+      debug!(
+        "Unable to read the architecture of {}, using {}",
+        executable.display(),
+        build_arch()
+      );
+      build_arch()
+    }
+  }
+}
+
+/// `libdoorstop_x64.so` on Linux, named the way the platform names shared libraries.
 fn doorstop_lib() -> String {
-  environment::fetch_var(DOORSTOP_LIB_VAR, "libdoorstop_x64.so")
+  let default = format!("{}doorstop_{}{}", DLL_PREFIX, doorstop_arch(), DLL_SUFFIX);
+  let lib = environment::fetch_var(DOORSTOP_LIB_VAR, &default);
+  debug!("Using doorstop library {}", lib);
+  lib
+}
+
+/// Where a path under the working directory goes in an environment variable.
+fn working_path(components: &[&str]) -> String {
+  components
+    .iter()
+    .fold(get_working_dir(), |path, component| path.join(component))
+    .to_string_lossy()
+    .to_string()
 }
 
 fn doorstop_libs() -> String {
-  environment::fetch_var(
-    DOORSTOP_LIBS_VAR,
-    format!("{}/doorstop_libs", get_working_dir()).as_str(),
-  )
+  environment::fetch_var(DOORSTOP_LIBS_VAR, &working_path(&["doorstop_libs"]))
 }
 
 fn doorstop_insert_lib() -> String {
-  let default = format!("{}/{}", doorstop_libs(), doorstop_lib().replace(":", ""));
-  environment::fetch_var(DYLD_INSERT_LIBRARIES_VAR, default.as_str())
+  let default = Path::new(&doorstop_libs()).join(doorstop_lib().replace(":", ""));
+  environment::fetch_var(DYLD_INSERT_LIBRARIES_VAR, &default.to_string_lossy())
 }
 
 fn doorstop_invoke_dll() -> String {
   environment::fetch_var(
     DOORSTOP_INVOKE_DLL_PATH_VAR,
-    format!("{}/BepInEx/core/BepInEx.Preloader.dll", get_working_dir()).as_str(),
+    &working_path(&["BepInEx", "core", "BepInEx.Preloader.dll"]),
   )
 }
 
@@ -48,14 +135,18 @@ pub struct BepInExEnvironment {
 pub fn build_environment() -> BepInExEnvironment {
   let ld_preload =
     environment::fetch_var(constants::LD_PRELOAD_VAR, "").add(doorstop_lib().as_str());
-  let ld_library_path = environment::fetch_var(
-    constants::LD_LIBRARY_PATH_VAR,
-    format!("./linux64:{}", doorstop_libs()).as_str(),
-  );
+  let default_library_path = env::join_paths([
+    Path::new(".").join("linux64"),
+    PathBuf::from(doorstop_libs()),
+  ])
+  .map(|paths| paths.to_string_lossy().to_string())
+  .unwrap_or_default();
+  let ld_library_path =
+    environment::fetch_var(constants::LD_LIBRARY_PATH_VAR, &default_library_path);
   let doorstop_invoke_dll_value = doorstop_invoke_dll();
   let doorstop_corlib_override_path = environment::fetch_var(
     DOORSTOP_CORLIB_OVERRIDE_PATH_VAR,
-    format!("{}/{}", get_working_dir(), "unstripped_corlib").as_str(),
+    &working_path(&["unstripped_corlib"]),
   );
   let dyld_library_path = environment::fetch_var(DYLD_LIBRARY_PATH_VAR, doorstop_libs().as_str());
   let dyld_insert_libraries =
@@ -89,52 +180,316 @@ pub fn build_environment() -> BepInExEnvironment {
   environment
 }
 
-pub fn is_bepinex_installed() -> bool {
+/// The files BepInEx needs that `is_bepinex_installed` looked for, split by whether they were
+/// found.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BepInExInstallation {
+  pub(crate) present: Vec<String>,
+  pub(crate) missing: Vec<String>,
+  /// Present but not matching `BEPINEX_CHECKSUMS`, likely a partial or corrupted download.
+  pub(crate) corrupted: Vec<String>,
+}
+
+impl BepInExInstallation {
+  pub fn is_installed(&self) -> bool {
+    self.missing.is_empty()
+  }
+
+  pub fn is_intact(&self) -> bool {
+    self.corrupted.is_empty()
+  }
+}
+
+/// The files and hashes in `sha256sum` output, relative paths resolved against `base`.
+fn parse_checksums(content: &str, base: &Path) -> Vec<(PathBuf, String)> {
+  content
+    .lines()
+    .filter_map(|line| {
+      let (hash, path) = line.trim().split_once(char::is_whitespace)?;
+      // `sha256sum -b` marks binary files with a `*`.
+      let path = path.trim_start().trim_start_matches('*');
+      if hash.is_empty() || path.is_empty() || line.trim_start().starts_with('#') {
+        return None;
+      }
+      Some((base.join(path), hash.to_lowercase()))
+    })
+    .collect()
+}
+
+/// Hashes every file in `checksums`. The ones that are gone are added to the missing files,
+/// the ones that don't match to the corrupted files.
+fn verify_checksums(checksums: &[(PathBuf, String)], installation: &mut BepInExInstallation) {
+  for (path, expected) in checksums {
+    let display = path.to_string_lossy().to_string();
+    if !path.exists() {
+      debug!("[missing] {}", display);
+      if !installation.missing.contains(&display) {
+        installation.missing.push(display);
+      }
+      continue;
+    }
+    match hash_file(path) {
+      Ok(hash) if &hash == expected => debug!("[verified] {}", display),
+      Ok(hash) => {
+        debug!("[corrupted] {}: {} instead of {}", display, hash, expected);
+        installation.corrupted.push(display);
+      }
+      Err(e) => {
+        debug!("[corrupted] {}: {}", display, e);
+        installation.corrupted.push(display);
+      }
+    }
+  }
+}
+
+/// `BEPINEX_CHECKSUMS` with the version filled in, `None` when it isn't set or the version isn't
+/// known yet.
+fn checksums_path() -> Option<PathBuf> {
+  let value = environment::fetch_var(BEPINEX_CHECKSUMS_VAR, "");
+  if value.trim().is_empty() {
+    return None;
+  }
+  if !value.contains(VERSION_PLACEHOLDER) {
+    return Some(PathBuf::from(value.trim()));
+  }
+  match bepinex_version() {
+    Some(version) => Some(PathBuf::from(
+      value.trim().replace(VERSION_PLACEHOLDER, &version),
+    )),
+    None => {
+      debug!(
+        "BepInEx hasn't loaded yet, its version for {} isn't known",
+        BEPINEX_CHECKSUMS_VAR
+      );
+      None
+    }
+  }
+}
+
+fn check_files(checks: &[(&str, &str)]) -> BepInExInstallation {
+  let mut installation = BepInExInstallation::default();
+  for (name, path) in checks {
+    if Path::new(path).exists() {
+      debug!("[present] {}: {}", name, path);
+      installation.present.push(path.to_string());
+    } else {
+      debug!("[missing] {}: {}", name, path);
+      installation.missing.push(path.to_string());
+    }
+  }
+  installation
+}
+
+pub fn is_bepinex_installed() -> BepInExInstallation {
   let bepinex_env: BepInExEnvironment = build_environment();
   debug!("Checking for BepInEx specific files...");
-  let checks = &[
-    bepinex_env.doorstop_corlib_override_path,
-    bepinex_env.dyld_insert_libraries,
-    bepinex_env.dyld_library_path,
-    bepinex_env.doorstop_invoke_dll,
-  ];
-  let expected_state = true;
-  let output = checks.iter().all(|ref v| path_exists(&v) == expected_state);
-  if output {
+  let mut installation = check_files(&[
+    (
+      DOORSTOP_CORLIB_OVERRIDE_PATH_VAR,
+      &bepinex_env.doorstop_corlib_override_path,
+    ),
+    (
+      DYLD_INSERT_LIBRARIES_VAR,
+      &bepinex_env.dyld_insert_libraries,
+    ),
+    (DYLD_LIBRARY_PATH_VAR, &bepinex_env.dyld_library_path),
+    (
+      DOORSTOP_INVOKE_DLL_PATH_VAR,
+      &bepinex_env.doorstop_invoke_dll,
+    ),
+  ]);
+  if let Some(path) = checksums_path() {
+    match fs::read_to_string(&path) {
+      Ok(content) => {
+        let checksums = parse_checksums(&content, &get_working_dir());
+        debug!(
+          "Checking {} files against {}",
+          checksums.len(),
+          path.display()
+        );
+        verify_checksums(&checksums, &mut installation);
+      }
+      Err(e) => warn!("Unable to read {}: {}", path.display(), e),
+    }
+  }
+  if !installation.is_intact() {
+    warn!(
+      "BepInEx looks corrupted, {} don't match their known good checksums. Download BepInEx again and extract it over the server.",
+      installation.corrupted.join(", ")
+    );
+  }
+  if installation.is_installed() {
     debug!("Yay! looks like we found all the required files for BepInEx to run! <3")
   } else {
-    debug!("Uhh ohh!!! Looks like you are missing something.")
+    debug!(
+      "Uhh ohh!!! Looks like you are missing {}",
+      installation.missing.join(", ")
+    )
   }
-  output
+  installation
 }
 
-pub fn invoke(command: &mut Command, environment: &BepInExEnvironment) -> std::io::Result<Child> {
-  info!("BepInEx found! Setting up Environment...");
-  command
-    // DOORSTOP_ENABLE must not have quotes around it.
-    .env(DOORSTOP_ENABLE_VAR, &environment.doorstop_enable)
-    // DOORSTOP_INVOKE_DLL_PATH must not have quotes around it.
-    .env(
-      DOORSTOP_INVOKE_DLL_PATH_VAR,
-      &environment.doorstop_invoke_dll,
-    )
-    .env(
-      DOORSTOP_CORLIB_OVERRIDE_PATH_VAR,
-      &environment.doorstop_corlib_override_path,
-    )
-    // LD_LIBRARY_PATH must not have quotes around it.
-    .env(constants::LD_LIBRARY_PATH_VAR, &environment.ld_library_path)
-    // LD_PRELOAD must not have quotes around it.
-    .env(constants::LD_PRELOAD_VAR, &environment.ld_preload)
-    // DYLD_LIBRARY_PATH is weird af and MUST have quotes around it.
-    .env(
-      DYLD_LIBRARY_PATH_VAR,
-      format!("\"{}\"", &environment.dyld_library_path),
-    )
-    // DYLD_INSERT_LIBRARIES must not have quotes around it.
-    .env(
-      DYLD_INSERT_LIBRARIES_VAR,
-      &environment.dyld_insert_libraries,
-    )
-    .spawn()
+impl BepInExEnvironment {
+  /// The variables the server is launched with, as they are set.
+  pub fn vars(&self) -> Vec<(&'static str, String)> {
+    vec![
+      // DOORSTOP_ENABLE must not have quotes around it.
+      (DOORSTOP_ENABLE_VAR, self.doorstop_enable.clone()),
+      // DOORSTOP_INVOKE_DLL_PATH must not have quotes around it.
+      (
+        DOORSTOP_INVOKE_DLL_PATH_VAR,
+        self.doorstop_invoke_dll.clone(),
+      ),
+      (
+        DOORSTOP_CORLIB_OVERRIDE_PATH_VAR,
+        self.doorstop_corlib_override_path.clone(),
+      ),
+      // LD_LIBRARY_PATH must not have quotes around it.
+      (constants::LD_LIBRARY_PATH_VAR, self.ld_library_path.clone()),
+      // LD_PRELOAD must not have quotes around it.
+      (constants::LD_PRELOAD_VAR, self.ld_preload.clone()),
+      // DYLD_LIBRARY_PATH is weird af and MUST have quotes around it.
+      (
+        DYLD_LIBRARY_PATH_VAR,
+        format!("\"{}\"", &self.dyld_library_path),
+      ),
+      // DYLD_INSERT_LIBRARIES must not have quotes around it.
+      (
+        DYLD_INSERT_LIBRARIES_VAR,
+        self.dyld_insert_libraries.clone(),
+      ),
+    ]
+  }
+}
+
+/// Whether the BepInEx preloader is where doorstop will look for it.
+pub fn is_bepinex_present() -> bool {
+  Path::new(&doorstop_invoke_dll()).exists()
+}
+
+/// The version in the first line of `LogOutput.log`, like
+/// `[Message:   BepInEx] BepInEx 5.4.21.0 - valheim_server`.
+fn parse_bepinex_version(log: &str) -> Option<String> {
+  let first_line = log.lines().next()?;
+  let start = first_line.find(BEPINEX_VERSION_MARKER)? + BEPINEX_VERSION_MARKER.len();
+  first_line[start..]
+    .split_whitespace()
+    .next()
+    .map(str::to_string)
+}
+
+/// Where BepInEx writes its own log, rewritten every time the server loads it.
+pub fn bepinex_log_path() -> PathBuf {
+  PathBuf::from(working_path(&BEPINEX_LOG))
+}
+
+/// The version of BepInEx that last loaded into the server, `None` before it ever has.
+pub fn bepinex_version() -> Option<String> {
+  let log = std::fs::read_to_string(working_path(&BEPINEX_LOG)).ok()?;
+  parse_bepinex_version(&log)
+}
+
+#[cfg(test)]
+mod bepinex_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::fs;
+
+  fn elf_header(class_data: u8, machine: [u8; 2]) -> Vec<u8> {
+    let mut header = b"\x7fELF\x02".to_vec();
+    header.push(class_data);
+    header.resize(18, 0);
+    header.extend_from_slice(&machine);
+    header
+  }
+
+  #[test]
+  fn reads_the_architecture_from_the_executable() {
+    assert_eq!(elf_arch(&elf_header(1, [0x3e, 0])), Some("x64"));
+    assert_eq!(elf_arch(&elf_header(1, [0x03, 0])), Some("x86"));
+    assert_eq!(elf_arch(&elf_header(1, [0xb7, 0])), Some("arm64"));
+    assert_eq!(elf_arch(&elf_header(2, [0, 0x28])), Some("arm"));
+    assert_eq!(elf_arch(&elf_header(1, [0x08, 0])), None);
+    assert_eq!(elf_arch(b"#!/bin/bash\necho not an elf\n"), None);
+  }
+
+  #[test]
+  fn reads_the_version_from_the_log() {
+    assert_eq!(
+      parse_bepinex_version(
+        "[Message:   BepInEx] BepInEx 5.4.21.0 - valheim_server (2/20/2021 7:32:05 PM)\n[Info   :   BepInEx] Running under Unity v2020.3.33\n"
+      ),
+      Some("5.4.21.0".to_string())
+    );
+    assert_eq!(
+      parse_bepinex_version("[Info   :   BepInEx] Loading plugins"),
+      None
+    );
+    assert_eq!(parse_bepinex_version(""), None);
+  }
+
+  #[test]
+  fn reports_each_missing_file() {
+    let temp = test_dir("bepinex");
+    let root = temp.path();
+    fs::create_dir_all(root.join("doorstop_libs")).unwrap();
+    fs::write(root.join("doorstop_libs/libdoorstop_x64.so"), "").unwrap();
+    let path = |name: &str| root.join(name).to_string_lossy().to_string();
+    let installation = check_files(&[
+      (
+        DOORSTOP_CORLIB_OVERRIDE_PATH_VAR,
+        &path("unstripped_corlib"),
+      ),
+      (
+        DYLD_INSERT_LIBRARIES_VAR,
+        &path("doorstop_libs/libdoorstop_x64.so"),
+      ),
+      (DYLD_LIBRARY_PATH_VAR, &path("doorstop_libs")),
+      (
+        DOORSTOP_INVOKE_DLL_PATH_VAR,
+        &path("BepInEx/core/BepInEx.Preloader.dll"),
+      ),
+    ]);
+    assert!(!installation.is_installed());
+    assert_eq!(
+      installation.missing,
+      vec![
+        path("unstripped_corlib"),
+        path("BepInEx/core/BepInEx.Preloader.dll")
+      ]
+    );
+    assert_eq!(installation.present.len(), 2);
+  }
+
+  #[test]
+  fn tells_corrupted_files_from_missing_ones() {
+    let temp = test_dir("bepinex-checksums");
+    let root = temp.path();
+    fs::create_dir_all(root.join("BepInEx/core")).unwrap();
+    fs::write(root.join("BepInEx/core/BepInEx.Preloader.dll"), "preloader").unwrap();
+    fs::write(root.join("BepInEx/core/BepInEx.dll"), "truncat").unwrap();
+    let preloader = hash_file(&root.join("BepInEx/core/BepInEx.Preloader.dll")).unwrap();
+    let content = format!(
+      "# BepInEx 5.4.21.0\n{}  BepInEx/core/BepInEx.Preloader.dll\n{} *BepInEx/core/BepInEx.dll\n{}  doorstop_libs/libdoorstop_x64.so\n",
+      preloader.to_uppercase(),
+      preloader,
+      preloader
+    );
+    let checksums = parse_checksums(&content, root);
+    assert_eq!(checksums.len(), 3);
+    assert_eq!(checksums[1].0, root.join("BepInEx/core/BepInEx.dll"));
+
+    let mut installation = BepInExInstallation::default();
+    verify_checksums(&checksums, &mut installation);
+    let path = |name: &str| root.join(name).to_string_lossy().to_string();
+    assert_eq!(
+      installation.corrupted,
+      vec![path("BepInEx/core/BepInEx.dll")]
+    );
+    assert_eq!(
+      installation.missing,
+      vec![path("doorstop_libs/libdoorstop_x64.so")]
+    );
+    assert!(!installation.is_intact());
+  }
 }
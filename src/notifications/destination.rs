@@ -0,0 +1,142 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use std::str::FromStr;
+
+use crate::errors::VariantNotFound;
+use crate::notifications::discord::is_discord_webhook;
+use crate::notifications::matrix::is_matrix_webhook;
+
+pub const WEBHOOK_DESTINATIONS: &str = "WEBHOOK_DESTINATIONS";
+
+/// The body builder used for a destination.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum WebhookFormat {
+  Json,
+  Discord,
+  Matrix,
+}
+
+impl FromStr for WebhookFormat {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> Result<WebhookFormat, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "json" => Ok(WebhookFormat::Json),
+      "discord" => Ok(WebhookFormat::Discord),
+      "matrix" => Ok(WebhookFormat::Matrix),
+      _ => Err(VariantNotFound {
+        v: format!(
+          "Unknown webhook format '{}', expected json, discord or matrix",
+          s
+        ),
+      }),
+    }
+  }
+}
+
+impl WebhookFormat {
+  pub fn detect(webhook_url: &str) -> Self {
+    if is_discord_webhook(webhook_url) {
+      WebhookFormat::Discord
+    } else if is_matrix_webhook(webhook_url) {
+      WebhookFormat::Matrix
+    } else {
+      WebhookFormat::Json
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebhookDestination {
+  pub(crate) url: String,
+  pub(crate) format: WebhookFormat,
+}
+
+impl WebhookDestination {
+  /// A destination whose format is picked from the url.
+  pub fn new(webhook_url: &str) -> Self {
+    WebhookDestination {
+      url: webhook_url.to_string(),
+      format: WebhookFormat::detect(webhook_url),
+    }
+  }
+
+  /// Parses `<format>=<url>` or a bare `<url>`, in which case the format is detected.
+  pub fn parse(entry: &str) -> Result<Self, String> {
+    let entry = entry.trim().trim_matches('"');
+    let destination = match entry.split_once('=') {
+      Some((format, url)) if WebhookFormat::from_str(format).is_ok() => WebhookDestination {
+        url: url.trim().to_string(),
+        format: WebhookFormat::from_str(format).unwrap(),
+      },
+      _ => WebhookDestination::new(entry),
+    };
+    match Url::parse(&destination.url) {
+      Ok(_) => Ok(destination),
+      Err(e) => Err(format!("Invalid webhook '{}': {}", entry, e)),
+    }
+  }
+}
+
+/// Parses a comma separated list of destinations, returning the invalid entries as errors.
+pub fn parse_destinations(value: &str) -> Vec<Result<WebhookDestination, String>> {
+  value
+    .split(',')
+    .filter(|entry| !entry.trim().is_empty())
+    .map(WebhookDestination::parse)
+    .collect()
+}
+
+#[cfg(test)]
+mod destination_tests {
+  use super::*;
+
+  #[test]
+  fn parses_formats_per_destination() {
+    let destinations: Vec<WebhookDestination> = parse_destinations(
+      "json=http://127.0.0.1:3000/logs, https://discord.com/api/webhooks/1/abc,discord=http://relay.local/?a=b",
+    )
+    .into_iter()
+    .map(Result::unwrap)
+    .collect();
+    assert_eq!(
+      destinations,
+      vec![
+        WebhookDestination {
+          url: "http://127.0.0.1:3000/logs".to_string(),
+          format: WebhookFormat::Json,
+        },
+        WebhookDestination {
+          url: "https://discord.com/api/webhooks/1/abc".to_string(),
+          format: WebhookFormat::Discord,
+        },
+        WebhookDestination {
+          url: "http://relay.local/?a=b".to_string(),
+          format: WebhookFormat::Discord,
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn query_strings_are_not_mistaken_for_formats() {
+    let destination = WebhookDestination::parse("http://127.0.0.1/hook?token=abc").unwrap();
+    assert_eq!(destination.url, "http://127.0.0.1/hook?token=abc");
+    assert_eq!(destination.format, WebhookFormat::Json);
+  }
+
+  #[test]
+  fn detects_matrix_rooms() {
+    let destination = WebhookDestination::parse(
+      "https://matrix.org/_matrix/client/v3/rooms/!abc:matrix.org/send/m.room.message",
+    )
+    .unwrap();
+    assert_eq!(destination.format, WebhookFormat::Matrix);
+  }
+
+  #[test]
+  fn invalid_destinations_are_errors() {
+    assert!(WebhookDestination::parse("json=not a url").is_err());
+    assert!(WebhookDestination::parse("LOCALHOST").is_err());
+  }
+}
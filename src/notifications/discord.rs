@@ -3,7 +3,9 @@ use crate::files::{
   FileManager,
 };
 use crate::notifications::EventStatus;
+use crate::notifications::NotificationLevel;
 use crate::notifications::NotificationMessage;
+use crate::utils::environment::fetch_var;
 use inflections::case::to_title_case;
 use log::debug;
 use serde::{Deserialize, Serialize};
@@ -13,9 +15,12 @@ use std::{env, str::FromStr};
 enum Color {
   Success = 0x4B_B5_43,
   Failure = 0xFA_11_3D,
+  Warning = 0xF5_A6_23,
   Generic = 0x00_7F_66,
 }
 
+pub const WEBHOOK_MENTION_ON_FAILURE: &str = "WEBHOOK_MENTION_ON_FAILURE";
+
 const DISCORD_WEBHOOK_BASE: &str = "https://discord.com/api/webhooks";
 const DISCORDAPP_WEBHOOK_BASE: &str = "https://discordapp.com/api/webhooks";
 
@@ -30,6 +35,16 @@ impl From<EventStatus> for Color {
   }
 }
 
+impl From<NotificationLevel> for Color {
+  fn from(level: NotificationLevel) -> Self {
+    match level {
+      NotificationLevel::Info => Self::Generic,
+      NotificationLevel::Warning => Self::Warning,
+      NotificationLevel::Error => Self::Failure,
+    }
+  }
+}
+
 pub fn is_discord_webhook(webhook_url: &str) -> bool {
   webhook_url.starts_with(DISCORD_WEBHOOK_BASE) || webhook_url.starts_with(DISCORDAPP_WEBHOOK_BASE)
 }
@@ -41,10 +56,47 @@ pub struct DiscordWebHookEmbed {
   color: i32,
 }
 
+/// Who Discord is allowed to ping, nobody unless listed.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct DiscordAllowedMentions {
+  parse: Vec<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  roles: Vec<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  users: Vec<String>,
+}
+
+impl DiscordAllowedMentions {
+  /// Parses a comma separated list of `<@&role>`, `<@user>` or bare role ids.
+  pub fn parse(value: &str) -> Self {
+    let mut mentions = DiscordAllowedMentions::default();
+    for mention in value.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+      let inner = mention.trim_start_matches('<').trim_end_matches('>');
+      if let Some(role) = inner.strip_prefix("@&") {
+        mentions.roles.push(role.to_string());
+      } else if let Some(user) = inner.strip_prefix('@') {
+        mentions
+          .users
+          .push(user.trim_start_matches('!').to_string());
+      } else {
+        mentions.roles.push(inner.to_string());
+      }
+    }
+    mentions
+  }
+
+  fn content(&self) -> String {
+    let roles = self.roles.iter().map(|role| format!("<@&{}>", role));
+    let users = self.users.iter().map(|user| format!("<@{}>", user));
+    roles.chain(users).collect::<Vec<String>>().join(" ")
+  }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct DiscordWebHookBody {
   content: String,
   embeds: Vec<DiscordWebHookEmbed>,
+  allowed_mentions: DiscordAllowedMentions,
 }
 
 impl DiscordWebHookBody {
@@ -64,12 +116,28 @@ impl DiscordWebHookBody {
       }
     };
     let status = &event.event_type.status;
-    let event_status = EventStatus::from_str(status).unwrap_or(EventStatus::Failed);
-    let color: i32 = Color::from(event_status) as i32;
+    let color = match NotificationLevel::from_str(status) {
+      Ok(level) => Color::from(level),
+      Err(_) => Color::from(EventStatus::from_str(status).unwrap_or(EventStatus::Failed)),
+    } as i32;
+    let mut content = to_title_case(format!("Notification From: {}", server_name).as_str());
+    let allowed_mentions = if EventStatus::from_str(status).ok() == Some(EventStatus::Failed) {
+      DiscordAllowedMentions::parse(&fetch_var(WEBHOOK_MENTION_ON_FAILURE, ""))
+    } else {
+      DiscordAllowedMentions::default()
+    };
+    let mentions = allowed_mentions.content();
+    if !mentions.is_empty() {
+      content = format!("{} {}", mentions, content);
+    }
     let payload = DiscordWebHookBody {
-      content: to_title_case(format!("Notification From: {}", server_name).as_str()),
+      content,
+      allowed_mentions,
       embeds: vec![DiscordWebHookEmbed {
-        title: String::from(&event.event_type.name),
+        title: event
+          .title
+          .clone()
+          .unwrap_or_else(|| String::from(&event.event_type.name)),
         description: String::from(&event.event_message),
         color,
       }],
@@ -87,3 +155,52 @@ impl From<&NotificationMessage> for DiscordWebHookBody {
     Self::new(event)
   }
 }
+
+#[cfg(test)]
+mod discord_tests {
+  use super::*;
+  use crate::notifications::enums::notification_event::NotificationEvent;
+  use serial_test::serial;
+  use std::env::{remove_var, set_var};
+
+  #[test]
+  #[serial]
+  fn level_and_title_shape_the_embed() {
+    set_var("NAME", "Test Server");
+    let mut notification = NotificationEvent::Broadcast.create_notification_message();
+    notification.event_type.status = NotificationLevel::Warning.to_string();
+    notification.title = Some("Disk space".to_string());
+    let body = DiscordWebHookBody::from(&notification);
+    assert_eq!(body.embeds[0].title, "Disk space");
+    assert_eq!(body.embeds[0].color, Color::Warning as i32);
+  }
+
+  #[test]
+  fn parses_mentions() {
+    let mentions = DiscordAllowedMentions::parse("<@&123>, 456,<@!789>");
+    assert_eq!(mentions.roles, vec!["123", "456"]);
+    assert_eq!(mentions.users, vec!["789"]);
+    assert_eq!(mentions.content(), "<@&123> <@&456> <@789>");
+  }
+
+  #[test]
+  #[serial]
+  fn only_failures_mention() {
+    set_var("NAME", "Test Server");
+    set_var(WEBHOOK_MENTION_ON_FAILURE, "<@&123>");
+    let failed = DiscordWebHookBody::from(
+      &NotificationEvent::Start(EventStatus::Failed).create_notification_message(),
+    );
+    let started = DiscordWebHookBody::from(
+      &NotificationEvent::Start(EventStatus::Successful).create_notification_message(),
+    );
+    remove_var(WEBHOOK_MENTION_ON_FAILURE);
+
+    assert!(failed.content.starts_with("<@&123> "));
+    assert_eq!(failed.allowed_mentions.roles, vec!["123"]);
+    assert!(!started.content.contains("<@"));
+    assert_eq!(started.allowed_mentions, DiscordAllowedMentions::default());
+    let json = serde_json::to_value(&started).unwrap();
+    assert_eq!(json["allowed_mentions"]["parse"], serde_json::json!([]));
+  }
+}
@@ -1,2 +1,3 @@
 pub mod event_status;
 pub mod notification_event;
+pub mod notification_level;
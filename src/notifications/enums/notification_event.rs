@@ -1,17 +1,32 @@
 use crate::errors::VariantNotFound;
 use crate::notifications::enums::event_status::EventStatus;
+use crate::notifications::enums::notification_level::NotificationLevel;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// The status of events that don't have one, like `Broadcast`.
+pub const TRIGGERED_STATUS: &str = "Triggered";
+
 #[derive(PartialEq, Debug, Deserialize, Serialize)]
 pub enum NotificationEvent {
   Broadcast,
   Update(EventStatus),
   Start(EventStatus),
   Stop(EventStatus),
+  /// Crossplay is on but the server didn't register with PlayFab.
+  Crossplay(NotificationLevel),
+  Backup(EventStatus),
+  PlayerJoined,
+  PlayerLeft,
+  /// Sent on every autosave, so only when asked for.
+  WorldSaved,
+  /// The server's process runs but it stopped answering queries.
+  ServerUnresponsive,
+  /// Nobody was online for `IDLE_SHUTDOWN_MINUTES` and the server was stopped.
+  IdleShutdown,
 }
 
-#[derive(PartialEq, Debug, Deserialize, Serialize)]
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 pub struct EventType {
   pub(crate) name: String,
   pub(crate) status: String,
@@ -21,8 +36,8 @@ impl NotificationEvent {
   pub(crate) fn to_event_type(&self) -> EventType {
     let event = self.to_string();
     let parsed_event: Vec<&str> = event.split(' ').collect();
-    let name = parsed_event.get(0).unwrap_or(&"EVENT NAME").to_string();
-    let status = parsed_event.get(1).unwrap_or(&"Triggered").to_string();
+    let name = parsed_event.first().unwrap_or(&"EVENT NAME").to_string();
+    let status = parsed_event.get(1).unwrap_or(&TRIGGERED_STATUS).to_string();
     EventType { name, status }
   }
 }
@@ -38,18 +53,34 @@ impl fmt::Display for NotificationEvent {
 impl std::str::FromStr for NotificationEvent {
   type Err = VariantNotFound;
   fn from_str(s: &str) -> core::result::Result<NotificationEvent, Self::Err> {
-    use NotificationEvent::{Broadcast, Start, Stop, Update};
+    use NotificationEvent::{
+      Backup, Broadcast, Crossplay, IdleShutdown, PlayerJoined, PlayerLeft, ServerUnresponsive,
+      Start, Stop, Update, WorldSaved,
+    };
     let parts: Vec<&str> = s.split(' ').collect();
     let event = parts[0];
     if event.eq(Broadcast.to_string().as_str()) {
       ::std::result::Result::Ok(Broadcast)
+    } else if event.eq("Crossplay") {
+      NotificationLevel::from_str(parts.get(1).unwrap_or(&"")).map(Crossplay)
+    } else if event.eq(PlayerJoined.to_string().as_str()) {
+      ::std::result::Result::Ok(PlayerJoined)
+    } else if event.eq(PlayerLeft.to_string().as_str()) {
+      ::std::result::Result::Ok(PlayerLeft)
+    } else if event.eq(WorldSaved.to_string().as_str()) {
+      ::std::result::Result::Ok(WorldSaved)
+    } else if event.eq(ServerUnresponsive.to_string().as_str()) {
+      ::std::result::Result::Ok(ServerUnresponsive)
+    } else if event.eq(IdleShutdown.to_string().as_str()) {
+      ::std::result::Result::Ok(IdleShutdown)
     } else {
       let status = parts[1];
-      let event_status = EventStatus::from_str(&status).unwrap();
+      let event_status = EventStatus::from_str(status).unwrap();
       match event {
         "Update" => ::std::result::Result::Ok(Update(event_status)),
         "Start" => ::std::result::Result::Ok(Start(event_status)),
         "Stop" => ::std::result::Result::Ok(Stop(event_status)),
+        "Backup" => ::std::result::Result::Ok(Backup(event_status)),
         _ => ::std::result::Result::Err(VariantNotFound {
           v: String::from("Failed to find Notification Event"),
         }),
@@ -67,5 +98,35 @@ mod notification_event_tests {
   #[test]
   fn parse_enum_from_string() {
     assert_eq!(NotificationEvent::from_str("Broadcast").unwrap(), Broadcast);
+    let crossplay = NotificationEvent::Crossplay(NotificationLevel::Warning);
+    assert_eq!(
+      NotificationEvent::from_str(&crossplay.to_string()).unwrap(),
+      crossplay
+    );
+    assert_eq!(crossplay.to_event_type().status, "Warning");
+    assert_eq!(
+      NotificationEvent::from_str("Backup Failed").unwrap(),
+      NotificationEvent::Backup(EventStatus::Failed)
+    );
+    assert_eq!(
+      NotificationEvent::from_str("PlayerJoined").unwrap(),
+      NotificationEvent::PlayerJoined
+    );
+    assert_eq!(
+      NotificationEvent::PlayerLeft.to_event_type().status,
+      TRIGGERED_STATUS
+    );
+    assert_eq!(
+      NotificationEvent::from_str("WorldSaved").unwrap(),
+      NotificationEvent::WorldSaved
+    );
+    assert_eq!(
+      NotificationEvent::from_str("ServerUnresponsive").unwrap(),
+      NotificationEvent::ServerUnresponsive
+    );
+    assert_eq!(
+      NotificationEvent::from_str("IdleShutdown").unwrap(),
+      NotificationEvent::IdleShutdown
+    );
   }
 }
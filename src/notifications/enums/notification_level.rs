@@ -0,0 +1,50 @@
+use crate::errors::VariantNotFound;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+pub enum NotificationLevel {
+  Info,
+  Warning,
+  Error,
+}
+
+impl fmt::Display for NotificationLevel {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(self, f)
+  }
+}
+
+impl std::str::FromStr for NotificationLevel {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> ::std::result::Result<NotificationLevel, Self::Err> {
+    use NotificationLevel::{Error, Info, Warning};
+    match s.to_lowercase().as_str() {
+      "info" => ::std::result::Result::Ok(Info),
+      "warning" => ::std::result::Result::Ok(Warning),
+      "error" => ::std::result::Result::Ok(Error),
+      _ => ::std::result::Result::Err(VariantNotFound {
+        v: format!(
+          "Unknown notification level '{}', expected info, warning or error",
+          s
+        ),
+      }),
+    }
+  }
+}
+
+#[cfg(test)]
+mod notification_level_tests {
+  use super::*;
+  use std::str::FromStr;
+
+  #[test]
+  fn parse_enum_from_string() {
+    assert_eq!(
+      NotificationLevel::from_str("Warning").unwrap(),
+      NotificationLevel::Warning
+    );
+    assert_eq!(NotificationLevel::Error.to_string(), "Error");
+    assert!(NotificationLevel::from_str("debug").is_err());
+  }
+}
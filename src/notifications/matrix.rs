@@ -0,0 +1,147 @@
+use crate::notifications::NotificationMessage;
+use crate::utils::environment::fetch_var;
+use log::{debug, error};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+pub const MATRIX_ACCESS_TOKEN: &str = "MATRIX_ACCESS_TOKEN";
+
+const MATRIX_CLIENT_API: &str = "/_matrix/client/";
+const MATRIX_SEND_MESSAGE: &str = "/send/m.room.message";
+
+/// Matches a homeserver's message send endpoint, for example
+/// `https://matrix.org/_matrix/client/v3/rooms/!room:matrix.org/send/m.room.message`.
+pub fn is_matrix_webhook(webhook_url: &str) -> bool {
+  webhook_url.contains(MATRIX_CLIENT_API)
+    && webhook_url
+      .trim_end_matches('/')
+      .ends_with(MATRIX_SEND_MESSAGE)
+}
+
+fn escape_html(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\n', "<br>")
+}
+
+/// Built from the message so retries and queued deliveries reuse it, letting the homeserver
+/// drop the duplicates.
+fn transaction_id(event: &NotificationMessage) -> String {
+  let id: String = format!("{}{}", event.timestamp, event.label())
+    .chars()
+    .filter(char::is_ascii_alphanumeric)
+    .collect();
+  format!("odin{}", id)
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MatrixMessageBody {
+  msgtype: String,
+  body: String,
+  format: String,
+  formatted_body: String,
+}
+
+impl MatrixMessageBody {
+  pub fn new(event: &NotificationMessage) -> Self {
+    let heading = match &event.title {
+      Some(title) => format!("{} ({})", title, event.event_type.status),
+      None => event.label(),
+    };
+    let payload = MatrixMessageBody {
+      msgtype: String::from("m.text"),
+      body: format!("[{}] {}", heading, event.event_message),
+      format: String::from("org.matrix.custom.html"),
+      formatted_body: format!(
+        "<b>{}</b><br>{}",
+        escape_html(&heading),
+        escape_html(&event.event_message)
+      ),
+    };
+    debug!(
+      "Matrix Payload: {}",
+      serde_json::to_string(&payload).unwrap()
+    );
+    payload
+  }
+}
+
+impl From<&NotificationMessage> for MatrixMessageBody {
+  fn from(event: &NotificationMessage) -> Self {
+    Self::new(event)
+  }
+}
+
+/// The send request for the event, `None` when there is no access token to send it with.
+pub fn build_matrix_request(
+  client: Client,
+  webhook_url: &str,
+  event: &NotificationMessage,
+) -> Option<RequestBuilder> {
+  let access_token = fetch_var(MATRIX_ACCESS_TOKEN, "");
+  if access_token.is_empty() {
+    error!(
+      "{} must be set to send notifications to Matrix!",
+      MATRIX_ACCESS_TOKEN
+    );
+    return None;
+  }
+  let send_url = format!(
+    "{}/{}",
+    webhook_url.trim_end_matches('/'),
+    transaction_id(event)
+  );
+  Some(
+    client
+      .put(send_url)
+      .bearer_auth(access_token)
+      .json(&MatrixMessageBody::from(event)),
+  )
+}
+
+#[cfg(test)]
+mod matrix_tests {
+  use super::*;
+  use crate::notifications::enums::event_status::EventStatus;
+  use crate::notifications::enums::notification_event::NotificationEvent;
+
+  #[test]
+  fn detects_send_endpoints() {
+    assert!(is_matrix_webhook(
+      "https://matrix.org/_matrix/client/v3/rooms/!abc:matrix.org/send/m.room.message"
+    ));
+    assert!(is_matrix_webhook(
+      "https://chat.local/_matrix/client/r0/rooms/!abc:chat.local/send/m.room.message/"
+    ));
+    assert!(!is_matrix_webhook(
+      "https://matrix.org/_matrix/client/v3/rooms/!abc:matrix.org/state"
+    ));
+    assert!(!is_matrix_webhook("https://discord.com/api/webhooks/1/abc"));
+  }
+
+  #[test]
+  fn formats_event_type_and_status() {
+    let mut notification =
+      NotificationEvent::Update(EventStatus::Failed).create_notification_message();
+    notification.event_message = "steamcmd <failed>".to_string();
+    let body = MatrixMessageBody::from(&notification);
+    assert_eq!(body.msgtype, "m.text");
+    assert_eq!(body.body, "[Update Failed] steamcmd <failed>");
+    assert_eq!(
+      body.formatted_body,
+      "<b>Update Failed</b><br>steamcmd &lt;failed&gt;"
+    );
+  }
+
+  #[test]
+  fn transaction_id_is_stable_per_message() {
+    let notification = NotificationEvent::Broadcast.create_notification_message();
+    assert_eq!(transaction_id(&notification), transaction_id(&notification));
+    assert!(transaction_id(&notification)
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric()));
+  }
+}
@@ -1,42 +1,129 @@
-use std::env;
+use std::{
+  env,
+  sync::atomic::{AtomicBool, Ordering},
+  thread,
+  time::Duration,
+};
 
 use chrono::prelude::*;
 use inflections::case::{to_constant_case, to_title_case};
 use log::{debug, error, info, warn};
-use reqwest::blocking::RequestBuilder;
-use reqwest::StatusCode;
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder};
+use reqwest::{NoProxy, Proxy, StatusCode};
 use serde::{Deserialize, Serialize};
 
-use crate::notifications::discord::{is_discord_webhook, DiscordWebHookBody};
+use crate::constants;
+use crate::notifications::destination::{
+  parse_destinations, WebhookDestination, WebhookFormat, WEBHOOK_DESTINATIONS,
+};
+use crate::notifications::discord::DiscordWebHookBody;
 use crate::notifications::enums::event_status::EventStatus;
-use crate::notifications::enums::notification_event::{EventType, NotificationEvent};
-use crate::utils::environment::fetch_var;
+use crate::notifications::enums::notification_event::{
+  EventType, NotificationEvent, TRIGGERED_STATUS,
+};
+use crate::notifications::enums::notification_level::NotificationLevel;
+use crate::notifications::matrix::build_matrix_request;
+use crate::notifications::placeholders::{event_placeholders, fill_placeholders};
+use crate::notifications::queue::NotificationQueue;
+use crate::notifications::throttle::{throttle_window, NotificationThrottle};
+use crate::utils::environment::{active_profile, fetch_var};
 use reqwest::Url;
 
+pub mod destination;
 mod discord;
 pub mod enums;
+mod matrix;
+pub mod ping;
+mod placeholders;
+mod queue;
+mod throttle;
 
 pub const WEBHOOK_URL: &str = "WEBHOOK_URL";
+pub const WEBHOOK_PROXY: &str = "WEBHOOK_PROXY";
+pub const WEBHOOK_CONNECT_TIMEOUT: &str = "WEBHOOK_CONNECT_TIMEOUT_SECONDS";
+pub const WEBHOOK_TIMEOUT: &str = "WEBHOOK_TIMEOUT_SECONDS";
+pub const WEBHOOK_RETRY_ATTEMPTS: &str = "WEBHOOK_RETRY_ATTEMPTS";
+/// Title of the notification `odin notify --test` sends.
+const TEST_NOTIFICATION_TITLE: &str = "Odin Test Notification";
+
+static SILENCED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps events from being sent until `resume_notifications`, for `--no-notify`.
+pub fn silence_notifications() {
+  SILENCED.store(true, Ordering::Relaxed);
+}
+
+pub fn resume_notifications() {
+  SILENCED.store(false, Ordering::Relaxed);
+}
+
+fn is_silenced() -> bool {
+  SILENCED.load(Ordering::Relaxed)
+}
+
+/// Tags `message` with the profile odin runs with, so messages from several servers can be told
+/// apart in the same channel.
+fn tag_with_profile(message: &str, profile: Option<&str>) -> String {
+  match profile {
+    Some(profile) if !message.starts_with(&format!("[{}]", profile)) => {
+      format!("[{}] {}", profile, message)
+    }
+    _ => message.to_string(),
+  }
+}
+
+fn with_profile(message: &str) -> String {
+  tag_with_profile(message, active_profile().as_deref())
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct NotificationMessage {
   event_type: EventType,
   event_message: String,
   timestamp: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  title: Option<String>,
 }
 
-fn fetch_webhook_url() -> String {
-  fetch_var(WEBHOOK_URL, "")
+fn fetch_url_var(name: &str) -> String {
+  fetch_var(name, "")
     .trim_start_matches('"')
     .trim_end_matches('"')
     .to_string()
 }
 
-fn is_webhook_enabled() -> bool {
-  let url = fetch_webhook_url();
+fn fetch_webhook_url() -> String {
+  fetch_url_var(WEBHOOK_URL)
+}
+
+fn parse_webhook_url_env_var(event_type: &EventType) -> String {
+  to_constant_case(format!("WEBHOOK_{}_URL", event_type.name).as_str())
+}
+
+/// `WEBHOOK_<EVENT>_THROTTLE_SECONDS`, like `WEBHOOK_WORLD_SAVED_THROTTLE_SECONDS`.
+fn parse_webhook_throttle_env_var(event_type: &EventType) -> String {
+  to_constant_case(format!("WEBHOOK_{}_THROTTLE_SECONDS", event_type.name).as_str())
+}
+
+fn fetch_event_webhook_url(event: &NotificationEvent) -> String {
+  fetch_url_var(&parse_webhook_url_env_var(&event.to_event_type()))
+}
+
+/// The url an event is sent to, `WEBHOOK_<EVENT>_URL` (like `WEBHOOK_START_URL`) when it is set
+/// and `WEBHOOK_URL` otherwise.
+pub fn event_webhook_url(event: &NotificationEvent) -> String {
+  let event_url = fetch_event_webhook_url(event);
+  if event_url.is_empty() {
+    fetch_webhook_url()
+  } else {
+    event_url
+  }
+}
+
+fn is_valid_webhook_url(url: &str) -> bool {
   if !url.is_empty() {
     debug!("Webhook Url found!: {}", url);
-    let is_valid = Url::parse(url.as_str()).is_ok();
+    let is_valid = Url::parse(url).is_ok();
     if !is_valid {
       warn!(
         "Webhook provided but does not look valid!! Is this right? {}",
@@ -48,77 +135,350 @@ fn is_webhook_enabled() -> bool {
   false
 }
 
+fn is_webhook_enabled() -> bool {
+  is_valid_webhook_url(&fetch_webhook_url())
+}
+
+/// The event's url followed by every entry in `WEBHOOK_DESTINATIONS`. Invalid entries are skipped.
+pub fn configured_destinations(event: &NotificationEvent) -> Vec<WebhookDestination> {
+  let mut destinations = Vec::new();
+  let event_url = fetch_event_webhook_url(event);
+  // Detected per url, so each event can go to a different kind of service.
+  if event_url.is_empty() {
+    if is_webhook_enabled() {
+      destinations.push(WebhookDestination::new(&fetch_webhook_url()));
+    }
+  } else if is_valid_webhook_url(&event_url) {
+    destinations.push(WebhookDestination::new(&event_url));
+  }
+  for destination in parse_destinations(&fetch_var(WEBHOOK_DESTINATIONS, "")) {
+    match destination {
+      Ok(destination) => destinations.push(destination),
+      Err(e) => warn!("Skipping webhook destination. {}", e),
+    }
+  }
+  destinations
+}
+
+fn seconds_var(name: &str, default: u64) -> Duration {
+  Duration::from_secs(
+    fetch_var(name, &default.to_string())
+      .parse::<u64>()
+      .unwrap_or(default),
+  )
+}
+
+/// A dead webhook gives up after these instead of holding up the server.
+fn client_builder() -> ClientBuilder {
+  Client::builder()
+    .connect_timeout(seconds_var(WEBHOOK_CONNECT_TIMEOUT, 5))
+    .timeout(seconds_var(WEBHOOK_TIMEOUT, 10))
+}
+
+fn build_client() -> reqwest::Result<Client> {
+  // Without an explicit proxy reqwest already honors HTTP_PROXY, HTTPS_PROXY and NO_PROXY.
+  let mut builder = client_builder();
+  let proxy_url = fetch_var(WEBHOOK_PROXY, "");
+  if !proxy_url.is_empty() {
+    debug!("Routing webhooks through proxy: {}", proxy_url);
+    builder = builder.proxy(Proxy::all(proxy_url.as_str())?.no_proxy(NoProxy::from_env()));
+  }
+  builder.build()
+}
+
+/// `WEBHOOK_<EVENT>_<STATUS>_MESSAGE`, or `WEBHOOK_<EVENT>_MESSAGE` for events without a status
+/// like `WEBHOOK_PLAYER_JOINED_MESSAGE`.
 fn parse_webhook_env_var(event_type: EventType) -> String {
-  if event_type.name.to_lowercase().eq("broadcast") {
+  if event_type.name.to_lowercase().eq("broadcast") || event_type.status == TRIGGERED_STATUS {
     to_constant_case(format!("WEBHOOK_{}_MESSAGE", event_type.name).as_str())
   } else {
     to_constant_case(format!("WEBHOOK_{}_{}_MESSAGE", event_type.name, event_type.status).as_str())
   }
 }
 
-impl NotificationEvent {
-  fn create_notification_message(&self) -> NotificationMessage {
-    NotificationMessage {
-      event_type: self.to_event_type(),
-      event_message: format!(
-        "Server Status: {}",
-        to_title_case(self.to_string().as_str())
-      ),
-      timestamp: Local::now().to_rfc3339(),
-    }
+impl NotificationMessage {
+  fn label(&self) -> String {
+    format!("{} {}", self.event_type.name, self.event_type.status)
   }
-  fn handle_request(&self, request: RequestBuilder) {
-    let response = request.send();
-    if let Ok(parsed_response) = response {
-      let response_status = parsed_response.status();
-      let response_message = parsed_response.text().unwrap();
-      match response_status.as_u16() {
-        204 | 201 => info!("[{}]: Webhook message sent successfully!", self),
-        _ => error!("Request failed! {}, {}", response_status, response_message),
+
+  /// Fills this event's placeholders and `placeholders` into a `WEBHOOK_*_MESSAGE` override.
+  fn fill_placeholders(&self, message: &str, placeholders: &[(&str, &str)]) -> String {
+    let event = event_placeholders(
+      &self.timestamp,
+      &self.event_type.name,
+      &self.event_type.status,
+    );
+    let placeholders: Vec<(&str, &str)> = event
+      .iter()
+      .map(|(placeholder, value)| (*placeholder, value.as_str()))
+      .chain(placeholders.iter().copied())
+      .collect();
+    fill_placeholders(message, &placeholders)
+  }
+}
+
+/// Sends `request`, `Ok` with the status when the webhook accepted it and `Err` with why not.
+fn send_request(label: &str, request: RequestBuilder) -> Result<StatusCode, String> {
+  let response = request.send();
+  if let Ok(parsed_response) = response {
+    let response_status = parsed_response.status();
+    // Notifications are best effort, a body that can't be read is treated as empty.
+    let response_message = parsed_response.text().unwrap_or_else(|e| {
+      debug!("[{}]: Unable to read the webhook response: {}", label, e);
+      String::new()
+    });
+    match response_status.as_u16() {
+      200 | 201 | 204 => {
+        debug!("[{}]: Webhook answered {}", label, response_status);
+        info!("[{}]: Webhook message sent successfully!", label);
+        Ok(response_status)
+      }
+      _ => {
+        error!("Request failed! {}, {}", response_status, response_message);
+        Err(
+          format!("{} {}", response_status, response_message.trim())
+            .trim()
+            .to_string(),
+        )
       }
+    }
+  } else {
+    let error = response.err().unwrap();
+    if error.is_timeout() {
+      warn!("[{}]: Webhook timed out! {}", label, error);
     } else {
       error!(
         "[{}]: Error with webhook! Status {}",
-        self,
-        response
-          .err()
-          .unwrap()
+        label,
+        error
           .status()
           .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
           .as_str()
       );
     }
+    Err(error.to_string())
   }
-  fn build_request(&self, webhook_url: &str) -> RequestBuilder {
-    let client = reqwest::blocking::Client::new();
-    debug!("Webhook URL: {}", webhook_url);
-    client.post(webhook_url)
-  }
-  pub fn send_custom_notification(&self, webhook_url: &str, message: &str) {
-    let mut notification = self.create_notification_message();
-    notification.event_message = message.to_string();
-    debug!("Webhook enabled, sending notification {}", self.to_string());
+}
+
+fn handle_request(label: &str, request: RequestBuilder) -> bool {
+  send_request(label, request).is_ok()
+}
+
+fn webhook_client() -> Client {
+  build_client().unwrap_or_else(|e| {
+    error!(
+      "Failed to set up the webhook client with {}: {}. Sending without it!",
+      WEBHOOK_PROXY, e
+    );
+    client_builder().build().unwrap_or_default()
+  })
+}
+
+fn build_request(webhook_url: &str) -> RequestBuilder {
+  debug!("Webhook URL: {}", webhook_url);
+  webhook_client().post(webhook_url)
+}
 
-    let mut req = self.build_request(webhook_url);
-    req = if is_discord_webhook(webhook_url) {
+/// The request delivering `notification` in the destination's format.
+fn notification_request(
+  destination: &WebhookDestination,
+  notification: &NotificationMessage,
+) -> Option<RequestBuilder> {
+  let req = match destination.format {
+    WebhookFormat::Discord => {
       info!("Sending discord notification <3");
-      req.json(&DiscordWebHookBody::from(&notification))
-    } else {
+      build_request(&destination.url).json(&DiscordWebHookBody::from(notification))
+    }
+    WebhookFormat::Matrix => {
+      info!("Sending matrix notification");
+      build_matrix_request(webhook_client(), &destination.url, notification)?
+    }
+    WebhookFormat::Json => {
       debug!(
         "Webhook Payload: {}",
-        serde_json::to_string(&notification).unwrap()
+        serde_json::to_string(notification).unwrap()
       );
-      req.json(&notification)
-    };
-    self.handle_request(req);
+      build_request(&destination.url).json(notification)
+    }
+  };
+  Some(req)
+}
+
+fn deliver_notification(
+  destination: &WebhookDestination,
+  notification: &NotificationMessage,
+) -> bool {
+  notification_request(destination, notification)
+    .is_some_and(|req| handle_request(&notification.label(), req))
+}
+
+fn deliver_with_retries(
+  destination: &WebhookDestination,
+  notification: &NotificationMessage,
+) -> bool {
+  let attempts = fetch_var(WEBHOOK_RETRY_ATTEMPTS, "3")
+    .parse::<u64>()
+    .unwrap_or(3)
+    .max(1);
+  for attempt in 1..=attempts {
+    if deliver_notification(destination, notification) {
+      return true;
+    }
+    if attempt < attempts {
+      warn!(
+        "[{}]: Webhook attempt {} of {} failed, retrying...",
+        notification.label(),
+        attempt,
+        attempts
+      );
+      thread::sleep(Duration::from_secs(attempt));
+    }
+  }
+  false
+}
+
+/// The `WEBHOOK_BROADCAST_MESSAGE` override, if one is set.
+pub fn broadcast_message_override() -> Option<String> {
+  let value = fetch_var(
+    &parse_webhook_env_var(NotificationEvent::Broadcast.to_event_type()),
+    "",
+  );
+  if value.is_empty() {
+    None
+  } else {
+    let notification = NotificationEvent::Broadcast.create_notification_message();
+    Some(notification.fill_placeholders(&value, &[]))
+  }
+}
+
+/// Sends a one-off broadcast and reports whether it was delivered. Failures are not queued, the
+/// caller is expected to deal with them.
+pub fn send_broadcast(
+  destination: &WebhookDestination,
+  message: &str,
+  title: Option<&str>,
+  level: NotificationLevel,
+) -> bool {
+  let mut notification = NotificationEvent::Broadcast.create_notification_message();
+  notification.event_message = message.to_string();
+  notification.event_type.status = level.to_string();
+  notification.title = title.map(String::from);
+  deliver_with_retries(destination, &notification)
+}
+
+/// Sends a notification labeled as a test, once, and tells how the webhook answered. Nothing is
+/// queued when it fails.
+pub fn send_test_notification(destination: &WebhookDestination) -> Result<StatusCode, String> {
+  let mut notification = NotificationEvent::Broadcast.create_notification_message();
+  notification.title = Some(TEST_NOTIFICATION_TITLE.to_string());
+  notification.event_message = format!(
+    "This is a test notification from Odin {}. If you can read it, the webhook works!",
+    constants::VERSION
+  );
+  notification.event_type.status = NotificationLevel::Info.to_string();
+  let request = notification_request(destination, &notification)
+    .ok_or_else(|| "Unable to build the request".to_string())?;
+  send_request(&notification.label(), request)
+}
+
+/// Attempts to deliver notifications that previously failed. Called once by each command that
+/// sends notifications.
+pub fn flush_queued_notifications() {
+  if is_silenced() {
+    debug!("Leaving the queued notifications for the next run, notifications are off");
+    return;
+  }
+  NotificationQueue::new().flush(|entry| {
+    info!(
+      "Retrying queued notification [{}] from {}",
+      entry.notification.label(),
+      entry.queued_at
+    );
+    deliver_notification(&entry.destination(), &entry.notification)
+  });
+}
+
+impl NotificationEvent {
+  fn create_notification_message(&self) -> NotificationMessage {
+    NotificationMessage {
+      event_type: self.to_event_type(),
+      event_message: format!(
+        "Server Status: {}",
+        to_title_case(self.to_string().as_str())
+      ),
+      timestamp: Local::now().to_rfc3339(),
+      title: None,
+    }
+  }
+  /// Sends the message to every destination, each with its own format. Destinations that can't
+  /// be reached get the message queued for the next run.
+  pub fn send_custom_notification(&self, destinations: &[WebhookDestination], message: &str) {
+    debug!("Webhook enabled, sending notification {}", self.to_string());
+    let message = with_profile(message);
+    for destination in destinations {
+      let mut notification = self.create_notification_message();
+      notification.event_message = message.clone();
+      if !deliver_with_retries(destination, &notification) {
+        warn!(
+          "[{}]: Giving up on the webhook for now, it will be retried on the next odin run.",
+          self
+        );
+        NotificationQueue::new().push(destination, notification);
+      }
+    }
   }
   pub fn send_notification(&self) {
-    if is_webhook_enabled() {
+    self.send_notification_with(None)
+  }
+  /// Sends `details` in place of the default `Server Status: ...` message. A message set with the
+  /// event's `WEBHOOK_<EVENT>_<STATUS>_MESSAGE` still wins, with its placeholders
+  /// filled in.
+  pub fn send_notification_with_details(&self, details: &str) {
+    self.send_notification_with(Some(details))
+  }
+  /// Sends `details` about `player`. `{player}` in a `WEBHOOK_<EVENT>_MESSAGE` override is replaced
+  /// with their name.
+  pub fn send_player_notification(&self, player: &str, details: &str) {
+    self.send_notification_with_placeholders(Some(details), &[("{player}", player)])
+  }
+  fn send_notification_with(&self, details: Option<&str>) {
+    self.send_notification_with_placeholders(details, &[])
+  }
+  fn send_notification_with_placeholders(
+    &self,
+    details: Option<&str>,
+    placeholders: &[(&str, &str)],
+  ) {
+    if is_silenced() {
+      info!(
+        "[{}]: Not sending the notification, --no-notify is set",
+        self
+      );
+      return;
+    }
+    let destinations = configured_destinations(self);
+    if !destinations.is_empty() {
       debug!("Webhook found! Starting notification process...");
       let event = self.create_notification_message();
-      let env_var_name = parse_webhook_env_var(event.event_type);
-      let notification_message = env::var(env_var_name).unwrap_or(event.event_message);
-      self.send_custom_notification(fetch_webhook_url().as_str(), notification_message.as_str());
+      let suppressed = match throttle_window(&parse_webhook_throttle_env_var(&event.event_type)) {
+        Some(window) => match NotificationThrottle::new().check(&event.label(), window) {
+          Some(suppressed) => suppressed,
+          None => return,
+        },
+        None => 0,
+      };
+      let message_override = env::var(parse_webhook_env_var(event.event_type.clone()))
+        .ok()
+        .map(|message| event.fill_placeholders(&message, placeholders));
+      let mut notification_message =
+        message_override.unwrap_or_else(|| details.map_or(event.event_message, str::to_string));
+      if suppressed > 0 {
+        notification_message = format!(
+          "{} ({} similar events suppressed)",
+          notification_message, suppressed
+        );
+      }
+      self.send_custom_notification(&destinations, notification_message.as_str());
     } else {
       debug!("Skipping notification, no webhook supplied!");
     }
@@ -135,28 +495,285 @@ mod webhook_tests {
   #[serial]
   fn is_webhook_enabled_found_var_valid_url() {
     set_var("WEBHOOK_URL", "http://127.0.0.1:3000/dummy-url");
-    assert_eq!(is_webhook_enabled(), true);
+    assert!(is_webhook_enabled());
   }
 
   #[test]
   #[serial]
   fn is_webhook_enabled_found_var_invalid_url() {
     set_var("WEBHOOK_URL", "LOCALHOST");
-    assert_eq!(is_webhook_enabled(), false);
+    assert!(!is_webhook_enabled());
   }
 
   #[test]
   #[serial]
   fn is_webhook_enabled_not_found_var() {
     remove_var("WEBHOOK_URL");
-    assert_eq!(is_webhook_enabled(), false);
+    assert!(!is_webhook_enabled());
   }
 
   #[test]
   #[serial]
   fn is_webhook_enabled_empty_var() {
     set_var("WEBHOOK_URL", "");
-    assert_eq!(is_webhook_enabled(), false);
+    assert!(!is_webhook_enabled());
+  }
+
+  #[test]
+  fn tags_messages_with_the_profile() {
+    assert_eq!(
+      tag_with_profile("Server Status: Start Successful", Some("events")),
+      "[events] Server Status: Start Successful"
+    );
+    assert_eq!(
+      tag_with_profile("[events] Up", Some("events")),
+      "[events] Up"
+    );
+    assert_eq!(tag_with_profile("Up", None), "Up");
+  }
+}
+
+#[cfg(test)]
+mod routing_tests {
+  use super::*;
+  use crate::notifications::enums::event_status::EventStatus;
+  use serial_test::serial;
+  use std::env::{remove_var, set_var};
+
+  #[test]
+  #[serial]
+  fn events_use_their_own_url() {
+    remove_var(WEBHOOK_DESTINATIONS);
+    set_var(WEBHOOK_URL, "http://127.0.0.1:3000/everything");
+    set_var(
+      "WEBHOOK_BROADCAST_URL",
+      "https://discord.com/api/webhooks/1/public",
+    );
+    let broadcast = configured_destinations(&NotificationEvent::Broadcast);
+    let start = configured_destinations(&NotificationEvent::Start(EventStatus::Failed));
+    remove_var("WEBHOOK_BROADCAST_URL");
+    remove_var(WEBHOOK_URL);
+
+    assert_eq!(
+      broadcast,
+      vec![WebhookDestination::new(
+        "https://discord.com/api/webhooks/1/public"
+      )]
+    );
+    assert_eq!(broadcast[0].format, WebhookFormat::Discord);
+    assert_eq!(
+      start,
+      vec![WebhookDestination::new("http://127.0.0.1:3000/everything")]
+    );
+    assert_eq!(start[0].format, WebhookFormat::Json);
+  }
+
+  #[test]
+  #[serial]
+  fn event_url_alone_is_enough() {
+    remove_var(WEBHOOK_URL);
+    remove_var(WEBHOOK_DESTINATIONS);
+    set_var("WEBHOOK_STOP_URL", "http://127.0.0.1:3000/ops");
+    let stop = configured_destinations(&NotificationEvent::Stop(EventStatus::Running));
+    let update = configured_destinations(&NotificationEvent::Update(EventStatus::Running));
+    remove_var("WEBHOOK_STOP_URL");
+    assert_eq!(stop.len(), 1);
+    assert!(update.is_empty());
+  }
+}
+
+#[cfg(test)]
+mod proxy_tests {
+  use super::*;
+  use serial_test::serial;
+  use std::env::{remove_var, set_var};
+  use std::io::{Read, Write};
+  use std::net::TcpListener;
+  use std::thread;
+
+  #[test]
+  #[serial]
+  fn webhook_proxy_routes_request() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_address = listener.local_addr().unwrap();
+    let proxy = thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut buffer = [0; 1024];
+      let read = stream.read(&mut buffer).unwrap();
+      stream
+        .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+        .unwrap();
+      String::from_utf8_lossy(&buffer[..read]).to_string()
+    });
+
+    remove_var("NO_PROXY");
+    set_var(WEBHOOK_PROXY, format!("http://{}", proxy_address));
+    let response = build_client()
+      .unwrap()
+      .post("http://webhook.invalid/hook")
+      .send()
+      .unwrap();
+    remove_var(WEBHOOK_PROXY);
+
+    assert_eq!(response.status().as_u16(), 204);
+    let request = proxy.join().unwrap();
+    assert!(request.starts_with("POST http://webhook.invalid/hook HTTP/1.1"));
+  }
+
+  #[test]
+  #[serial]
+  fn invalid_webhook_proxy_is_an_error() {
+    set_var(WEBHOOK_PROXY, "not a url");
+    assert!(build_client().is_err());
+    remove_var(WEBHOOK_PROXY);
+  }
+}
+
+#[cfg(test)]
+mod response_tests {
+  use super::*;
+  use serial_test::serial;
+  use std::env::{remove_var, set_var};
+  use std::io::{Read, Write};
+  use std::net::TcpListener;
+  use std::thread;
+  use std::time::Instant;
+
+  // Answers one request with `response` and hangs up.
+  fn respond_with(response: &'static [u8]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut buffer = [0; 4096];
+      let _ = stream.read(&mut buffer);
+      stream.write_all(response).unwrap();
+    });
+    url
+  }
+
+  #[test]
+  fn test_notifications_tell_how_the_webhook_answered() {
+    let url = respond_with(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n");
+    assert_eq!(
+      send_test_notification(&WebhookDestination::new(&url)),
+      Ok(StatusCode::NO_CONTENT)
+    );
+    let url = respond_with(b"HTTP/1.1 404 Not Found\r\nContent-Length: 15\r\n\r\nUnknown Webhook");
+    assert_eq!(
+      send_test_notification(&WebhookDestination::new(&url)),
+      Err("404 Not Found Unknown Webhook".to_string())
+    );
+  }
+
+  #[test]
+  fn unreadable_bodies_do_not_panic() {
+    // Cut short, and not UTF-8 either.
+    let url = respond_with(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 100\r\n\r\n\xff\xfe");
+    assert!(!handle_request("Test", Client::new().post(url)));
+    let url = respond_with(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n\xff");
+    assert!(handle_request("Test", Client::new().post(url)));
+  }
+
+  #[test]
+  #[serial]
+  fn a_hanging_webhook_times_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    thread::spawn(move || {
+      // Takes the request and never answers it.
+      let (_stream, _) = listener.accept().unwrap();
+      thread::sleep(Duration::from_secs(10));
+    });
+    set_var(WEBHOOK_TIMEOUT, "1");
+    let started = Instant::now();
+    assert!(!handle_request("Test", build_request(&url)));
+    remove_var(WEBHOOK_TIMEOUT);
+    assert!(started.elapsed() < Duration::from_secs(5));
+  }
+}
+
+#[cfg(test)]
+mod fan_out_tests {
+  use super::*;
+  use crate::notifications::enums::event_status::EventStatus;
+  use serial_test::serial;
+  use std::env::{remove_var, set_var};
+  use std::io::{Read, Write};
+  use std::net::TcpListener;
+  use std::thread::{self, JoinHandle};
+
+  // Accepts a single webhook and hands back its body.
+  fn capture_webhook() -> (String, JoinHandle<serde_json::Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut request = Vec::new();
+      let mut buffer = [0; 1024];
+      loop {
+        let read = stream.read(&mut buffer).unwrap();
+        request.extend_from_slice(&buffer[..read]);
+        let text = String::from_utf8_lossy(&request).to_string();
+        if let Some(header_end) = text.find("\r\n\r\n") {
+          let content_length = text[..header_end]
+            .lines()
+            .find_map(|line| {
+              line
+                .to_lowercase()
+                .strip_prefix("content-length:")
+                .map(|v| v.trim().to_string())
+            })
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(0);
+          if request.len() >= header_end + 4 + content_length {
+            stream
+              .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+              .unwrap();
+            return serde_json::from_slice(&request[header_end + 4..]).unwrap();
+          }
+        }
+      }
+    });
+    (url, handle)
+  }
+
+  #[test]
+  #[serial]
+  fn each_destination_gets_its_own_format() {
+    set_var("NAME", "Fan Out Test");
+    let (json_url, json_body) = capture_webhook();
+    let (discord_url, discord_body) = capture_webhook();
+    let destinations = vec![
+      WebhookDestination::parse(&format!("json={}", json_url)).unwrap(),
+      WebhookDestination::parse(&format!("discord={}", discord_url)).unwrap(),
+    ];
+
+    NotificationEvent::Broadcast.send_custom_notification(&destinations, "hello vikings");
+
+    let json_body = json_body.join().unwrap();
+    assert_eq!(json_body["event_message"], "hello vikings");
+    assert_eq!(json_body["event_type"]["name"], "Broadcast");
+    assert!(json_body.get("embeds").is_none());
+
+    let discord_body = discord_body.join().unwrap();
+    assert!(discord_body.get("event_type").is_none());
+    assert_eq!(discord_body["embeds"][0]["description"], "hello vikings");
+    remove_var("NAME");
+  }
+
+  #[test]
+  #[serial]
+  fn silenced_events_are_not_sent() {
+    let (url, body) = capture_webhook();
+    set_var(WEBHOOK_URL, &url);
+    silence_notifications();
+    NotificationEvent::Stop(EventStatus::Running).send_notification();
+    resume_notifications();
+    NotificationEvent::Start(EventStatus::Successful).send_notification();
+    // The webhook only takes one request, the first one it gets.
+    assert_eq!(body.join().unwrap()["event_type"]["name"], "Start");
+    remove_var(WEBHOOK_URL);
   }
 }
 
@@ -173,6 +790,22 @@ mod enum_tests {
     assert_eq!(to_title_case(Broadcast.to_string().as_str()), "Broadcast");
   }
 
+  #[test]
+  fn events_without_a_status_have_a_shorter_message_var() {
+    assert_eq!(
+      parse_webhook_env_var(NotificationEvent::PlayerJoined.to_event_type()),
+      "WEBHOOK_PLAYER_JOINED_MESSAGE"
+    );
+    assert_eq!(
+      parse_webhook_env_var(NotificationEvent::Backup(EventStatus::Failed).to_event_type()),
+      "WEBHOOK_BACKUP_FAILED_MESSAGE"
+    );
+    assert_eq!(
+      parse_webhook_throttle_env_var(&NotificationEvent::WorldSaved.to_event_type()),
+      "WEBHOOK_WORLD_SAVED_THROTTLE_SECONDS"
+    );
+  }
+
   #[test]
   fn parse_enum_create_notification() {
     let event = NotificationEvent::Stop(EventStatus::Running);
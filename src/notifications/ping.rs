@@ -0,0 +1,145 @@
+use log::{debug, warn};
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::notifications::{build_client, fetch_url_var, handle_request};
+use crate::utils::environment::fetch_var;
+
+/// Pinged after every backup, for monitors like healthchecks.io or Uptime Kuma. Separate from
+/// `WEBHOOK_URL` so chat notifications and monitoring can go to different places.
+pub const BACKUP_PING_URL_VAR: &str = "BACKUP_PING_URL";
+/// Set to `1` to POST the `BackupPing` as JSON instead of a plain GET.
+pub const BACKUP_PING_BODY_VAR: &str = "BACKUP_PING_BODY";
+
+/// Healthchecks.io style, added to the path when a backup fails.
+const FAIL_PATH: &str = "fail";
+/// Uptime Kuma style push urls carry these, they are filled in instead of adding `/fail`.
+const STATUS_PARAM: &str = "status";
+const MESSAGE_PARAM: &str = "msg";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct BackupPing {
+  pub(crate) success: bool,
+  /// Where the backup was written, it is gone when the backup failed.
+  pub(crate) archive: String,
+  pub(crate) size_bytes: u64,
+  pub(crate) duration_seconds: f64,
+  pub(crate) worlds: Vec<String>,
+  /// The backup's summary, or why it failed.
+  pub(crate) message: String,
+}
+
+/// `base` with the outcome of the backup in it. A url with a `status` parameter gets `up` or
+/// `down` (and the message in `msg`, when it has one), any other url gets `/fail` added when the
+/// backup failed. Successful backups add their size and duration as parameters.
+pub fn ping_url(base: &str, ping: &BackupPing) -> Result<Url, String> {
+  let mut url = Url::parse(base).map_err(|e| format!("Invalid {}: {}", BACKUP_PING_URL_VAR, e))?;
+  let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+  let has_status = pairs.iter().any(|(key, _)| key == STATUS_PARAM);
+  if !has_status && !ping.success {
+    let path = format!("{}/{}", url.path().trim_end_matches('/'), FAIL_PATH);
+    url.set_path(&path);
+  }
+  let message = ping.message.lines().next().unwrap_or_default();
+  let mut query = url.query_pairs_mut();
+  query.clear();
+  for (key, value) in &pairs {
+    match key.as_str() {
+      STATUS_PARAM => query.append_pair(key, if ping.success { "up" } else { "down" }),
+      MESSAGE_PARAM => query.append_pair(key, message),
+      _ => query.append_pair(key, value),
+    };
+  }
+  if ping.success {
+    query.append_pair("size_bytes", &ping.size_bytes.to_string());
+    query.append_pair("duration_seconds", &format!("{:.1}", ping.duration_seconds));
+  }
+  drop(query);
+  // Nothing was appended, don't leave a bare `?` behind.
+  if url.query() == Some("") {
+    url.set_query(None);
+  }
+  Ok(url)
+}
+
+pub fn is_ping_enabled() -> bool {
+  !fetch_url_var(BACKUP_PING_URL_VAR).is_empty()
+}
+
+/// Tells the monitor at `BACKUP_PING_URL` how the backup went. Best effort, a monitor that can't be
+/// reached is logged and never fails the backup.
+pub fn send_backup_ping(ping: &BackupPing) {
+  let base = fetch_url_var(BACKUP_PING_URL_VAR);
+  if base.is_empty() {
+    return;
+  }
+  let url = match ping_url(&base, ping) {
+    Ok(url) => url,
+    Err(e) => {
+      warn!("{}", e);
+      return;
+    }
+  };
+  let client = match build_client() {
+    Ok(client) => client,
+    Err(e) => {
+      warn!("Unable to ping the backup monitor: {}", e);
+      return;
+    }
+  };
+  let request = if fetch_var(BACKUP_PING_BODY_VAR, "0") == "1" {
+    client.post(url).json(ping)
+  } else {
+    client.get(url)
+  };
+  debug!("Pinging the backup monitor, success: {}", ping.success);
+  if !handle_request("Backup Ping", request) {
+    warn!(
+      "The backup monitor at {} wasn't told about the backup",
+      BACKUP_PING_URL_VAR
+    );
+  }
+}
+
+#[cfg(test)]
+mod ping_tests {
+  use super::*;
+
+  fn ping(success: bool) -> BackupPing {
+    BackupPing {
+      success,
+      archive: "/home/steam/backups/20210301-120000-backup.tar.gz".to_string(),
+      size_bytes: 1024,
+      duration_seconds: 2.5,
+      worlds: vec!["Dedicated".to_string()],
+      message: "Backed up 2 file(s)\nsecond line".to_string(),
+    }
+  }
+
+  #[test]
+  fn fills_in_healthchecks_style_urls() {
+    let base = "https://hc-ping.com/5a3b/";
+    assert_eq!(
+      ping_url(base, &ping(true)).unwrap().as_str(),
+      "https://hc-ping.com/5a3b/?size_bytes=1024&duration_seconds=2.5"
+    );
+    assert_eq!(
+      ping_url(base, &ping(false)).unwrap().as_str(),
+      "https://hc-ping.com/5a3b/fail"
+    );
+    assert!(ping_url("not a url", &ping(true)).is_err());
+  }
+
+  #[test]
+  fn fills_in_status_parameters() {
+    let base = "http://kuma:3001/api/push/abc?status=up&msg=OK&ping=";
+    assert_eq!(
+      ping_url(base, &ping(false)).unwrap().as_str(),
+      "http://kuma:3001/api/push/abc?status=down&msg=Backed+up+2+file%28s%29&ping="
+    );
+    assert!(ping_url(base, &ping(true))
+      .unwrap()
+      .as_str()
+      .starts_with("http://kuma:3001/api/push/abc?status=up&msg=Backed"));
+  }
+}
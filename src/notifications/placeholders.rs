@@ -0,0 +1,126 @@
+use std::{net::SocketAddr, time::Duration};
+
+use inflections::case::to_title_case;
+
+use crate::{
+  files::{config::configured, metadata::configured_world},
+  server::{game_port, query_info, query_port},
+  utils::environment::{active_profile, fetch_var},
+};
+
+/// The configured server name, `NAME` before odin is configured.
+pub const SERVER_NAME_PLACEHOLDER: &str = "{server_name}";
+/// How many players the server says are online, left blank when it doesn't answer.
+pub const PLAYER_COUNT_PLACEHOLDER: &str = "{player_count}";
+/// The world the server loads.
+pub const WORLD_PLACEHOLDER: &str = "{world}";
+/// When the event happened, in RFC 3339.
+pub const TIMESTAMP_PLACEHOLDER: &str = "{timestamp}";
+/// The event, like `Player Joined`.
+pub const EVENT_PLACEHOLDER: &str = "{event}";
+/// The event's status, like `Successful`, `Triggered` for events without one.
+pub const STATUS_PLACEHOLDER: &str = "{status}";
+/// The profile odin runs with, blank without one.
+pub const PROFILE_PLACEHOLDER: &str = "{profile}";
+/// Short, the notification shouldn't wait long on a server that is down.
+const PLAYER_COUNT_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn server_name() -> String {
+  configured()
+    .map(|config| config.name)
+    .unwrap_or_else(|| fetch_var("NAME", ""))
+}
+
+fn player_count() -> String {
+  let port = game_port(
+    &configured()
+      .map(|config| config.port)
+      .unwrap_or_else(|| fetch_var("PORT", "2456")),
+  );
+  query_port(&port)
+    .and_then(|port| {
+      query_info(
+        SocketAddr::from(([127, 0, 0, 1], port)),
+        PLAYER_COUNT_TIMEOUT,
+      )
+      .ok()
+    })
+    .map(|info| info.players.to_string())
+    .unwrap_or_default()
+}
+
+/// The placeholders every event fills in, from its notification.
+pub fn event_placeholders(
+  timestamp: &str,
+  event: &str,
+  status: &str,
+) -> Vec<(&'static str, String)> {
+  vec![
+    (TIMESTAMP_PLACEHOLDER, timestamp.to_string()),
+    (EVENT_PLACEHOLDER, to_title_case(event)),
+    (STATUS_PLACEHOLDER, status.to_string()),
+  ]
+}
+
+fn replace(message: String, placeholder: &str, value: impl FnOnce() -> String) -> String {
+  if message.contains(placeholder) {
+    message.replace(placeholder, &value())
+  } else {
+    message
+  }
+}
+
+/// Fills `placeholders` and the server's own into a `WEBHOOK_*_MESSAGE` override. The server is
+/// only asked for what the message uses, anything that isn't a known placeholder is left as is.
+pub fn fill_placeholders(message: &str, placeholders: &[(&str, &str)]) -> String {
+  let message = placeholders
+    .iter()
+    .fold(message.to_string(), |message, (placeholder, value)| {
+      message.replace(placeholder, value)
+    });
+  let message = replace(message, SERVER_NAME_PLACEHOLDER, server_name);
+  let message = replace(message, WORLD_PLACEHOLDER, configured_world);
+  let message = replace(message, PROFILE_PLACEHOLDER, || {
+    active_profile().unwrap_or_default()
+  });
+  replace(message, PLAYER_COUNT_PLACEHOLDER, player_count)
+}
+
+#[cfg(test)]
+mod placeholder_tests {
+  use super::*;
+  use serial_test::serial;
+  use std::env::{remove_var, set_var};
+
+  #[test]
+  #[serial]
+  fn fills_in_what_is_known() {
+    set_var("NAME", "Midgard");
+    // Nothing answers queries here.
+    set_var("PORT", "1");
+    assert_eq!(
+      fill_placeholders(
+        "{player} joined {server_name} ({player_count} online)",
+        &[("{player}", "Ragnar")]
+      ),
+      "Ragnar joined Midgard ( online)"
+    );
+    assert_eq!(fill_placeholders("Server is up", &[]), "Server is up");
+    set_var("WORLD", "Asgard");
+    let event = event_placeholders("2026-10-15T12:00:00+00:00", "PlayerJoined", "Triggered");
+    let placeholders: Vec<(&str, &str)> = event
+      .iter()
+      .map(|(placeholder, value)| (*placeholder, value.as_str()))
+      .collect();
+    assert_eq!(
+      fill_placeholders(
+        "[{timestamp}] {event} {status} on {world}{profile} {unknown}",
+        &placeholders
+      ),
+      "[2026-10-15T12:00:00+00:00] Player Joined Triggered on Asgard {unknown}"
+    );
+    remove_var("WORLD");
+    remove_var("NAME");
+    remove_var("PORT");
+  }
+}
@@ -0,0 +1,246 @@
+use chrono::{DateTime, Duration, Local};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+
+use std::{
+  fs::{self, OpenOptions},
+  io,
+  path::PathBuf,
+};
+
+use crate::notifications::destination::{WebhookDestination, WebhookFormat};
+use crate::notifications::NotificationMessage;
+use crate::utils::{environment::fetch_var, get_odin_dir};
+
+pub const WEBHOOK_QUEUE_MAX_AGE: &str = "WEBHOOK_QUEUE_MAX_AGE_MINUTES";
+const QUEUE_FILE_NAME: &str = "notification-queue.json";
+
+#[derive(Deserialize, Serialize)]
+pub struct QueuedNotification {
+  pub(crate) webhook_url: String,
+  /// Missing on entries queued before destinations had formats, those are detected from the url.
+  #[serde(default)]
+  pub(crate) format: Option<WebhookFormat>,
+  pub(crate) notification: NotificationMessage,
+  pub(crate) queued_at: String,
+}
+
+impl QueuedNotification {
+  pub fn destination(&self) -> WebhookDestination {
+    WebhookDestination {
+      url: self.webhook_url.clone(),
+      format: self
+        .format
+        .unwrap_or_else(|| WebhookFormat::detect(&self.webhook_url)),
+    }
+  }
+
+  fn is_stale(&self, max_age: Duration) -> bool {
+    match DateTime::parse_from_rfc3339(&self.queued_at) {
+      Ok(queued_at) => Local::now().signed_duration_since(queued_at) > max_age,
+      Err(_) => true,
+    }
+  }
+}
+
+/// Spool of notifications that could not be delivered. Every read-modify-write happens while
+/// holding an exclusive lock on a sidecar lock file so concurrent odin runs can't clobber it.
+pub struct NotificationQueue {
+  path: PathBuf,
+}
+
+impl NotificationQueue {
+  pub fn new() -> Self {
+    NotificationQueue {
+      path: get_odin_dir().join(QUEUE_FILE_NAME),
+    }
+  }
+
+  fn lock_path(&self) -> PathBuf {
+    self.path.with_extension("lock")
+  }
+
+  fn read_entries(&self) -> Vec<QueuedNotification> {
+    match fs::read_to_string(&self.path) {
+      Ok(content) if !content.trim().is_empty() => {
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+          warn!(
+            "Discarding unreadable notification queue at {}: {}",
+            self.path.display(),
+            e
+          );
+          Vec::new()
+        })
+      }
+      _ => Vec::new(),
+    }
+  }
+
+  fn write_entries(&self, entries: &[QueuedNotification]) -> io::Result<()> {
+    if entries.is_empty() {
+      if self.path.exists() {
+        fs::remove_file(&self.path)?;
+      }
+      Ok(())
+    } else {
+      fs::write(&self.path, serde_json::to_string(entries)?)
+    }
+  }
+
+  fn with_lock<T>(&self, action: impl FnOnce(&mut Vec<QueuedNotification>) -> T) -> io::Result<T> {
+    if let Some(parent) = self.path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    let lock_file = OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(self.lock_path())?;
+    lock_file.lock()?;
+    let mut entries = self.read_entries();
+    let output = action(&mut entries);
+    self.write_entries(&entries)?;
+    lock_file.unlock()?;
+    Ok(output)
+  }
+
+  pub fn push(&self, destination: &WebhookDestination, notification: NotificationMessage) {
+    let entry = QueuedNotification {
+      webhook_url: destination.url.clone(),
+      format: Some(destination.format),
+      notification,
+      queued_at: Local::now().to_rfc3339(),
+    };
+    match self.with_lock(|entries| entries.push(entry)) {
+      Ok(_) => debug!("Queued notification in {}", self.path.display()),
+      Err(e) => error!(
+        "Failed to queue notification in {}: {}",
+        self.path.display(),
+        e
+      ),
+    }
+  }
+
+  /// Hands every fresh entry to `send`; anything it fails to deliver goes back on the queue.
+  pub fn flush(&self, send: impl Fn(&QueuedNotification) -> bool) {
+    if !self.path.exists() {
+      return;
+    }
+    let max_age_minutes = fetch_var(WEBHOOK_QUEUE_MAX_AGE, "60")
+      .parse::<i64>()
+      .unwrap_or(60);
+    let max_age = Duration::minutes(max_age_minutes);
+    // Take the entries out so the lock isn't held while waiting on the network.
+    let entries = match self.with_lock(std::mem::take) {
+      Ok(entries) => entries,
+      Err(e) => {
+        error!(
+          "Failed to read notification queue at {}: {}",
+          self.path.display(),
+          e
+        );
+        return;
+      }
+    };
+    let mut failed = Vec::new();
+    for entry in entries {
+      if entry.is_stale(max_age) {
+        warn!(
+          "Dropping queued notification [{}] from {}, it is older than {} minutes.",
+          entry.notification.label(),
+          entry.queued_at,
+          max_age_minutes
+        );
+      } else if !send(&entry) {
+        failed.push(entry);
+      }
+    }
+    if !failed.is_empty() {
+      if let Err(e) = self.with_lock(|entries| entries.append(&mut failed)) {
+        error!(
+          "Failed to re-queue notifications in {}: {}",
+          self.path.display(),
+          e
+        );
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod queue_tests {
+  use super::*;
+  use crate::notifications::enums::event_status::EventStatus;
+  use crate::notifications::enums::notification_event::NotificationEvent;
+  use crate::utils::testing::test_dir;
+  use std::cell::RefCell;
+  use tempfile::TempDir;
+
+  fn test_queue() -> (TempDir, NotificationQueue) {
+    let dir = test_dir("queue");
+    let queue = NotificationQueue {
+      path: dir.path().join(QUEUE_FILE_NAME),
+    };
+    (dir, queue)
+  }
+
+  fn stop_message() -> NotificationMessage {
+    NotificationEvent::Stop(EventStatus::Successful).create_notification_message()
+  }
+
+  #[test]
+  fn flush_delivers_and_clears() {
+    let (_dir, queue) = test_queue();
+    queue.push(
+      &WebhookDestination::new("http://127.0.0.1/hook"),
+      stop_message(),
+    );
+    queue.push(
+      &WebhookDestination::new("http://127.0.0.1/hook"),
+      stop_message(),
+    );
+    let delivered = RefCell::new(0);
+    queue.flush(|_| {
+      *delivered.borrow_mut() += 1;
+      true
+    });
+    assert_eq!(*delivered.borrow(), 2);
+    assert!(!queue.path.exists());
+  }
+
+  #[test]
+  fn flush_requeues_failures() {
+    let (_dir, queue) = test_queue();
+    queue.push(
+      &WebhookDestination::new("http://127.0.0.1/first"),
+      stop_message(),
+    );
+    queue.push(
+      &WebhookDestination::new("http://127.0.0.1/second"),
+      stop_message(),
+    );
+    queue.flush(|entry| entry.webhook_url.ends_with("first"));
+    let remaining = queue.read_entries();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].webhook_url, "http://127.0.0.1/second");
+  }
+
+  #[test]
+  fn flush_drops_stale_entries() {
+    let (_dir, queue) = test_queue();
+    let stale = QueuedNotification {
+      webhook_url: "http://127.0.0.1/hook".to_string(),
+      format: None,
+      notification: stop_message(),
+      queued_at: (Local::now() - Duration::days(7)).to_rfc3339(),
+    };
+    queue.with_lock(|entries| entries.push(stale)).unwrap();
+    let delivered = RefCell::new(0);
+    queue.flush(|_| {
+      *delivered.borrow_mut() += 1;
+      true
+    });
+    assert_eq!(*delivered.borrow(), 0);
+    assert!(queue.read_entries().is_empty());
+  }
+}
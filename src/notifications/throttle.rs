@@ -0,0 +1,205 @@
+use chrono::{DateTime, Duration, Local};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use std::{
+  collections::HashMap,
+  fs::{self, OpenOptions},
+  io,
+  path::PathBuf,
+};
+
+use crate::utils::{environment::fetch_var, get_odin_dir};
+
+pub const WEBHOOK_THROTTLE_SECONDS: &str = "WEBHOOK_THROTTLE_SECONDS";
+const THROTTLE_FILE_NAME: &str = "notification-throttle.json";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ThrottleEntry {
+  pub(crate) last_sent_at: String,
+  pub(crate) suppressed: u32,
+}
+
+pub type ThrottleState = HashMap<String, ThrottleEntry>;
+
+/// Decides whether the event with `key` goes out now. Returns how many identical events were
+/// suppressed since the last one that was sent, or `None` if this one should be suppressed too.
+pub fn check_throttle(
+  state: &mut ThrottleState,
+  key: &str,
+  now: DateTime<Local>,
+  window: Duration,
+) -> Option<u32> {
+  let entry = state.entry(key.to_string()).or_default();
+  let within_window = DateTime::parse_from_rfc3339(&entry.last_sent_at)
+    .map(|last_sent_at| now.signed_duration_since(last_sent_at) < window)
+    .unwrap_or(false);
+  if within_window {
+    entry.suppressed += 1;
+    return None;
+  }
+  let suppressed = entry.suppressed;
+  *entry = ThrottleEntry {
+    last_sent_at: now.to_rfc3339(),
+    suppressed: 0,
+  };
+  Some(suppressed)
+}
+
+/// Throttling is opt in, a window of zero sends everything. `event_var`, like
+/// `WEBHOOK_WORLD_SAVED_THROTTLE_SECONDS`, sets the window of one kind of event over
+/// `WEBHOOK_THROTTLE_SECONDS`.
+pub fn throttle_window(event_var: &str) -> Option<Duration> {
+  let event_seconds = fetch_var(event_var, "");
+  let (name, seconds) = if event_seconds.trim().is_empty() {
+    (
+      WEBHOOK_THROTTLE_SECONDS,
+      fetch_var(WEBHOOK_THROTTLE_SECONDS, "0"),
+    )
+  } else {
+    (event_var, event_seconds)
+  };
+  match seconds.trim().parse::<i64>() {
+    Ok(seconds) if seconds > 0 => Some(Duration::seconds(seconds)),
+    Ok(_) => None,
+    Err(_) => {
+      warn!(
+        "{} must be a number of seconds, not throttling notifications",
+        name
+      );
+      None
+    }
+  }
+}
+
+/// When each kind of event was last sent, shared between odin runs.
+pub struct NotificationThrottle {
+  path: PathBuf,
+}
+
+impl NotificationThrottle {
+  pub fn new() -> Self {
+    NotificationThrottle {
+      path: get_odin_dir().join(THROTTLE_FILE_NAME),
+    }
+  }
+
+  fn read_state(&self) -> ThrottleState {
+    fs::read_to_string(&self.path)
+      .ok()
+      .and_then(|content| serde_json::from_str(&content).ok())
+      .unwrap_or_default()
+  }
+
+  fn with_lock<T>(&self, action: impl FnOnce(&mut ThrottleState) -> T) -> io::Result<T> {
+    if let Some(parent) = self.path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    let lock_file = OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(self.path.with_extension("lock"))?;
+    lock_file.lock()?;
+    let mut state = self.read_state();
+    let output = action(&mut state);
+    fs::write(&self.path, serde_json::to_string(&state)?)?;
+    lock_file.unlock()?;
+    Ok(output)
+  }
+
+  /// Same as `check_throttle` against the shared state. If the state can't be used the event is
+  /// sent rather than lost.
+  pub fn check(&self, key: &str, window: Duration) -> Option<u32> {
+    match self.with_lock(|state| check_throttle(state, key, Local::now(), window)) {
+      Ok(result) => {
+        if result.is_none() {
+          debug!("[{}]: Suppressing duplicate notification", key);
+        }
+        result
+      }
+      Err(e) => {
+        warn!("Unable to throttle notifications: {}", e);
+        Some(0)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod throttle_tests {
+  use super::*;
+  use serial_test::serial;
+
+  #[test]
+  fn suppresses_within_window_and_counts() {
+    let mut state = ThrottleState::new();
+    let now = Local::now();
+    let window = Duration::seconds(60);
+    assert_eq!(
+      check_throttle(&mut state, "Start Failed", now, window),
+      Some(0)
+    );
+    assert_eq!(
+      check_throttle(
+        &mut state,
+        "Start Failed",
+        now + Duration::seconds(10),
+        window
+      ),
+      None
+    );
+    assert_eq!(
+      check_throttle(
+        &mut state,
+        "Start Failed",
+        now + Duration::seconds(20),
+        window
+      ),
+      None
+    );
+    // Other events aren't affected.
+    assert_eq!(
+      check_throttle(
+        &mut state,
+        "Stop Failed",
+        now + Duration::seconds(20),
+        window
+      ),
+      Some(0)
+    );
+    assert_eq!(
+      check_throttle(
+        &mut state,
+        "Start Failed",
+        now + Duration::seconds(61),
+        window
+      ),
+      Some(2)
+    );
+    assert_eq!(
+      check_throttle(
+        &mut state,
+        "Start Failed",
+        now + Duration::seconds(200),
+        window
+      ),
+      Some(0)
+    );
+  }
+
+  #[test]
+  #[serial]
+  fn event_window_wins_over_the_default() {
+    let event_var = "WEBHOOK_WORLD_SAVED_THROTTLE_SECONDS";
+    std::env::set_var(WEBHOOK_THROTTLE_SECONDS, "60");
+    assert_eq!(throttle_window(event_var), Some(Duration::seconds(60)));
+    std::env::set_var(event_var, "3600");
+    assert_eq!(throttle_window(event_var), Some(Duration::seconds(3600)));
+    std::env::set_var(event_var, "0");
+    assert_eq!(throttle_window(event_var), None);
+    std::env::remove_var(event_var);
+    std::env::remove_var(WEBHOOK_THROTTLE_SECONDS);
+    assert_eq!(throttle_window(event_var), None);
+  }
+}
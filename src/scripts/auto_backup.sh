@@ -1,20 +1,32 @@
 #!/usr/bin/env bash
 # Cron uses blank env and does not pick up /usr/local/bin files.
 export PATH="/usr/local/bin:$PATH"
+# The entrypoint keeps the job's settings out of the crontab, in this file.
+if [ -f "${CRON_ENV_FILE:-}" ]; then
+    # shellcheck disable=SC1090
+    . "${CRON_ENV_FILE}"
+fi
 cd /home/steam/ || exit 1
+# Keep odin's state next to the server so the report can see this backup.
+export ODIN_WORKING_DIR="${ODIN_WORKING_DIR:-/home/steam/valheim}"
 
 log() {
   PREFIX="[Valheim][steam]"
   printf "%-16s: %s\n" "${PREFIX}" "$1"
 }
 
-file_name="$(date +"%Y%m%d-%H%M%S")-${1:-"backup"}.tar.gz"
-
 log "Starting auto backup process..."
-odin backup /home/steam/.config/unity3d/IronGate/Valheim "/home/steam/backups/${file_name}" || exit 1
+if [ -n "${BACKUP_NAME_FORMAT:-}" ]; then
+    # Odin names the backup it writes into the directory.
+    odin backup /home/steam/.config/unity3d/IronGate/Valheim /home/steam/backups || exit 1
+else
+    file_name="$(date +"%Y%m%d-%H%M%S")-${1:-"backup"}.tar.gz"
+    odin backup /home/steam/.config/unity3d/IronGate/Valheim "/home/steam/backups/${file_name}" || exit 1
+fi
 
 if [ "${AUTO_BACKUP_REMOVE_OLD:=0}" -eq 1 ]; then
     find /home/steam/backups -mtime +${AUTO_BACKUP_DAYS_TO_LIVE:-5} -exec rm {} \;
 fi
 
-log "Backup process complete! Created ${file_name}"
+# Odin swaps the extension when BACKUP_COMPRESSION picks another format and logs the file it wrote.
+log "Backup process complete!"
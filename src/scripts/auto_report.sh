@@ -0,0 +1,18 @@
+#!/usr/bin/env bash
+# Cron uses blank env and does not pick up /usr/local/bin files.
+export PATH="/usr/local/bin:$PATH"
+# The entrypoint keeps the job's settings out of the crontab, in this file.
+if [ -f "${CRON_ENV_FILE:-}" ]; then
+    # shellcheck disable=SC1090
+    . "${CRON_ENV_FILE}"
+fi
+cd /home/steam/valheim || exit 1
+
+log() {
+  PREFIX="[Valheim][steam]"
+  printf "%-16s: %s\n" "${PREFIX}" "$1"
+}
+
+log "Sending server report..."
+odin report --days "${AUTO_REPORT_PERIOD_DAYS:-7}" || exit 1
+log "Server report sent!"
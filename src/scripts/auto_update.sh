@@ -1,6 +1,11 @@
 #!/usr/bin/env bash
 # Cron uses blank env and does not pick up /usr/local/bin files.
 export PATH="/usr/local/bin:$PATH"
+# The entrypoint keeps the job's settings out of the crontab, in this file.
+if [ -f "${CRON_ENV_FILE:-}" ]; then
+    # shellcheck disable=SC1090
+    . "${CRON_ENV_FILE}"
+fi
 
 log() {
   PREFIX="[Valheim][steam]"
@@ -36,10 +41,7 @@ if odin update --check; then
         odin stop || exit 1
     fi
 
-    if [ "${AUTO_BACKUP_ON_UPDATE:=0}" -eq 1 ]; then
-        /bin/bash /home/steam/scripts/auto_backup.sh "pre-update-backup"
-    fi
-
+    # Odin backs up the saves itself before updating unless BACKUP_ON_UPDATE=0.
     odin update || exit 1
 
     # Start the server if it was running before
@@ -47,17 +47,49 @@ clean_up() {
 
 trap 'clean_up' INT TERM
 
+# Every cron job gets these on top of its own.
+CRON_COMMON_ENV=(
+    DEBUG_MODE ODIN_CONFIG_FILE ODIN_WORKING_DIR
+    WEBHOOK_URL WEBHOOK_DESTINATIONS WEBHOOK_THROTTLE_SECONDS WEBHOOK_START_URL WEBHOOK_STOP_URL
+    WEBHOOK_UPDATE_URL WEBHOOK_BROADCAST_URL MATRIX_ACCESS_TOKEN WEBHOOK_MENTION_ON_FAILURE
+)
+# Everything `odin backup` reads, for the jobs that back up.
+CRON_BACKUP_ENV=(
+    NAME WORLD BACKUP_SOURCE_DIR BACKUP_OUTPUT_DIR BACKUP_WORLDS BACKUP_SCOPE
+    BACKUP_COMPRESSION BACKUP_COMPRESSION_LEVEL BACKUP_STRATEGY BACKUP_FULL_INTERVAL BACKUP_NAME_FORMAT
+    BACKUP_INCLUDE BACKUP_EXCLUDE BACKUP_MAX_WAIT BACKUP_THREADS BACKUP_NICE
+    BACKUP_ENCRYPTION_KEY BACKUP_AGE_RECIPIENT BACKUPS_TO_KEEP BACKUPS_MAX_AGE_DAYS
+    BACKUP_DESTINATIONS BACKUP_DESTINATION_POLICY
+    BACKUP_S3_BUCKET BACKUP_S3_ENDPOINT BACKUP_S3_REGION BACKUP_S3_PREFIX AWS_ACCESS_KEY_ID AWS_SECRET_ACCESS_KEY AWS_REGION
+    BACKUP_SFTP_HOST BACKUP_SFTP_PORT BACKUP_SFTP_USER BACKUP_SFTP_KEY_PATH BACKUP_SFTP_PASSWORD
+    BACKUP_SFTP_REMOTE_DIR BACKUP_SFTP_KNOWN_HOSTS BACKUP_SFTP_INSECURE BACKUP_SFTP_PRUNE
+    BACKUP_PING_URL BACKUP_PING_BODY BACKUP_PRE_HOOK BACKUP_POST_HOOK
+)
+# Where the jobs' env files go, only the steam user can read them.
+CRON_ENV_DIR="/home/steam/.cron"
+
+# Usage: setup_cron <name> <script> <schedule> [var...]
+# The vars are written to an env file the script sources, not into the world readable crontab,
+# which would show secrets to anyone and where cron reads a `%` as a newline.
 setup_cron() {
     set -f
     CRON_NAME=$1
     SCRIPT_PATH="/home/steam/scripts/$2"
     CRON_SCHEDULE=$3
-    CRON_ENV=$4
+    shift 3
+    CRON_ENV_FILE="${CRON_ENV_DIR}/${CRON_NAME}.env"
     LOG_LOCATION="/home/steam/valheim/logs/$CRON_NAME.out"
     rm $LOG_LOCATION > /dev/null
-    printf "%s %s /usr/sbin/gosu steam /bin/bash %s >> %s 2>&1" \
+    install -d -m 0700 -o steam -g steam "${CRON_ENV_DIR}"
+    install -m 0600 -o steam -g steam /dev/null "${CRON_ENV_FILE}"
+    for name in "${CRON_COMMON_ENV[@]}" "$@"; do
+        if [ -n "${!name:-}" ]; then
+            printf "export %s=%q\n" "${name}" "${!name}" >>"${CRON_ENV_FILE}"
+        fi
+    done
+    printf "%s CRON_ENV_FILE=%s /usr/sbin/gosu steam /bin/bash %s >> %s 2>&1" \
     "${CRON_SCHEDULE}"  \
-    "DEBUG_MODE=${DEBUG_MODE:-0} ODIN_CONFIG_FILE=${ODIN_CONFIG_FILE} ODIN_WORKING_DIR=${ODIN_WORKING_DIR} WEBHOOK_URL=${WEBHOOK_URL:-""} ${CRON_ENV}"   \
+    "${CRON_ENV_FILE}"  \
     "${SCRIPT_PATH}"    \
     "${LOG_LOCATION}"   \
     >/etc/cron.d/${CRON_NAME}
@@ -111,6 +143,7 @@ log "$(groupmod -g ${PGID} steam)"
 # Configure Cron
 AUTO_UPDATE="${AUTO_UPDATE:-0}"
 AUTO_BACKUP="${AUTO_BACKUP:-0}"
+AUTO_REPORT="${AUTO_REPORT:-0}"
 
 
 if [ "${AUTO_UPDATE}" -eq 1 ]; then
@@ -121,7 +154,7 @@ if [ "${AUTO_UPDATE}" -eq 1 ]; then
     "auto-update" \
     "auto_update.sh" \
     "${AUTO_UPDATE_SCHEDULE}" \
-    "AUTO_BACKUP_ON_UPDATE=${AUTO_BACKUP_ON_UPDATE:-0}"
+    AUTO_BACKUP_ON_UPDATE BACKUP_ON_UPDATE "${CRON_BACKUP_ENV[@]}"
 fi
 
 if [ "${AUTO_BACKUP}" -eq 1 ]; then
@@ -132,11 +165,22 @@ if [ "${AUTO_BACKUP}" -eq 1 ]; then
     "auto-backup"               \
     "auto_backup.sh"            \
     "${AUTO_BACKUP_SCHEDULE}"   \
-    "AUTO_BACKUP_REMOVE_OLD=${AUTO_BACKUP_REMOVE_OLD} AUTO_BACKUP_DAYS_TO_LIVE=${AUTO_BACKUP_DAYS_TO_LIVE}"
+    AUTO_BACKUP_REMOVE_OLD AUTO_BACKUP_DAYS_TO_LIVE "${CRON_BACKUP_ENV[@]}"
+fi
+
+if [ "${AUTO_REPORT}" -eq 1 ]; then
+    log "Auto Report Enabled..."
+    log "Auto Report Schedule: ${AUTO_REPORT_SCHEDULE}"
+    AUTO_REPORT_SCHEDULE=$(echo "$AUTO_REPORT_SCHEDULE" | tr -d '"')
+    setup_cron                  \
+    "auto-report"               \
+    "auto_report.sh"            \
+    "${AUTO_REPORT_SCHEDULE}"   \
+    AUTO_REPORT_PERIOD_DAYS
 fi
 
 # Apply cron job
-if [ "${AUTO_BACKUP}" -eq 1 ] || [ "${AUTO_UPDATE}" -eq 1 ]; then
+if [ "${AUTO_BACKUP}" -eq 1 ] || [ "${AUTO_UPDATE}" -eq 1 ] || [ "${AUTO_REPORT}" -eq 1 ]; then
     cat /etc/cron.d/* | crontab -
     /usr/sbin/cron -f &
     export CRON_PID=$!
@@ -26,6 +26,9 @@ cleanup() {
         log "Backup on shutdown triggered! Running backup tool..."
         /bin/bash /home/steam/scripts/auto_backup.sh "shutdown"
     fi
+    if [[ -n $WATCH_PID ]];then
+      kill $WATCH_PID
+    fi
     if [[ -n $TAIL_PID ]];then
       kill $TAIL_PID
     fi
@@ -73,9 +76,25 @@ odin start || exit 1
 
 sleep 2
 
+# Odin stays with a server it watches, passes its output on to the container and sends the
+# notifications itself.
+ODIN_WATCHES_SERVER=0
+if [ -n "${ON_SERVER_EXIT:-}" ] || [ "${AUTO_RESTART:-0}" = "1" ]; then
+    ODIN_WATCHES_SERVER=1
+fi
+
+if [ "${ODIN_WATCHES_SERVER}" -eq 0 ] && { [ "${PLAYER_NOTIFICATIONS:=0}" -eq 1 ] || [ "${WORLD_SAVE_NOTIFICATIONS:=0}" -eq 1 ]; }; then
+    log "Watching the server log for notifications..."
+    odin watch &
+    export WATCH_PID=$!
+fi
+
 # Initializing all logs
 log "Herding Graydwarfs..."
-log_names=("valheim_server.log" "valheim_server.err" "output.log" "auto-update.out" "auto-backup.out")
+log_names=("output.log" "auto-update.out" "auto-backup.out")
+if [ "${ODIN_WATCHES_SERVER}" -eq 0 ]; then
+    log_names=("valheim_server.log" "valheim_server.err" "${log_names[@]}")
+fi
 log_files=("${log_names[@]/#/\/home\/steam\/valheim\/logs/}")
 touch "${log_files[@]}" # Destroy logs on start up, this can be changed later to roll logs or archive them.
 tail -F ${log_files[*]} &
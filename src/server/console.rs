@@ -0,0 +1,144 @@
+use log::{debug, warn};
+
+use std::{
+  ffi::CString,
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  os::unix::{
+    ffi::OsStrExt,
+    fs::{FileTypeExt, OpenOptionsExt},
+  },
+  path::{Path, PathBuf},
+  process::Stdio,
+};
+
+use crate::utils::get_odin_dir;
+
+const CONSOLE_FILE_NAME: &str = "console";
+
+/// The named pipe the server reads its stdin from. It outlives whichever odin started the server,
+/// so any odin can send commands for as long as the server runs.
+pub fn console_path() -> PathBuf {
+  get_odin_dir().join(CONSOLE_FILE_NAME)
+}
+
+fn create_fifo(path: &Path) -> io::Result<()> {
+  match fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.file_type().is_fifo() => return Ok(()),
+    Ok(_) => fs::remove_file(path)?,
+    Err(_) => {}
+  }
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let c_path = CString::new(path.as_os_str().as_bytes())
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+  if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } == 0 {
+    Ok(())
+  } else {
+    Err(io::Error::last_os_error())
+  }
+}
+
+/// Opened for reading and writing so the server never sees the end of its input when a command
+/// has been written and the writer goes away.
+fn open_fifo(path: &Path) -> io::Result<File> {
+  create_fifo(path)?;
+  OpenOptions::new().read(true).write(true).open(path)
+}
+
+/// stdin for the server process, falling back to none if the console can't be set up.
+pub fn console_stdin() -> Stdio {
+  match open_fifo(&console_path()) {
+    Ok(fifo) => {
+      debug!("Server console at {}", console_path().display());
+      Stdio::from(fifo)
+    }
+    Err(e) => {
+      warn!(
+        "Unable to set up the server console at {}, `odin cmd` won't work: {}",
+        console_path().display(),
+        e
+      );
+      Stdio::null()
+    }
+  }
+}
+
+fn write_command(path: &Path, command: &str) -> Result<(), String> {
+  let command = command.trim();
+  if command.is_empty() {
+    return Err("The command is empty".to_string());
+  }
+  if command.contains('\n') || command.contains('\r') {
+    return Err("Send one command at a time, it can't contain line breaks".to_string());
+  }
+  let not_listening = || {
+    format!(
+      "The server isn't listening for commands at {}, was it started by this version of odin?",
+      path.display()
+    )
+  };
+  match fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.file_type().is_fifo() => {}
+    _ => return Err(not_listening()),
+  }
+  // Non blocking, so nobody reading fails right away rather than hanging.
+  let mut console = OpenOptions::new()
+    .write(true)
+    .custom_flags(libc::O_NONBLOCK)
+    .open(path)
+    .map_err(|e| match e.raw_os_error() {
+      Some(libc::ENXIO) => not_listening(),
+      _ => format!("Unable to open {}: {}", path.display(), e),
+    })?;
+  console
+    .write_all(format!("{}\n", command).as_bytes())
+    .map_err(|e| format!("Failed to send '{}': {}", command, e))
+}
+
+/// Sends a line to the running server's console.
+pub fn send_command(command: &str) -> Result<(), String> {
+  write_command(&console_path(), command)
+}
+
+#[cfg(test)]
+mod console_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::io::{BufRead, BufReader};
+  use tempfile::TempDir;
+
+  fn test_console() -> (TempDir, PathBuf) {
+    let dir = test_dir("console");
+    let path = dir.path().join(CONSOLE_FILE_NAME);
+    (dir, path)
+  }
+
+  #[test]
+  fn commands_reach_the_reader() {
+    let (_dir, path) = test_console();
+    let server = open_fifo(&path).unwrap();
+    write_command(&path, "save").unwrap();
+    write_command(&path, " kick Ragnar ").unwrap();
+    let mut lines = BufReader::new(server).lines();
+    assert_eq!(lines.next().unwrap().unwrap(), "save");
+    assert_eq!(lines.next().unwrap().unwrap(), "kick Ragnar");
+  }
+
+  #[test]
+  fn fails_without_a_server() {
+    let (_dir, path) = test_console();
+    assert!(write_command(&path, "save").is_err());
+    create_fifo(&path).unwrap();
+    assert!(write_command(&path, "save").is_err());
+  }
+
+  #[test]
+  fn rejects_multiple_lines() {
+    let (_dir, path) = test_console();
+    let _server = open_fifo(&path).unwrap();
+    assert!(write_command(&path, "save\nkick Ragnar").is_err());
+    assert!(write_command(&path, "  ").is_err());
+  }
+}
@@ -0,0 +1,253 @@
+use log::{error, info, warn};
+use serde::Serialize;
+use sysinfo::{ProcessExt, System, SystemExt};
+
+use std::{
+  fmt, fs, thread,
+  time::{Duration, Instant},
+};
+
+use crate::{
+  constants,
+  files::state,
+  notifications::enums::{
+    notification_event::NotificationEvent, notification_level::NotificationLevel,
+  },
+  server::{is_running, server_log_path, LogParser, LogWatcher, ServerEvent},
+  utils::{environment::fetch_var, parse_duration},
+};
+
+/// How long a crossplay server may be ready over Steam without registering with PlayFab before
+/// odin warns about it, `0` never warns.
+pub const CROSSPLAY_REGISTRATION_TIMEOUT_VAR: &str = "CROSSPLAY_REGISTRATION_TIMEOUT";
+/// The launch flag that has the server register with PlayFab.
+const CROSSPLAY_FLAG: &str = "-crossplay";
+/// How often the server's log is read again while waiting for the registration.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What the server's log says about registering with PlayFab.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrossplayBackend {
+  Registered,
+  Failed,
+  /// Nothing was logged about it yet.
+  Unknown,
+}
+
+impl fmt::Display for CrossplayBackend {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CrossplayBackend::Registered => write!(f, "registered"),
+      CrossplayBackend::Failed => write!(f, "failed"),
+      CrossplayBackend::Unknown => write!(f, "unknown"),
+    }
+  }
+}
+
+impl CrossplayBackend {
+  /// The state `event` leaves the backend in, `None` when it says nothing about PlayFab.
+  pub fn after(event: &ServerEvent) -> Option<Self> {
+    match event {
+      ServerEvent::CrossplayRegistered => Some(CrossplayBackend::Registered),
+      ServerEvent::CrossplayFailed(_) => Some(CrossplayBackend::Failed),
+      _ => None,
+    }
+  }
+
+  /// The last word on PlayFab in `lines`, a registration after a failed attempt counts.
+  pub fn from_lines<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+    let mut parser = LogParser::default();
+    lines
+      .into_iter()
+      .filter_map(|line| parser.parse(line))
+      .filter_map(|event| Self::after(&event))
+      .last()
+      .unwrap_or(CrossplayBackend::Unknown)
+  }
+}
+
+/// What the server logged about PlayFab since it was last started.
+pub fn logged_crossplay_backend() -> CrossplayBackend {
+  fs::read_to_string(server_log_path())
+    .map(|log| CrossplayBackend::from_lines(log.lines()))
+    .unwrap_or(CrossplayBackend::Unknown)
+}
+
+/// Whether the running server was launched with `-crossplay`.
+pub fn runs_crossplay() -> bool {
+  let mut system = System::new();
+  system.refresh_processes();
+  system
+    .get_process_by_name(constants::VALHEIM_EXECUTABLE_NAME)
+    .iter()
+    .any(|process| process.cmd().iter().any(|arg| arg == CROSSPLAY_FLAG))
+}
+
+/// Waits for a crossplay server to register with PlayFab once it is ready over Steam. Steam
+/// players can join either way, so a server that never registers is otherwise easy to miss.
+pub struct CrossplayCheck {
+  pub(crate) after: Duration,
+  backend: CrossplayBackend,
+  last_failure: Option<String>,
+  ready_since: Option<Instant>,
+  warned: bool,
+}
+
+impl CrossplayCheck {
+  pub fn new(after: Duration) -> Self {
+    CrossplayCheck {
+      after,
+      backend: CrossplayBackend::Unknown,
+      last_failure: None,
+      ready_since: None,
+      warned: false,
+    }
+  }
+
+  /// `None` when `CROSSPLAY_REGISTRATION_TIMEOUT` is `0`.
+  pub fn from_env() -> Result<Option<Self>, String> {
+    let value = fetch_var(CROSSPLAY_REGISTRATION_TIMEOUT_VAR, "5m");
+    match parse_duration(&value).and_then(|after| after.to_std().ok()) {
+      Some(after) if after.as_secs() == 0 => Ok(None),
+      Some(after) => Ok(Some(Self::new(after))),
+      None => Err(format!(
+        "Invalid {} '{}', expected something like 5m or 300s",
+        CROSSPLAY_REGISTRATION_TIMEOUT_VAR, value
+      )),
+    }
+  }
+
+  pub fn observe(&mut self, event: &ServerEvent, now: Instant) {
+    match event {
+      ServerEvent::ServerReady => {
+        self.ready_since.get_or_insert(now);
+      }
+      ServerEvent::CrossplayRegistered => {
+        if self.warned {
+          info!("The server registered with PlayFab after all, crossplay players can join");
+        }
+        self.backend = CrossplayBackend::Registered;
+      }
+      ServerEvent::CrossplayFailed(message) => {
+        warn!("Registering with PlayFab failed: {}", message);
+        self.backend = CrossplayBackend::Failed;
+        self.last_failure = Some(message.clone());
+      }
+      _ => (),
+    }
+  }
+
+  pub fn is_registered(&self) -> bool {
+    self.backend == CrossplayBackend::Registered
+  }
+
+  /// The warning to send once the server has been ready for `after` without registering, only
+  /// the first time.
+  pub fn overdue(&mut self, now: Instant) -> Option<String> {
+    let since = self.ready_since?;
+    if self.warned || self.is_registered() || now.saturating_duration_since(since) < self.after {
+      return None;
+    }
+    self.warned = true;
+    Some(format!(
+      "Crossplay is on but the server didn't register with PlayFab within {} minute(s) of being ready, only Steam players can join{}",
+      (self.after.as_secs() / 60).max(1),
+      self
+        .last_failure
+        .as_ref()
+        .map_or_else(String::new, |failure| format!(". Last error: {}", failure))
+    ))
+  }
+}
+
+/// Watches the log of a server launched with `-crossplay` and sends a `Crossplay Warning` once it
+/// went `CROSSPLAY_REGISTRATION_TIMEOUT` without registering with PlayFab after it was ready.
+/// Returns once it registered or the server stopped.
+pub fn watch_crossplay_registration() {
+  if !runs_crossplay() {
+    return;
+  }
+  let mut check = match CrossplayCheck::from_env() {
+    Ok(Some(check)) => check,
+    Ok(None) => return,
+    Err(message) => {
+      error!("{}", message);
+      return;
+    }
+  };
+  info!(
+    "Crossplay: warning when the server doesn't register with PlayFab within {} minute(s) of being ready",
+    (check.after.as_secs() / 60).max(1)
+  );
+  let mut log = LogWatcher::for_server();
+  while is_running() {
+    let now = Instant::now();
+    for event in log.poll() {
+      check.observe(&event, now);
+    }
+    if check.is_registered() {
+      return;
+    }
+    if let Some(warning) = check.overdue(now) {
+      warn!("{}", warning);
+      state::record_warning(&warning);
+      NotificationEvent::Crossplay(NotificationLevel::Warning).send_notification();
+    }
+    thread::sleep(POLL_INTERVAL);
+  }
+}
+
+#[cfg(test)]
+mod crossplay_tests {
+  use super::*;
+
+  #[test]
+  fn tells_the_backend_from_the_log() {
+    assert_eq!(
+      CrossplayBackend::from_lines(vec![
+        "10/15/2026 12:00:30: Game server connected",
+        "10/15/2026 12:00:31: PlayFab: Failed to create the lobby",
+      ]),
+      CrossplayBackend::Failed
+    );
+    assert_eq!(
+      CrossplayBackend::from_lines(vec![
+        "10/15/2026 12:00:31: PlayFab: Failed to create the lobby",
+        "10/15/2026 12:01:31: Session \"Midgard\" with join code 482913 and IP 203.0.113.7:2456 is active with 0 player(s)",
+      ]),
+      CrossplayBackend::Registered
+    );
+    assert_eq!(
+      CrossplayBackend::from_lines(vec!["10/15/2026 12:00:30: Game server connected"]),
+      CrossplayBackend::Unknown
+    );
+    assert_eq!(
+      serde_json::to_string(&CrossplayBackend::Failed).unwrap(),
+      "\"failed\""
+    );
+  }
+
+  #[test]
+  fn warns_once_when_registration_is_overdue() {
+    let start = Instant::now();
+    let mut check = CrossplayCheck::new(Duration::from_secs(300));
+    assert_eq!(check.overdue(start + Duration::from_secs(3600)), None);
+    check.observe(&ServerEvent::ServerReady, start);
+    check.observe(
+      &ServerEvent::CrossplayFailed("PlayFab: Login failed".to_string()),
+      start,
+    );
+    assert_eq!(check.overdue(start + Duration::from_secs(299)), None);
+    let warning = check.overdue(start + Duration::from_secs(300)).unwrap();
+    assert!(warning.contains("within 5 minute(s)"), "{}", warning);
+    assert!(warning.ends_with("Last error: PlayFab: Login failed"));
+    assert_eq!(check.overdue(start + Duration::from_secs(900)), None);
+
+    let mut registered = CrossplayCheck::new(Duration::from_secs(300));
+    registered.observe(&ServerEvent::ServerReady, start);
+    registered.observe(&ServerEvent::CrossplayRegistered, start);
+    assert!(registered.is_registered());
+    assert_eq!(registered.overdue(start + Duration::from_secs(900)), None);
+  }
+}
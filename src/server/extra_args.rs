@@ -0,0 +1,204 @@
+use log::warn;
+
+use crate::utils::environment::fetch_var;
+
+/// Arguments added to the server's command line after odin's own, split like a shell would.
+pub const SERVER_EXTRA_ARGS_VAR: &str = "SERVER_EXTRA_ARGS";
+/// Read when `SERVER_EXTRA_ARGS` isn't set.
+pub const SERVER_EXTRA_LAUNCH_ARGS_VAR: &str = "SERVER_EXTRA_LAUNCH_ARGS";
+/// `1` passes on extra arguments odin sets itself instead of dropping them, like `--allow-overrides`.
+pub const SERVER_EXTRA_ARGS_ALLOW_OVERRIDES_VAR: &str = "SERVER_EXTRA_ARGS_ALLOW_OVERRIDES";
+
+/// The arguments odin launches the server with itself and how many values each takes.
+const MANAGED_ARGS: &[(&str, usize)] = &[
+  ("-nographics", 0),
+  ("-batchmode", 0),
+  ("-port", 1),
+  ("-name", 1),
+  ("-world", 1),
+  ("-password", 1),
+  ("-public", 1),
+  ("-crossplay", 0),
+  ("-preset", 1),
+  ("-modifier", 2),
+  ("-setkey", 1),
+  ("-saveinterval", 1),
+  ("-backups", 1),
+  ("-backupshort", 1),
+  ("-backuplong", 1),
+];
+
+/// Splits `value` on whitespace, keeping what is in single or double quotes together. A backslash
+/// escapes the next character, except inside single quotes.
+pub fn split_args(value: &str) -> Result<Vec<String>, String> {
+  let mut args = Vec::new();
+  let mut current = String::new();
+  // Tells `""` apart from no argument at all.
+  let mut in_arg = false;
+  let mut quote = None;
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    match (quote, c) {
+      (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+      (Some('\''), _) => current.push(c),
+      (_, '\\') => match chars.next() {
+        Some(escaped) => {
+          current.push(escaped);
+          in_arg = true;
+        }
+        None => return Err(format!("'{}' ends with a lone backslash", value)),
+      },
+      (Some(_), _) => current.push(c),
+      (None, '\'') | (None, '"') => {
+        quote = Some(c);
+        in_arg = true;
+      }
+      (None, c) if c.is_whitespace() => {
+        if in_arg {
+          args.push(std::mem::take(&mut current));
+          in_arg = false;
+        }
+      }
+      (None, _) => {
+        current.push(c);
+        in_arg = true;
+      }
+    }
+  }
+  if quote.is_some() {
+    return Err(format!("'{}' has an unterminated quote", value));
+  }
+  if in_arg {
+    args.push(current);
+  }
+  Ok(args)
+}
+
+/// Splits `args` into the ones kept and the odin managed ones dropped, with their values.
+pub fn without_managed_args(args: Vec<String>) -> (Vec<String>, Vec<String>) {
+  let mut kept = Vec::new();
+  let mut dropped = Vec::new();
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    let lowercase = arg.to_lowercase();
+    match MANAGED_ARGS.iter().find(|(name, _)| *name == lowercase) {
+      Some((_, values)) => {
+        dropped.push(arg);
+        dropped.extend(args.by_ref().take(*values));
+      }
+      None => kept.push(arg),
+    }
+  }
+  (kept, dropped)
+}
+
+/// `--extra-args`, or `SERVER_EXTRA_ARGS` when it isn't given, without anything odin already sets
+/// unless `allow_overrides`.
+pub fn extra_args(value: Option<&str>, allow_overrides: bool) -> Result<Vec<String>, String> {
+  let value = value.map_or_else(
+    || {
+      fetch_var(
+        SERVER_EXTRA_ARGS_VAR,
+        &fetch_var(SERVER_EXTRA_LAUNCH_ARGS_VAR, ""),
+      )
+    },
+    str::to_string,
+  );
+  let args = split_args(&value).map_err(|e| format!("Invalid {}: {}", SERVER_EXTRA_ARGS_VAR, e))?;
+  let (kept, dropped) = without_managed_args(args.clone());
+  if allow_overrides {
+    if !dropped.is_empty() {
+      warn!(
+        "Passing '{}' on to the server although odin sets those itself, the server sees both",
+        dropped.join(" ")
+      );
+    }
+    return Ok(args);
+  }
+  if !dropped.is_empty() {
+    warn!(
+      "Ignoring '{}' from the extra arguments, odin sets those itself. Use its settings instead, or --allow-overrides",
+      dropped.join(" ")
+    );
+  }
+  Ok(kept)
+}
+
+/// `args` as they would be typed in a shell, quoting the ones that need it.
+pub fn display_args<'a>(args: impl IntoIterator<Item = &'a str>) -> String {
+  args
+    .into_iter()
+    .map(|arg| {
+      let plain = |c: char| c.is_alphanumeric() || "-_./:=,+@%".contains(c);
+      if arg.is_empty() || !arg.chars().all(plain) {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+      } else {
+        arg.to_string()
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+#[cfg(test)]
+mod extra_args_tests {
+  use super::*;
+
+  #[test]
+  fn splits_like_a_shell() {
+    assert_eq!(
+      split_args(r#"-logFile "/home/steam/my logs/server.log"  -mod 'a "b"' c\ d """#).unwrap(),
+      vec![
+        "-logFile",
+        "/home/steam/my logs/server.log",
+        "-mod",
+        "a \"b\"",
+        "c d",
+        ""
+      ]
+    );
+    assert_eq!(split_args(r#""say \"hi\"""#).unwrap(), vec!["say \"hi\""]);
+    assert!(split_args("   ").unwrap().is_empty());
+    assert!(split_args("-name 'Odin").is_err());
+  }
+
+  #[test]
+  fn splits_escaped_quotes_and_empty_input() {
+    assert!(split_args("").unwrap().is_empty());
+    assert_eq!(
+      split_args(r#"-message \"quoted\" 'it'\''s' "a \\ b""#).unwrap(),
+      vec!["-message", "\"quoted\"", "it's", "a \\ b"]
+    );
+    assert_eq!(split_args("''").unwrap(), vec![""]);
+    assert!(split_args(r"-logFile \").is_err());
+    assert!(split_args(r#"-name "Odin"#).is_err());
+  }
+
+  #[test]
+  fn drops_what_odin_sets_itself() {
+    let args =
+      split_args("-instanceid 2 -Port 2457 -modifier raids none -nographics -logAppend").unwrap();
+    assert_eq!(
+      without_managed_args(args),
+      (
+        vec![
+          "-instanceid".to_string(),
+          "2".to_string(),
+          "-logAppend".to_string()
+        ],
+        vec![
+          "-Port".to_string(),
+          "2457".to_string(),
+          "-modifier".to_string(),
+          "raids".to_string(),
+          "none".to_string(),
+          "-nographics".to_string()
+        ]
+      )
+    );
+    assert_eq!(
+      display_args(vec!["-name", "My Server", "it's"]),
+      r#"-name 'My Server' 'it'\''s'"#
+    );
+  }
+}
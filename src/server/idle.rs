@@ -0,0 +1,168 @@
+use log::info;
+
+use std::time::{Duration, Instant};
+
+use crate::{server::ServerEvent, utils::environment::fetch_var};
+
+/// How long the server may be empty before a supervising odin stops it, unset or `0` leaves it up.
+pub const IDLE_SHUTDOWN_MINUTES_VAR: &str = "IDLE_SHUTDOWN_MINUTES";
+/// Odin exits with it after an idle shutdown, so an autoscaler or restart policy can tell it from a
+/// crash (`EX_TEMPFAIL`).
+pub const IDLE_SHUTDOWN_EXIT_CODE: i32 = 75;
+/// How often the countdown is logged while it runs.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Counts down while nobody is online, from the join and leave lines in the server's log. It only
+/// starts once the server logged that it is ready, a server still loading its world is never idle.
+pub struct IdleShutdown {
+  pub(crate) after: Duration,
+  ready: bool,
+  players: usize,
+  empty_since: Option<Instant>,
+  last_progress: Option<Instant>,
+}
+
+fn minutes(duration: Duration) -> u64 {
+  duration.as_secs() / 60
+}
+
+impl IdleShutdown {
+  pub fn new(after: Duration) -> Self {
+    IdleShutdown {
+      after,
+      ready: false,
+      players: 0,
+      empty_since: None,
+      last_progress: None,
+    }
+  }
+
+  /// `None` when `IDLE_SHUTDOWN_MINUTES` isn't set or is `0`.
+  pub fn from_env() -> Result<Option<Self>, String> {
+    let value = fetch_var(IDLE_SHUTDOWN_MINUTES_VAR, "0");
+    match value.trim().parse::<u64>() {
+      Ok(0) => Ok(None),
+      Ok(minutes) => Ok(Some(Self::new(Duration::from_secs(minutes * 60)))),
+      Err(_) => Err(format!(
+        "Invalid {} '{}', expected a number of minutes",
+        IDLE_SHUTDOWN_MINUTES_VAR, value
+      )),
+    }
+  }
+
+  fn start_countdown(&mut self, now: Instant, reason: &str) {
+    self.empty_since = Some(now);
+    self.last_progress = Some(now);
+    info!(
+      "Idle shutdown: {}, stopping the server in {} minute(s) unless someone joins",
+      reason,
+      minutes(self.after)
+    );
+  }
+
+  /// Keeps count of who is online from the server's log.
+  pub fn observe(&mut self, event: &ServerEvent, now: Instant) {
+    match event {
+      ServerEvent::ServerReady | ServerEvent::CrossplayRegistered if !self.ready => {
+        self.ready = true;
+        if self.players == 0 {
+          self.start_countdown(now, "the server is ready and nobody is online");
+        }
+      }
+      ServerEvent::PlayerConnected(name) => {
+        self.players += 1;
+        if self.empty_since.take().is_some() {
+          info!("Idle shutdown: {} joined, countdown reset", name);
+        }
+      }
+      ServerEvent::PlayerDisconnected(name) => {
+        self.players = self.players.saturating_sub(1);
+        if self.players == 0 && self.ready {
+          self.start_countdown(now, &format!("{} left and nobody is online", name));
+        }
+      }
+      _ => (),
+    }
+  }
+
+  /// For when the server says `players` are online while the log showed nobody, like players who
+  /// joined before odin counted them.
+  pub fn players_online(&mut self, players: usize) {
+    self.players = players;
+    if players > 0 && self.empty_since.take().is_some() {
+      info!(
+        "Idle shutdown: the server says {} player(s) are online, countdown reset",
+        players
+      );
+    }
+  }
+
+  /// Whether the server has been empty for long enough, logging the countdown now and then.
+  pub fn is_due(&mut self, now: Instant) -> bool {
+    let since = match self.empty_since {
+      Some(since) if self.ready && self.players == 0 => since,
+      _ => return false,
+    };
+    let empty_for = now.saturating_duration_since(since);
+    if empty_for >= self.after {
+      return true;
+    }
+    if self
+      .last_progress
+      .is_none_or(|last| now.saturating_duration_since(last) >= PROGRESS_INTERVAL)
+    {
+      self.last_progress = Some(now);
+      info!(
+        "Idle shutdown: empty for {} of {} minute(s)",
+        minutes(empty_for),
+        minutes(self.after)
+      );
+    }
+    false
+  }
+
+  /// How long the server has been empty.
+  pub fn empty_for(&self, now: Instant) -> Duration {
+    self
+      .empty_since
+      .map_or(Duration::ZERO, |since| now.saturating_duration_since(since))
+  }
+
+  /// For a new server process, which has to load its world before the countdown starts again.
+  pub fn reset(&mut self) {
+    self.ready = false;
+    self.players = 0;
+    self.empty_since = None;
+    self.last_progress = None;
+  }
+}
+
+#[cfg(test)]
+mod idle_tests {
+  use super::*;
+
+  #[test]
+  fn counts_down_only_while_empty_after_startup() {
+    let start = Instant::now();
+    let mut idle = IdleShutdown::new(Duration::from_secs(600));
+    let later = start + Duration::from_secs(3600);
+    assert!(!idle.is_due(later), "still loading the world");
+    idle.observe(&ServerEvent::ServerReady, start);
+    assert!(!idle.is_due(start + Duration::from_secs(599)));
+    idle.observe(
+      &ServerEvent::PlayerConnected("Ragnar".to_string()),
+      start + Duration::from_secs(599),
+    );
+    assert!(!idle.is_due(later));
+    idle.observe(
+      &ServerEvent::PlayerDisconnected("Ragnar".to_string()),
+      later,
+    );
+    assert!(!idle.is_due(later + Duration::from_secs(599)));
+    assert!(idle.is_due(later + Duration::from_secs(600)));
+    idle.players_online(2);
+    assert!(!idle.is_due(later + Duration::from_secs(900)));
+    idle.reset();
+    assert!(!idle.is_due(later + Duration::from_secs(9000)));
+  }
+}
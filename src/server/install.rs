@@ -1,34 +1,180 @@
-use log::{debug, info};
+use log::{debug, info, warn};
 
 use std::{
   io,
-  path::Path,
-  process::{ExitStatus, Stdio},
+  time::{Duration, Instant},
 };
 
 use crate::{
-  constants, executable::execute_mut, steamcmd::steamcmd_command, utils::get_working_dir,
+  constants,
+  notifications::enums::{event_status::EventStatus, notification_event::NotificationEvent},
+  steamcmd::{
+    cleanup_isolated_home, execute_with_progress, steamcmd_command, SteamcmdProgress, SteamcmdRun,
+  },
+  utils::{environment::fetch_var, get_working_dir, parse_duration},
 };
 
+/// How often an `Update` `Running` notification tells how far the download got, `0` turns them off.
+pub const UPDATE_PROGRESS_INTERVAL_VAR: &str = "WEBHOOK_UPDATE_PROGRESS_INTERVAL";
+const DEFAULT_UPDATE_PROGRESS_INTERVAL: &str = "5m";
+
+fn parse_progress_interval(value: &str) -> Result<Option<Duration>, String> {
+  if value.trim() == "0" {
+    return Ok(None);
+  }
+  parse_duration(value)
+    .and_then(|interval| interval.to_std().ok())
+    .filter(|interval| !interval.is_zero())
+    .map(Some)
+    .ok_or_else(|| {
+      format!(
+        "Invalid {} '{}', expected something like 90s or 5m",
+        UPDATE_PROGRESS_INTERVAL_VAR, value
+      )
+    })
+}
+
+/// Paces the progress notifications so a long download doesn't flood the webhook.
+struct ProgressNotifications {
+  interval: Option<Duration>,
+  last: Instant,
+  last_percent: Option<u64>,
+}
+
+impl ProgressNotifications {
+  fn new(interval: Option<Duration>, started: Instant) -> Self {
+    ProgressNotifications {
+      interval,
+      last: started,
+      last_percent: None,
+    }
+  }
+
+  fn from_env() -> Self {
+    let value = fetch_var(
+      UPDATE_PROGRESS_INTERVAL_VAR,
+      DEFAULT_UPDATE_PROGRESS_INTERVAL,
+    );
+    let interval = parse_progress_interval(&value).unwrap_or_else(|message| {
+      // Never worth failing an update over.
+      warn!("{}, using {}", message, DEFAULT_UPDATE_PROGRESS_INTERVAL);
+      parse_progress_interval(DEFAULT_UPDATE_PROGRESS_INTERVAL).unwrap()
+    });
+    Self::new(interval, Instant::now())
+  }
+
+  /// Whether `progress` gets a notification, once every interval and only when it moved on.
+  fn is_due(&mut self, progress: &SteamcmdProgress, now: Instant) -> bool {
+    let interval = match self.interval {
+      Some(interval) => interval,
+      None => return false,
+    };
+    let percent = progress.percent as u64;
+    if now.duration_since(self.last) < interval || self.last_percent == Some(percent) {
+      return false;
+    }
+    self.last = now;
+    self.last_percent = Some(percent);
+    true
+  }
+}
+
 pub fn is_installed() -> bool {
-  Path::new(&get_working_dir())
+  get_working_dir()
     .join(constants::VALHEIM_EXECUTABLE_NAME)
     .exists()
 }
 
-pub fn install(app_id: i64) -> io::Result<ExitStatus> {
-  info!("Installing {} to {}", app_id, get_working_dir());
-
-  let login = "+login anonymous".to_string();
-  let force_install_dir = format!("+force_install_dir {}", get_working_dir());
-  let app_update = format!("+app_update {}", app_id);
-  let mut steamcmd = steamcmd_command();
-  let install_command = steamcmd
-    .args(&[login, force_install_dir, app_update])
-    .arg("+quit")
-    .stdout(Stdio::inherit())
-    .stderr(Stdio::inherit());
+/// The arguments steamcmd is run with to install or update `app_id`. With `validate` it checks
+/// every installed file too, and downloads again the ones that don't match.
+pub fn install_args(app_id: i64, validate: bool) -> Vec<String> {
+  vec![
+    "+login anonymous".to_string(),
+    format!("+force_install_dir {}", get_working_dir().display()),
+    if validate {
+      format!("+app_update {} validate", app_id)
+    } else {
+      format!("+app_update {}", app_id)
+    },
+    "+quit".to_string(),
+  ]
+}
+
+/// Installs or updates `app_id` with steamcmd. Webhooks hear that it started and, every
+/// `WEBHOOK_UPDATE_PROGRESS_INTERVAL`, how far the download got. Whoever called it tells them how
+/// it ended.
+pub fn install(app_id: i64, validate: bool) -> io::Result<SteamcmdRun> {
+  info!("Installing {} to {}", app_id, get_working_dir().display());
+  if validate {
+    info!("Validating the installed files, the ones that don't match are downloaded again");
+  }
+  NotificationEvent::Update(EventStatus::Running)
+    .send_notification_with_details("Downloading the server files");
+  let mut notifications = ProgressNotifications::from_env();
+
+  let mut steamcmd = steamcmd_command().map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?;
+  let install_command = steamcmd.args(install_args(app_id, validate));
   debug!("Launching install command: {:#?}", install_command);
 
-  execute_mut(install_command)
+  let result = execute_with_progress(install_command, |progress| {
+    if notifications.is_due(progress, Instant::now()) {
+      NotificationEvent::Update(EventStatus::Running)
+        .send_notification_with_details(&format!("Server files are {}", progress));
+    }
+  });
+  cleanup_isolated_home();
+  result
+}
+
+#[cfg(test)]
+mod install_tests {
+  use super::*;
+  use serial_test::serial;
+  use std::env;
+
+  #[test]
+  fn paces_progress_notifications() {
+    assert_eq!(parse_progress_interval("0"), Ok(None));
+    assert_eq!(
+      parse_progress_interval("90s"),
+      Ok(Some(Duration::from_secs(90)))
+    );
+    assert!(parse_progress_interval("soon").is_err());
+
+    let progress = |percent: f64| SteamcmdProgress {
+      state: "downloading".to_string(),
+      percent,
+      done_bytes: 0,
+      total_bytes: 0,
+    };
+    let started = Instant::now();
+    let minute = Duration::from_secs(60);
+    let mut notifications = ProgressNotifications::new(Some(minute), started);
+    assert!(!notifications.is_due(&progress(10.0), started + minute / 2));
+    assert!(notifications.is_due(&progress(20.0), started + minute));
+    assert!(!notifications.is_due(&progress(30.0), started + minute * 3 / 2));
+    assert!(notifications.is_due(&progress(40.0), started + minute * 2));
+    // Stuck at the same percent, nothing new to tell.
+    assert!(!notifications.is_due(&progress(40.5), started + minute * 3));
+
+    let mut off = ProgressNotifications::new(None, started);
+    assert!(!off.is_due(&progress(50.0), started + minute * 60));
+  }
+
+  #[test]
+  #[serial]
+  fn installs_into_the_working_dir() {
+    env::set_var(constants::ODIN_WORKING_DIR, "/home/steam/valheim");
+    assert_eq!(
+      install_args(896660, false),
+      vec![
+        "+login anonymous",
+        "+force_install_dir /home/steam/valheim",
+        "+app_update 896660",
+        "+quit"
+      ]
+    );
+    assert_eq!(install_args(896660, true)[2], "+app_update 896660 validate");
+    env::remove_var(constants::ODIN_WORKING_DIR);
+  }
 }
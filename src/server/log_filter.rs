@@ -0,0 +1,188 @@
+use log::info;
+use regex::Regex;
+
+use std::{
+  io::{self, BufRead, BufReader, Read},
+  time::{Duration, Instant},
+};
+
+use crate::utils::environment::fetch_var;
+
+/// `1` drops the empty lines from the server's output.
+pub const LOG_FILTER_EMPTY_VAR: &str = "VALHEIM_LOG_FILTER_EMPTY";
+/// `;` separated regexes, a line matching any of them is dropped. `default` stands for the
+/// built-in ones and `none` turns the filtering off.
+pub const LOG_FILTER_PATTERNS_VAR: &str = "VALHEIM_LOG_FILTER_PATTERNS";
+/// How often the dropped lines are counted up in the log.
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Longer lines are passed on in pieces, so a line without an end can't fill the memory.
+const MAX_LINE_LENGTH: u64 = 64 * 1024;
+
+/// What Unity prints after nearly every line, and other chatter nobody reads.
+const DEFAULT_PATTERNS: &[&str] = &[
+  r"^\(Filename: .* Line: -?\d+\)$",
+  r"^Fallback handler could not load library",
+  r"^Unloading \d+ [Uu]nused (Serialized files|Assets)",
+  r"^Total: .* ms \(FindLiveObjects",
+  r"^UnloadTime: ",
+];
+
+/// Which lines of the server's output are noise.
+#[derive(Clone, Debug)]
+pub struct LogFilter {
+  pub(crate) empty: bool,
+  pub(crate) patterns: Vec<Regex>,
+}
+
+fn format_count(count: u64) -> String {
+  let digits = count.to_string();
+  let mut formatted = String::new();
+  for (i, digit) in digits.chars().enumerate() {
+    if i > 0 && (digits.len() - i).is_multiple_of(3) {
+      formatted.push(',');
+    }
+    formatted.push(digit);
+  }
+  formatted
+}
+
+impl LogFilter {
+  pub fn parse(empty: &str, patterns: &str) -> Result<Self, Vec<String>> {
+    let mut problems = Vec::new();
+    let mut filter = LogFilter {
+      empty: empty.trim() == "1",
+      patterns: Vec::new(),
+    };
+    for pattern in patterns.split(';').map(str::trim) {
+      match pattern {
+        "" | "none" => (),
+        "default" => filter.patterns.extend(
+          DEFAULT_PATTERNS
+            .iter()
+            .map(|pattern| Regex::new(pattern).unwrap()),
+        ),
+        pattern => match Regex::new(pattern) {
+          Ok(regex) => filter.patterns.push(regex),
+          Err(e) => problems.push(format!("Invalid pattern '{}': {}", pattern, e)),
+        },
+      }
+    }
+    if problems.is_empty() {
+      Ok(filter)
+    } else {
+      Err(problems)
+    }
+  }
+
+  pub fn from_env() -> Result<Self, Vec<String>> {
+    Self::parse(
+      &fetch_var(LOG_FILTER_EMPTY_VAR, "0"),
+      &fetch_var(LOG_FILTER_PATTERNS_VAR, "default"),
+    )
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.empty || !self.patterns.is_empty()
+  }
+
+  pub fn is_noise(&self, line: &str) -> bool {
+    if line.trim().is_empty() {
+      return self.empty;
+    }
+    self.patterns.iter().any(|pattern| pattern.is_match(line))
+  }
+
+  /// Hands `input` to `pass_on` line by line, without the line ending, leaving out the noise.
+  /// Returns how many lines were left out, every `SUMMARY_INTERVAL` and at the end it logs how many
+  /// if any.
+  pub fn filter<R, F>(&self, input: R, mut pass_on: F) -> io::Result<u64>
+  where
+    R: Read,
+    F: FnMut(&str) -> io::Result<()>,
+  {
+    let mut input = BufReader::new(input);
+    let mut line = Vec::new();
+    let mut total = 0;
+    let mut suppressed = 0;
+    let mut since = Instant::now();
+    loop {
+      line.clear();
+      if (&mut input)
+        .take(MAX_LINE_LENGTH)
+        .read_until(b'\n', &mut line)?
+        == 0
+      {
+        break;
+      }
+      let text = String::from_utf8_lossy(&line);
+      let text = text.trim_end_matches(&['\r', '\n'][..]);
+      if self.is_noise(text) {
+        suppressed += 1;
+        total += 1;
+      } else {
+        pass_on(text)?;
+      }
+      if since.elapsed() >= SUMMARY_INTERVAL {
+        report_suppressed(suppressed, "in the last 10 minutes");
+        suppressed = 0;
+        since = Instant::now();
+      }
+    }
+    report_suppressed(suppressed, "before it closed its output");
+    Ok(total)
+  }
+}
+
+fn report_suppressed(count: u64, when: &str) {
+  if count > 0 {
+    info!(
+      "Suppressed {} noise lines from the server {}",
+      format_count(count),
+      when
+    );
+  }
+}
+
+#[cfg(test)]
+mod log_filter_tests {
+  use super::*;
+
+  #[test]
+  fn drops_only_the_noise() {
+    let filter = LogFilter::parse("1", "default").unwrap();
+    let output = "10/15/2026 12:00:00: Game server connected\n\
+      (Filename: ./Runtime/Export/Debug/Debug.bindings.h Line: 35)\n\
+      \n\
+      Fallback handler could not load library /valheim/Valheim_Data/Mono/libc\n\
+      10/15/2026 12:00:01: Got character ZDOID from Odin : 1:1\n\
+      \r\n";
+    let mut kept = Vec::new();
+    let suppressed = filter
+      .filter(output.as_bytes(), |line| {
+        kept.push(line.to_string());
+        Ok(())
+      })
+      .unwrap();
+    assert_eq!(suppressed, 4);
+    assert_eq!(
+      kept,
+      vec![
+        "10/15/2026 12:00:00: Game server connected",
+        "10/15/2026 12:00:01: Got character ZDOID from Odin : 1:1"
+      ]
+    );
+  }
+
+  #[test]
+  fn parses_the_patterns() {
+    let filter = LogFilter::parse("0", r"none; ^Steam; \[Info\s*: BepInEx\]").unwrap();
+    assert_eq!(filter.patterns.len(), 2);
+    assert!(filter.is_noise("[Info   : BepInEx] Loading plugins"));
+    assert!(!filter.is_noise(""));
+    assert!(!LogFilter::parse("0", "none").unwrap().is_enabled());
+    assert_eq!(LogFilter::parse("0", "default").unwrap().patterns.len(), 5);
+    assert!(LogFilter::parse("0", "default;(unclosed").is_err());
+    assert_eq!(format_count(1234567), "1,234,567");
+    assert_eq!(format_count(999), "999");
+  }
+}
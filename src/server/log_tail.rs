@@ -0,0 +1,173 @@
+use std::{
+  fs::{self, File, Metadata},
+  io::{self, Read, Seek, SeekFrom},
+  os::unix::fs::MetadataExt,
+  path::{Path, PathBuf},
+};
+
+use crate::utils::get_working_dir;
+
+/// Where the daemonized server's stdout goes.
+pub fn server_log_path() -> PathBuf {
+  get_working_dir().join("logs").join("valheim_server.log")
+}
+
+/// Where the daemonized server's stderr goes.
+pub fn server_error_log_path() -> PathBuf {
+  get_working_dir().join("logs").join("valheim_server.err")
+}
+
+/// How much `previous_lines` reads at a time, going backwards from the end.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Tells one file from another at the same path.
+fn file_id(meta: &Metadata) -> (u64, u64) {
+  (meta.dev(), meta.ino())
+}
+
+/// Follows a log file like `tail -F`, picking up from where the file ended when it was opened.
+pub struct LogTail {
+  path: PathBuf,
+  position: u64,
+  /// The file last read, to notice it being replaced.
+  file_id: Option<(u64, u64)>,
+  /// The end of the last read when it stopped mid line.
+  partial: String,
+}
+
+impl LogTail {
+  pub fn from_end(path: &Path) -> Self {
+    let meta = fs::metadata(path).ok();
+    LogTail {
+      path: path.to_path_buf(),
+      position: meta.as_ref().map(|meta| meta.len()).unwrap_or(0),
+      file_id: meta.as_ref().map(file_id),
+      partial: String::new(),
+    }
+  }
+
+  /// Up to `count` complete lines before where the tail starts, oldest first. A line still being
+  /// written is kept to complete with the next read.
+  pub fn previous_lines(&mut self, count: usize) -> io::Result<Vec<String>> {
+    let mut file = File::open(&self.path)?;
+    let mut start = self.position;
+    let mut buffer = Vec::new();
+    while start > 0 && buffer.iter().filter(|byte| **byte == b'\n').count() <= count {
+      let size = start.min(CHUNK_SIZE);
+      start -= size;
+      let mut chunk = vec![0; size as usize];
+      file.seek(SeekFrom::Start(start))?;
+      file.read_exact(&mut chunk)?;
+      chunk.extend_from_slice(&buffer);
+      buffer = chunk;
+    }
+    let text = String::from_utf8_lossy(&buffer);
+    let complete = match text.rfind('\n') {
+      Some(end) => {
+        self.partial = text[end + 1..].to_string();
+        &text[..end]
+      }
+      None => {
+        self.partial = text.to_string();
+        return Ok(Vec::new());
+      }
+    };
+    let lines: Vec<String> = complete
+      .lines()
+      .map(|line| line.trim_end_matches('\r').to_string())
+      .filter(|line| !line.is_empty())
+      .collect();
+    let skipped = lines.len().saturating_sub(count);
+    Ok(lines.into_iter().skip(skipped).collect())
+  }
+
+  /// The lines written since the last call. A file that shrank or was replaced, the server
+  /// truncates its log on every start, is read again from the beginning.
+  pub fn read_lines(&mut self) -> Vec<String> {
+    let mut file = match File::open(&self.path) {
+      Ok(file) => file,
+      Err(_) => return Vec::new(),
+    };
+    let meta = file.metadata().ok();
+    let length = meta.as_ref().map(|meta| meta.len()).unwrap_or(0);
+    let id = meta.as_ref().map(file_id);
+    if length < self.position || (self.file_id.is_some() && id != self.file_id) {
+      self.position = 0;
+      self.partial.clear();
+    }
+    self.file_id = id;
+    let mut buffer = Vec::new();
+    if file.seek(SeekFrom::Start(self.position)).is_err() || file.read_to_end(&mut buffer).is_err()
+    {
+      return Vec::new();
+    }
+    self.position += buffer.len() as u64;
+    self.partial.push_str(&String::from_utf8_lossy(&buffer));
+    let complete = match self.partial.rfind('\n') {
+      Some(end) => end,
+      None => return Vec::new(),
+    };
+    let rest = self.partial.split_off(complete + 1);
+    let lines = self
+      .partial
+      .lines()
+      .map(|line| line.trim_end_matches('\r').to_string())
+      .filter(|line| !line.is_empty())
+      .collect();
+    self.partial = rest;
+    lines
+  }
+}
+
+#[cfg(test)]
+mod log_tail_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::io::Write;
+
+  #[test]
+  fn follows_new_lines_and_restarts() {
+    let temp = test_dir("log-tail");
+    let path = temp.path().join("log-tail.log");
+    fs::write(&path, "before the tail\n").unwrap();
+    let mut tail = LogTail::from_end(&path);
+    assert!(tail.read_lines().is_empty());
+
+    let mut log = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    write!(log, "first\nsecond, half").unwrap();
+    assert_eq!(tail.read_lines(), vec!["first"]);
+    writeln!(log, " done").unwrap();
+    assert_eq!(tail.read_lines(), vec!["second, half done"]);
+
+    fs::write(&path, "after a restart\n").unwrap();
+    assert_eq!(tail.read_lines(), vec!["after a restart"]);
+
+    // A rotated log is picked up even when the new one is already longer than the old.
+    let rotated = path.with_extension("log.1");
+    fs::rename(&path, &rotated).unwrap();
+    fs::write(&path, "a new log, longer than the one rotated away\n").unwrap();
+    assert_eq!(
+      tail.read_lines(),
+      vec!["a new log, longer than the one rotated away"]
+    );
+  }
+
+  #[test]
+  fn reads_the_lines_before_the_tail() {
+    let temp = test_dir("log-tail");
+    let path = temp.path().join("previous.log");
+    let lines: Vec<String> = (0..20_000).map(|i| format!("line {}", i)).collect();
+    fs::write(&path, format!("{}\nhalf a li", lines.join("\n"))).unwrap();
+    let mut tail = LogTail::from_end(&path);
+    assert_eq!(
+      tail.previous_lines(3).unwrap(),
+      vec!["line 19997", "line 19998", "line 19999"]
+    );
+    assert_eq!(tail.previous_lines(20_000).unwrap(), lines);
+    assert!(tail.previous_lines(0).unwrap().is_empty());
+
+    let mut log = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    writeln!(log, "ne").unwrap();
+    assert_eq!(tail.read_lines(), vec!["half a line"]);
+  }
+}
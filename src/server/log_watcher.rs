@@ -0,0 +1,425 @@
+use chrono::NaiveDateTime;
+
+use std::{
+  fmt, fs,
+  path::PathBuf,
+  sync::{
+    mpsc::{self, Receiver, Sender},
+    Mutex,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::notifications::enums::notification_event::NotificationEvent;
+use crate::server::{
+  owns_server_output, server_error_log_path, server_log_path, LogTail, PlayerTracker,
+};
+
+/// Every `LogWatcher` in this odin following the server's output as odin passes it on.
+static FOLLOWERS: Mutex<Vec<Sender<String>>> = Mutex::new(Vec::new());
+
+/// `World saved ( 42.177ms )`, written after every save.
+const WORLD_SAVED_MARKER: &str = "World saved (";
+/// `DungeonDB Start 3150`, once the dungeons are loaded while the world comes up.
+const DUNGEON_LOADED_MARKER: &str = "DungeonDB Start";
+/// Logged once the server is registered with Steam and players can join.
+const SERVER_READY_MARKER: &str = "Game server connected";
+/// `Session "My Server" with join code 123456 and IP ...`, logged once PlayFab took the server.
+const JOIN_CODE_MARKER: &str = "with join code";
+/// `Session "My Server" registered with PlayFab`, what some crossplay builds log in its place.
+const SESSION_MARKER: &str = "Session ";
+const SESSION_REGISTERED_MARKER: &str = " registered";
+/// PlayFab lines that mention one of these say registering for crossplay went wrong, like
+/// `PlayFab: Failed to create the lobby` or `ZPlayFabMatchmaking error: ServiceUnavailable`.
+const PLAYFAB_MARKER: &str = "playfab";
+const PLAYFAB_FAILURE_MARKERS: &[&str] = &["fail", "error"];
+/// How far back `last_world_save` looks, so a huge log can't hold up whoever asks.
+const SAVE_SEARCH_LINES: usize = 20_000;
+
+/// Something the server did, read from its log.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerEvent {
+  /// How long the save took, when the line says.
+  WorldSaved(Option<Duration>),
+  DungeonLoaded,
+  /// Registered with Steam, Steam players can join.
+  ServerReady,
+  /// Registered with PlayFab, crossplay players can join too.
+  CrossplayRegistered,
+  /// What the server logged about PlayFab failing.
+  CrossplayFailed(String),
+  /// A character spawned for the first time since its player connected.
+  PlayerConnected(String),
+  PlayerDisconnected(String),
+}
+
+impl ServerEvent {
+  /// The notification sent for this event, if it has one.
+  pub fn notification_event(&self) -> Option<NotificationEvent> {
+    match self {
+      ServerEvent::PlayerConnected(_) => Some(NotificationEvent::PlayerJoined),
+      ServerEvent::PlayerDisconnected(_) => Some(NotificationEvent::PlayerLeft),
+      ServerEvent::WorldSaved(_) => Some(NotificationEvent::WorldSaved),
+      _ => None,
+    }
+  }
+
+  /// Whether players can join, over Steam or crossplay.
+  pub fn is_ready(&self) -> bool {
+    matches!(
+      self,
+      ServerEvent::ServerReady | ServerEvent::CrossplayRegistered
+    )
+  }
+}
+
+impl fmt::Display for ServerEvent {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ServerEvent::WorldSaved(Some(took)) => {
+        write!(f, "World saved in {:.0}ms", took.as_secs_f64() * 1000.0)
+      }
+      ServerEvent::WorldSaved(None) => write!(f, "World saved"),
+      ServerEvent::DungeonLoaded => write!(f, "Dungeons loaded"),
+      ServerEvent::ServerReady => write!(f, "Server is ready for players"),
+      ServerEvent::CrossplayRegistered => write!(f, "Server registered with PlayFab for crossplay"),
+      ServerEvent::CrossplayFailed(message) => {
+        write!(f, "Registering with PlayFab failed: {}", message)
+      }
+      ServerEvent::PlayerConnected(name) => write!(f, "{} joined the server", name),
+      ServerEvent::PlayerDisconnected(name) => write!(f, "{} left the server", name),
+    }
+  }
+}
+
+/// The `42.177` in `World saved ( 42.177ms )`.
+fn save_duration(line: &str) -> Option<Duration> {
+  let start = line.find(WORLD_SAVED_MARKER)? + WORLD_SAVED_MARKER.len();
+  let millis = line[start..].trim_start().split("ms").next()?.trim();
+  millis
+    .parse::<f64>()
+    .ok()
+    .filter(|millis| millis.is_finite() && *millis >= 0.0)
+    .map(|millis| Duration::from_secs_f64(millis / 1000.0))
+}
+
+/// Whether `line` says the server registered with PlayFab, so crossplay players can join.
+fn is_crossplay_registered_line(line: &str) -> bool {
+  if line.contains(JOIN_CODE_MARKER) {
+    return true;
+  }
+  line
+    .find(SESSION_MARKER)
+    .is_some_and(|start| line[start..].contains(SESSION_REGISTERED_MARKER))
+}
+
+/// What the server logged about PlayFab failing, if `line` says that. Only the server's own
+/// lines count, they start with the time it logged them, odin's output can end up in the same
+/// file and mentions PlayFab too.
+fn crossplay_failure(line: &str) -> Option<&str> {
+  parse_log_time(line)?;
+  let message = line[19..].trim_start_matches(':').trim();
+  let lowercase = message.to_lowercase();
+  let failed = lowercase.contains(PLAYFAB_MARKER)
+    && PLAYFAB_FAILURE_MARKERS
+      .iter()
+      .any(|marker| lowercase.contains(marker));
+  failed.then_some(message)
+}
+
+/// Turns server log lines into `ServerEvent`s. Player events need the lines before them, so one
+/// parser has to see every line in order.
+#[derive(Default)]
+pub struct LogParser {
+  players: PlayerTracker,
+}
+
+impl LogParser {
+  pub fn parse(&mut self, line: &str) -> Option<ServerEvent> {
+    if line.contains(WORLD_SAVED_MARKER) {
+      return Some(ServerEvent::WorldSaved(save_duration(line)));
+    }
+    if line.contains(DUNGEON_LOADED_MARKER) {
+      return Some(ServerEvent::DungeonLoaded);
+    }
+    if is_crossplay_registered_line(line) {
+      return Some(ServerEvent::CrossplayRegistered);
+    }
+    if line.contains(SERVER_READY_MARKER) {
+      return Some(ServerEvent::ServerReady);
+    }
+    if let Some(message) = crossplay_failure(line) {
+      return Some(ServerEvent::CrossplayFailed(message.to_string()));
+    }
+    self.players.observe(line)
+  }
+}
+
+/// Whether the server said it is ready since it was last started, `odin start` begins a new log
+/// every time.
+pub fn server_logged_ready() -> bool {
+  let mut parser = LogParser::default();
+  fs::read_to_string(server_log_path())
+    .map(|log| {
+      log
+        .lines()
+        .any(|line| parser.parse(line).is_some_and(|event| event.is_ready()))
+    })
+    .unwrap_or(false)
+}
+
+/// Hands a line of the server's output to every `LogWatcher` following it in this odin.
+pub fn publish_server_line(line: &str) {
+  if let Ok(mut followers) = FOLLOWERS.lock() {
+    // Watchers that were dropped stop listening.
+    followers.retain(|follower| follower.send(line.to_string()).is_ok());
+  }
+}
+
+/// Where a `LogWatcher` reads the server's lines from.
+enum LogSource {
+  Files(Vec<LogTail>),
+  /// The lines odin passes on itself, without reading them back from the rotated log files.
+  Output(Receiver<String>),
+}
+
+/// The `10/15/2026 12:00:00` the server starts its lines with.
+pub fn parse_log_time(line: &str) -> Option<NaiveDateTime> {
+  NaiveDateTime::parse_from_str(line.get(..19)?, "%m/%d/%Y %H:%M:%S").ok()
+}
+
+/// When the server last logged saving the world since it was started, `None` when it hasn't or
+/// the line doesn't say when.
+pub fn last_world_save() -> Option<NaiveDateTime> {
+  LogTail::from_end(&server_log_path())
+    .previous_lines(SAVE_SEARCH_LINES)
+    .ok()?
+    .iter()
+    .rev()
+    .find(|line| line.contains(WORLD_SAVED_MARKER))
+    .and_then(|line| parse_log_time(line))
+}
+
+/// Follows the server's stdout and stderr logs, the files `odin start` sends them to, and hands
+/// every event it finds to whoever is subscribed.
+pub struct LogWatcher {
+  source: LogSource,
+  parser: LogParser,
+}
+
+impl LogWatcher {
+  /// Only sees what the server logs from now on.
+  pub fn new(paths: &[PathBuf]) -> Self {
+    LogWatcher {
+      source: LogSource::Files(paths.iter().map(|path| LogTail::from_end(path)).collect()),
+      parser: LogParser::default(),
+    }
+  }
+
+  /// Follows the lines given to `publish_server_line` from now on.
+  pub fn following_output() -> Self {
+    let (sender, receiver) = mpsc::channel();
+    if let Ok(mut followers) = FOLLOWERS.lock() {
+      followers.push(sender);
+    }
+    LogWatcher {
+      source: LogSource::Output(receiver),
+      parser: LogParser::default(),
+    }
+  }
+
+  /// Reads what odin passes on when the server's output goes through it, the log files otherwise.
+  pub fn for_server() -> Self {
+    if owns_server_output() {
+      Self::following_output()
+    } else {
+      Self::new(&[server_log_path(), server_error_log_path()])
+    }
+  }
+
+  /// The events logged since the last poll.
+  pub fn poll(&mut self) -> Vec<ServerEvent> {
+    let lines: Vec<String> = match &mut self.source {
+      LogSource::Files(tails) => tails
+        .iter_mut()
+        .flat_map(|tail| tail.read_lines())
+        .collect(),
+      LogSource::Output(receiver) => receiver.try_iter().collect(),
+    };
+    let parser = &mut self.parser;
+    lines.iter().filter_map(|line| parser.parse(line)).collect()
+  }
+
+  /// Calls `on_event` with every event as it is logged, polling every `interval` until
+  /// `should_stop` says so or `timeout` runs out.
+  pub fn watch<S, F>(
+    &mut self,
+    interval: Duration,
+    timeout: Option<Duration>,
+    should_stop: S,
+    mut on_event: F,
+  ) where
+    S: Fn() -> bool,
+    F: FnMut(&ServerEvent),
+  {
+    let started = Instant::now();
+    while !should_stop() && timeout.is_none_or(|timeout| started.elapsed() < timeout) {
+      for event in self.poll() {
+        on_event(&event);
+      }
+      thread::sleep(interval);
+    }
+  }
+}
+
+#[cfg(test)]
+mod log_watcher_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::{fs, io::Write};
+
+  #[test]
+  fn parses_known_lines() {
+    let mut parser = LogParser::default();
+    let events: Vec<ServerEvent> = [
+      "02/20/2021 19:31:02: DungeonDB Start 3150",
+      "02/20/2021 19:31:04: Game server connected",
+      "02/20/2021 19:32:05: Got connection SteamID 76561198000000001",
+      "02/20/2021 19:32:20: Got character ZDOID from Ragnar : -1328425869:1",
+      "02/20/2021 19:50:00: World saved ( 42.177ms )",
+      "02/20/2021 19:51:00: Closing socket 76561198000000001",
+      "02/20/2021 19:52:00: Some other line",
+    ]
+    .iter()
+    .filter_map(|line| parser.parse(line))
+    .collect();
+    assert_eq!(
+      events,
+      vec![
+        ServerEvent::DungeonLoaded,
+        ServerEvent::ServerReady,
+        ServerEvent::PlayerConnected("Ragnar".to_string()),
+        ServerEvent::WorldSaved(Some(Duration::from_micros(42177))),
+        ServerEvent::PlayerDisconnected("Ragnar".to_string()),
+      ]
+    );
+    assert_eq!(events[3].to_string(), "World saved in 42ms");
+    assert_eq!(
+      parse_log_time("02/20/2021 19:50:00: World saved ( 42.177ms )")
+        .unwrap()
+        .to_string(),
+      "2021-02-20 19:50:00"
+    );
+    assert_eq!(parse_log_time("World saved ( 42.177ms )"), None);
+    assert_eq!(
+      parser.parse("World saved (weird)"),
+      Some(ServerEvent::WorldSaved(None))
+    );
+    assert_eq!(
+      parser.parse(
+        "02/20/2021 19:31:04: Session \"My Server\" with join code 123456 and IP 1.2.3.4:2456 is active with 0 player(s)"
+      ),
+      Some(ServerEvent::CrossplayRegistered)
+    );
+    assert!(ServerEvent::CrossplayRegistered.is_ready());
+  }
+
+  #[test]
+  fn parses_playfab_registration() {
+    let mut parser = LogParser::default();
+    let events: Vec<ServerEvent> = [
+      "10/15/2026 12:00:20: PlayFab: Registering session",
+      "10/15/2026 12:00:25: PlayFab: Failed to create the lobby, ServiceUnavailable",
+      "10/15/2026 12:00:26: ZPlayFabMatchmaking error: Timeout",
+      "10/15/2026 12:00:30: Game server connected",
+      "10/15/2026 12:00:31: Session \"Midgard\" registered with PlayFab",
+      "10/15/2026 12:00:40: Failed to load the texture",
+      "[WARN ] - Registering with PlayFab failed: PlayFab: Login failed",
+    ]
+    .iter()
+    .filter_map(|line| parser.parse(line))
+    .collect();
+    assert_eq!(
+      events,
+      vec![
+        ServerEvent::CrossplayFailed(
+          "PlayFab: Failed to create the lobby, ServiceUnavailable".to_string()
+        ),
+        ServerEvent::CrossplayFailed("ZPlayFabMatchmaking error: Timeout".to_string()),
+        ServerEvent::ServerReady,
+        ServerEvent::CrossplayRegistered,
+      ]
+    );
+  }
+
+  #[test]
+  fn tells_when_players_can_join() {
+    let ready = |line| {
+      LogParser::default()
+        .parse(line)
+        .is_some_and(|event| event.is_ready())
+    };
+    for line in [
+      "02/20/2021 19:31:04: Game server connected",
+      "10/15/2026 12:00:31: Session \"Midgard\" with join code 482913 and IP 203.0.113.7:2456 is active with 0 player(s)",
+      "10/15/2026 12:00:31: Session \"Midgard\" registered with PlayFab",
+    ] {
+      assert!(ready(line), "{}", line);
+    }
+    for line in [
+      "02/20/2021 19:31:01: Steam game server initialized",
+      "10/15/2026 12:00:20: PlayFab: Registering session",
+      "10/15/2026 12:00:20: Registered 2 custom RPCs for the Session",
+      "10/15/2026 12:00:30: Game server connecting",
+    ] {
+      assert!(!ready(line), "{}", line);
+    }
+  }
+
+  #[test]
+  fn delivers_events_from_every_log() {
+    let temp = test_dir("log-watcher");
+    let root = temp.path();
+    let (stdout, stderr) = (root.join("server.log"), root.join("server.err"));
+    fs::write(&stdout, "DungeonDB Start 1\n").unwrap();
+    let mut watcher = LogWatcher::new(&[stdout.clone(), stderr.clone()]);
+    fs::OpenOptions::new()
+      .append(true)
+      .open(&stdout)
+      .unwrap()
+      .write_all(b"World saved ( 1.5ms )\n")
+      .unwrap();
+    fs::write(&stderr, "Game server connected\n").unwrap();
+
+    let mut seen = Vec::new();
+    watcher.watch(
+      Duration::from_millis(1),
+      Some(Duration::from_millis(20)),
+      || false,
+      |event| seen.push(event.clone()),
+    );
+    assert_eq!(
+      seen,
+      vec![
+        ServerEvent::WorldSaved(Some(Duration::from_micros(1500))),
+        ServerEvent::ServerReady
+      ]
+    );
+  }
+
+  #[test]
+  fn follows_the_output_odin_passes_on() {
+    publish_server_line("World saved ( 1.5ms )");
+    let mut watcher = LogWatcher::following_output();
+    publish_server_line("DungeonDB Start 1");
+    publish_server_line("Game server connected");
+    assert_eq!(
+      watcher.poll(),
+      vec![ServerEvent::DungeonLoaded, ServerEvent::ServerReady]
+    );
+    assert!(watcher.poll().is_empty());
+    drop(watcher);
+    publish_server_line("World saved ( 1.5ms )");
+  }
+}
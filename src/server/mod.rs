@@ -1,8 +1,30 @@
+mod console;
+mod crossplay;
+mod extra_args;
+mod idle;
 mod install;
+mod log_filter;
+mod log_tail;
+mod log_watcher;
+mod password;
+mod players;
+mod ports;
+mod query;
+mod restart;
+mod save_settings;
+mod server_output;
 mod shutdown;
 mod startup;
+mod supervisor;
 mod update;
 mod utils;
+mod watchdog;
+mod world_modifiers;
 
 // Rexport all public functions
-pub use crate::server::{install::*, shutdown::*, startup::*, update::*, utils::*};
+pub use crate::server::{
+  console::*, crossplay::*, extra_args::*, idle::*, install::*, log_filter::*, log_tail::*,
+  log_watcher::*, password::*, players::*, ports::*, query::*, restart::*, save_settings::*,
+  server_output::*, shutdown::*, startup::*, supervisor::*, update::*, utils::*,
+  world_modifiers::*,
+};
@@ -0,0 +1,146 @@
+use std::{
+  fmt,
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::utils::environment::fetch_var;
+
+/// Set to `1` to only warn about passwords breaking the rules, like `--ignore-password-validation`.
+pub const IGNORE_PASSWORD_VALIDATION_VAR: &str = "IGNORE_PASSWORD_VALIDATION";
+/// Valheim counts the password's length like C# does, in UTF-16 code units.
+pub const MIN_PASSWORD_LENGTH: usize = 5;
+
+static IGNORE_PASSWORD_VALIDATION: AtomicBool = AtomicBool::new(false);
+
+/// A rule of Valheim's that the password breaks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PasswordProblem {
+  /// A public server needs a password, only private ones may go without.
+  MissingOnPublicServer,
+  TooShort(usize),
+  RepeatedCharacter,
+  /// Valheim refuses to start when the server name contains the password.
+  PartOfName(String),
+  /// Players may not be able to type it in the game.
+  NotAscii,
+}
+
+impl fmt::Display for PasswordProblem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PasswordProblem::MissingOnPublicServer => write!(
+        f,
+        "A public server needs a password of {} characters or greater, Valheim refuses to start without one. Set PUBLIC to 0 for a private server without a password",
+        MIN_PASSWORD_LENGTH
+      ),
+      PasswordProblem::TooShort(length) => write!(
+        f,
+        "The password must be {} characters or greater, it has {}",
+        MIN_PASSWORD_LENGTH, length
+      ),
+      PasswordProblem::RepeatedCharacter => {
+        write!(f, "The password can't be a single repeated character")
+      }
+      PasswordProblem::PartOfName(name) => write!(
+        f,
+        "The password can't be part of the server name '{}', Valheim refuses to start. Change the password or the NAME",
+        name
+      ),
+      PasswordProblem::NotAscii => write!(
+        f,
+        "The password should only use ASCII letters, digits and symbols, players may not be able to type anything else in the game"
+      ),
+    }
+  }
+}
+
+/// Every rule `password` breaks for a server called `name`.
+pub fn check_password(password: &str, name: &str, public: bool) -> Vec<PasswordProblem> {
+  let mut problems = Vec::new();
+  if password.is_empty() {
+    // A private server on the LAN can go without one.
+    if public {
+      problems.push(PasswordProblem::MissingOnPublicServer);
+    }
+    return problems;
+  }
+  let length = password.encode_utf16().count();
+  if length < MIN_PASSWORD_LENGTH {
+    problems.push(PasswordProblem::TooShort(length));
+  }
+  let first = password.chars().next().unwrap();
+  if password.chars().all(|c| c == first) {
+    problems.push(PasswordProblem::RepeatedCharacter);
+  }
+  if name.to_lowercase().contains(&password.to_lowercase()) {
+    problems.push(PasswordProblem::PartOfName(name.to_string()));
+  }
+  if !password
+    .chars()
+    .all(|c| c.is_ascii() && !c.is_ascii_control())
+  {
+    problems.push(PasswordProblem::NotAscii);
+  }
+  problems
+}
+
+/// Passwords breaking the rules only get a warning from here on, in case Valheim's rules change.
+pub fn ignore_password_validation() {
+  IGNORE_PASSWORD_VALIDATION.store(true, Ordering::Relaxed);
+}
+
+pub fn is_password_validation_ignored() -> bool {
+  IGNORE_PASSWORD_VALIDATION.load(Ordering::Relaxed)
+    || fetch_var(IGNORE_PASSWORD_VALIDATION_VAR, "0") == "1"
+}
+
+#[cfg(test)]
+mod password_tests {
+  use super::*;
+
+  #[test]
+  fn rejects_passwords_in_the_name() {
+    assert_eq!(
+      check_password("viking", "My Viking Server", true),
+      vec![PasswordProblem::PartOfName("My Viking Server".to_string())]
+    );
+    assert!(check_password("vikings", "My Viking Server", true).is_empty());
+  }
+
+  #[test]
+  fn lets_private_servers_go_without_one() {
+    assert!(check_password("", "Odin", false).is_empty());
+    assert_eq!(
+      check_password("", "Odin", true),
+      vec![PasswordProblem::MissingOnPublicServer]
+    );
+    assert_eq!(
+      check_password("abc", "Odin", false),
+      vec![PasswordProblem::TooShort(3)]
+    );
+  }
+
+  #[test]
+  fn checks_unicode_passwords() {
+    assert_eq!(
+      check_password("skål!", "Odin", true),
+      vec![PasswordProblem::NotAscii]
+    );
+    // Two UTF-16 code units each, like Valheim counts them.
+    assert_eq!(
+      check_password("🪓🪓🛡", "Odin", true),
+      vec![PasswordProblem::NotAscii]
+    );
+    assert_eq!(
+      check_password("🪓🛡", "Odin", true),
+      vec![PasswordProblem::TooShort(4), PasswordProblem::NotAscii]
+    );
+    assert_eq!(
+      check_password("ååååå", "Odin", true),
+      vec![
+        PasswordProblem::RepeatedCharacter,
+        PasswordProblem::NotAscii
+      ]
+    );
+  }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use crate::server::ServerEvent;
+
+/// Logged when a client opens a connection, before it has picked a character.
+const CONNECTION_MARKER: &str = "Got connection SteamID ";
+/// Logged when a character spawns, `Got character ZDOID from <name> : <id>:<n>`. A `0:0` id is the
+/// character dying.
+const CHARACTER_MARKER: &str = "Got character ZDOID from ";
+const DEAD_CHARACTER_ID: &str = "0:0";
+/// Logged when a client disconnects.
+const DISCONNECT_MARKER: &str = "Closing socket ";
+
+/// Pieces together who is online from the server log. Valheim logs the Steam id when a client
+/// connects and disconnects, but the character name only once it spawns, so a connection is
+/// named by the first new character that spawns after it.
+#[derive(Default)]
+pub struct PlayerTracker {
+  /// Steam ids that connected but haven't spawned a character yet, oldest first.
+  connecting: Vec<String>,
+  /// Character names by Steam id.
+  online: HashMap<String, String>,
+}
+
+impl PlayerTracker {
+  pub fn observe(&mut self, line: &str) -> Option<ServerEvent> {
+    if let Some(steam_id) = after(line, CONNECTION_MARKER) {
+      self.connecting.push(steam_id.to_string());
+      return None;
+    }
+    if let Some(rest) = after_full(line, CHARACTER_MARKER) {
+      let (name, id) = rest.rsplit_once(" : ")?;
+      let name = name.trim();
+      if id.trim() == DEAD_CHARACTER_ID || self.online.values().any(|online| online == name) {
+        // Dying and respawning, not joining.
+        return None;
+      }
+      let steam_id = if self.connecting.is_empty() {
+        // Connected before odin started watching, named after the character instead.
+        name.to_string()
+      } else {
+        self.connecting.remove(0)
+      };
+      self.online.insert(steam_id, name.to_string());
+      return Some(ServerEvent::PlayerConnected(name.to_string()));
+    }
+    if let Some(steam_id) = after(line, DISCONNECT_MARKER) {
+      self.connecting.retain(|connecting| connecting != steam_id);
+      return self
+        .online
+        .remove(steam_id)
+        .map(ServerEvent::PlayerDisconnected);
+    }
+    None
+  }
+}
+
+/// The first word after `marker`.
+fn after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+  after_full(line, marker)?.split_whitespace().next()
+}
+
+fn after_full<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+  line.find(marker).map(|start| &line[start + marker.len()..])
+}
+
+#[cfg(test)]
+mod players_tests {
+  use super::*;
+
+  fn observe_all(tracker: &mut PlayerTracker, lines: &[&str]) -> Vec<ServerEvent> {
+    lines
+      .iter()
+      .filter_map(|line| tracker.observe(line))
+      .collect()
+  }
+
+  #[test]
+  fn names_connections_by_their_character() {
+    let mut tracker = PlayerTracker::default();
+    let events = observe_all(
+      &mut tracker,
+      &[
+        "02/20/2021 19:32:05: Got connection SteamID 76561198000000001",
+        "02/20/2021 19:32:07: Got connection SteamID 76561198000000002",
+        "02/20/2021 19:32:20: Got character ZDOID from Bjorn Ironside : -1328425869:1",
+        "02/20/2021 19:32:25: Got character ZDOID from Lagertha : 225614318:1",
+        "02/20/2021 19:35:00: Got character ZDOID from Bjorn Ironside : 0:0",
+        "02/20/2021 19:35:20: Got character ZDOID from Bjorn Ironside : -1328425869:7",
+        "02/20/2021 19:40:01: Closing socket 76561198000000002",
+        "02/20/2021 19:41:00: Closing socket 76561198000000003",
+      ],
+    );
+    assert_eq!(
+      events,
+      vec![
+        ServerEvent::PlayerConnected("Bjorn Ironside".to_string()),
+        ServerEvent::PlayerConnected("Lagertha".to_string()),
+        ServerEvent::PlayerDisconnected("Lagertha".to_string()),
+      ]
+    );
+    assert_eq!(events[0].to_string(), "Bjorn Ironside joined the server");
+  }
+
+  #[test]
+  fn ignores_players_leaving_before_they_spawn() {
+    let mut tracker = PlayerTracker::default();
+    let events = observe_all(
+      &mut tracker,
+      &[
+        "Got connection SteamID 76561198000000001",
+        "Closing socket 76561198000000001",
+        "Got connection SteamID 76561198000000002",
+        "Got character ZDOID from Floki : 1:1",
+      ],
+    );
+    assert_eq!(
+      events,
+      vec![ServerEvent::PlayerConnected("Floki".to_string())]
+    );
+    assert_eq!(
+      tracker.observe("Closing socket 76561198000000002"),
+      Some(ServerEvent::PlayerDisconnected("Floki".to_string()))
+    );
+  }
+}
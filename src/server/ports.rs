@@ -0,0 +1,153 @@
+use std::{
+  fs,
+  io::ErrorKind,
+  net::{SocketAddr, UdpSocket},
+  path::Path,
+};
+
+use crate::server::query_port;
+use crate::utils::environment::fetch_var;
+
+/// The port the server listens on for players, over `PORT` and the one odin was configured with.
+pub const GAME_PORT_VAR: &str = "GAME_PORT";
+
+/// Below are the ports only root may bind.
+pub const MIN_GAME_PORT: u16 = 1024;
+/// The server also listens on the ports after the game port.
+pub const MAX_GAME_PORT: u16 = u16::MAX - 2;
+
+/// `GAME_PORT` when it is set, otherwise `port`.
+pub fn game_port(port: &str) -> String {
+  fetch_var(GAME_PORT_VAR, port)
+}
+
+/// The game port and the query port, `QUERY_PORT` or the one after the game port.
+pub fn server_ports(game_port: u16) -> [u16; 2] {
+  let query = query_port(&game_port.to_string()).unwrap_or(game_port.saturating_add(1));
+  [game_port, query]
+}
+
+/// Whether servers on these game ports would share a port, each may listen on the two after it.
+pub fn ports_overlap(game_port: u16, other_game_port: u16) -> bool {
+  game_port.abs_diff(other_game_port) <= u16::MAX - MAX_GAME_PORT
+}
+
+/// Which of the server's UDP ports something on this host already listens on. The server's own
+/// error for that only says it couldn't start.
+pub fn ports_in_use(game_port: u16) -> Vec<u16> {
+  server_ports(game_port)
+    .iter()
+    .copied()
+    .filter(|port| {
+      matches!(
+        UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], *port))),
+        Err(e) if e.kind() == ErrorKind::AddrInUse
+      )
+    })
+    .collect()
+}
+
+/// The inodes of the UDP sockets bound to `port` in a `/proc/net/udp` table.
+fn socket_inodes(table: &str, port: u16) -> Vec<u64> {
+  table
+    .lines()
+    .skip(1)
+    .filter_map(|line| {
+      let fields: Vec<&str> = line.split_whitespace().collect();
+      // `0100007F:0998`, the port in hex after the address.
+      let local_port = fields.get(1)?.rsplit_once(':')?.1;
+      let inode = fields.get(9)?.parse::<u64>().ok()?;
+      (u16::from_str_radix(local_port, 16).ok()? == port && inode != 0).then_some(inode)
+    })
+    .collect()
+}
+
+/// The process holding one of `inodes` open, with its name.
+fn socket_owner(proc: &Path, inodes: &[u64]) -> Option<(u32, String)> {
+  let links: Vec<String> = inodes
+    .iter()
+    .map(|inode| format!("socket:[{}]", inode))
+    .collect();
+  fs::read_dir(proc).ok()?.flatten().find_map(|entry| {
+    let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+    let holds_socket = fs::read_dir(entry.path().join("fd"))
+      .ok()?
+      .flatten()
+      .any(|fd| {
+        fs::read_link(fd.path())
+          .is_ok_and(|target| links.iter().any(|link| target.as_os_str() == link.as_str()))
+      });
+    let name = fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+    holds_socket.then(|| (pid, name.trim().to_string()))
+  })
+}
+
+/// The pid and name of the process bound to UDP `port`, when this host's `/proc` tells. Processes
+/// of other users can't be looked into without root.
+pub fn port_owner(port: u16) -> Option<(u32, String)> {
+  let proc = Path::new("/proc");
+  let inodes: Vec<u64> = ["udp", "udp6"]
+    .iter()
+    .filter_map(|table| fs::read_to_string(proc.join("net").join(table)).ok())
+    .flat_map(|table| socket_inodes(&table, port))
+    .collect();
+  if inodes.is_empty() {
+    return None;
+  }
+  socket_owner(proc, &inodes)
+}
+
+/// Why the server's ports can't be bound, one line per port in use naming what holds it.
+pub fn describe_ports_in_use(game_port: u16) -> Vec<String> {
+  ports_in_use(game_port)
+    .into_iter()
+    .map(|port| match port_owner(port) {
+      Some((pid, name)) => format!(
+        "Port {}/udp is already in use by {} (pid {})",
+        port, name, pid
+      ),
+      None => format!(
+        "Port {}/udp is already in use by a process odin can't see",
+        port
+      ),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod ports_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::os::unix::fs::symlink;
+
+  #[test]
+  fn finds_bound_ports() {
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let port = socket.local_addr().unwrap().port();
+    assert_eq!(ports_in_use(port), vec![port]);
+    assert_eq!(server_ports(2456), [2456, 2457]);
+    assert!(ports_overlap(2456, 2457));
+    assert!(ports_overlap(2457, 2456));
+    assert!(ports_overlap(2456, 2458));
+    assert!(!ports_overlap(2456, 2459));
+  }
+
+  #[test]
+  fn finds_who_holds_a_port() {
+    let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  1: 00000000:0998 00000000:0000 07 00000000:00000000 00:00000000 00000000  1000        0 4242 2 0000000000000000 0
+  2: 00000000:0999 00000000:0000 07 00000000:00000000 00:00000000 00000000  1000        0 4343 2 0000000000000000 0";
+    assert_eq!(socket_inodes(table, 2456), vec![4242]);
+    assert!(socket_inodes(table, 2458).is_empty());
+    let temp = test_dir("proc");
+    let proc = temp.path();
+    fs::create_dir_all(proc.join("42").join("fd")).unwrap();
+    fs::write(proc.join("42").join("comm"), "valheim_server.\n").unwrap();
+    symlink("socket:[4242]", proc.join("42").join("fd").join("7")).unwrap();
+    assert_eq!(
+      socket_owner(proc, &[4242]),
+      Some((42, "valheim_server.".to_string()))
+    );
+    assert_eq!(socket_owner(proc, &[4343]), None);
+  }
+}
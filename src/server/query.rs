@@ -0,0 +1,366 @@
+use std::{
+  io,
+  net::{SocketAddr, UdpSocket},
+  time::{Duration, Instant},
+};
+
+use crate::utils::environment::fetch_var;
+
+/// Steam answers queries one port above the game port.
+pub const QUERY_PORT_OFFSET: u16 = 1;
+/// The port the server answers queries on, when it isn't the one above the game port.
+pub const QUERY_PORT_VAR: &str = "QUERY_PORT";
+
+const PACKET_HEADER: [u8; 4] = [0xFF; 4];
+const INFO_REQUEST: u8 = 0x54;
+const INFO_PAYLOAD: &[u8] = b"Source Engine Query\0";
+const INFO_RESPONSE: u8 = 0x49;
+const PLAYER_REQUEST: u8 = 0x55;
+const PLAYER_RESPONSE: u8 = 0x44;
+const CHALLENGE_RESPONSE: u8 = 0x41;
+/// Sent in place of a challenge to ask for one.
+const NO_CHALLENGE: [u8; 4] = [0xFF; 4];
+
+/// What a server says about itself in its A2S_INFO reply.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerInfo {
+  pub(crate) name: String,
+  pub(crate) map: String,
+  pub(crate) players: u8,
+  pub(crate) max_players: u8,
+  pub(crate) version: String,
+}
+
+/// One player in the A2S_PLAYER reply. Valheim leaves the names empty unless a mod fills them in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlayerInfo {
+  pub(crate) name: String,
+  pub(crate) score: i32,
+  pub(crate) connected: Duration,
+}
+
+#[derive(Debug, PartialEq)]
+enum InfoReply {
+  /// The server wants the request again with this challenge appended.
+  Challenge([u8; 4]),
+  Info(ServerInfo),
+}
+
+/// `QUERY_PORT` when it is set, otherwise the port above `game_port`. `None` when either isn't a
+/// port.
+fn resolve_query_port(query_port: &str, game_port: &str) -> Option<u16> {
+  match query_port.trim() {
+    "" => game_port
+      .trim()
+      .parse::<u16>()
+      .ok()?
+      .checked_add(QUERY_PORT_OFFSET),
+    query_port => query_port.parse::<u16>().ok().filter(|port| *port > 0),
+  }
+}
+
+/// The query port for `game_port`, `None` when it isn't a port.
+pub fn query_port(game_port: &str) -> Option<u16> {
+  resolve_query_port(&fetch_var(QUERY_PORT_VAR, ""), game_port)
+}
+
+/// Whether the query port is set with `QUERY_PORT` rather than derived from the game port.
+pub fn is_query_port_set() -> bool {
+  !fetch_var(QUERY_PORT_VAR, "").trim().is_empty()
+}
+
+fn info_request(challenge: Option<&[u8; 4]>) -> Vec<u8> {
+  let mut request = PACKET_HEADER.to_vec();
+  request.push(INFO_REQUEST);
+  request.extend_from_slice(INFO_PAYLOAD);
+  if let Some(challenge) = challenge {
+    request.extend_from_slice(challenge);
+  }
+  request
+}
+
+fn player_request(challenge: Option<&[u8; 4]>) -> Vec<u8> {
+  let mut request = PACKET_HEADER.to_vec();
+  request.push(PLAYER_REQUEST);
+  request.extend_from_slice(challenge.unwrap_or(&NO_CHALLENGE));
+  request
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads the fields of a reply one at a time.
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+  fn byte(&mut self) -> io::Result<u8> {
+    let (first, rest) = self
+      .bytes
+      .split_first()
+      .ok_or_else(|| invalid("the reply ended early"))?;
+    self.bytes = rest;
+    Ok(*first)
+  }
+
+  fn four_bytes(&mut self) -> io::Result<[u8; 4]> {
+    let mut bytes = [0; 4];
+    for byte in bytes.iter_mut() {
+      *byte = self.byte()?;
+    }
+    Ok(bytes)
+  }
+
+  fn skip(&mut self, count: usize) -> io::Result<()> {
+    if self.bytes.len() < count {
+      return Err(invalid("the reply ended early"));
+    }
+    self.bytes = &self.bytes[count..];
+    Ok(())
+  }
+
+  fn string(&mut self) -> io::Result<String> {
+    let end = self
+      .bytes
+      .iter()
+      .position(|byte| *byte == 0)
+      .ok_or_else(|| invalid("a string in the reply isn't terminated"))?;
+    let value = String::from_utf8_lossy(&self.bytes[..end]).to_string();
+    self.bytes = &self.bytes[end + 1..];
+    Ok(value)
+  }
+}
+
+fn parse_reply(reply: &[u8]) -> io::Result<InfoReply> {
+  let body = reply
+    .strip_prefix(&PACKET_HEADER[..])
+    .ok_or_else(|| invalid("the reply isn't a single packet"))?;
+  let mut reader = Reader { bytes: body };
+  match reader.byte()? {
+    CHALLENGE_RESPONSE => Ok(InfoReply::Challenge(reader.four_bytes()?)),
+    INFO_RESPONSE => {
+      // Protocol version.
+      reader.skip(1)?;
+      let name = reader.string()?;
+      let map = reader.string()?;
+      // Folder and game.
+      reader.string()?;
+      reader.string()?;
+      // Steam app id.
+      reader.skip(2)?;
+      let players = reader.byte()?;
+      let max_players = reader.byte()?;
+      // Bots, server type, environment, visibility and VAC.
+      reader.skip(5)?;
+      let version = reader.string()?;
+      Ok(InfoReply::Info(ServerInfo {
+        name,
+        map,
+        players,
+        max_players,
+        version,
+      }))
+    }
+    kind => Err(invalid(&format!("unexpected reply type 0x{:02X}", kind))),
+  }
+}
+
+/// The players in an A2S_PLAYER reply, or the challenge to send the request again with.
+fn parse_player_reply(reply: &[u8]) -> io::Result<Result<Vec<PlayerInfo>, [u8; 4]>> {
+  let body = reply
+    .strip_prefix(&PACKET_HEADER[..])
+    .ok_or_else(|| invalid("the reply isn't a single packet"))?;
+  let mut reader = Reader { bytes: body };
+  match reader.byte()? {
+    CHALLENGE_RESPONSE => Ok(Err(reader.four_bytes()?)),
+    PLAYER_RESPONSE => {
+      let count = reader.byte()?;
+      let mut players = Vec::with_capacity(count as usize);
+      for _ in 0..count {
+        // Index, always 0.
+        reader.skip(1)?;
+        let name = reader.string()?;
+        let score = i32::from_le_bytes(reader.four_bytes()?);
+        let seconds = f32::from_le_bytes(reader.four_bytes()?);
+        players.push(PlayerInfo {
+          name,
+          score,
+          connected: Duration::from_secs_f32(seconds.max(0.0)),
+        });
+      }
+      Ok(Ok(players))
+    }
+    kind => Err(invalid(&format!("unexpected reply type 0x{:02X}", kind))),
+  }
+}
+
+/// Sends `request` to `address` and reads the answer, sending it again once with the challenge the
+/// server asks for. Returns the answer and how long its round trip took.
+fn exchange<T>(
+  address: SocketAddr,
+  timeout: Duration,
+  request: impl Fn(Option<&[u8; 4]>) -> Vec<u8>,
+  parse: impl Fn(&[u8]) -> io::Result<Result<T, [u8; 4]>>,
+) -> io::Result<(T, Duration)> {
+  let socket = UdpSocket::bind(if address.is_ipv4() {
+    "0.0.0.0:0"
+  } else {
+    "[::]:0"
+  })?;
+  socket.set_read_timeout(Some(timeout))?;
+  socket.connect(address)?;
+  let mut challenge = None;
+  let mut buffer = [0; 1400];
+  // A server hands out one challenge, a second means it isn't taking the first.
+  for _ in 0..2 {
+    let sent = Instant::now();
+    socket.send(&request(challenge.as_ref()))?;
+    let received = socket.recv(&mut buffer)?;
+    let round_trip = sent.elapsed();
+    match parse(&buffer[..received])? {
+      Ok(answer) => return Ok((answer, round_trip)),
+      Err(next) => challenge = Some(next),
+    }
+  }
+  Err(invalid("the server kept answering with a challenge"))
+}
+
+/// Asks the server at `address` for its A2S_INFO, answering a challenge if it sends one. Also
+/// returns the round trip of the answer, the server's ping.
+pub fn query_info_with_ping(
+  address: SocketAddr,
+  timeout: Duration,
+) -> io::Result<(ServerInfo, Duration)> {
+  exchange(address, timeout, info_request, |reply| {
+    parse_reply(reply).map(|reply| match reply {
+      InfoReply::Info(info) => Ok(info),
+      InfoReply::Challenge(challenge) => Err(challenge),
+    })
+  })
+}
+
+/// Asks the server at `address` for its A2S_INFO, answering a challenge if it sends one.
+pub fn query_info(address: SocketAddr, timeout: Duration) -> io::Result<ServerInfo> {
+  query_info_with_ping(address, timeout).map(|(info, _)| info)
+}
+
+/// Asks the server at `address` who is online with A2S_PLAYER.
+pub fn query_players(address: SocketAddr, timeout: Duration) -> io::Result<Vec<PlayerInfo>> {
+  exchange(address, timeout, player_request, parse_player_reply).map(|(players, _)| players)
+}
+
+#[cfg(test)]
+mod query_tests {
+  use super::*;
+  use std::thread;
+
+  fn info_reply() -> Vec<u8> {
+    let mut reply = PACKET_HEADER.to_vec();
+    reply.extend_from_slice(&[INFO_RESPONSE, 17]);
+    reply.extend_from_slice(b"My Server\0Dedicated\0valheim\0Valheim\0");
+    reply.extend_from_slice(&[0, 0, 3, 10, 0, b'd', b'l', 0, 0]);
+    reply.extend_from_slice(b"0.217.46\0");
+    reply
+  }
+
+  #[test]
+  fn query_port_is_one_above_the_game_port() {
+    assert_eq!(resolve_query_port("", "2456"), Some(2457));
+    assert_eq!(resolve_query_port("", "65535"), None);
+    assert_eq!(resolve_query_port("", "port"), None);
+    assert_eq!(resolve_query_port("27015", "2456"), Some(27015));
+    assert_eq!(resolve_query_port("0", "2456"), None);
+    assert_eq!(resolve_query_port("query", "2456"), None);
+  }
+
+  #[test]
+  fn parses_info_and_challenge_replies() {
+    assert_eq!(
+      parse_reply(&info_reply()).unwrap(),
+      InfoReply::Info(ServerInfo {
+        name: "My Server".to_string(),
+        map: "Dedicated".to_string(),
+        players: 3,
+        max_players: 10,
+        version: "0.217.46".to_string(),
+      })
+    );
+    assert_eq!(
+      parse_reply(&[0xFF, 0xFF, 0xFF, 0xFF, CHALLENGE_RESPONSE, 1, 2, 3, 4]).unwrap(),
+      InfoReply::Challenge([1, 2, 3, 4])
+    );
+    let reply = info_reply();
+    assert!(parse_reply(&reply[..reply.len() - 3]).is_err());
+    assert!(parse_reply(b"garbage").is_err());
+  }
+
+  #[test]
+  fn parses_player_replies() {
+    // Two players as Valheim sends them, without names, then one with a name.
+    let mut reply = PACKET_HEADER.to_vec();
+    reply.extend_from_slice(&[PLAYER_RESPONSE, 2]);
+    reply.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0x00, 0x00, 0x70, 0x42]);
+    reply.extend_from_slice(&[0, b'V', b'i', b'k', 0, 5, 0, 0, 0, 0x00, 0x00, 0x80, 0x3F]);
+    let players = parse_player_reply(&reply).unwrap().unwrap();
+    assert_eq!(
+      players,
+      vec![
+        PlayerInfo {
+          name: String::new(),
+          score: 0,
+          connected: Duration::from_secs(60),
+        },
+        PlayerInfo {
+          name: "Vik".to_string(),
+          score: 5,
+          connected: Duration::from_secs(1),
+        },
+      ]
+    );
+    assert_eq!(
+      parse_player_reply(&[0xFF, 0xFF, 0xFF, 0xFF, CHALLENGE_RESPONSE, 1, 2, 3, 4]).unwrap(),
+      Err([1, 2, 3, 4])
+    );
+    assert!(parse_player_reply(&reply[..reply.len() - 2]).is_err());
+    assert_eq!(
+      player_request(None),
+      vec![
+        0xFF,
+        0xFF,
+        0xFF,
+        0xFF,
+        PLAYER_REQUEST,
+        0xFF,
+        0xFF,
+        0xFF,
+        0xFF
+      ]
+    );
+  }
+
+  #[test]
+  fn answers_the_challenge() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    let responder = thread::spawn(move || {
+      let mut buffer = [0; 1400];
+      let (received, client) = server.recv_from(&mut buffer).unwrap();
+      assert_eq!(&buffer[..received], &info_request(None)[..]);
+      server
+        .send_to(
+          &[0xFF, 0xFF, 0xFF, 0xFF, CHALLENGE_RESPONSE, 9, 8, 7, 6],
+          client,
+        )
+        .unwrap();
+      let (received, client) = server.recv_from(&mut buffer).unwrap();
+      assert_eq!(&buffer[..received], &info_request(Some(&[9, 8, 7, 6]))[..]);
+      server.send_to(&info_reply(), client).unwrap();
+    });
+    let info = query_info(address, Duration::from_secs(5)).unwrap();
+    responder.join().unwrap();
+    assert_eq!(info.name, "My Server");
+    assert_eq!((info.players, info.max_players), (3, 10));
+  }
+}
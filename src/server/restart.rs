@@ -0,0 +1,243 @@
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDateTime, NaiveTime, TimeZone};
+use cron::Schedule;
+use std::fmt;
+
+use crate::utils::{
+  environment::fetch_var,
+  parse_duration,
+  schedule::{next_run, parse_schedule},
+};
+
+pub const RESTART_WARNING_VAR: &str = "RESTART_WARNING";
+/// Restarts on this schedule when `--schedule-restart` isn't given.
+pub const SCHEDULED_RESTART_VAR: &str = "SCHEDULED_RESTART";
+/// `1` skips scheduled restarts while players are online.
+pub const SCHEDULED_RESTART_ONLY_IF_EMPTY_VAR: &str = "SCHEDULED_RESTART_ONLY_IF_EMPTY";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RestartTime {
+  /// Every day at this local time.
+  Daily(NaiveTime),
+  Cron(Box<Schedule>),
+}
+
+impl fmt::Display for RestartTime {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RestartTime::Daily(at) => write!(f, "every day at {}", at.format("%H:%M")),
+      RestartTime::Cron(schedule) => write!(f, "on the schedule '{}'", schedule),
+    }
+  }
+}
+
+/// Restarts at a local time of day or on a cron schedule, warned about ahead of time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestartSchedule {
+  pub(crate) at: RestartTime,
+  pub(crate) warning: Duration,
+  /// Skip restarts that come due while players are online.
+  pub(crate) only_if_empty: bool,
+}
+
+impl RestartSchedule {
+  /// A 24 hour time like `04:00`, or a cron expression like `0 4 * * *`.
+  pub fn parse(at: &str) -> Result<Self, String> {
+    let at = match NaiveTime::parse_from_str(at.trim(), "%H:%M") {
+      Ok(time) => RestartTime::Daily(time),
+      Err(_) if at.split_whitespace().count() > 1 => RestartTime::Cron(Box::new(parse_schedule(at)?)),
+      Err(_) => {
+        return Err(format!(
+          "Invalid restart time '{}', expected a 24 hour time like 04:00 or a cron expression like \"0 4 * * *\"",
+          at
+        ))
+      }
+    };
+    let warning_value = fetch_var(RESTART_WARNING_VAR, "5m");
+    let warning = parse_duration(&warning_value).ok_or_else(|| {
+      format!(
+        "Invalid {} '{}', expected something like 90s or 5m",
+        RESTART_WARNING_VAR, warning_value
+      )
+    })?;
+    Ok(RestartSchedule {
+      at,
+      warning,
+      only_if_empty: fetch_var(SCHEDULED_RESTART_ONLY_IF_EMPTY_VAR, "0") == "1",
+    })
+  }
+
+  /// The next restart after `now`, `None` when the schedule has no more.
+  pub fn next_after(&self, now: &DateTime<Local>) -> Option<DateTime<Local>> {
+    match &self.at {
+      RestartTime::Daily(at) => Some(next_restart(*at, now)),
+      RestartTime::Cron(schedule) => next_run(schedule, *now),
+    }
+  }
+
+  /// `--schedule-restart`, or `SCHEDULED_RESTART` when it isn't given.
+  pub fn from_arg_or_env(at: Option<&str>) -> Result<Option<Self>, String> {
+    let value = match at {
+      Some(at) => at.to_string(),
+      None => fetch_var(SCHEDULED_RESTART_VAR, ""),
+    };
+    if value.trim().is_empty() {
+      return Ok(None);
+    }
+    Self::parse(&value).map(Some)
+  }
+}
+
+/// The wall clock time `local` falls on. A time skipped by DST moving forward happens an hour
+/// later, and one repeated by DST moving back only happens the first time round.
+fn resolve<Tz: TimeZone>(timezone: &Tz, local: NaiveDateTime) -> Option<DateTime<Tz>> {
+  match timezone.from_local_datetime(&local) {
+    LocalResult::Single(at) => Some(at),
+    LocalResult::Ambiguous(earliest, _) => Some(earliest),
+    LocalResult::None => timezone
+      .from_local_datetime(&(local + Duration::hours(1)))
+      .earliest(),
+  }
+}
+
+/// The first time after `now` the clock reads `at`, today or on a later day.
+pub fn next_restart<Tz: TimeZone>(at: NaiveTime, now: &DateTime<Tz>) -> DateTime<Tz> {
+  let timezone = now.timezone();
+  let mut date = now.date().naive_local();
+  loop {
+    if let Some(restart) = resolve(&timezone, date.and_time(at)) {
+      if restart > *now {
+        return restart;
+      }
+    }
+    date = date.succ();
+  }
+}
+
+#[cfg(test)]
+mod restart_tests {
+  use super::*;
+  use chrono::{FixedOffset, NaiveDate, Offset, Timelike, Utc};
+
+  fn time(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms(hour, minute, 0)
+  }
+
+  #[test]
+  fn restarts_later_today_or_tomorrow() {
+    let timezone = FixedOffset::east(2 * 3600);
+    let now = timezone.ymd(2021, 3, 1).and_hms(3, 0, 0);
+    assert_eq!(
+      next_restart(time(4, 0), &now),
+      timezone.ymd(2021, 3, 1).and_hms(4, 0, 0)
+    );
+    let now = timezone.ymd(2021, 3, 1).and_hms(4, 0, 0);
+    assert_eq!(
+      next_restart(time(4, 0), &now),
+      timezone.ymd(2021, 3, 2).and_hms(4, 0, 0)
+    );
+  }
+
+  #[test]
+  fn crosses_midnight_and_months() {
+    let now = Utc.ymd(2021, 2, 28).and_hms(23, 59, 0);
+    assert_eq!(
+      next_restart(time(0, 30), &now),
+      Utc.ymd(2021, 3, 1).and_hms(0, 30, 0)
+    );
+    assert_eq!(
+      next_restart(time(23, 0), &now),
+      Utc.ymd(2021, 3, 1).and_hms(23, 0, 0)
+    );
+  }
+
+  /// Clocks go forward from 02:00 to 03:00 and back from 03:00 to 02:00 on the days below.
+  #[derive(Clone, Copy, Debug)]
+  struct Dst;
+
+  impl TimeZone for Dst {
+    type Offset = FixedOffset;
+    fn from_offset(_: &FixedOffset) -> Self {
+      Dst
+    }
+    fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+      self.offset_from_local_datetime(&local.and_hms(12, 0, 0))
+    }
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+      let (winter, summer) = (FixedOffset::east(3600), FixedOffset::east(7200));
+      let forward = NaiveDate::from_ymd(2021, 3, 28);
+      let back = NaiveDate::from_ymd(2021, 10, 31);
+      let hour = local.hour();
+      if local.date() == forward && hour == 2 {
+        LocalResult::None
+      } else if local.date() == back && hour == 2 {
+        LocalResult::Ambiguous(summer, winter)
+      } else if local.date() < forward || local.date() > back {
+        LocalResult::Single(winter)
+      } else if local.date() == forward {
+        LocalResult::Single(if hour < 2 { winter } else { summer })
+      } else if local.date() == back {
+        LocalResult::Single(if hour < 2 { summer } else { winter })
+      } else {
+        LocalResult::Single(summer)
+      }
+    }
+    fn offset_from_utc_date(&self, _: &NaiveDate) -> FixedOffset {
+      FixedOffset::east(3600)
+    }
+    fn offset_from_utc_datetime(&self, _: &NaiveDateTime) -> FixedOffset {
+      FixedOffset::east(3600)
+    }
+  }
+
+  #[test]
+  fn skipped_times_happen_an_hour_later() {
+    let now = resolve(&Dst, NaiveDate::from_ymd(2021, 3, 28).and_hms(1, 0, 0)).unwrap();
+    let restart = next_restart(time(2, 30), &now);
+    assert_eq!(
+      restart.naive_local(),
+      NaiveDate::from_ymd(2021, 3, 28).and_hms(3, 30, 0)
+    );
+    assert_eq!(restart.offset().fix(), FixedOffset::east(7200));
+  }
+
+  #[test]
+  fn repeated_times_happen_once() {
+    let now = resolve(&Dst, NaiveDate::from_ymd(2021, 10, 31).and_hms(1, 0, 0)).unwrap();
+    let first = next_restart(time(2, 30), &now);
+    assert_eq!(first.offset().fix(), FixedOffset::east(7200));
+    let next = next_restart(time(2, 30), &first);
+    assert_eq!(
+      next.naive_local(),
+      NaiveDate::from_ymd(2021, 11, 1).and_hms(2, 30, 0)
+    );
+  }
+
+  #[test]
+  fn rejects_invalid_times() {
+    assert_eq!(
+      RestartSchedule::parse(" 04:00 ").unwrap().at,
+      RestartTime::Daily(time(4, 0))
+    );
+    assert!(RestartSchedule::parse("25:00").is_err());
+    assert!(RestartSchedule::parse("4am").is_err());
+    assert!(RestartSchedule::parse("0 4 * *").is_err());
+  }
+
+  #[test]
+  fn restarts_on_cron_schedules() {
+    let schedule = RestartSchedule::parse("\"30 4 * * Mon\"").unwrap();
+    // 2021-03-01 is a Monday.
+    let now = Local.ymd(2021, 3, 1).and_hms(5, 0, 0);
+    assert_eq!(
+      schedule.next_after(&now),
+      Some(Local.ymd(2021, 3, 8).and_hms(4, 30, 0))
+    );
+    assert_eq!(
+      RestartSchedule::parse("04:00")
+        .unwrap()
+        .next_after(&now)
+        .map(|at| at.naive_local()),
+      Some(NaiveDate::from_ymd(2021, 3, 2).and_hms(4, 0, 0))
+    );
+  }
+}
@@ -0,0 +1,170 @@
+use crate::utils::environment::fetch_var;
+
+/// Seconds between the server's world saves, launched with `-saveinterval`.
+pub const SAVE_INTERVAL_VAR: &str = "SAVE_INTERVAL_SECONDS";
+/// How many of its own backups of the world the server keeps, launched with `-backups`.
+pub const GAME_BACKUPS_VAR: &str = "GAME_BACKUPS_COUNT";
+/// Seconds before the server's first backup, launched with `-backupshort`.
+pub const GAME_BACKUP_SHORT_VAR: &str = "GAME_BACKUP_SHORT_SECONDS";
+/// Seconds between the server's later backups, launched with `-backuplong`.
+pub const GAME_BACKUP_LONG_VAR: &str = "GAME_BACKUP_LONG_SECONDS";
+
+/// Saving more often than this stalls the server for everyone.
+const MIN_SAVE_INTERVAL: u64 = 60;
+const MIN_BACKUP_INTERVAL: u64 = 60;
+
+/// What the game uses when it isn't launched with a value, as of the Ashlands update.
+const GAME_SAVE_INTERVAL: u64 = 1800;
+const GAME_BACKUPS: u64 = 4;
+const GAME_BACKUP_SHORT: u64 = 7200;
+const GAME_BACKUP_LONG: u64 = 43200;
+
+/// The server's own saving and backups. Unset ones aren't passed, so the game's defaults apply.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SaveSettings {
+  pub(crate) save_interval: Option<u64>,
+  pub(crate) backups: Option<u64>,
+  pub(crate) backup_short: Option<u64>,
+  pub(crate) backup_long: Option<u64>,
+}
+
+/// `None` for an empty value, a whole number no smaller than `min` otherwise.
+fn parse_setting(name: &str, value: &str, min: u64) -> Result<Option<u64>, String> {
+  let value = value.trim();
+  if value.is_empty() {
+    return Ok(None);
+  }
+  match value.parse::<u64>() {
+    Ok(number) if number >= min => Ok(Some(number)),
+    Ok(_) => Err(format!("{} must be at least {}, got {}", name, min, value)),
+    Err(_) => Err(format!(
+      "{} must be a positive whole number, got '{}'",
+      name, value
+    )),
+  }
+}
+
+fn describe(value: Option<u64>, default: u64, described: impl Fn(u64) -> String) -> String {
+  match value {
+    Some(value) => described(value),
+    None => format!("{} (game default)", described(default)),
+  }
+}
+
+impl SaveSettings {
+  pub fn parse(
+    save_interval: &str,
+    backups: &str,
+    backup_short: &str,
+    backup_long: &str,
+  ) -> Result<Self, Vec<String>> {
+    let mut problems = Vec::new();
+    let mut setting = |name: &str, value: &str, min: u64| {
+      parse_setting(name, value, min).unwrap_or_else(|problem| {
+        problems.push(problem);
+        None
+      })
+    };
+    let settings = SaveSettings {
+      save_interval: setting(SAVE_INTERVAL_VAR, save_interval, MIN_SAVE_INTERVAL),
+      backups: setting(GAME_BACKUPS_VAR, backups, 1),
+      backup_short: setting(GAME_BACKUP_SHORT_VAR, backup_short, MIN_BACKUP_INTERVAL),
+      backup_long: setting(GAME_BACKUP_LONG_VAR, backup_long, MIN_BACKUP_INTERVAL),
+    };
+    if let (Some(short), Some(long)) = (settings.backup_short, settings.backup_long) {
+      if long < short {
+        problems.push(format!(
+          "{} ({}) can't be shorter than {} ({})",
+          GAME_BACKUP_LONG_VAR, long, GAME_BACKUP_SHORT_VAR, short
+        ));
+      }
+    }
+    if problems.is_empty() {
+      Ok(settings)
+    } else {
+      Err(problems)
+    }
+  }
+
+  /// Each of the given values, or its env variable when it isn't given.
+  pub fn resolve(
+    save_interval: Option<&str>,
+    backups: Option<&str>,
+    backup_short: Option<&str>,
+    backup_long: Option<&str>,
+  ) -> Result<Self, Vec<String>> {
+    let or_env =
+      |value: Option<&str>, name: &str| value.map_or_else(|| fetch_var(name, ""), str::to_string);
+    Self::parse(
+      &or_env(save_interval, SAVE_INTERVAL_VAR),
+      &or_env(backups, GAME_BACKUPS_VAR),
+      &or_env(backup_short, GAME_BACKUP_SHORT_VAR),
+      &or_env(backup_long, GAME_BACKUP_LONG_VAR),
+    )
+  }
+
+  pub fn from_env() -> Result<Self, Vec<String>> {
+    Self::resolve(None, None, None, None)
+  }
+
+  pub fn launch_args(&self) -> Vec<String> {
+    [
+      ("-saveinterval", self.save_interval),
+      ("-backups", self.backups),
+      ("-backupshort", self.backup_short),
+      ("-backuplong", self.backup_long),
+    ]
+    .iter()
+    .filter_map(|(arg, value)| value.map(|value| [arg.to_string(), value.to_string()]))
+    .flatten()
+    .collect()
+  }
+
+  /// What the server does with its saves, for the start-up log, the game's defaults included.
+  pub fn summary(&self) -> String {
+    let seconds = |seconds: u64| format!("{}s", seconds);
+    format!(
+      "save interval {}, backups kept {}, first backup after {}, later backups every {}",
+      describe(self.save_interval, GAME_SAVE_INTERVAL, seconds),
+      describe(self.backups, GAME_BACKUPS, |count| count.to_string()),
+      describe(self.backup_short, GAME_BACKUP_SHORT, seconds),
+      describe(self.backup_long, GAME_BACKUP_LONG, seconds),
+    )
+  }
+}
+
+#[cfg(test)]
+mod save_settings_tests {
+  use super::*;
+
+  #[test]
+  fn passes_only_what_is_set() {
+    let settings = SaveSettings::parse("600", "", " 7200 ", "").unwrap();
+    assert_eq!(
+      settings.launch_args(),
+      vec!["-saveinterval", "600", "-backupshort", "7200"]
+    );
+    assert!(SaveSettings::parse("", "", "", "")
+      .unwrap()
+      .launch_args()
+      .is_empty());
+    assert_eq!(
+      SaveSettings::parse("", "4", "", "").unwrap().summary(),
+      "save interval 1800s (game default), backups kept 4, first backup after 7200s (game default), later backups every 43200s (game default)"
+    );
+  }
+
+  #[test]
+  fn rejects_values_out_of_bounds() {
+    assert_eq!(
+      SaveSettings::parse("1", "0", "soon", "-5").unwrap_err(),
+      vec![
+        "SAVE_INTERVAL_SECONDS must be at least 60, got 1",
+        "GAME_BACKUPS_COUNT must be at least 1, got 0",
+        "GAME_BACKUP_SHORT_SECONDS must be a positive whole number, got 'soon'",
+        "GAME_BACKUP_LONG_SECONDS must be a positive whole number, got '-5'",
+      ]
+    );
+    assert!(SaveSettings::parse("", "", "7200", "3600").is_err());
+  }
+}
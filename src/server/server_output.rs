@@ -0,0 +1,185 @@
+use log::{error, warn};
+
+use std::{
+  io::{self, Read, Write},
+  path::PathBuf,
+  sync::atomic::{AtomicBool, Ordering},
+  thread::{self, JoinHandle},
+};
+
+use crate::{
+  logger::{RotatingFile, BYTES_PER_MEGABYTE},
+  server::{publish_server_line, LogFilter},
+  utils::environment::fetch_var,
+};
+
+/// Size in megabytes at which the server's log files are rotated.
+pub const SERVER_LOG_MAX_SIZE_VAR: &str = "SERVER_LOG_MAX_SIZE_MB";
+/// How many of each of the server's log files are kept, the one being written included.
+pub const SERVER_LOG_MAX_FILES_VAR: &str = "SERVER_LOG_MAX_FILES";
+const DEFAULT_SERVER_LOG_MAX_SIZE: u64 = 50;
+const DEFAULT_SERVER_LOG_MAX_FILES: u64 = 5;
+
+static OWNS_SERVER_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// The server's output passes through odin from here on. Only for an odin that stays around as
+/// long as the server does, the server can't write anywhere once it is gone.
+pub fn own_server_output() {
+  OWNS_SERVER_OUTPUT.store(true, Ordering::Relaxed);
+}
+
+pub fn owns_server_output() -> bool {
+  OWNS_SERVER_OUTPUT.load(Ordering::Relaxed)
+}
+
+fn parse_log_setting(name: &str, default: u64) -> u64 {
+  let value = fetch_var(name, &default.to_string());
+  match value.trim().parse::<u64>() {
+    Ok(parsed) if parsed >= 1 => parsed,
+    _ => {
+      // Never worth keeping the server from starting over.
+      warn!(
+        "{} must be a whole number of at least 1, got '{}', using {}",
+        name, value, default
+      );
+      default
+    }
+  }
+}
+
+/// In megabytes.
+pub fn server_log_max_size() -> u64 {
+  parse_log_setting(SERVER_LOG_MAX_SIZE_VAR, DEFAULT_SERVER_LOG_MAX_SIZE)
+}
+
+/// A log file of the server's, rotated at `SERVER_LOG_MAX_SIZE_MB`.
+pub fn server_log_file(path: PathBuf) -> RotatingFile {
+  RotatingFile::new(
+    path,
+    server_log_max_size() * BYTES_PER_MEGABYTE,
+    parse_log_setting(SERVER_LOG_MAX_FILES_VAR, DEFAULT_SERVER_LOG_MAX_FILES) as usize,
+  )
+}
+
+/// Writes `line` to the log file and `tee`. The log file failing doesn't keep the line from `tee`.
+fn pass_on<W: Write>(
+  line: &str,
+  log: &mut Option<RotatingFile>,
+  tee: &mut W,
+  name: &str,
+) -> io::Result<()> {
+  publish_server_line(line);
+  if let Some(file) = log.as_mut() {
+    if let Err(e) = file.write_line(line) {
+      error!(
+        "Unable to write the server's {} to its log file, only passing it on from now on: {}",
+        name, e
+      );
+      *log = None;
+    }
+  }
+  writeln!(tee, "{}", line)?;
+  tee.flush()
+}
+
+/// Passes one of the server's streams on to its log file and `tee` on a thread of its own, leaving
+/// out the noise `filter` knows. The thread finishes once the server closes the stream.
+pub fn pass_on_output<R, W>(
+  name: &'static str,
+  input: R,
+  filter: LogFilter,
+  mut log: RotatingFile,
+  mut tee: W,
+) -> JoinHandle<()>
+where
+  R: Read + Send + 'static,
+  W: Write + Send + 'static,
+{
+  thread::spawn(move || {
+    let mut log = match log.start_over() {
+      Ok(_) => Some(log),
+      Err(e) => {
+        error!("Unable to start a new log for the server's {}: {}", name, e);
+        None
+      }
+    };
+    if let Err(e) = filter.filter(input, |line| pass_on(line, &mut log, &mut tee, name)) {
+      error!("Stopped passing on the server's {}: {}", name, e);
+    }
+    if let Some(file) = log.as_mut() {
+      file.flush();
+    }
+  })
+}
+
+#[cfg(test)]
+mod server_output_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use serial_test::serial;
+  use std::{
+    env, fs,
+    sync::{Arc, Mutex},
+  };
+
+  /// Keeps what the thread passed on where the test can still read it.
+  #[derive(Clone, Default)]
+  struct Teed(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for Teed {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn logs_and_tees_what_is_kept() {
+    let temp = test_dir("server-output");
+    let root = temp.path();
+    let path = root.join("valheim_server.log");
+    fs::write(&path, "the last run\n").unwrap();
+    let output =
+      "Game server connected\n(Filename: ./Runtime/Export/Debug.h Line: 35)\nWorld saved ( 1.5ms )\n";
+    let teed = Teed::default();
+    pass_on_output(
+      "output",
+      output.as_bytes(),
+      LogFilter::parse("0", "default").unwrap(),
+      RotatingFile::new(path.clone(), 1024, 2),
+      teed.clone(),
+    )
+    .join()
+    .unwrap();
+    let kept = "Game server connected\nWorld saved ( 1.5ms )\n";
+    assert_eq!(
+      String::from_utf8(teed.0.lock().unwrap().clone()).unwrap(),
+      kept
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), kept);
+    assert_eq!(
+      fs::read_to_string(root.join("valheim_server.log.1")).unwrap(),
+      "the last run\n"
+    );
+  }
+
+  #[test]
+  #[serial]
+  fn falls_back_to_the_default_sizes() {
+    env::set_var(SERVER_LOG_MAX_SIZE_VAR, "0");
+    env::set_var(SERVER_LOG_MAX_FILES_VAR, "3");
+    assert_eq!(
+      parse_log_setting(SERVER_LOG_MAX_SIZE_VAR, DEFAULT_SERVER_LOG_MAX_SIZE),
+      50
+    );
+    assert_eq!(
+      parse_log_setting(SERVER_LOG_MAX_FILES_VAR, DEFAULT_SERVER_LOG_MAX_FILES),
+      3
+    );
+    env::remove_var(SERVER_LOG_MAX_SIZE_VAR);
+    env::remove_var(SERVER_LOG_MAX_FILES_VAR);
+  }
+}
@@ -1,20 +1,87 @@
-use log::{error, info};
-use sysinfo::{ProcessExt, Signal, System, SystemExt};
+use log::{error, info, warn};
+use sysinfo::{Pid, ProcessExt, Signal, System, SystemExt};
 
-use std::{thread, time::Duration};
+use std::{
+  thread,
+  time::{Duration, Instant},
+};
 
-use crate::constants;
+use crate::{
+  constants,
+  files::state::record_server_stop,
+  server::{
+    server_log_path, server_processes,
+    supervisor::{request_stop, stop_timeout},
+    LogParser, LogTail, ServerEvent,
+  },
+};
 
-pub fn blocking_shutdown() {
+/// How often a stopping server is checked on.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a killed server gets to disappear.
+const KILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the server went down.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShutdownOutcome {
+  /// It didn't exit within the timeout and was killed.
+  pub(crate) forced: bool,
+  /// The server logged its final `World saved` line.
+  pub(crate) world_saved: bool,
+}
+
+/// Interrupts the server so it saves the world and exits, killing it if it takes longer than
+/// `SHUTDOWN_TIMEOUT_SECONDS` (120 by default).
+pub fn blocking_shutdown() -> Result<ShutdownOutcome, String> {
+  let timeout = stop_timeout()?;
+  request_stop();
+  // Only the lines logged from here on tell whether this shutdown saved the world.
+  let mut log = LogTail::from_end(&server_log_path());
   send_shutdown_signal();
-  wait_for_exit();
+  info!(
+    "Waiting up to {} seconds for the server to save the world and shutdown...",
+    timeout.as_secs()
+  );
+  let outcome = wait_for_exit(
+    || !server_pids().is_empty(),
+    &mut log,
+    timeout,
+    POLL_INTERVAL,
+  );
+  if outcome.forced {
+    error!(
+      "Server didn't stop within {} seconds, killing it. {}",
+      timeout.as_secs(),
+      if outcome.world_saved {
+        "The world was saved before that."
+      } else {
+        "The world may not have been saved!"
+      }
+    );
+    kill_server();
+    let killed = wait_for_exit(
+      || !server_pids().is_empty(),
+      &mut log,
+      KILL_TIMEOUT,
+      POLL_INTERVAL,
+    );
+    if killed.forced {
+      error!("Server is still running after being killed!");
+    }
+  } else if outcome.world_saved {
+    info!("Server saved the world and shutdown gracefully!")
+  } else {
+    info!("Server has been shutdown gracefully!")
+  }
+  record_server_stop();
+  Ok(outcome)
 }
 
 pub fn send_shutdown_signal() {
   info!("Scanning for Valheim process");
   let mut system = System::new();
   system.refresh_all();
-  let processes = system.get_process_by_name(constants::VALHEIM_EXECUTABLE_NAME);
+  let processes = server_processes(&system);
   if processes.is_empty() {
     info!("Process NOT found!")
   } else {
@@ -32,18 +99,143 @@ pub fn send_shutdown_signal() {
   }
 }
 
-fn wait_for_exit() {
-  info!("Waiting for server to completely shutdown...");
+fn kill_server() {
+  let mut system = System::new();
+  system.refresh_all();
+  for process in server_processes(&system) {
+    warn!("Killing the server with pid {}", process.pid());
+    if !process.kill(Signal::Kill) {
+      error!("Failed to kill the server with pid {}", process.pid());
+    }
+  }
+}
+
+/// The pids of the processes called `name`, sorted. Other processes only containing it, like a
+/// shell running `tail valheim_server.x86_64.log`, are left alone.
+fn pids_named<'a>(processes: impl IntoIterator<Item = (Pid, &'a str)>, name: &str) -> Vec<Pid> {
+  let mut pids: Vec<Pid> = processes
+    .into_iter()
+    .filter(|(_, process_name)| *process_name == name)
+    .map(|(pid, _)| pid)
+    .collect();
+  pids.sort_unstable();
+  pids
+}
+
+/// Pids of every running Valheim server process.
+pub fn server_pids() -> Vec<Pid> {
   let mut system = System::new();
+  system.refresh_processes();
+  let pids = pids_named(
+    system
+      .get_processes()
+      .values()
+      .map(|process| (process.pid(), process.name())),
+    constants::VALHEIM_EXECUTABLE_NAME,
+  );
+  let managed: Vec<Pid> = server_processes(&system)
+    .iter()
+    .map(|process| process.pid())
+    .collect();
+  pids
+    .into_iter()
+    .filter(|pid| managed.contains(pid))
+    .collect()
+}
+
+/// Polls `is_running` until the server is gone or `timeout` passes, which counts as forced. Reads
+/// `log` along the way for the server's `World saved` line.
+fn wait_for_exit(
+  mut is_running: impl FnMut() -> bool,
+  log: &mut LogTail,
+  timeout: Duration,
+  poll: Duration,
+) -> ShutdownOutcome {
+  let deadline = Instant::now() + timeout;
+  let mut parser = LogParser::default();
+  let mut outcome = ShutdownOutcome::default();
   loop {
-    system.refresh_all();
-    let processes = system.get_process_by_name(constants::VALHEIM_EXECUTABLE_NAME);
-    if processes.is_empty() {
-      break;
-    } else {
-      // Delay to keep down CPU usage
-      thread::sleep(Duration::from_secs(1));
+    let saved = log
+      .read_lines()
+      .iter()
+      .any(|line| matches!(parser.parse(line), Some(ServerEvent::WorldSaved(_))));
+    if saved && !outcome.world_saved {
+      info!("World saved, waiting for the server to exit...");
+      outcome.world_saved = true;
+    }
+    if !is_running() {
+      return outcome;
     }
+    if Instant::now() >= deadline {
+      outcome.forced = true;
+      return outcome;
+    }
+    // Delay to keep down CPU usage
+    thread::sleep(poll);
+  }
+}
+
+#[cfg(test)]
+mod shutdown_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use std::{fs, io::Write};
+
+  #[test]
+  fn finds_only_server_processes() {
+    let processes = vec![
+      (42, "valheim_server.x86_64"),
+      (7, "odin"),
+      (12, "valheim_server.x86_64"),
+      (99, "tail valheim_server.x86_64.log"),
+    ];
+    assert_eq!(
+      pids_named(processes, constants::VALHEIM_EXECUTABLE_NAME),
+      vec![12, 42]
+    );
+    assert!(pids_named(vec![(7, "odin")], constants::VALHEIM_EXECUTABLE_NAME).is_empty());
+  }
+
+  #[test]
+  fn waits_for_the_server_to_save_and_exit() {
+    let temp = test_dir("shutdown");
+    let path = temp.path().join("shutdown.log");
+    fs::write(&path, "World saved ( 3.0ms )\n").unwrap();
+    let mut log = LogTail::from_end(&path);
+    let poll = Duration::from_millis(1);
+
+    // Exits on the third check, after saving.
+    let mut checks = 0;
+    let outcome = wait_for_exit(
+      || {
+        checks += 1;
+        if checks == 2 {
+          let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+          file.write_all(b"World saved ( 1.5ms )\n").unwrap();
+        }
+        checks < 3
+      },
+      &mut log,
+      Duration::from_secs(60),
+      poll,
+    );
+    assert_eq!(
+      outcome,
+      ShutdownOutcome {
+        forced: false,
+        world_saved: true
+      }
+    );
+
+    // Never exits, the save from before the shutdown doesn't count.
+    let mut log = LogTail::from_end(&path);
+    let outcome = wait_for_exit(|| true, &mut log, Duration::from_millis(20), poll);
+    assert_eq!(
+      outcome,
+      ShutdownOutcome {
+        forced: true,
+        world_saved: false
+      }
+    );
   }
-  info!("Server has been shutdown successfully!")
 }
@@ -1,77 +1,316 @@
 use daemonize::{Daemonize, DaemonizeError};
-use log::{debug, info};
+use log::{debug, info, warn};
 
-use std::{io, process::Child};
+use serde::Serialize;
+
+use std::{
+  collections::BTreeMap,
+  fs::File,
+  io,
+  os::fd::AsFd,
+  process::{Child, Stdio},
+};
 
 use crate::{
   constants,
   executable::create_execution,
-  files::{create_file, ValheimArguments},
+  files::{create_file, worlds::saves_dir, ValheimArguments},
+  logger::REDACTED,
   messages,
   mods::bepinex,
+  server::{
+    console_stdin, display_args, owns_server_output, pass_on_output, record_server_pid,
+    server_error_log_path, server_log_file, server_log_path, LogFilter,
+  },
   utils::{environment, get_working_dir},
 };
 
 type CommandResult = io::Result<Child>;
 
 pub fn start_daemonized(config: ValheimArguments) -> Result<CommandResult, DaemonizeError> {
-  let stdout = create_file(format!("{}/logs/valheim_server.log", get_working_dir()).as_str());
-  let stderr = create_file(format!("{}/logs/valheim_server.err", get_working_dir()).as_str());
+  start_daemonized_then(config, || ())
+}
+
+/// Like `start_daemonized`, with `after_launch` run by the process that launched the daemon right
+/// before it exits.
+pub fn start_daemonized_then<F: FnOnce() + 'static>(
+  config: ValheimArguments,
+  after_launch: F,
+) -> Result<CommandResult, DaemonizeError> {
+  // When odin passes the server's output on it logs to where `odin start` does, like the
+  // container's output, and leaves the log files to the server's output.
+  let (stdout, stderr) = match (owns_server_output(), duplicate_output()) {
+    (true, Ok(output)) => output,
+    (owned, duplicated) => {
+      if let (true, Err(e)) = (owned, duplicated) {
+        warn!("Unable to pass the server's output on to odin's: {}", e);
+      }
+      (
+        create_file(&server_log_path().to_string_lossy()),
+        create_file(&server_error_log_path().to_string_lossy()),
+      )
+    }
+  };
+  let crossplay = config.crossplay;
   Daemonize::new()
     .working_directory(get_working_dir())
     .user("steam")
     .group("steam")
     .stdout(stdout)
     .stderr(stderr)
-    .exit_action(|| {
-      if bepinex::is_bepinex_installed() {
+    .exit_action(move || {
+      if bepinex::is_bepinex_installed().is_installed() {
         info!("Server has been started with BepInEx! Keep in mind this may cause errors!!");
         messages::modding_disclaimer()
       }
       info!("Server has been started and Daemonized. It should be online shortly!");
-      info!("Keep an eye out for 'Game server connected' in the log!");
-      info!("(this indicates its online without any errors.)")
+      if crossplay {
+        info!("Keep an eye out for 'with join code' in the log!");
+      } else {
+        info!("Keep an eye out for 'Game server connected' in the log!");
+      }
+      info!("(this indicates its online without any errors.)");
+      after_launch()
     })
     .privileged_action(move || start(&config))
     .start()
 }
 
+/// Odin's own stdout and stderr, for the daemon to keep writing to.
+fn duplicate_output() -> io::Result<(File, File)> {
+  Ok((
+    File::from(io::stdout().as_fd().try_clone_to_owned()?),
+    File::from(io::stderr().as_fd().try_clone_to_owned()?),
+  ))
+}
+
+fn crossplay_args(crossplay: bool) -> Vec<&'static str> {
+  if crossplay {
+    vec!["-crossplay"]
+  } else {
+    Vec::new()
+  }
+}
+
+/// A profile's server saves to the profile's own saves directory, Valheim's default otherwise.
+pub fn savedir_args() -> Vec<String> {
+  if environment::active_profile().is_some() {
+    vec![
+      "-savedir".to_string(),
+      saves_dir().to_string_lossy().to_string(),
+    ]
+  } else {
+    Vec::new()
+  }
+}
+
+/// The server's arguments, the extra ones from `SERVER_ARGS` last after everything odin manages.
+pub fn launch_args(config: &ValheimArguments) -> Vec<String> {
+  let mut args: Vec<String> = [
+    "-nographics",
+    "-batchmode",
+    "-port",
+    config.port.as_str(),
+    "-name",
+    config.name.as_str(),
+    "-world",
+    config.world.as_str(),
+    "-password",
+    config.password.as_str(),
+    "-public",
+    config.public.as_str(),
+  ]
+  .iter()
+  .map(|arg| arg.to_string())
+  .collect();
+  args.extend(
+    crossplay_args(config.crossplay)
+      .iter()
+      .map(|arg| arg.to_string()),
+  );
+  args.extend(config.world_modifiers.launch_args());
+  args.extend(config.save_settings.launch_args());
+  args.extend(savedir_args());
+  args.extend(config.extra_args.iter().cloned());
+  args
+}
+
+/// What odin sets in the server's environment on top of its own, BepInEx's when it is launched
+/// with it.
+pub fn launch_env(
+  bepinex_env: Option<&bepinex::BepInExEnvironment>,
+) -> Vec<(&'static str, String)> {
+  let mut vars = vec![("SteamAppId", environment::fetch_var("APPID", "892970"))];
+  match bepinex_env {
+    Some(bepinex_env) => vars.extend(bepinex_env.vars()),
+    None => vars.push((
+      constants::LD_LIBRARY_PATH_VAR,
+      environment::fetch_multiple_var(
+        constants::LD_LIBRARY_PATH_VAR,
+        &get_working_dir().join("linux64").to_string_lossy(),
+      ),
+    )),
+  }
+  vars
+}
+
+/// Everything the server would be launched with, what `odin start --dry-run` shows.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LaunchPlan {
+  pub(crate) command: String,
+  pub(crate) args: Vec<String>,
+  pub(crate) working_dir: String,
+  /// Set on top of the environment the server inherits from odin.
+  pub(crate) env: BTreeMap<String, String>,
+  pub(crate) bepinex: bool,
+}
+
+impl LaunchPlan {
+  pub fn new(config: &ValheimArguments) -> Self {
+    let bepinex_env = bepinex::is_bepinex_installed()
+      .is_installed()
+      .then(bepinex::build_environment);
+    LaunchPlan {
+      command: config.command.clone(),
+      args: launch_args(config),
+      working_dir: get_working_dir().to_string_lossy().to_string(),
+      env: launch_env(bepinex_env.as_ref())
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect(),
+      bepinex: bepinex_env.is_some(),
+    }
+  }
+
+  /// The plan with the password, and any variable holding a secret, shown as `<redacted>`.
+  pub fn masked(mut self) -> Self {
+    let mut after_password = false;
+    for arg in self.args.iter_mut() {
+      if after_password && !arg.is_empty() {
+        *arg = REDACTED.to_string();
+      }
+      after_password = arg == "-password";
+    }
+    for (name, value) in self.env.iter_mut() {
+      if environment::is_secret_name(name) && !value.is_empty() {
+        *value = REDACTED.to_string();
+      }
+    }
+    self
+  }
+
+  /// The launch as shell lines that can be pasted to run the server by hand.
+  pub fn shell_command(&self) -> String {
+    let mut lines = vec![format!(
+      "cd {} &&",
+      display_args([self.working_dir.as_str()])
+    )];
+    lines.extend(
+      self
+        .env
+        .iter()
+        .map(|(name, value)| format!("  {}={}", name, display_args([value.as_str()]))),
+    );
+    lines.push(format!(
+      "  {}",
+      display_args(
+        std::iter::once(self.command.as_str()).chain(self.args.iter().map(String::as_str))
+      )
+    ));
+    lines.join(" \\\n")
+  }
+}
+
 pub fn start(config: &ValheimArguments) -> CommandResult {
   let mut command = create_execution(&config.command);
   info!("--------------------------------------------------------------------------------------------------------------");
-  let ld_library_path_value = environment::fetch_multiple_var(
-    constants::LD_LIBRARY_PATH_VAR,
-    format!("{}/linux64", get_working_dir()).as_str(),
-  );
   debug!("Setting up base command");
   let base_command = command
-    .args(&[
-      "-nographics",
-      "-batchmode",
-      "-port",
-      &config.port.as_str(),
-      "-name",
-      &config.name.as_str(),
-      "-world",
-      &config.world.as_str(),
-      "-password",
-      &config.password.as_str(),
-      "-public",
-      &config.public.as_str(),
-    ])
-    .env("SteamAppId", environment::fetch_var("APPID", "892970"))
+    .args(launch_args(config))
+    // Read from the console pipe so `odin cmd` can reach the server.
+    .stdin(console_stdin())
     .current_dir(get_working_dir());
+  info!(
+    "Crossplay is {}",
+    if config.crossplay {
+      "enabled, launching with -crossplay"
+    } else {
+      "disabled"
+    }
+  );
+  if !config.world_modifiers.is_empty() {
+    debug!("World modifiers: {}", config.world_modifiers);
+  }
+  info!("Valheim's own saves: {}", config.save_settings.summary());
+  info!("Those stay in the worlds folder and are separate from `odin backup` archives");
   info!("Executable: {}", &config.command);
+  info!(
+    "Arguments: {}",
+    display_args(base_command.get_args().filter_map(|arg| arg.to_str()))
+  );
   info!("Launching Command...");
+  let log_filter = server_log_filter();
+  if owns_server_output() {
+    base_command.stdout(Stdio::piped()).stderr(Stdio::piped());
+  }
 
-  if bepinex::is_bepinex_installed() {
+  let mut child = if bepinex::is_bepinex_installed().is_installed() {
     info!("BepInEx detected! Switching to run with BepInEx...");
     let bepinex_env = bepinex::build_environment();
-    bepinex::invoke(base_command, &bepinex_env)
+    info!("BepInEx found! Setting up Environment...");
+    base_command.envs(launch_env(Some(&bepinex_env))).spawn()
   } else {
     info!("Everything looks good! Running normally!");
-    base_command
-      .env(constants::LD_LIBRARY_PATH_VAR, ld_library_path_value)
-      .spawn()
+    base_command.envs(launch_env(None)).spawn()
+  }?;
+  record_server_pid(child.id());
+  // They finish on their own once the server closes its output.
+  if let Some(stdout) = child.stdout.take() {
+    let log = server_log_file(server_log_path());
+    pass_on_output("output", stdout, log_filter.clone(), log, io::stdout());
+  }
+  if let Some(stderr) = child.stderr.take() {
+    let log = server_log_file(server_error_log_path());
+    pass_on_output("errors", stderr, log_filter, log, io::stderr());
+  }
+  Ok(child)
+}
+
+/// What is left out of the server's output when odin passes it on.
+fn server_log_filter() -> LogFilter {
+  // `odin start` checked it before launching.
+  let filter = LogFilter::from_env().unwrap_or_else(|_| LogFilter::parse("0", "none").unwrap());
+  if owns_server_output() && filter.is_enabled() {
+    info!(
+      "Leaving {} noise pattern(s){} out of the server's output",
+      filter.patterns.len(),
+      if filter.empty { " and empty lines" } else { "" }
+    );
+  }
+  filter
+}
+
+#[cfg(test)]
+mod launch_tests {
+  use super::*;
+
+  #[test]
+  fn masks_the_password_and_quotes_for_the_shell() {
+    let plan = LaunchPlan {
+      command: "/home/steam/valheim/valheim_server.x86_64".to_string(),
+      args: vec![
+        "-name".to_string(),
+        "My Server".to_string(),
+        "-password".to_string(),
+        "hunter22".to_string(),
+      ],
+      working_dir: "/home/steam/valheim".to_string(),
+      env: BTreeMap::from([("SteamAppId".to_string(), "892970".to_string())]),
+      bepinex: false,
+    };
+    assert_eq!(
+      plan.masked().shell_command(),
+      "cd /home/steam/valheim && \\\n  SteamAppId=892970 \\\n  /home/steam/valheim/valheim_server.x86_64 -name 'My Server' -password '<redacted>'"
+    );
   }
 }
@@ -0,0 +1,820 @@
+use chrono::{DateTime, Local};
+use log::{debug, error, info, warn};
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use std::{
+  collections::VecDeque,
+  fs, io,
+  net::SocketAddr,
+  os::unix::process::ExitStatusExt,
+  process::{Child, ExitStatus},
+  str::FromStr,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  thread::{self, JoinHandle},
+  time::{Duration, Instant},
+};
+
+use crate::{
+  errors::{CommandError, CommandResult, VariantNotFound},
+  executable::handle_exit_status,
+  files::{
+    config::load_config,
+    lock::{is_in_progress, BACKUP_LOCK_FILE_NAME, UPDATE_LOCK_FILE_NAME},
+    state,
+  },
+  notifications::{
+    configured_destinations,
+    enums::{event_status::EventStatus, notification_event::NotificationEvent},
+  },
+  server::{
+    crossplay::watch_crossplay_registration,
+    idle::{IdleShutdown, IDLE_SHUTDOWN_EXIT_CODE},
+    log_watcher::LogWatcher,
+    query::{query_info, query_port},
+    restart::RestartSchedule,
+    shutdown::send_shutdown_signal,
+    startup,
+    watchdog::Watchdog,
+  },
+  utils::{environment::fetch_var, get_odin_dir, parse_duration},
+};
+
+pub const ON_SERVER_EXIT_VAR: &str = "ON_SERVER_EXIT";
+pub const SHUTDOWN_TIMEOUT_VAR: &str = "SHUTDOWN_TIMEOUT_SECONDS";
+/// The older name for `SHUTDOWN_TIMEOUT_SECONDS`, a duration like `5m`, read when that isn't set.
+pub const SERVER_STOP_TIMEOUT_VAR: &str = "SERVER_STOP_TIMEOUT";
+/// `1` restarts a crashed server when `ON_SERVER_EXIT` isn't set, like `restart-on-crash`.
+pub const AUTO_RESTART_VAR: &str = "AUTO_RESTART";
+pub const AUTO_RESTART_DELAY_VAR: &str = "AUTO_RESTART_DELAY_SECONDS";
+pub const AUTO_RESTART_MAX_ATTEMPTS_VAR: &str = "AUTO_RESTART_MAX_ATTEMPTS";
+pub const AUTO_RESTART_WINDOW_VAR: &str = "AUTO_RESTART_WINDOW_SECONDS";
+const STOP_REQUESTED_FILE: &str = "stop-requested";
+/// How often a supervising odin looks at the server and for termination signals.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the server gets to say how many players are online.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A backup taken every `every` while odin supervises the server, each on its own thread so the
+/// server is still looked after while it runs.
+#[derive(Clone, Copy)]
+pub struct PeriodicBackup {
+  pub(crate) every: Duration,
+  pub(crate) run: fn(),
+}
+
+impl PeriodicBackup {
+  pub fn parse(every: &str, run: fn()) -> Result<Self, String> {
+    match parse_duration(every).and_then(|every| every.to_std().ok()) {
+      Some(every) if every > Duration::from_secs(0) => Ok(PeriodicBackup { every, run }),
+      _ => Err(format!(
+        "Invalid backup interval '{}', expected something like 30m or 6h",
+        every
+      )),
+    }
+  }
+}
+
+/// Starts a backup unless the last one is still going, in which case this one is skipped.
+fn start_backup(backup: &PeriodicBackup, running: &mut Option<JoinHandle<()>>) {
+  if running.as_ref().is_some_and(|handle| !handle.is_finished()) {
+    warn!("The last periodic backup is still going, skipping this one");
+    return;
+  }
+  info!("Starting the periodic backup");
+  let run = backup.run;
+  *running = Some(thread::spawn(run));
+}
+
+/// Lets a backup that is still going finish before odin exits, so it isn't cut off halfway.
+fn wait_for_backup(running: &mut Option<JoinHandle<()>>) {
+  if let Some(handle) = running.take() {
+    if !handle.is_finished() {
+      info!("Waiting for the periodic backup to finish...");
+    }
+    if handle.join().is_err() {
+      error!("The periodic backup panicked");
+    }
+  }
+}
+
+/// What odin does when the server exits without `odin stop` asking it to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ServerExitBehavior {
+  Propagate,
+  Restart,
+  /// Restarts after a non-zero exit or a signal, exits like `Propagate` after a clean one.
+  RestartOnCrash,
+  Stay,
+}
+
+impl FromStr for ServerExitBehavior {
+  type Err = VariantNotFound;
+  fn from_str(s: &str) -> Result<ServerExitBehavior, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "propagate" => Ok(ServerExitBehavior::Propagate),
+      "restart" => Ok(ServerExitBehavior::Restart),
+      "restart-on-crash" => Ok(ServerExitBehavior::RestartOnCrash),
+      "stay" => Ok(ServerExitBehavior::Stay),
+      _ => Err(VariantNotFound {
+        v: format!(
+          "Unknown {} '{}', expected propagate, restart, restart-on-crash or stay",
+          ON_SERVER_EXIT_VAR, s
+        ),
+      }),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExitDecision {
+  Exit(i32),
+  Restart,
+  Stay,
+}
+
+/// `None` means nothing was configured and odin leaves the server running on its own.
+pub fn server_exit_behavior() -> Result<Option<ServerExitBehavior>, VariantNotFound> {
+  let value = fetch_var(ON_SERVER_EXIT_VAR, "");
+  if value.is_empty() {
+    return Ok(
+      (fetch_var(AUTO_RESTART_VAR, "0") == "1").then_some(ServerExitBehavior::RestartOnCrash),
+    );
+  }
+  ServerExitBehavior::from_str(&value).map(Some)
+}
+
+pub fn decide_on_exit(
+  behavior: ServerExitBehavior,
+  exit_code: Option<i32>,
+  stop_requested: bool,
+) -> ExitDecision {
+  if stop_requested {
+    return ExitDecision::Exit(0);
+  }
+  match behavior {
+    // A signal has no exit code, which we count as a failure.
+    ServerExitBehavior::Propagate => ExitDecision::Exit(exit_code.unwrap_or(1)),
+    ServerExitBehavior::Restart => ExitDecision::Restart,
+    ServerExitBehavior::RestartOnCrash if exit_code == Some(0) => ExitDecision::Exit(0),
+    ServerExitBehavior::RestartOnCrash => ExitDecision::Restart,
+    ServerExitBehavior::Stay => ExitDecision::Stay,
+  }
+}
+
+/// Paces the restarts after the server exits on its own, so one that keeps crashing isn't
+/// relaunched in a tight loop.
+pub struct CrashRestarts {
+  pub(crate) delay: Duration,
+  /// How many restarts `window` may hold, `0` for no limit.
+  pub(crate) max_attempts: usize,
+  pub(crate) window: Duration,
+  attempts: VecDeque<Instant>,
+}
+
+fn seconds_var(name: &str, default: &str) -> Result<u64, String> {
+  let value = fetch_var(name, default);
+  value
+    .trim()
+    .parse::<u64>()
+    .map_err(|_| format!("Invalid {} '{}', expected a number", name, value))
+}
+
+impl CrashRestarts {
+  pub fn new(delay: Duration, max_attempts: usize, window: Duration) -> Self {
+    CrashRestarts {
+      delay,
+      max_attempts,
+      window,
+      attempts: VecDeque::new(),
+    }
+  }
+
+  pub fn from_env() -> Result<Self, String> {
+    Ok(Self::new(
+      Duration::from_secs(seconds_var(AUTO_RESTART_DELAY_VAR, "10")?),
+      seconds_var(AUTO_RESTART_MAX_ATTEMPTS_VAR, "5")? as usize,
+      Duration::from_secs(seconds_var(AUTO_RESTART_WINDOW_VAR, "3600")?),
+    ))
+  }
+
+  /// Counts a restart at `now`, returning how many there have been within the window including
+  /// this one, or `None` when the window is already full and the server should stay down.
+  pub fn attempt(&mut self, now: Instant) -> Option<usize> {
+    while let Some(oldest) = self.attempts.front() {
+      if now.duration_since(*oldest) < self.window {
+        break;
+      }
+      self.attempts.pop_front();
+    }
+    if self.max_attempts > 0 && self.attempts.len() >= self.max_attempts {
+      return None;
+    }
+    self.attempts.push_back(now);
+    Some(self.attempts.len())
+  }
+
+  fn describe(&self, attempt: usize) -> String {
+    if self.max_attempts > 0 {
+      format!("{} of {}", attempt, self.max_attempts)
+    } else {
+      attempt.to_string()
+    }
+  }
+}
+
+/// How the server exited, for logs and notifications.
+fn describe_exit(result: &io::Result<ExitStatus>) -> String {
+  match result {
+    Ok(status) => match (status.code(), status.signal()) {
+      (Some(code), _) => format!("exit code {}", code),
+      (None, Some(signal)) => format!("signal {}", signal),
+      (None, None) => status.to_string(),
+    },
+    Err(e) => format!("an unknown status ({})", e),
+  }
+}
+
+/// Marks the next server exit as intentional so a supervising odin doesn't react to it.
+pub fn request_stop() {
+  let marker = get_odin_dir().join(STOP_REQUESTED_FILE);
+  if let Err(e) = fs::create_dir_all(get_odin_dir()).and_then(|_| fs::write(&marker, "")) {
+    warn!("Failed to write stop marker {}: {}", marker.display(), e);
+  }
+}
+
+pub fn take_stop_request() -> bool {
+  fs::remove_file(get_odin_dir().join(STOP_REQUESTED_FILE)).is_ok()
+}
+
+/// Raised once odin receives SIGTERM or SIGINT, for example from `docker stop`.
+pub fn register_termination() -> Arc<AtomicBool> {
+  let terminate = Arc::new(AtomicBool::new(false));
+  for signal in [SIGTERM, SIGINT].iter() {
+    if let Err(e) = signal_hook::flag::register(*signal, Arc::clone(&terminate)) {
+      warn!("Unable to handle signal {}: {}", signal, e);
+    }
+  }
+  terminate
+}
+
+/// How long the server gets to save the world and exit once odin is told to terminate.
+pub fn stop_timeout() -> Result<Duration, String> {
+  let legacy = fetch_var(SERVER_STOP_TIMEOUT_VAR, "");
+  if legacy.is_empty() || !fetch_var(SHUTDOWN_TIMEOUT_VAR, "").is_empty() {
+    return seconds_var(SHUTDOWN_TIMEOUT_VAR, "120").map(Duration::from_secs);
+  }
+  parse_duration(&legacy)
+    .and_then(|timeout| timeout.to_std().ok())
+    .ok_or_else(|| {
+      format!(
+        "Invalid {} '{}', expected something like 90s or 5m",
+        SERVER_STOP_TIMEOUT_VAR, legacy
+      )
+    })
+}
+
+/// Stops the server the same way `odin stop` does and waits for it to save the world. The server
+/// is killed if it takes longer than `timeout`, in which case `None` is returned.
+fn stop_child(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+  request_stop();
+  send_shutdown_signal();
+  let deadline = Instant::now() + timeout;
+  let status = loop {
+    match child.try_wait() {
+      Ok(Some(status)) => break Some(status),
+      Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+      Ok(None) => {
+        error!(
+          "Server didn't stop within {} seconds, killing it. The world may not have been saved!",
+          timeout.as_secs()
+        );
+        if let Err(e) = child.kill() {
+          error!("Failed to kill the server: {}", e);
+        }
+        let _ = child.wait();
+        break None;
+      }
+      Err(e) => {
+        error!("Lost track of the server while it was stopping: {}", e);
+        break None;
+      }
+    }
+  };
+  take_stop_request();
+  state::record_server_stop();
+  status
+}
+
+/// Saves the world once odin is told to terminate and waits for any backup still going, odin
+/// exits with what it returns.
+fn shutdown_gracefully(
+  child: &mut Child,
+  timeout: Duration,
+  backup: &mut Option<JoinHandle<()>>,
+) -> CommandResult {
+  info!("Received a termination signal, saving the world and stopping the server...");
+  let status = stop_child(child, timeout);
+  wait_for_backup(backup);
+  match status {
+    Some(status) => {
+      handle_exit_status(Ok(status), "Server stopped cleanly".to_string());
+      Ok(())
+    }
+    // `stop_child` already said why.
+    None => Err(CommandError::quiet(1)),
+  }
+}
+
+/// Stops the server nobody was on for `empty_for`, odin then exits with `IDLE_SHUTDOWN_EXIT_CODE`.
+fn shutdown_idle(
+  child: &mut Child,
+  timeout: Duration,
+  backup: &mut Option<JoinHandle<()>>,
+  empty_for: Duration,
+) -> CommandError {
+  let message = format!(
+    "Nobody was online for {} minute(s), stopping the server",
+    (empty_for.as_secs() / 60).max(1)
+  );
+  info!("Idle shutdown: {}", message);
+  NotificationEvent::IdleShutdown.send_notification_with_details(&message);
+  let status = stop_child(child, timeout);
+  wait_for_backup(backup);
+  match status {
+    Some(status) => {
+      handle_exit_status(Ok(status), "Server stopped cleanly".to_string());
+      info!(
+        "Idle shutdown: exiting with {} so the container or autoscaler can act on it",
+        IDLE_SHUTDOWN_EXIT_CODE
+      );
+      CommandError::quiet(IDLE_SHUTDOWN_EXIT_CODE)
+    }
+    None => CommandError::quiet(1),
+  }
+}
+
+/// Stops and starts the server again, returning the new server process.
+fn restart_child(
+  child: &mut Child,
+  timeout: Duration,
+  reason: &str,
+) -> Result<Child, CommandError> {
+  info!("Restarting the server {}...", reason);
+  NotificationEvent::Stop(EventStatus::Running).send_notification();
+  if stop_child(child, timeout).is_some() {
+    NotificationEvent::Stop(EventStatus::Successful).send_notification();
+  } else {
+    NotificationEvent::Stop(EventStatus::Failed).send_notification();
+  }
+  NotificationEvent::Start(EventStatus::Running).send_notification();
+  match startup::start(&load_config()) {
+    Ok(child) => {
+      state::record_server_start();
+      NotificationEvent::Start(EventStatus::Successful).send_notification();
+      thread::spawn(watch_crossplay_registration);
+      Ok(child)
+    }
+    Err(e) => {
+      NotificationEvent::Start(EventStatus::Failed).send_notification();
+      Err(CommandError::failed(format!(
+        "Failed to start the server after restarting it {}: {}",
+        reason, e
+      )))
+    }
+  }
+}
+
+fn broadcast(message: &str) {
+  info!("{}", message);
+  NotificationEvent::Broadcast.send_custom_notification(
+    &configured_destinations(&NotificationEvent::Broadcast),
+    message,
+  );
+}
+
+fn warn_of_restart(schedule: &RestartSchedule, at: DateTime<Local>) {
+  broadcast(&format!(
+    "The server restarts in {} minute(s) for its scheduled {} restart",
+    schedule.warning.num_minutes().max(1),
+    at.format("%H:%M")
+  ));
+}
+
+/// How many players the server says are online, `None` when it doesn't answer.
+fn online_players() -> Option<u8> {
+  let port = query_port(&load_config().port)?;
+  match query_info(SocketAddr::from(([127, 0, 0, 1], port)), QUERY_TIMEOUT) {
+    Ok(info) => Some(info.players),
+    Err(e) => {
+      debug!("Unable to count the players online: {}", e);
+      None
+    }
+  }
+}
+
+/// Why a scheduled restart that came due shouldn't happen, if anything stands in its way.
+fn restart_blocker(schedule: &RestartSchedule, backup: &Option<JoinHandle<()>>) -> Option<String> {
+  let backing_up = backup.as_ref().is_some_and(|handle| !handle.is_finished());
+  if backing_up || is_in_progress(BACKUP_LOCK_FILE_NAME) {
+    return Some("a backup is in progress".to_string());
+  }
+  if is_in_progress(UPDATE_LOCK_FILE_NAME) {
+    return Some("an update is in progress".to_string());
+  }
+  if schedule.only_if_empty {
+    if let Some(players) = online_players().filter(|players| *players > 0) {
+      return Some(format!("{} player(s) are online", players));
+    }
+  }
+  None
+}
+
+/// Looks after the server until odin should exit, which it then does with what this returns.
+pub fn supervise(
+  mut child: Child,
+  behavior: ServerExitBehavior,
+  terminate: Arc<AtomicBool>,
+  restart: Option<RestartSchedule>,
+  backups: Option<PeriodicBackup>,
+) -> CommandResult {
+  let timeout = stop_timeout()?;
+  let mut restarts = CrashRestarts::from_env()?;
+  info!(
+    "Supervising server process {} ({}={:?})",
+    child.id(),
+    ON_SERVER_EXIT_VAR,
+    behavior
+  );
+  let mut next_restart_at = restart
+    .as_ref()
+    .and_then(|schedule| schedule.next_after(&Local::now()));
+  let mut warned = false;
+  if let Some(at) = next_restart_at {
+    info!("Next scheduled restart at {}", at.to_rfc3339());
+  }
+  let mut next_backup_at = backups.map(|backup| {
+    info!(
+      "Backing up every {} minute(s) while the server runs",
+      (backup.every.as_secs() / 60).max(1)
+    );
+    Instant::now() + backup.every
+  });
+  let mut running_backup = None;
+  let config = load_config();
+  let mut watchdog = Watchdog::from_env(config.crossplay)?;
+  let query_address = query_port(&config.port).map(|port| SocketAddr::from(([127, 0, 0, 1], port)));
+  if let (Some(watchdog), Some(address)) = (watchdog.as_ref(), query_address) {
+    info!(
+      "Querying the server on {} every {}s, {} unanswered in a row count as unresponsive",
+      address,
+      watchdog.interval.as_secs(),
+      watchdog.threshold
+    );
+  }
+  let mut idle = IdleShutdown::from_env()?;
+  // Who is online comes from the log, crossplay servers may not answer queries.
+  let mut idle_log = idle.as_ref().map(|idle| {
+    info!(
+      "Idle shutdown: stopping the server once nobody was online for {} minute(s) after it is ready, exiting with {}",
+      idle.after.as_secs() / 60,
+      IDLE_SHUTDOWN_EXIT_CODE
+    );
+    LogWatcher::for_server()
+  });
+  loop {
+    let result = loop {
+      if terminate.load(Ordering::SeqCst) {
+        return shutdown_gracefully(&mut child, timeout, &mut running_backup);
+      }
+      if let (Some(backup), Some(at)) = (backups, next_backup_at) {
+        if Instant::now() >= at {
+          start_backup(&backup, &mut running_backup);
+          next_backup_at = Some(Instant::now() + backup.every);
+        }
+      }
+      if let (Some(schedule), Some(at)) = (restart.as_ref(), next_restart_at) {
+        let now = Local::now();
+        if !warned && now >= at - schedule.warning {
+          warn_of_restart(schedule, at);
+          warned = true;
+        }
+        if now >= at {
+          match restart_blocker(schedule, &running_backup) {
+            Some(reason) => broadcast(&format!(
+              "Skipping the scheduled {} restart, {}",
+              at.format("%H:%M"),
+              reason
+            )),
+            None => {
+              child = restart_child(&mut child, timeout, "as scheduled")?;
+              if let Some(idle) = idle.as_mut() {
+                idle.reset();
+              }
+            }
+          }
+          // From after the restart, so a slow one can't make it fire twice.
+          next_restart_at = schedule.next_after(&Local::now());
+          match next_restart_at {
+            Some(at) => info!("Next scheduled restart at {}", at.to_rfc3339()),
+            None => warn!("The restart schedule has no upcoming restarts"),
+          }
+          warned = false;
+          continue;
+        }
+      }
+      if let (Some(dog), Some(address)) = (watchdog.as_mut(), query_address) {
+        if dog.check(address) {
+          let message = format!(
+            "The server's process runs but it didn't answer queries on {} {} times in a row",
+            address,
+            dog.failures()
+          );
+          error!("{}", message);
+          NotificationEvent::ServerUnresponsive.send_notification_with_details(&message);
+          if dog.restart {
+            child = restart_child(&mut child, timeout, "as it is unresponsive")?;
+            dog.reset();
+            if let Some(idle) = idle.as_mut() {
+              idle.reset();
+            }
+            continue;
+          }
+        }
+      }
+      if let (Some(idle), Some(log)) = (idle.as_mut(), idle_log.as_mut()) {
+        let now = Instant::now();
+        for event in log.poll() {
+          idle.observe(&event, now);
+        }
+        if idle.is_due(now) {
+          // The log can miss players who joined before odin counted them, the server knows.
+          let players = if config.crossplay {
+            None
+          } else {
+            online_players().filter(|players| *players > 0)
+          };
+          match players {
+            Some(players) => idle.players_online(players as usize),
+            None => {
+              return Err(shutdown_idle(
+                &mut child,
+                timeout,
+                &mut running_backup,
+                idle.empty_for(now),
+              ))
+            }
+          }
+        }
+      }
+      match child.try_wait() {
+        Ok(Some(status)) => break Ok(status),
+        Ok(None) => thread::sleep(POLL_INTERVAL),
+        Err(e) => break Err(e),
+      }
+    };
+    let stop_requested = take_stop_request();
+    let exit_code = result.as_ref().ok().and_then(|status| status.code());
+    debug!(
+      "Server exited with {:?}, stop requested: {}",
+      exit_code, stop_requested
+    );
+    if !stop_requested {
+      state::record_server_stop();
+      state::record_warning(&format!("Server exited on its own with {:?}", exit_code));
+    }
+    match decide_on_exit(behavior, exit_code, stop_requested) {
+      ExitDecision::Exit(code) => {
+        handle_exit_status(result, "Server exited cleanly".to_string());
+        wait_for_backup(&mut running_backup);
+        return match code {
+          0 => Ok(()),
+          code => Err(CommandError::quiet(code)),
+        };
+      }
+      ExitDecision::Restart => {
+        let previous = describe_exit(&result);
+        let attempt = match restarts.attempt(Instant::now()) {
+          Some(attempt) => attempt,
+          None => {
+            let message = format!(
+              "Server exited with {} after being restarted {} times within {} seconds, giving up",
+              previous,
+              restarts.max_attempts,
+              restarts.window.as_secs()
+            );
+            NotificationEvent::Start(EventStatus::Failed).send_notification_with_details(&message);
+            wait_for_backup(&mut running_backup);
+            return Err(CommandError::new(
+              exit_code.filter(|code| *code != 0).unwrap_or(1),
+              message,
+            ));
+          }
+        };
+        let message = format!(
+          "Server exited on its own with {}, restart attempt {} in {} seconds",
+          previous,
+          restarts.describe(attempt),
+          restarts.delay.as_secs()
+        );
+        warn!("{}", message);
+        NotificationEvent::Start(EventStatus::Running).send_notification_with_details(&message);
+        let restart_at = Instant::now() + restarts.delay;
+        while Instant::now() < restart_at {
+          if terminate.load(Ordering::SeqCst) {
+            info!("Received a termination signal, not restarting the server");
+            wait_for_backup(&mut running_backup);
+            return Ok(());
+          }
+          thread::sleep(POLL_INTERVAL);
+        }
+        child = match startup::start(&load_config()) {
+          Ok(child) => {
+            thread::spawn(watch_crossplay_registration);
+            if let Some(dog) = watchdog.as_mut() {
+              dog.reset();
+            }
+            if let Some(idle) = idle.as_mut() {
+              idle.reset();
+            }
+            state::record_server_start();
+            NotificationEvent::Start(EventStatus::Successful).send_notification();
+            child
+          }
+          Err(e) => {
+            NotificationEvent::Start(EventStatus::Failed).send_notification();
+            return Err(CommandError::failed(format!(
+              "Failed to restart the server: {}",
+              e
+            )));
+          }
+        };
+      }
+      ExitDecision::Stay => {
+        info!("Server exited on its own. Odin will stay up until it is stopped.");
+        while !terminate.load(Ordering::SeqCst) {
+          thread::sleep(POLL_INTERVAL);
+        }
+        info!("Received a termination signal, exiting");
+        wait_for_backup(&mut running_backup);
+        return Ok(());
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod supervisor_tests {
+  use super::*;
+  use serial_test::serial;
+  use std::env;
+
+  #[test]
+  fn parses_backup_intervals() {
+    fn run() {}
+    assert_eq!(
+      PeriodicBackup::parse("6h", run).unwrap().every,
+      Duration::from_secs(6 * 60 * 60)
+    );
+    assert_eq!(
+      PeriodicBackup::parse("90", run).unwrap().every,
+      Duration::from_secs(90 * 60)
+    );
+    assert!(PeriodicBackup::parse("0m", run).is_err());
+    assert!(PeriodicBackup::parse("-1h", run).is_err());
+    assert!(PeriodicBackup::parse("often", run).is_err());
+  }
+
+  #[test]
+  #[serial]
+  fn reads_the_stop_timeout_in_seconds() {
+    env::remove_var(SHUTDOWN_TIMEOUT_VAR);
+    env::remove_var(SERVER_STOP_TIMEOUT_VAR);
+    assert_eq!(stop_timeout(), Ok(Duration::from_secs(120)));
+    env::set_var(SERVER_STOP_TIMEOUT_VAR, "5m");
+    assert_eq!(stop_timeout(), Ok(Duration::from_secs(300)));
+    env::set_var(SHUTDOWN_TIMEOUT_VAR, "45");
+    assert_eq!(stop_timeout(), Ok(Duration::from_secs(45)));
+    env::set_var(SHUTDOWN_TIMEOUT_VAR, "soon");
+    assert!(stop_timeout().is_err());
+    env::remove_var(SHUTDOWN_TIMEOUT_VAR);
+    env::remove_var(SERVER_STOP_TIMEOUT_VAR);
+  }
+
+  #[test]
+  fn parses_behavior() {
+    assert_eq!(
+      ServerExitBehavior::from_str("Restart").unwrap(),
+      ServerExitBehavior::Restart
+    );
+    assert!(ServerExitBehavior::from_str("explode").is_err());
+  }
+
+  #[test]
+  fn propagate_uses_child_code() {
+    use ServerExitBehavior::Propagate;
+    assert_eq!(
+      decide_on_exit(Propagate, Some(0), false),
+      ExitDecision::Exit(0)
+    );
+    assert_eq!(
+      decide_on_exit(Propagate, Some(3), false),
+      ExitDecision::Exit(3)
+    );
+    assert_eq!(
+      decide_on_exit(Propagate, None, false),
+      ExitDecision::Exit(1)
+    );
+  }
+
+  #[test]
+  fn restart_relaunches_on_any_exit() {
+    use ServerExitBehavior::Restart;
+    assert_eq!(
+      decide_on_exit(Restart, Some(0), false),
+      ExitDecision::Restart
+    );
+    assert_eq!(
+      decide_on_exit(Restart, Some(139), false),
+      ExitDecision::Restart
+    );
+    assert_eq!(decide_on_exit(Restart, None, false), ExitDecision::Restart);
+  }
+
+  #[test]
+  fn restart_on_crash_leaves_clean_exits_alone() {
+    use ServerExitBehavior::RestartOnCrash;
+    assert_eq!(
+      ServerExitBehavior::from_str("restart-on-crash").unwrap(),
+      RestartOnCrash
+    );
+    assert_eq!(
+      decide_on_exit(RestartOnCrash, Some(0), false),
+      ExitDecision::Exit(0)
+    );
+    assert_eq!(
+      decide_on_exit(RestartOnCrash, Some(139), false),
+      ExitDecision::Restart
+    );
+    assert_eq!(
+      decide_on_exit(RestartOnCrash, None, false),
+      ExitDecision::Restart
+    );
+  }
+
+  #[test]
+  fn limits_restarts_within_the_window() {
+    let window = Duration::from_secs(60);
+    let mut restarts = CrashRestarts::new(Duration::from_secs(0), 2, window);
+    let start = Instant::now();
+    assert_eq!(restarts.attempt(start), Some(1));
+    assert_eq!(restarts.attempt(start + Duration::from_secs(10)), Some(2));
+    assert_eq!(restarts.attempt(start + Duration::from_secs(20)), None);
+    // The first restart has left the window.
+    assert_eq!(restarts.attempt(start + Duration::from_secs(61)), Some(2));
+    assert_eq!(restarts.describe(2), "2 of 2");
+
+    let mut unlimited = CrashRestarts::new(Duration::from_secs(0), 0, window);
+    assert!((1..=10).all(|n| unlimited.attempt(start) == Some(n)));
+  }
+
+  #[test]
+  fn describes_exits() {
+    assert_eq!(
+      describe_exit(&Ok(ExitStatus::from_raw(3 << 8))),
+      "exit code 3"
+    );
+    assert_eq!(describe_exit(&Ok(ExitStatus::from_raw(9))), "signal 9");
+  }
+
+  #[test]
+  fn stay_keeps_odin_up() {
+    use ServerExitBehavior::Stay;
+    assert_eq!(decide_on_exit(Stay, Some(0), false), ExitDecision::Stay);
+    assert_eq!(decide_on_exit(Stay, Some(1), false), ExitDecision::Stay);
+  }
+
+  #[test]
+  fn termination_signals_are_caught() {
+    let terminate = register_termination();
+    assert!(!terminate.load(Ordering::SeqCst));
+    signal_hook::low_level::raise(SIGTERM).unwrap();
+    assert!(terminate.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn requested_stop_always_exits_cleanly() {
+    for behavior in &[
+      ServerExitBehavior::Propagate,
+      ServerExitBehavior::Restart,
+      ServerExitBehavior::Stay,
+    ] {
+      assert_eq!(
+        decide_on_exit(*behavior, Some(1), true),
+        ExitDecision::Exit(0)
+      );
+    }
+  }
+}
@@ -1,9 +1,19 @@
 use log::{debug, error, info};
 
-use std::{fs, io::ErrorKind, path::Path, process::exit};
+use std::{fs, io::ErrorKind, process::exit};
 
 use crate::{
-  constants, files::config::load_config, server, steamcmd::steamcmd_command, utils::get_working_dir,
+  constants,
+  files::{
+    config::load_config,
+    failure::{enter_phase, fail_operation},
+    lock::{lock_operation, OperationLock, UPDATE_LOCK_FILE_NAME},
+    state,
+  },
+  notifications::enums::{event_status::EventStatus, notification_event::NotificationEvent},
+  server,
+  steamcmd::{appinfo_cache_path, cleanup_isolated_home, steamcmd_command},
+  utils::get_working_dir,
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -13,11 +23,11 @@ pub struct UpdateInfo {
 }
 
 impl UpdateInfo {
-  pub fn new() -> Self {
+  pub fn new() -> Result<Self, String> {
     let current_build_id = get_current_build_id();
-    let latest_build_id = get_latest_build_id();
+    let latest_build_id = get_latest_build_id()?;
 
-    Self::internal_new(current_build_id, latest_build_id)
+    Ok(Self::internal_new(current_build_id, latest_build_id))
   }
 
   #[cfg(test)]
@@ -48,43 +58,77 @@ impl UpdateInfo {
   // }
 }
 
-pub fn update_is_available() -> bool {
-  let info = UpdateInfo::new();
+pub fn update_is_available() -> Result<bool, String> {
+  let info = UpdateInfo::new()?;
   debug!("{:#?}", info);
 
-  info.update_available()
+  Ok(info.update_available())
+}
+
+/// The update failed but the (old build of the) server is still up.
+pub const EXIT_UPDATE_FAILED_SERVER_RUNNING: i32 = 11;
+/// The update failed and the server is not running.
+pub const EXIT_UPDATE_FAILED_SERVER_DOWN: i32 = 12;
+
+fn fail_update(message: &str) -> ! {
+  NotificationEvent::Update(EventStatus::Failed).send_notification_with_details(message);
+  let exit_code = if server::is_running() {
+    EXIT_UPDATE_FAILED_SERVER_RUNNING
+  } else {
+    EXIT_UPDATE_FAILED_SERVER_DOWN
+  };
+  fail_operation(exit_code, message)
 }
 
 pub fn update_server() {
+  let _lock = match lock_operation(UPDATE_LOCK_FILE_NAME) {
+    OperationLock::Acquired(lock) => lock,
+    OperationLock::InProgress => fail_operation(1, "Another update is still in progress"),
+  };
   // Shutdown the server if it's running
   let server_was_running = server::is_running();
   if server_was_running {
-    server::blocking_shutdown();
+    enter_phase("stopping server");
+    if let Err(e) = server::blocking_shutdown() {
+      fail_update(&e);
+    }
   }
 
   // Update the installation
-  if let Err(e) = server::install(constants::GAME_ID) {
-    error!("Failed to install server: {}", e);
-    exit(1);
+  enter_phase("installing");
+  match server::install(constants::GAME_ID, false) {
+    Ok(run) if run.success() => {}
+    Ok(run) => fail_update(&format!(
+      "Failed to install server: steamcmd exited with {}{}",
+      run.status,
+      run
+        .outcome
+        .map(|outcome| format!(", {}", outcome))
+        .unwrap_or_default()
+    )),
+    Err(e) => fail_update(&format!("Failed to install server: {}", e)),
   }
+  state::record_update();
+  NotificationEvent::Update(EventStatus::Successful).send_notification();
 
   // Bring the server up if it was running before
   if server_was_running {
+    enter_phase("starting server");
     let config = load_config();
     match server::start_daemonized(config) {
-      Ok(_) => info!("Server daemon started"),
-      Err(e) => {
-        error!("Error daemonizing: {}", e);
-        exit(1);
+      Ok(_) => {
+        info!("Server daemon started");
+        state::record_server_start();
       }
+      Err(e) => fail_update(&format!("Error daemonizing: {}", e)),
     }
   }
 }
 
 fn get_current_build_id() -> String {
-  let manifest_path = Path::new(&get_working_dir())
+  let manifest_path = get_working_dir()
     .join("steamapps")
-    .join(&format!("appmanifest_{}.acf", constants::GAME_ID));
+    .join(format!("appmanifest_{}.acf", constants::GAME_ID));
   let manifest_data = fs::read_to_string(&manifest_path).unwrap_or_else(|_| {
     panic!(
       "Failed to read manifest file at '{}'",
@@ -94,11 +138,23 @@ fn get_current_build_id() -> String {
   extract_build_id_from_manifest(&manifest_data).to_string()
 }
 
-fn get_latest_build_id() -> String {
+/// Build id of the installed server, `None` when there is no readable manifest.
+pub fn installed_build_id() -> Option<String> {
+  let manifest_path = get_working_dir()
+    .join("steamapps")
+    .join(format!("appmanifest_{}.acf", constants::GAME_ID));
+  let manifest_data = fs::read_to_string(manifest_path).ok()?;
+  manifest_data
+    .lines()
+    .find(|line| line.trim().starts_with("\"buildid\""))
+    .map(|line| split_vdf_key_val(line).1.to_string())
+}
+
+fn get_latest_build_id() -> Result<String, String> {
   // Remove the cached file to force an updated response. This is done because `steamcmd` seems to
   // refuse to update information before querying the app_info even with `+app_info_update 1` or
   // `+@bCSForceNoCache 1`
-  let appinfo_file = Path::new("/home/steam/Steam/appcache/appinfo.vdf");
+  let appinfo_file = appinfo_cache_path();
   fs::remove_file(&appinfo_file).unwrap_or_else(|e| match e.kind() {
     // AOK if it doesn't exist
     ErrorKind::NotFound => {}
@@ -119,15 +175,16 @@ fn get_latest_build_id() -> String {
     &format!("+app_info_print {}", constants::GAME_ID),
     "+quit",
   ];
-  let mut steamcmd = steamcmd_command();
+  let mut steamcmd = steamcmd_command()?;
   let app_info_output = steamcmd
     .args(args)
     .output()
     .expect("Failed to run steamcmd");
+  cleanup_isolated_home();
   assert!(app_info_output.status.success());
 
   let stdout = String::from_utf8(app_info_output.stdout).expect("steamcmd returned invalid UTF-8");
-  extract_build_id_from_app_info(&stdout).to_string()
+  Ok(extract_build_id_from_app_info(&stdout).to_string())
 }
 
 fn extract_build_id_from_manifest(manifest: &str) -> &str {
@@ -142,7 +199,7 @@ fn extract_build_id_from_manifest(manifest: &str) -> &str {
 
 fn extract_build_id_from_app_info(app_info: &str) -> &str {
   let mut lines = app_info.lines();
-  while let Some(line) = lines.next() {
+  for line in lines.by_ref() {
     if line.trim() == "\"public\"" {
       break;
     }
@@ -165,7 +222,7 @@ fn extract_build_id_from_app_info(app_info: &str) -> &str {
 // Note: This is super brittle and will fail if there is whitespace within the key or value _or_ if
 // there are escaped " at the end of the key or value
 fn split_vdf_key_val(vdf_pair: &str) -> (&str, &str) {
-  let mut pieces = vdf_pair.trim().split_whitespace();
+  let mut pieces = vdf_pair.split_whitespace();
   let key = pieces.next().expect("Missing vdf key").trim_matches('"');
   let val = pieces.next().expect("Missing vdf val").trim_matches('"');
 
@@ -178,7 +235,7 @@ mod tests {
 
   use once_cell::sync::Lazy;
 
-  use std::path::PathBuf;
+  use std::path::{Path, PathBuf};
 
   static TEST_ASSET_DIR: Lazy<PathBuf> = Lazy::new(|| {
     Path::new(env!("CARGO_MANIFEST_DIR"))
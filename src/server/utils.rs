@@ -1,11 +1,129 @@
-use sysinfo::{System, SystemExt};
+use log::warn;
+use sysinfo::{Pid, Process, ProcessExt, System, SystemExt};
 
-use crate::constants;
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+  constants,
+  utils::{environment::active_profile, get_odin_dir},
+};
+
+/// Where odin keeps the pid of the server it launched last.
+pub fn server_pid_path() -> PathBuf {
+  get_odin_dir().join("server.pid")
+}
+
+pub fn record_server_pid(pid: u32) {
+  let path = server_pid_path();
+  if let Err(e) = path
+    .parent()
+    .map_or(Ok(()), fs::create_dir_all)
+    .and_then(|_| fs::write(&path, format!("{}\n", pid)))
+  {
+    warn!(
+      "Unable to write the server's pid to {}: {}",
+      path.display(),
+      e
+    );
+  }
+}
+
+/// The pid of the server odin launched last, if it wrote one down.
+pub fn recorded_server_pid() -> Option<u32> {
+  fs::read_to_string(server_pid_path())
+    .ok()?
+    .trim()
+    .parse()
+    .ok()
+}
+
+/// Whether `pid` is a process that hasn't exited, a zombie left for its parent to reap has.
+pub fn is_pid_alive(proc: &Path, pid: u32) -> bool {
+  match fs::read_to_string(proc.join(pid.to_string()).join("stat")) {
+    // `1234 (valheim_server.) S ...`, the name may hold spaces and parentheses.
+    Ok(stat) => stat
+      .rsplit_once(')')
+      .and_then(|(_, rest)| rest.split_whitespace().next())
+      .is_some_and(|state| state != "Z" && state != "X"),
+    Err(_) => false,
+  }
+}
+
+/// Whether a server process with `pid` and `parent` is the one a profile launched as `recorded`,
+/// or was started by it.
+fn is_profile_process(pid: Pid, parent: Option<Pid>, recorded: Option<u32>) -> bool {
+  recorded.is_some_and(|recorded| {
+    let recorded = recorded as Pid;
+    pid == recorded || parent == Some(recorded)
+  })
+}
+
+/// The server processes this run manages. Without a profile that is every one of them, with one
+/// only the server it launched, so other profiles' servers are left alone.
+pub fn server_processes(system: &System) -> Vec<&Process> {
+  let processes = system.get_process_by_name(constants::VALHEIM_EXECUTABLE_NAME);
+  if active_profile().is_none() {
+    return processes;
+  }
+  let recorded = recorded_server_pid();
+  processes
+    .into_iter()
+    .filter(|process| is_profile_process(process.pid(), process.parent(), recorded))
+    .collect()
+}
 
 pub fn is_running() -> bool {
   let mut system = System::new();
   system.refresh_processes();
-  let valheim_processes = system.get_process_by_name(constants::VALHEIM_EXECUTABLE_NAME);
+  let valheim_processes = server_processes(&system);
 
   !valheim_processes.is_empty()
 }
+
+/// The pid of the running server and how long it has been up, the oldest process when there are
+/// several.
+pub fn server_process() -> Option<(Pid, Duration)> {
+  let mut system = System::new();
+  system.refresh_processes();
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |now| now.as_secs());
+  server_processes(&system)
+    .iter()
+    .min_by_key(|process| process.start_time())
+    .map(|process| {
+      let uptime = now.saturating_sub(process.start_time());
+      (process.pid(), Duration::from_secs(uptime))
+    })
+}
+
+#[cfg(test)]
+mod utils_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+
+  #[test]
+  fn tells_exited_processes_apart() {
+    assert!(is_pid_alive(Path::new("/proc"), std::process::id()));
+    let temp = test_dir("proc");
+    let proc = temp.path();
+    fs::create_dir_all(proc.join("42")).unwrap();
+    fs::write(proc.join("42").join("stat"), "42 (a (b) c) Z 1 42").unwrap();
+    assert!(!is_pid_alive(proc, 42));
+    fs::write(proc.join("42").join("stat"), "42 (a (b) c) S 1 42").unwrap();
+    assert!(is_pid_alive(proc, 42));
+    assert!(!is_pid_alive(proc, 43));
+  }
+
+  #[test]
+  fn profiles_only_see_their_own_server() {
+    assert!(is_profile_process(42, Some(1), Some(42)));
+    assert!(is_profile_process(43, Some(42), Some(42)));
+    assert!(!is_profile_process(44, Some(1), Some(42)));
+    assert!(!is_profile_process(42, Some(1), None));
+  }
+}
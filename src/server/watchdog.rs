@@ -0,0 +1,157 @@
+use log::{debug, info, warn};
+
+use std::{
+  net::SocketAddr,
+  time::{Duration, Instant},
+};
+
+use crate::{
+  server::query::query_info,
+  utils::{environment::fetch_var, parse_bool, parse_duration},
+};
+
+/// How often a supervising odin queries the server, unset or `0` leaves the watchdog off.
+pub const WATCHDOG_INTERVAL_VAR: &str = "WATCHDOG_INTERVAL";
+/// How many queries in a row the server may leave unanswered before it counts as unresponsive.
+pub const WATCHDOG_FAILURES_VAR: &str = "WATCHDOG_FAILURES";
+/// `1` restarts a server that became unresponsive.
+pub const WATCHDOG_RESTART_VAR: &str = "WATCHDOG_RESTART";
+/// How long the server gets to answer each of the watchdog's queries.
+const WATCHDOG_QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Queries the server every `interval` while its process runs, to catch one that is alive but
+/// hung. It only counts failures once the server answered, a server still starting is left be.
+pub struct Watchdog {
+  pub(crate) interval: Duration,
+  pub(crate) threshold: u32,
+  pub(crate) restart: bool,
+  failures: u32,
+  answered: bool,
+  next_check: Instant,
+}
+
+fn invalid(name: &str, value: &str, expected: &str) -> String {
+  format!("Invalid {} '{}', expected {}", name, value, expected)
+}
+
+impl Watchdog {
+  pub fn new(interval: Duration, threshold: u32, restart: bool) -> Self {
+    Watchdog {
+      interval,
+      threshold,
+      restart,
+      failures: 0,
+      answered: false,
+      next_check: Instant::now() + interval,
+    }
+  }
+
+  /// `None` when `WATCHDOG_INTERVAL` isn't set, or for crossplay servers which may never answer.
+  pub fn from_env(crossplay: bool) -> Result<Option<Self>, String> {
+    let value = fetch_var(WATCHDOG_INTERVAL_VAR, "0");
+    let interval = match parse_duration(&value).and_then(|interval| interval.to_std().ok()) {
+      Some(interval) if interval.as_secs() == 0 => return Ok(None),
+      Some(interval) => interval,
+      None => {
+        return Err(invalid(
+          WATCHDOG_INTERVAL_VAR,
+          &value,
+          "something like 30s or 2m",
+        ))
+      }
+    };
+    if crossplay {
+      warn!(
+        "Crossplay servers may not answer queries, leaving the watchdog off despite {}",
+        WATCHDOG_INTERVAL_VAR
+      );
+      return Ok(None);
+    }
+    let value = fetch_var(WATCHDOG_FAILURES_VAR, "3");
+    let threshold = match value.trim().parse::<u32>() {
+      Ok(threshold) if threshold > 0 => threshold,
+      _ => {
+        return Err(invalid(
+          WATCHDOG_FAILURES_VAR,
+          &value,
+          "a number of at least 1",
+        ))
+      }
+    };
+    let value = fetch_var(WATCHDOG_RESTART_VAR, "0");
+    let restart = match parse_bool(&value) {
+      Some(restart) => restart,
+      None => return Err(invalid(WATCHDOG_RESTART_VAR, &value, "1 or 0")),
+    };
+    Ok(Some(Self::new(interval, threshold, restart)))
+  }
+
+  /// Counts a query the server answered or not, `true` when that made it unresponsive. Only the
+  /// failure reaching the threshold is, so it is reported once until the server answers again.
+  pub fn record(&mut self, answered: bool) -> bool {
+    if answered {
+      if self.failures >= self.threshold {
+        info!("The server answers queries again");
+      }
+      self.answered = true;
+      self.failures = 0;
+      return false;
+    }
+    if !self.answered {
+      return false;
+    }
+    self.failures += 1;
+    self.failures == self.threshold
+  }
+
+  /// Queries the server at `address` when a check is due, `true` when it just became unresponsive.
+  pub fn check(&mut self, address: SocketAddr) -> bool {
+    let now = Instant::now();
+    if now < self.next_check {
+      return false;
+    }
+    self.next_check = now + self.interval;
+    let answered = match query_info(address, WATCHDOG_QUERY_TIMEOUT) {
+      Ok(_) => true,
+      Err(e) => {
+        debug!("Watchdog: {} didn't answer: {}", address, e);
+        false
+      }
+    };
+    self.record(answered)
+  }
+
+  /// For a new server process, which gets to start up before it is held to answering.
+  pub fn reset(&mut self) {
+    self.failures = 0;
+    self.answered = false;
+    self.next_check = Instant::now() + self.interval;
+  }
+
+  pub fn failures(&self) -> u32 {
+    self.failures
+  }
+}
+
+#[cfg(test)]
+mod watchdog_tests {
+  use super::*;
+
+  #[test]
+  fn reports_a_hung_server_once() {
+    let mut watchdog = Watchdog::new(Duration::from_secs(30), 2, false);
+    // Still starting, it hasn't answered yet.
+    assert!(!watchdog.record(false));
+    assert!(!watchdog.record(false));
+    assert!(!watchdog.record(true));
+    assert!(!watchdog.record(false));
+    assert!(watchdog.record(false));
+    assert!(!watchdog.record(false));
+    assert_eq!(watchdog.failures(), 3);
+    assert!(!watchdog.record(true));
+    assert!(!watchdog.record(false));
+    assert!(watchdog.record(false));
+    watchdog.reset();
+    assert!(!watchdog.record(false));
+  }
+}
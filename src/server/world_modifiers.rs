@@ -0,0 +1,194 @@
+use std::fmt;
+
+use crate::utils::environment::fetch_var;
+
+/// A difficulty preset, like `hard`, launched with `-preset`.
+pub const WORLD_PRESET_VAR: &str = "WORLD_PRESET";
+/// Comma separated `key=value` pairs, like `combat=hard,raids=none`, each launched with `-modifier`.
+pub const WORLD_MODIFIERS_VAR: &str = "WORLD_MODIFIERS";
+/// Comma separated keys, like `nomap`, each launched with `-setkey`.
+pub const WORLD_SET_KEYS_VAR: &str = "WORLD_SET_KEYS";
+
+const PRESETS: &[&str] = &[
+  "normal",
+  "casual",
+  "easy",
+  "hard",
+  "hardcore",
+  "immersive",
+  "hammer",
+];
+const MODIFIERS: &[(&str, &[&str])] = &[
+  ("combat", &["veryeasy", "easy", "hard", "veryhard"]),
+  (
+    "deathpenalty",
+    &["casual", "veryeasy", "easy", "hard", "hardcore"],
+  ),
+  (
+    "resources",
+    &["muchless", "less", "more", "muchmore", "most"],
+  ),
+  ("raids", &["none", "muchless", "less", "more", "muchmore"]),
+  ("portals", &["casual", "hard", "veryhard"]),
+];
+const SET_KEYS: &[&str] = &["nobuildcost", "playerevents", "passivemobs", "nomap"];
+
+/// The world's difficulty settings from the Hildir and Ashlands updates. Valheim silently ignores
+/// ones it doesn't know, so they are checked against the known names first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldModifiers {
+  pub(crate) preset: Option<String>,
+  /// Later ones replace earlier ones with the same key.
+  pub(crate) modifiers: Vec<(String, String)>,
+  pub(crate) set_keys: Vec<String>,
+}
+
+impl fmt::Display for WorldModifiers {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.launch_args().join(" "))
+  }
+}
+
+fn list(values: &str) -> impl Iterator<Item = String> + '_ {
+  values
+    .split(',')
+    .map(|value| value.trim().to_lowercase())
+    .filter(|value| !value.is_empty())
+}
+
+fn unknown(what: &str, value: &str, known: &[&str]) -> String {
+  format!(
+    "Unknown {} '{}', expected one of {}",
+    what,
+    value,
+    known.join(", ")
+  )
+}
+
+impl WorldModifiers {
+  /// Parses a preset and comma separated modifiers and set keys, every problem with them is
+  /// returned at once.
+  pub fn parse(preset: &str, modifiers: &str, set_keys: &str) -> Result<Self, Vec<String>> {
+    let mut problems = Vec::new();
+    let mut world = WorldModifiers::default();
+    let preset = preset.trim().to_lowercase();
+    if !preset.is_empty() {
+      if PRESETS.contains(&preset.as_str()) {
+        world.preset = Some(preset);
+      } else {
+        problems.push(unknown("world preset", &preset, PRESETS));
+      }
+    }
+    for modifier in list(modifiers) {
+      let (key, value) = match modifier.split_once('=') {
+        Some((key, value)) => (key.trim(), value.trim()),
+        None => {
+          problems.push(format!(
+            "Invalid world modifier '{}', expected key=value like combat=hard",
+            modifier
+          ));
+          continue;
+        }
+      };
+      match MODIFIERS.iter().find(|(name, _)| *name == key) {
+        None => {
+          let keys: Vec<&str> = MODIFIERS.iter().map(|(name, _)| *name).collect();
+          problems.push(unknown("world modifier", key, &keys));
+        }
+        Some((_, values)) if !values.contains(&value) => {
+          problems.push(unknown(&format!("{} value", key), value, values));
+        }
+        Some(_) => {
+          world.modifiers.retain(|(existing, _)| existing != key);
+          world.modifiers.push((key.to_string(), value.to_string()));
+        }
+      }
+    }
+    for key in list(set_keys) {
+      if !SET_KEYS.contains(&key.as_str()) {
+        problems.push(unknown("world key", &key, SET_KEYS));
+      } else if !world.set_keys.contains(&key) {
+        world.set_keys.push(key);
+      }
+    }
+    if problems.is_empty() {
+      Ok(world)
+    } else {
+      Err(problems)
+    }
+  }
+
+  /// Each of the given values, or its env variable when it isn't given.
+  pub fn resolve(
+    preset: Option<&str>,
+    modifiers: Option<&str>,
+    set_keys: Option<&str>,
+  ) -> Result<Self, Vec<String>> {
+    let or_env =
+      |value: Option<&str>, name: &str| value.map_or_else(|| fetch_var(name, ""), str::to_string);
+    Self::parse(
+      &or_env(preset, WORLD_PRESET_VAR),
+      &or_env(modifiers, WORLD_MODIFIERS_VAR),
+      &or_env(set_keys, WORLD_SET_KEYS_VAR),
+    )
+  }
+
+  pub fn from_env() -> Result<Self, Vec<String>> {
+    Self::resolve(None, None, None)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.preset.is_none() && self.modifiers.is_empty() && self.set_keys.is_empty()
+  }
+
+  /// `-preset hard -modifier combat hard -setkey nomap`, in that order.
+  pub fn launch_args(&self) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(preset) = &self.preset {
+      args.extend(["-preset".to_string(), preset.clone()]);
+    }
+    for (key, value) in &self.modifiers {
+      args.extend(["-modifier".to_string(), key.clone(), value.clone()]);
+    }
+    for key in &self.set_keys {
+      args.extend(["-setkey".to_string(), key.clone()]);
+    }
+    args
+  }
+}
+
+#[cfg(test)]
+mod world_modifiers_tests {
+  use super::*;
+
+  #[test]
+  fn builds_launch_arguments() {
+    let world = WorldModifiers::parse(
+      "Hard",
+      "combat=hard, raids=none,portals=casual,combat=veryhard",
+      "nomap,nomap",
+    )
+    .unwrap();
+    assert_eq!(
+      world.to_string(),
+      "-preset hard -modifier raids none -modifier portals casual -modifier combat veryhard -setkey nomap"
+    );
+    assert!(WorldModifiers::parse("", "", "").unwrap().is_empty());
+  }
+
+  #[test]
+  fn lists_the_valid_options_on_typos() {
+    let problems =
+      WorldModifiers::parse("hardest", "comabt=hard,raids=few,portals", "nomaps").unwrap_err();
+    assert_eq!(
+      problems,
+      vec![
+        "Unknown world preset 'hardest', expected one of normal, casual, easy, hard, hardcore, immersive, hammer",
+        "Unknown world modifier 'comabt', expected one of combat, deathpenalty, resources, raids, portals",
+        "Unknown raids value 'few', expected one of none, muchless, less, more, muchmore",
+        "Invalid world modifier 'portals', expected key=value like combat=hard",
+        "Unknown world key 'nomaps', expected one of nobuildcost, playerevents, passivemobs, nomap",
+      ]
+    );
+  }
+}
@@ -1,10 +1,265 @@
 use crate::executable::find_command;
-use log::{error, info};
-use std::process::{exit, Command};
+use crate::files::progress::format_bytes;
+use crate::utils::{environment::fetch_var, get_odin_dir};
+use log::{debug, error, info, warn};
+use std::{
+  fmt, fs,
+  io::{self, BufRead, BufReader, IsTerminal, Write},
+  path::PathBuf,
+  process::{Command, ExitStatus, Stdio},
+  time::{Duration, Instant},
+};
 
 const STEAMCMD_EXE: &str = "/home/steam/steamcmd/steamcmd.sh";
-pub fn steamcmd_command() -> Command {
-  match find_command("steamcmd") {
+const STEAM_HOME: &str = "/home/steam";
+pub const STEAMCMD_ISOLATED_VAR: &str = "STEAMCMD_ISOLATED";
+pub const STEAMCMD_ISOLATED_HOME_VAR: &str = "STEAMCMD_ISOLATED_HOME";
+pub const STEAMCMD_ISOLATED_CLEANUP_VAR: &str = "STEAMCMD_ISOLATED_CLEANUP";
+
+/// ` Update state (0x61) downloading, progress: 42.17 (1234567 / 2926264)`
+const PROGRESS_MARKER: &str = "Update state (";
+/// How long steamcmd can go without a progress line being logged.
+const REPORT_INTERVAL: Duration = Duration::from_secs(30);
+/// Percent between progress lines, whichever comes first with the interval.
+const REPORT_STEP: u64 = 10;
+const BAR_WIDTH: usize = 30;
+
+/// How far steamcmd got with what it is doing, from its `Update state` lines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SteamcmdProgress {
+  /// `downloading`, `verifying install`, `committing`...
+  pub(crate) state: String,
+  pub(crate) percent: f64,
+  pub(crate) done_bytes: u64,
+  pub(crate) total_bytes: u64,
+}
+
+impl fmt::Display for SteamcmdProgress {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {:.0}%", self.state, self.percent.floor())?;
+    if self.total_bytes > 0 {
+      write!(
+        f,
+        " ({} of {})",
+        format_bytes(self.done_bytes),
+        format_bytes(self.total_bytes)
+      )?;
+    }
+    Ok(())
+  }
+}
+
+pub fn parse_progress(line: &str) -> Option<SteamcmdProgress> {
+  let rest = &line[line.find(PROGRESS_MARKER)? + PROGRESS_MARKER.len()..];
+  let (state, rest) = rest.split_once(')')?.1.split_once(", progress:")?;
+  let (percent, bytes) = rest.trim().split_once('(')?;
+  let (done, total) = bytes.trim_end().trim_end_matches(')').split_once('/')?;
+  Some(SteamcmdProgress {
+    state: state.trim().to_string(),
+    percent: percent.trim().parse().ok()?,
+    done_bytes: done.trim().parse().ok()?,
+    total_bytes: total.trim().parse().ok()?,
+  })
+}
+
+/// How steamcmd says an `app_update` ended, from its `Success!` or `Error!` line.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SteamcmdOutcome {
+  Installed,
+  UpToDate,
+  /// What steamcmd said went wrong, like `App '896660' state is 0x202 after update job.`
+  Failed(String),
+}
+
+impl fmt::Display for SteamcmdOutcome {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SteamcmdOutcome::Installed => write!(f, "fully installed"),
+      SteamcmdOutcome::UpToDate => write!(f, "already up to date"),
+      SteamcmdOutcome::Failed(reason) => write!(f, "failed: {}", reason),
+    }
+  }
+}
+
+pub fn parse_outcome(line: &str) -> Option<SteamcmdOutcome> {
+  let line = line.trim();
+  let (verdict, rest) = line.split_once('!')?;
+  match verdict.to_lowercase().as_str() {
+    "success" if rest.contains("already up to date") => Some(SteamcmdOutcome::UpToDate),
+    "success" => Some(SteamcmdOutcome::Installed),
+    "error" => Some(SteamcmdOutcome::Failed(rest.trim().to_string())),
+    _ => None,
+  }
+}
+
+/// How a steamcmd run ended, its exit code alone doesn't always tell.
+#[derive(Debug)]
+pub struct SteamcmdRun {
+  pub(crate) status: ExitStatus,
+  /// The last outcome steamcmd printed, if it printed any.
+  pub(crate) outcome: Option<SteamcmdOutcome>,
+}
+
+impl SteamcmdRun {
+  pub fn success(&self) -> bool {
+    self.status.success() && !matches!(self.outcome, Some(SteamcmdOutcome::Failed(_)))
+  }
+}
+
+/// Tells how far steamcmd got, as a bar redrawn in place on a terminal and as a log line every
+/// `REPORT_STEP` percent or `REPORT_INTERVAL` otherwise.
+struct ProgressReport {
+  terminal: bool,
+  last: Option<SteamcmdProgress>,
+  last_report: Instant,
+  drawn: bool,
+}
+
+impl ProgressReport {
+  fn new(terminal: bool) -> Self {
+    ProgressReport {
+      terminal,
+      last: None,
+      last_report: Instant::now(),
+      drawn: false,
+    }
+  }
+
+  /// Whether `progress` is worth logging after what was logged last.
+  fn is_due(&self, progress: &SteamcmdProgress, now: Instant) -> bool {
+    match &self.last {
+      None => true,
+      Some(last) => {
+        last.state != progress.state
+          || progress.percent as u64 / REPORT_STEP != last.percent as u64 / REPORT_STEP
+          || now.duration_since(self.last_report) >= REPORT_INTERVAL
+      }
+    }
+  }
+
+  fn progress(&mut self, progress: SteamcmdProgress) {
+    if self.terminal {
+      let filled = (progress.percent.clamp(0.0, 100.0) / 100.0 * BAR_WIDTH as f64) as usize;
+      eprint!(
+        "\r[{}{}] {}\x1b[K",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        progress
+      );
+      let _ = io::stderr().flush();
+      self.drawn = true;
+    } else if self.is_due(&progress, Instant::now()) {
+      info!("steamcmd is {}", progress);
+      self.last_report = Instant::now();
+    } else {
+      return;
+    }
+    self.last = Some(progress);
+  }
+
+  /// Moves past the bar, so nothing is printed over it.
+  fn finish(&mut self) {
+    if self.drawn {
+      eprintln!();
+      self.drawn = false;
+    }
+  }
+
+  /// Anything else steamcmd prints goes below the bar.
+  fn line(&mut self, line: &str) {
+    self.finish();
+    println!("{}", line);
+  }
+}
+
+/// Runs steamcmd, reading what it prints as it goes so its download progress can be told instead
+/// of it looking stuck for minutes on a slow connection. `on_progress` sees every progress line.
+pub fn execute_with_progress<F: FnMut(&SteamcmdProgress)>(
+  command: &mut Command,
+  mut on_progress: F,
+) -> io::Result<SteamcmdRun> {
+  let mut steamcmd = command
+    .stdout(Stdio::piped())
+    .stderr(Stdio::inherit())
+    .spawn()?;
+  let mut report = ProgressReport::new(io::stderr().is_terminal());
+  let mut outcome = None;
+  if let Some(stdout) = steamcmd.stdout.take() {
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+      match parse_progress(&line) {
+        Some(progress) => {
+          on_progress(&progress);
+          report.progress(progress)
+        }
+        None => {
+          outcome = parse_outcome(&line).or(outcome);
+          report.line(&line)
+        }
+      }
+    }
+  }
+  report.finish();
+  Ok(SteamcmdRun {
+    status: steamcmd.wait()?,
+    outcome,
+  })
+}
+
+/// The directory steamcmd should use as its home when `STEAMCMD_ISOLATED=1`, otherwise `None`.
+pub fn isolated_home() -> Option<PathBuf> {
+  if fetch_var(STEAMCMD_ISOLATED_VAR, "0").eq("1") {
+    let default_home = get_odin_dir().join("steamcmd-home");
+    Some(PathBuf::from(fetch_var(
+      STEAMCMD_ISOLATED_HOME_VAR,
+      default_home.to_str().unwrap(),
+    )))
+  } else {
+    None
+  }
+}
+
+/// Location of steamcmd's cached app info for whichever home it is running with.
+pub fn appinfo_cache_path() -> PathBuf {
+  isolated_home()
+    .unwrap_or_else(|| PathBuf::from(STEAM_HOME))
+    .join("Steam")
+    .join("appcache")
+    .join("appinfo.vdf")
+}
+
+fn configure_isolation(command: &mut Command) -> Result<&mut Command, String> {
+  if let Some(home) = isolated_home() {
+    fs::create_dir_all(&home).map_err(|e| {
+      format!(
+        "Failed to create isolated steamcmd home at {}: {}",
+        home.display(),
+        e
+      )
+    })?;
+    info!("Running steamcmd with isolated home {}", home.display());
+    command.env("HOME", home);
+  }
+  Ok(command)
+}
+
+/// Removes the isolated steamcmd home after a run when `STEAMCMD_ISOLATED_CLEANUP=1`.
+pub fn cleanup_isolated_home() {
+  if let Some(home) = isolated_home() {
+    if fetch_var(STEAMCMD_ISOLATED_CLEANUP_VAR, "0").eq("1") {
+      debug!("Cleaning up isolated steamcmd home {}", home.display());
+      if let Err(e) = fs::remove_dir_all(&home) {
+        warn!(
+          "Failed to clean up isolated steamcmd home {}: {}",
+          home.display(),
+          e
+        );
+      }
+    }
+  }
+}
+
+pub fn steamcmd_command() -> Result<Command, String> {
+  let mut steamcmd = match find_command("steamcmd") {
     Some(steamcmd) => {
       info!("steamcmd found in path");
       steamcmd
@@ -17,10 +272,154 @@ pub fn steamcmd_command() -> Command {
           steamcmd
         }
         None => {
-          error!("\nSteamCMD Executable Not Found! \nPlease install steamcmd... \nhttps://developer.valvesoftware.com/wiki/SteamCMD\n");
-          exit(1);
+          return Err("\nSteamCMD Executable Not Found! \nPlease install steamcmd... \nhttps://developer.valvesoftware.com/wiki/SteamCMD\n".to_string());
         }
       }
     }
+  };
+  configure_isolation(&mut steamcmd)?;
+  Ok(steamcmd)
+}
+
+/// How `steamcmd_command` would launch steamcmd, for dry runs. Nothing is created.
+pub fn steamcmd_display() -> String {
+  let program = if which::which("steamcmd").is_ok() {
+    "steamcmd"
+  } else {
+    STEAMCMD_EXE
+  };
+  match isolated_home() {
+    Some(home) => format!("HOME={} {}", home.display(), program),
+    None => program.to_string(),
+  }
+}
+
+#[cfg(test)]
+mod progress_tests {
+  use super::*;
+
+  #[test]
+  fn parses_update_state_lines() {
+    let progress =
+      parse_progress(" Update state (0x61) downloading, progress: 42.17 (1234567 / 2926264)")
+        .unwrap();
+    assert_eq!(
+      progress,
+      SteamcmdProgress {
+        state: "downloading".to_string(),
+        percent: 42.17,
+        done_bytes: 1234567,
+        total_bytes: 2926264,
+      }
+    );
+    assert_eq!(progress.to_string(), "downloading 42% (1.2 MB of 2.8 MB)");
+    assert_eq!(
+      parse_progress(" Update state (0x5) verifying install, progress: 0.00 (0 / 0)")
+        .unwrap()
+        .state,
+      "verifying install"
+    );
+    assert_eq!(
+      parse_progress("Success! App '896660' fully installed."),
+      None
+    );
+  }
+
+  #[test]
+  fn parses_how_the_update_ended() {
+    assert_eq!(
+      parse_outcome("Success! App '896660' fully installed."),
+      Some(SteamcmdOutcome::Installed)
+    );
+    assert_eq!(
+      parse_outcome("Success! App '896660' already up to date."),
+      Some(SteamcmdOutcome::UpToDate)
+    );
+    let failed = parse_outcome("Error! App '896660' state is 0x202 after update job.").unwrap();
+    assert_eq!(
+      failed.to_string(),
+      "failed: App '896660' state is 0x202 after update job."
+    );
+    assert_eq!(
+      parse_outcome("ERROR! Failed to install app '896660' (Disk write failure)"),
+      Some(SteamcmdOutcome::Failed(
+        "Failed to install app '896660' (Disk write failure)".to_string()
+      ))
+    );
+    assert_eq!(parse_outcome("Loading Steam API...OK"), None);
+  }
+
+  #[test]
+  fn logs_every_step_or_state_change() {
+    let progress = |state: &str, percent: f64| SteamcmdProgress {
+      state: state.to_string(),
+      percent,
+      done_bytes: 0,
+      total_bytes: 0,
+    };
+    let mut report = ProgressReport::new(false);
+    let now = Instant::now();
+    assert!(report.is_due(&progress("downloading", 1.0), now));
+    report.progress(progress("downloading", 1.0));
+    assert!(!report.is_due(&progress("downloading", 9.9), now));
+    assert!(report.is_due(&progress("downloading", 10.0), now));
+    assert!(report.is_due(&progress("verifying install", 1.0), now));
+    assert!(report.is_due(
+      &progress("downloading", 2.0),
+      now + REPORT_INTERVAL + Duration::from_secs(1)
+    ));
+  }
+}
+
+#[cfg(test)]
+mod isolation_tests {
+  use super::*;
+  use crate::utils::testing::test_dir;
+  use serial_test::serial;
+  use std::env::{remove_var, set_var};
+  use std::ffi::OsStr;
+
+  fn home_env(command: &Command) -> Option<&OsStr> {
+    command
+      .get_envs()
+      .find(|(key, _)| *key == OsStr::new("HOME"))
+      .and_then(|(_, value)| value)
+  }
+
+  #[test]
+  #[serial]
+  fn isolated_home_applied_when_enabled() {
+    let temp = test_dir("steamcmd");
+    let home = temp.path().join("home");
+    set_var(STEAMCMD_ISOLATED_VAR, "1");
+    set_var(STEAMCMD_ISOLATED_HOME_VAR, &home);
+    let mut command = Command::new("steamcmd");
+    configure_isolation(&mut command).unwrap();
+    assert_eq!(home_env(&command), Some(home.as_os_str()));
+    assert!(home.is_dir());
+    assert_eq!(
+      appinfo_cache_path(),
+      home.join("Steam/appcache/appinfo.vdf")
+    );
+
+    set_var(STEAMCMD_ISOLATED_CLEANUP_VAR, "1");
+    cleanup_isolated_home();
+    assert!(!home.exists());
+    remove_var(STEAMCMD_ISOLATED_VAR);
+    remove_var(STEAMCMD_ISOLATED_HOME_VAR);
+    remove_var(STEAMCMD_ISOLATED_CLEANUP_VAR);
+  }
+
+  #[test]
+  #[serial]
+  fn home_untouched_when_disabled() {
+    remove_var(STEAMCMD_ISOLATED_VAR);
+    let mut command = Command::new("steamcmd");
+    configure_isolation(&mut command).unwrap();
+    assert_eq!(home_env(&command), None);
+    assert_eq!(
+      appinfo_cache_path(),
+      PathBuf::from("/home/steam/Steam/appcache/appinfo.vdf")
+    );
   }
 }
@@ -1,23 +1,258 @@
 use log::debug;
-use std::env;
+use std::{collections::HashMap, env, fs, sync::RwLock};
+use toml::Value;
+
+use crate::logger::{register_secret, REDACTED};
+
+/// Settings whose names hold one of these are never logged.
+const SECRET_NAME_MARKERS: [&str; 4] = ["PASSWORD", "PASSPHRASE", "SECRET", "TOKEN"];
+
+/// Picks the profile like `--profile` does.
+pub const ODIN_PROFILE_VAR: &str = "ODIN_PROFILE";
+/// `ODIN_PROFILE_EVENTS_PORT` sets `PORT` for the `events` profile.
+const PROFILE_VAR_PREFIX: &str = "ODIN_PROFILE_";
+/// The config file's `[profiles.<name>]` tables.
+const PROFILES_TABLE: &str = "profiles";
+
+// Values loaded from `--config`, keyed by the env var name they stand in for.
+static CONFIG_FILE_VALUES: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+static ACTIVE_PROFILE: RwLock<Option<String>> = RwLock::new(None);
+
+fn parse_config_value(key: &str, value: &Value) -> Result<String, String> {
+  match value {
+    Value::String(value) => Ok(value.clone()),
+    Value::Integer(value) => Ok(value.to_string()),
+    Value::Float(value) => Ok(value.to_string()),
+    Value::Boolean(value) => Ok(if *value { "1" } else { "0" }.to_string()),
+    Value::Array(values) => values
+      .iter()
+      .map(|value| parse_config_value(key, value))
+      .collect::<Result<Vec<String>, String>>()
+      .map(|values| values.join(",")),
+    _ => Err(format!(
+      "Unsupported value for '{}', expected a string, number, boolean or list",
+      key
+    )),
+  }
+}
+
+/// The settings of each `[profiles.<name>]` table, keyed like the `ODIN_PROFILE_*` env vars.
+fn parse_profiles(profiles: &Value) -> Result<Vec<(String, String)>, String> {
+  let profiles = match profiles {
+    Value::Table(profiles) => profiles,
+    _ => return Err("Expected [profiles.<name>] tables".to_string()),
+  };
+  let mut values = Vec::new();
+  for (profile, settings) in profiles {
+    check_profile_name(profile)?;
+    let settings = match settings {
+      Value::Table(settings) => settings,
+      _ => {
+        return Err(format!(
+          "Expected a table of settings for profile '{}'",
+          profile
+        ))
+      }
+    };
+    for (key, value) in settings {
+      values.push((
+        profile_var_name(profile, key),
+        parse_config_value(key, value)?,
+      ));
+    }
+  }
+  Ok(values)
+}
+
+pub fn parse_config(content: &str) -> Result<HashMap<String, String>, String> {
+  let table = match content.parse::<Value>() {
+    Ok(Value::Table(table)) => table,
+    Ok(_) => return Err("Expected a table of settings".to_string()),
+    Err(e) => return Err(e.to_string()),
+  };
+  let mut values = HashMap::new();
+  for (key, value) in &table {
+    if key == PROFILES_TABLE {
+      values.extend(parse_profiles(value)?);
+    } else {
+      values.insert(key.to_uppercase(), parse_config_value(key, value)?);
+    }
+  }
+  Ok(values)
+}
+
+/// Loads a TOML config file whose keys are env var names. Env vars still take precedence.
+pub fn load_config_file(path: &str) -> Result<(), String> {
+  let content =
+    fs::read_to_string(path).map_err(|e| format!("Unable to read config {}: {}", path, e))?;
+  let values = parse_config(&content).map_err(|e| format!("Invalid config {}: {}", path, e))?;
+  *CONFIG_FILE_VALUES.write().unwrap() = Some(values);
+  Ok(())
+}
+
+pub fn fetch_config_value(name: &str) -> Option<String> {
+  CONFIG_FILE_VALUES
+    .read()
+    .unwrap()
+    .as_ref()
+    .and_then(|values| values.get(&name.to_uppercase()).cloned())
+    .filter(|value| !value.is_empty())
+}
+
+/// Profile names end up in env var names, so they can only hold letters and digits.
+fn check_profile_name(profile: &str) -> Result<(), String> {
+  if !profile.is_empty() && profile.chars().all(|c| c.is_ascii_alphanumeric()) {
+    Ok(())
+  } else {
+    Err(format!(
+      "Invalid profile name '{}', use only letters and digits",
+      profile
+    ))
+  }
+}
+
+/// `ODIN_PROFILE_<PROFILE>_<NAME>`, what sets `name` for `profile`.
+pub fn profile_var_name(profile: &str, name: &str) -> String {
+  format!(
+    "{}{}_{}",
+    PROFILE_VAR_PREFIX,
+    profile.to_uppercase(),
+    name.to_uppercase()
+  )
+}
+
+/// The value `profile` gives `name`, from the environment or the config file.
+pub fn fetch_profile_value(profile: &str, name: &str) -> Option<String> {
+  let key = profile_var_name(profile, name);
+  env::var(&key)
+    .ok()
+    .filter(|value| !value.is_empty())
+    .or_else(|| fetch_config_value(&key))
+}
+
+/// Every profile some env var or the config file sets anything for, lowercased and sorted.
+pub fn profile_names() -> Vec<String> {
+  let config_keys: Vec<String> = CONFIG_FILE_VALUES
+    .read()
+    .unwrap()
+    .as_ref()
+    .map(|values| values.keys().cloned().collect())
+    .unwrap_or_default();
+  let mut names: Vec<String> = env::vars()
+    .map(|(key, _)| key)
+    .chain(config_keys)
+    .filter_map(|key| {
+      let rest = key.strip_prefix(PROFILE_VAR_PREFIX)?;
+      let (profile, name) = rest.split_once('_')?;
+      (!profile.is_empty() && !name.is_empty()).then(|| profile.to_lowercase())
+    })
+    .collect();
+  names.sort();
+  names.dedup();
+  names
+}
+
+/// Makes every setting read from here on come from `profile` first, for `--profile`.
+pub fn use_profile(profile: &str) -> Result<(), String> {
+  check_profile_name(profile)?;
+  let profile = profile.to_lowercase();
+  let known = profile_names();
+  if !known.contains(&profile) {
+    return Err(format!(
+      "Unknown profile '{}', set {} variables or add a [profiles.{}] table to the config file. Known profiles: {}",
+      profile,
+      profile_var_name(&profile, "<SETTING>"),
+      profile,
+      if known.is_empty() {
+        "none".to_string()
+      } else {
+        known.join(", ")
+      }
+    ));
+  }
+  *ACTIVE_PROFILE.write().unwrap() = Some(profile);
+  Ok(())
+}
+
+/// The profile picked with `--profile` or `ODIN_PROFILE`, if any.
+pub fn active_profile() -> Option<String> {
+  ACTIVE_PROFILE.read().unwrap().clone()
+}
+
+/// What the active profile sets `name` to.
+pub fn active_profile_value(name: &str) -> Option<String> {
+  active_profile().and_then(|profile| fetch_profile_value(&profile, name))
+}
+
+pub fn is_secret_name(name: &str) -> bool {
+  let name = name.to_uppercase();
+  SECRET_NAME_MARKERS
+    .iter()
+    .any(|marker| name.contains(marker))
+}
+
+/// What to log for the value of `name`, secrets are registered with the logger and masked.
+pub fn loggable_value(name: &str, value: &str) -> String {
+  if is_secret_name(name) {
+    register_secret(value);
+    REDACTED.to_string()
+  } else {
+    value.to_string()
+  }
+}
 
 pub fn fetch_var(name: &str, default: &str) -> String {
+  if let Some(value) = active_profile_value(name) {
+    debug!(
+      "Profile value found '{}': '{}'",
+      name,
+      loggable_value(name, &value)
+    );
+    return value;
+  }
   match env::var(name) {
-    Ok(value) => {
-      debug!("Env var found '{}': '{}'", name, value);
-      if value.is_empty() {
-        String::from(default)
-      } else {
+    Ok(value) if !value.is_empty() => {
+      debug!(
+        "Env var found '{}': '{}'",
+        name,
+        loggable_value(name, &value)
+      );
+      value
+    }
+    _ => match fetch_config_value(name) {
+      Some(value) => {
+        debug!(
+          "Config value found '{}': '{}'",
+          name,
+          loggable_value(name, &value)
+        );
         value
       }
-    }
-    Err(_) => {
-      debug!("Env var default '{}': '{}'", name, default);
-      String::from(default)
-    }
+      None => {
+        debug!(
+          "Env var default '{}': '{}'",
+          name,
+          loggable_value(name, default)
+        );
+        String::from(default)
+      }
+    },
   }
 }
 
+/// Like `fetch_var` with no default, but never logs the value, for keys and passphrases.
+pub fn fetch_secret_var(name: &str) -> String {
+  let value = match active_profile_value(name).map_or_else(|| env::var(name), Ok) {
+    Ok(value) if !value.is_empty() => {
+      debug!("Env var found '{}': {}", name, REDACTED);
+      value
+    }
+    _ => fetch_config_value(name).unwrap_or_default(),
+  };
+  register_secret(&value);
+  value
+}
+
 pub fn fetch_multiple_var(name: &str, default: &str) -> String {
   let value = fetch_var(name, default);
   if value.is_empty() {
@@ -29,7 +264,11 @@ pub fn fetch_multiple_var(name: &str, default: &str) -> String {
 
 #[cfg(test)]
 mod fetch_env_tests {
-  use crate::utils::environment::{fetch_multiple_var, fetch_var};
+  use crate::utils::environment::{
+    fetch_multiple_var, fetch_profile_value, fetch_var, parse_config, profile_names, use_profile,
+    CONFIG_FILE_VALUES,
+  };
+  use serial_test::serial;
   use std::env;
 
   #[test]
@@ -72,4 +311,63 @@ mod fetch_env_tests {
     let observed_value = fetch_multiple_var(expected_key, expected_value);
     assert_eq!(expected_value, observed_value);
   }
+  #[test]
+  fn parses_config_file() {
+    let values = parse_config(
+      r#"
+      name = "My Server"
+      port = 2456
+      auto_backup = true
+      backup_exclude = ["*.old", "*.bak"]
+      "#,
+    )
+    .unwrap();
+    assert_eq!(values["NAME"], "My Server");
+    assert_eq!(values["PORT"], "2456");
+    assert_eq!(values["AUTO_BACKUP"], "1");
+    assert_eq!(values["BACKUP_EXCLUDE"], "*.old,*.bak");
+  }
+  #[test]
+  fn rejects_malformed_config() {
+    assert!(parse_config("name = ").is_err());
+    assert!(parse_config("[server]\nname = \"nested\"").is_err());
+    assert!(parse_config("[profiles.my-events]\nport = 2466").is_err());
+  }
+  #[test]
+  fn reads_profiles_from_the_config_and_env() {
+    let values = parse_config(
+      r#"
+      port = 2456
+      [profiles.events]
+      port = 2466
+      world = "Arena"
+      "#,
+    )
+    .unwrap();
+    assert_eq!(values["PORT"], "2456");
+    assert_eq!(values["ODIN_PROFILE_EVENTS_PORT"], "2466");
+    assert_eq!(values["ODIN_PROFILE_EVENTS_WORLD"], "Arena");
+    env::set_var("ODIN_PROFILE_ENVTEST_WORLD", "Midgard");
+    assert_eq!(
+      fetch_profile_value("envtest", "world"),
+      Some("Midgard".to_string())
+    );
+    assert_eq!(fetch_profile_value("envtest", "PORT"), None);
+    assert!(profile_names().contains(&"envtest".to_string()));
+    assert!(use_profile("nosuchprofile").is_err());
+    env::remove_var("ODIN_PROFILE_ENVTEST_WORLD");
+  }
+  #[test]
+  #[serial]
+  fn env_takes_precedence_over_config() {
+    let config =
+      parse_config("config_only_key = \"from config\"\nconfig_and_env_key = \"from config\"")
+        .unwrap();
+    *CONFIG_FILE_VALUES.write().unwrap() = Some(config);
+    env::set_var("CONFIG_AND_ENV_KEY", "from env");
+    assert_eq!(fetch_var("CONFIG_ONLY_KEY", ""), "from config");
+    assert_eq!(fetch_var("CONFIG_AND_ENV_KEY", ""), "from env");
+    *CONFIG_FILE_VALUES.write().unwrap() = None;
+    env::remove_var("CONFIG_AND_ENV_KEY");
+  }
 }
@@ -1,43 +1,123 @@
 pub mod environment;
+pub mod schedule;
+#[cfg(test)]
+pub mod testing;
 
+use chrono::Duration;
 use clap::ArgMatches;
 use log::debug;
 use std::env;
-use std::path::Path;
+use std::path::PathBuf;
 
 use crate::constants;
 
-pub fn get_working_dir() -> String {
-  environment::fetch_var(
-    constants::ODIN_WORKING_DIR,
-    env::current_dir().unwrap().to_str().unwrap(),
-  )
+/// `ODIN_WORKING_DIR`, or the current directory when it isn't set. Paths under it should be built
+/// with `join` so they get the platform's separators.
+pub fn get_working_dir() -> PathBuf {
+  match environment::fetch_var(constants::ODIN_WORKING_DIR, "").as_str() {
+    "" => env::current_dir().unwrap(),
+    value => PathBuf::from(value),
+  }
+}
+
+/// Points `get_working_dir` at `dir`, over `ODIN_WORKING_DIR`. A relative `dir` is resolved
+/// against the current directory now, so it stays the same when odin changes directory later.
+pub fn set_working_dir(dir: &str) {
+  let path = env::current_dir().unwrap().join(dir);
+  env::set_var(constants::ODIN_WORKING_DIR, path);
+}
+
+/// Directory where odin keeps its own state, separate from the server files.
+pub fn get_odin_dir() -> PathBuf {
+  get_working_dir().join(".odin")
 }
 
 pub fn get_variable(args: &ArgMatches, name: &str, default: String) -> String {
   debug!("Checking env for {}", name);
+  if let Some(profile_val) = environment::active_profile_value(name) {
+    debug!(
+      "Profile value found {}={}",
+      name,
+      environment::loggable_value(name, &profile_val)
+    );
+    return profile_val;
+  }
   if let Ok(env_val) = env::var(name.to_uppercase()) {
     if !env_val.is_empty() {
-      debug!("Env variable found {}={}", name, env_val);
+      debug!(
+        "Env variable found {}={}",
+        name,
+        environment::loggable_value(name, &env_val)
+      );
       return env_val;
     }
   }
   if let Ok(env_val) = env::var(format!("SERVER_{}", name).to_uppercase()) {
-    debug!("Env variable found {}={}", name, env_val);
+    debug!(
+      "Env variable found {}={}",
+      name,
+      environment::loggable_value(name, &env_val)
+    );
     return env_val;
   }
-  args
-    .value_of(name)
-    .unwrap_or_else(|| default.as_str())
-    .to_string()
+  if args.occurrences_of(name) == 0 {
+    if let Some(config_val) = environment::fetch_config_value(name) {
+      debug!(
+        "Config value found {}={}",
+        name,
+        environment::loggable_value(name, &config_val)
+      );
+      return config_val;
+    }
+  }
+  args.value_of(name).unwrap_or(default.as_str()).to_string()
+}
+
+/// Parses durations like `90s`, `30m`, `12h` or `7d`. A bare number is taken as minutes.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+  let value = value.trim();
+  let (amount, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+    Some(index) => value.split_at(index),
+    None => (value, "m"),
+  };
+  let amount = amount.parse::<i64>().ok()?;
+  match unit.trim().to_lowercase().as_str() {
+    "s" => Some(Duration::seconds(amount)),
+    "m" => Some(Duration::minutes(amount)),
+    "h" => Some(Duration::hours(amount)),
+    "d" => Some(Duration::days(amount)),
+    _ => None,
+  }
+}
+
+/// Parses the ways a boolean setting can be spelled, `1`/`0`, `true`/`false` and `yes`/`no`.
+pub fn parse_bool(value: &str) -> Option<bool> {
+  match value.trim().to_lowercase().as_str() {
+    "1" | "true" | "yes" | "on" => Some(true),
+    "0" | "false" | "no" | "off" => Some(false),
+    _ => None,
+  }
 }
 
-pub(crate) fn path_exists(path: &str) -> bool {
-  let state = Path::new(path).exists();
-  debug!(
-    "Path {} {}",
-    path,
-    if state { "exists" } else { "does not exist" }
-  );
-  state
+#[cfg(test)]
+mod duration_tests {
+  use super::*;
+
+  #[test]
+  fn parses_durations() {
+    assert_eq!(parse_duration("90s"), Some(Duration::seconds(90)));
+    assert_eq!(parse_duration("12h"), Some(Duration::hours(12)));
+    assert_eq!(parse_duration("7d"), Some(Duration::days(7)));
+    assert_eq!(parse_duration("45"), Some(Duration::minutes(45)));
+    assert_eq!(parse_duration("soon"), None);
+    assert_eq!(parse_duration("5w"), None);
+  }
+
+  #[test]
+  fn parses_booleans() {
+    assert_eq!(parse_bool("1"), Some(true));
+    assert_eq!(parse_bool(" True "), Some(true));
+    assert_eq!(parse_bool("no"), Some(false));
+    assert_eq!(parse_bool("2"), None);
+  }
 }
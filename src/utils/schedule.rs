@@ -0,0 +1,92 @@
+use chrono::{DateTime, Local};
+use cron::Schedule;
+use log::{info, warn};
+
+use std::{str::FromStr, thread};
+
+/// Parses a cron expression. The usual five fields (`0 */2 * * *`) are accepted alongside the
+/// six and seven field forms that start with seconds.
+pub fn parse_schedule(expression: &str) -> Result<Schedule, String> {
+  let expression = expression.trim().trim_matches('"');
+  let normalized = if expression.split_whitespace().count() == 5 {
+    format!("0 {}", expression)
+  } else {
+    expression.to_string()
+  };
+  Schedule::from_str(&normalized)
+    .map_err(|e| format!("Invalid cron schedule '{}': {}", expression, e))
+}
+
+pub fn next_run(schedule: &Schedule, after: DateTime<Local>) -> Option<DateTime<Local>> {
+  schedule.after(&after).next()
+}
+
+/// Runs `job` at every time in the schedule, forever. Runs never overlap; anything that came due
+/// while a run was still going is skipped rather than queued up.
+pub fn run_on_schedule(name: &str, schedule: &Schedule, mut job: impl FnMut()) -> ! {
+  let mut next = next_run(schedule, Local::now());
+  loop {
+    let at = match next {
+      Some(at) => at,
+      None => {
+        warn!(
+          "The {} schedule has no upcoming runs, sleeping forever",
+          name
+        );
+        loop {
+          thread::park();
+        }
+      }
+    };
+    info!("Next {} scheduled for {}", name, at.to_rfc3339());
+    if let Ok(wait) = at.signed_duration_since(Local::now()).to_std() {
+      thread::sleep(wait);
+    }
+    job();
+    let finished = Local::now();
+    let skipped = schedule
+      .after(&at)
+      .take_while(|run| *run <= finished)
+      .count();
+    if skipped > 0 {
+      warn!(
+        "Skipped {} {} run(s) that came due while the last one was still going",
+        skipped, name
+      );
+    }
+    next = next_run(schedule, finished);
+  }
+}
+
+#[cfg(test)]
+mod schedule_tests {
+  use super::*;
+  use chrono::TimeZone;
+
+  #[test]
+  fn accepts_five_field_expressions() {
+    let schedule = parse_schedule("0 */2 * * *").unwrap();
+    let after = Local.ymd(2021, 3, 1).and_hms(1, 30, 0);
+    assert_eq!(
+      next_run(&schedule, after),
+      Some(Local.ymd(2021, 3, 1).and_hms(2, 0, 0))
+    );
+  }
+
+  #[test]
+  fn accepts_expressions_with_seconds() {
+    let schedule = parse_schedule("30 */15 * * * *").unwrap();
+    let after = Local.ymd(2021, 3, 1).and_hms(1, 0, 31);
+    assert_eq!(
+      next_run(&schedule, after),
+      Some(Local.ymd(2021, 3, 1).and_hms(1, 15, 30))
+    );
+  }
+
+  #[test]
+  fn rejects_invalid_expressions() {
+    assert!(parse_schedule("every hour").is_err());
+    assert!(parse_schedule("0 25 * * *").is_err());
+    assert!(parse_schedule("").is_err());
+  }
+}
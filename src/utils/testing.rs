@@ -0,0 +1,10 @@
+use tempfile::{Builder, TempDir};
+
+/// A new, empty directory for a test to work in, named after `name`. It is removed when the
+/// returned `TempDir` drops, so keep it alive for the whole test, also when an assert fails.
+pub fn test_dir(name: &str) -> TempDir {
+  Builder::new()
+    .prefix(&format!("odin-{}-", name))
+    .tempdir()
+    .unwrap()
+}